@@ -0,0 +1,402 @@
+//! Bearer-token authentication with API keys or identity provider JWTs,
+//! and key management.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::extract::{Json, Path};
+use super::limits::ClientIp;
+use crate::db::Database;
+use crate::errors::{ApiError, Result};
+use crate::models::owner::{ApiKey, IssuedKey, Owner};
+use crate::models::usage::{Payer, UsageReport};
+use crate::services::jwt::{self, JwtVerifier};
+use crate::services::limits::Limits;
+
+/// The authenticated owner of the current request, and where it came from.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub owner: Owner,
+    pub ip: IpAddr,
+}
+
+impl Caller {
+    pub fn id(&self) -> &Uuid {
+        &self.owner.id
+    }
+
+    /// Whose quotas the caller's work counts against.
+    pub fn payers(&self) -> [Payer; 2] {
+        [Payer::Owner(self.owner.id), Payer::Ip(self.ip)]
+    }
+
+    /// Fail unless the caller may act on `file_id`. Documents without an
+    /// owner predate authentication and are only open to admins.
+    pub fn authorize(&self, db: &Database, file_id: &Uuid) -> Result<()> {
+        match db.document_owner(file_id)? {
+            None => Err(ApiError::NotFound(format!(
+                "Document not found: {}",
+                file_id
+            ))),
+            Some(owner) if self.owner.admin || owner == Some(self.owner.id) => Ok(()),
+            Some(_) => Err(ApiError::AuthorizationError),
+        }
+    }
+
+    fn require_admin(&self) -> Result<()> {
+        if self.owner.admin {
+            Ok(())
+        } else {
+            Err(ApiError::AuthorizationError)
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Caller {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self> {
+        parts
+            .extensions
+            .get::<Caller>()
+            .cloned()
+            .ok_or(ApiError::AuthenticationError)
+    }
+}
+
+/// Middleware: resolve `Authorization: Bearer <key or JWT>` to a [`Caller`].
+pub async fn authenticate(
+    State(db): State<Database>,
+    State(verifier): State<Option<JwtVerifier>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response> {
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+        .ok_or(ApiError::AuthenticationError)?;
+    let owner = match &verifier {
+        Some(verifier) if jwt::is_jwt(&token) => db.sign_in(&verifier.verify(&token).await?)?,
+        _ => db
+            .authenticate(&token)?
+            .ok_or(ApiError::AuthenticationError)?,
+    };
+
+    let ip = request
+        .extensions()
+        .get::<ClientIp>()
+        .map_or(Ipv4Addr::UNSPECIFIED.into(), |ClientIp(ip)| *ip);
+    request.extensions_mut().insert(Caller { owner, ip });
+    Ok(next.run(request).await)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewKey {
+    name: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewOwner {
+    name: String,
+    #[serde(default)]
+    admin: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedOwner {
+    owner: Owner,
+    key: IssuedKey,
+}
+
+#[utoipa::path(get, path = "/api/v1/me", tag = "account", responses((status = 200, body = Owner)))]
+async fn current_owner(caller: Caller) -> Json<Owner> {
+    Json(caller.owner)
+}
+
+/// Today's usage against the caller's quotas.
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/usage",
+    tag = "account",
+    responses((status = 200, body = UsageReport))
+)]
+async fn current_usage(
+    State(db): State<Database>,
+    State(limits): State<Limits>,
+    caller: Caller,
+) -> Result<Json<UsageReport>> {
+    Ok(Json(limits.report(&db, caller.id())?))
+}
+
+#[utoipa::path(get, path = "/api/v1/keys", tag = "account", responses((status = 200, body = Vec<ApiKey>)))]
+async fn list_keys(State(db): State<Database>, caller: Caller) -> Result<Json<Vec<ApiKey>>> {
+    Ok(Json(db.api_keys(caller.id())?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/keys",
+    tag = "account",
+    request_body = NewKey,
+    responses((status = 201, body = IssuedKey))
+)]
+async fn create_key(
+    State(db): State<Database>,
+    caller: Caller,
+    Json(request): Json<NewKey>,
+) -> Result<impl IntoResponse> {
+    let name = validate_name(&request.name)?;
+    let key = db.create_api_key(caller.id(), name)?;
+    Ok((StatusCode::CREATED, Json(key)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/keys/{key_id}",
+    tag = "account",
+    params(("key_id" = Uuid, Path)),
+    responses((status = 204, description = "Revoked"))
+)]
+async fn revoke_key(
+    State(db): State<Database>,
+    caller: Caller,
+    Path(key_id): Path<Uuid>,
+) -> Result<StatusCode> {
+    if db.revoke_api_key(caller.id(), &key_id)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(format!("API key not found: {}", key_id)))
+    }
+}
+
+#[utoipa::path(get, path = "/api/v1/owners", tag = "admin", responses((status = 200, body = Vec<Owner>)))]
+async fn list_owners(State(db): State<Database>, caller: Caller) -> Result<Json<Vec<Owner>>> {
+    caller.require_admin()?;
+    Ok(Json(db.owners()?))
+}
+
+/// Create an owner along with their first key.
+#[utoipa::path(
+    post,
+    path = "/api/v1/owners",
+    tag = "admin",
+    request_body = NewOwner,
+    responses((status = 201, body = CreatedOwner))
+)]
+async fn create_owner(
+    State(db): State<Database>,
+    caller: Caller,
+    Json(request): Json<NewOwner>,
+) -> Result<impl IntoResponse> {
+    caller.require_admin()?;
+    let name = validate_name(&request.name)?;
+    let owner = db.create_owner(name, request.admin)?;
+    let key = db.create_api_key(&owner.id, "default")?;
+    Ok((StatusCode::CREATED, Json(CreatedOwner { owner, key })))
+}
+
+fn validate_name(name: &str) -> Result<&str> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > 100 {
+        return Err(ApiError::invalid_field(
+            "name",
+            "Name must be between 1 and 100 characters",
+        ));
+    }
+    Ok(name)
+}
+
+pub fn routes() -> Router<super::AppState> {
+    Router::new()
+        .route("/me", get(current_owner))
+        .route("/me/usage", get(current_usage))
+        .route("/keys", get(list_keys).post(create_key))
+        .route("/keys/:key_id", delete(revoke_key))
+        .route("/owners", get(list_owners).post(create_owner))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::api::testing::TestApi;
+
+    const SOURCE: &str = "\\documentclass{article}\\begin{document}Mine\\end{document}";
+
+    #[tokio::test]
+    async fn requires_a_known_key() {
+        let api = TestApi::start().await;
+        let (owner, key) = api.owner("reader", false);
+        let me = |token: Option<&str>| {
+            let request = api.client.get(api.url("/api/v1/me"));
+            match token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+            .send()
+        };
+
+        for token in [None, Some("nf_not_a_key"), Some("")] {
+            let response = me(token).await.unwrap();
+            assert_eq!(response.status(), 401, "{:?}", token);
+            assert_eq!(response.headers()["www-authenticate"], "Bearer");
+        }
+        let response = me(Some(&key)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["id"], owner.id.to_string());
+    }
+
+    #[tokio::test]
+    async fn scopes_documents_to_their_owner() {
+        let api = TestApi::start().await;
+        let (alice, alice_key) = api.owner("alice", false);
+        let (_, bob_key) = api.owner("bob", false);
+        let (_, admin_key) = api.owner("admin", true);
+        let document = api.document(&alice.id, SOURCE).await;
+        let get = |path: String, key: &str| api.client.get(api.url(&path)).bearer_auth(key).send();
+        let path = format!("/api/v1/documents/{}", document.id);
+
+        assert_eq!(get(path.clone(), &alice_key).await.unwrap().status(), 200);
+        assert_eq!(get(path.clone(), &bob_key).await.unwrap().status(), 403);
+        for export in ["exports/markdown", "exports/bundle", "pages", "shares"] {
+            let path = format!("{}/{}", path, export);
+            assert_eq!(
+                get(path.clone(), &bob_key).await.unwrap().status(),
+                403,
+                "{}",
+                path
+            );
+        }
+        let deleted = api
+            .client
+            .delete(api.url(&path))
+            .bearer_auth(&bob_key)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(deleted.status(), 403);
+        assert_eq!(get(path.clone(), &admin_key).await.unwrap().status(), 200);
+
+        // Listings only show other owners' documents to admins
+        for (key, total) in [(&bob_key, 0), (&admin_key, 1)] {
+            let list: Value = get("/api/v1/documents".to_string(), key)
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            assert_eq!(list["total"], total);
+        }
+    }
+
+    #[tokio::test]
+    async fn leaves_owner_management_to_admins() {
+        let api = TestApi::start().await;
+        let (_, key) = api.owner("reader", false);
+        let (_, admin_key) = api.owner("admin", true);
+        let create = |key: &str| {
+            api.client
+                .post(api.url("/api/v1/owners"))
+                .bearer_auth(key)
+                .json(&json!({ "name": "new" }))
+                .send()
+        };
+
+        assert_eq!(create(&key).await.unwrap().status(), 403);
+        let owners = api
+            .client
+            .get(api.url("/api/v1/owners"))
+            .bearer_auth(&key)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(owners.status(), 403);
+
+        let created = create(&admin_key).await.unwrap();
+        assert_eq!(created.status(), 201);
+        let created: Value = created.json().await.unwrap();
+        assert_eq!(created["owner"]["admin"], false);
+        let me = api
+            .client
+            .get(api.url("/api/v1/me"))
+            .bearer_auth(created["key"]["secret"].as_str().unwrap())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(me.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn revoked_keys_stop_working() {
+        let api = TestApi::start().await;
+        let (_, key) = api.owner("reader", false);
+        let (_, other_key) = api.owner("other", false);
+        let created = api
+            .client
+            .post(api.url("/api/v1/keys"))
+            .bearer_auth(&key)
+            .json(&json!({ "name": "laptop" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(created.status(), 201);
+        let created: Value = created.json().await.unwrap();
+        let (id, secret) = (
+            created["id"].as_str().unwrap(),
+            created["secret"].as_str().unwrap(),
+        );
+        let revoke = |key: &str| {
+            api.client
+                .delete(api.url(&format!("/api/v1/keys/{}", id)))
+                .bearer_auth(key)
+                .send()
+        };
+        let me = |key: &str| {
+            api.client
+                .get(api.url("/api/v1/me"))
+                .bearer_auth(key)
+                .send()
+        };
+
+        assert_eq!(me(secret).await.unwrap().status(), 200);
+        // Other owners can't revoke it, or learn that it exists
+        assert_eq!(revoke(&other_key).await.unwrap().status(), 404);
+        assert_eq!(me(secret).await.unwrap().status(), 200);
+        assert_eq!(revoke(&key).await.unwrap().status(), 204);
+        assert_eq!(me(secret).await.unwrap().status(), 401);
+        assert_eq!(me(&key).await.unwrap().status(), 200);
+    }
+
+    #[tokio::test]
+    async fn keeps_only_the_original_routes_at_the_root() {
+        let api = TestApi::start().await;
+        let (_, key) = api.owner("reader", false);
+        for path in ["/me", "/keys", "/documents", "/styles"] {
+            let response = api
+                .client
+                .get(api.url(path))
+                .bearer_auth(&key)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 404, "{}", path);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use uuid::Uuid;
+
+use super::auth::Caller;
+use super::convert::{load_document, page_images};
+use super::extract::Path;
+use super::openapi::Binary;
+use crate::db::Database;
+use crate::errors::Result;
+use crate::services::bundle::{self, PageImage};
+use crate::services::storage::Storage;
+use crate::utils::headers;
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{file_id}/exports/bundle",
+    tag = "exports",
+    params(("file_id" = Uuid, Path, description = "Document ID")),
+    responses((
+        status = 200,
+        description = "LaTeX, page images and a manifest",
+        content_type = "application/zip",
+        body = Binary,
+        headers(("etag" = String))
+    ))
+)]
+pub async fn download_bundle(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    caller.authorize(&db, &file_id)?;
+    let document = load_document(&db, &storage, &file_id).await?;
+
+    let mut images = Vec::new();
+    for key in page_images(&storage, &file_id).await? {
+        let data = storage.get(&key).await?;
+        let extension = key
+            .rsplit_once('.')
+            .map(|(_, extension)| extension)
+            .unwrap_or("bin")
+            .to_string();
+        images.push(PageImage { extension, data });
+    }
+
+    let bundle = bundle::build(&document, &images)?;
+
+    let mut headers = headers::attachment("application/zip", &format!("{}.zip", file_id));
+    if let Ok(etag) = format!("\"{}\"", bundle.etag).parse() {
+        headers.insert(axum::http::header::ETAG, etag);
+    }
+
+    Ok((headers, bundle.body))
+}
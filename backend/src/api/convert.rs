@@ -1,126 +1,537 @@
+use super::auth::Caller;
+use super::extract::{Json, Path, Query};
+use super::openapi::Binary;
 use crate::{
-    config::env::Config,
+    config::env::{Config, WebhookConfig},
+    db::Database,
     errors::{ApiError, Result},
-    models::document::Document,
-    services::{claude::ClaudeService, pdf::PdfService},
-    utils::headers::HeaderMap,
-};
-use axum::{
-    extract::{Path, Query},
-    response::{IntoResponse, Response},
-    Json,
+    models::document::{Document, DocumentOrigin},
+    models::job::JobKind,
+    models::usage::{Payer, Usage},
+    models::webhook::{EventData, EventKind},
+    services::{
+        claude::ClaudeService,
+        export,
+        limits::Limits,
+        metrics, parser,
+        pdf::PdfService,
+        storage::{self, Storage},
+        styles::{self, Style},
+        typst::TypstService,
+        webhooks,
+    },
+    utils::headers,
 };
+use axum::{extract::State, response::IntoResponse};
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::time::Instant;
+use tracing::Instrument;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ConvertParams {
+    /// Whether the upload has one image per page.
     is_multi_page: bool,
+    /// One of `/styles`; the default style when omitted.
+    style: Option<String>,
 }
 
-// Store converted LaTeX for later PDF generation
-async fn store_latex(file_id: &Uuid, content: &str) -> Result<()> {
-    let latex_dir = PathBuf::from("latex");
-    tokio::fs::create_dir_all(&latex_dir)
-        .await
-        .map_err(|e| ApiError::FileError(format!("Failed to create latex directory: {}", e)))?;
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ConvertRequest {
+    /// Whether the upload has one image per page; inferred from the upload
+    /// when omitted.
+    is_multi_page: Option<bool>,
+    /// One of `/styles`; the default style when omitted.
+    style: Option<String>,
+    /// Also send the `conversion.succeeded` or `conversion.failed` event here,
+    /// signed like the account's webhooks.
+    callback_url: Option<String>,
+}
 
-    let latex_path = latex_dir.join(format!("{}.tex", file_id));
-    tokio::fs::write(&latex_path, content)
-        .await
-        .map_err(|e| ApiError::FileError(format!("Failed to write LaTeX file: {}", e)))?;
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PdfParams {
+    /// Re-render in another of `/styles` before compiling.
+    style: Option<String>,
+    /// `pdflatex` (default) or `typst`.
+    engine: Option<String>,
+    /// Also send the `pdf.ready` event here, signed like the account's webhooks.
+    callback_url: Option<String>,
+}
 
+// Store converted LaTeX for later PDF generation, dropping PDFs built from
+// what it replaces
+pub(crate) async fn store_latex(storage: &Storage, file_id: &Uuid, content: &str) -> Result<()> {
+    storage
+        .put(&storage::latex_key(file_id), content.as_bytes().to_vec())
+        .await?;
+    for key in storage.list(&storage::pdf_prefix(file_id)).await? {
+        storage.delete(&key).await?;
+    }
     Ok(())
 }
 
 // Retrieve stored LaTeX content
-async fn get_latex(file_id: &Uuid) -> Result<String> {
-    let latex_path = PathBuf::from("latex").join(format!("{}.tex", file_id));
-    tokio::fs::read_to_string(&latex_path)
+async fn get_latex(storage: &Storage, file_id: &Uuid) -> Result<String> {
+    let data = storage
+        .get(&storage::latex_key(file_id))
         .await
-        .map_err(|e| ApiError::NotFound(format!("LaTeX file not found: {}", e)))
+        .map_err(|e| match e {
+            ApiError::NotFound(_) => {
+                ApiError::NotFound(format!("LaTeX file not found: {}", file_id))
+            }
+            e => e,
+        })?;
+    String::from_utf8(data)
+        .map_err(|_| ApiError::StorageError(format!("Stored LaTeX for {} is not UTF-8", file_id)))
+}
+
+// Rebuild the `Document` for previously converted LaTeX
+pub(crate) async fn load_document(
+    db: &Database,
+    storage: &Storage,
+    file_id: &Uuid,
+) -> Result<Document> {
+    let content = get_latex(storage, file_id).await?;
+    let created_at = db.created_at(file_id)?.unwrap_or_else(chrono::Utc::now);
+
+    Ok(Document {
+        id: *file_id,
+        filename: format!("{}.tex", file_id),
+        style: styles::detect(&content).name.to_string(),
+        content,
+        created_at,
+    })
+}
+
+// Storage keys of the uploaded page images for a file ID, in page order
+pub(crate) async fn page_images(storage: &Storage, file_id: &Uuid) -> Result<Vec<String>> {
+    let id = file_id.to_string();
+    let mut pages = Vec::new();
+    for key in storage.list(&storage::upload_key(&id)).await? {
+        let name = key.rsplit('/').next().unwrap_or_default();
+        let stem = name.split('.').next().unwrap_or_default();
+        // `{id}.ext` for a single page, `{id}_{n}.ext` for multi-page uploads
+        let index = if stem == id {
+            0
+        } else if let Some(index) = stem
+            .strip_prefix(&id)
+            .and_then(|rest| rest.strip_prefix('_'))
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            index
+        } else {
+            continue;
+        };
+        pages.push((index, key));
+    }
+
+    pages.sort();
+    Ok(pages.into_iter().map(|(_, key)| key).collect())
 }
 
+/// Deprecated alias of `POST /api/v1/documents/{file_id}/conversions`.
+#[utoipa::path(
+    get,
+    path = "/convert/{file_id}",
+    tag = "conversion",
+    params(("file_id" = Uuid, Path, description = "Upload ID from `/upload`"), ConvertParams),
+    responses((status = 200, body = Document))
+)]
 pub async fn convert_to_text(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    State(limits): State<Limits>,
+    State(model): State<Option<Config>>,
+    caller: Caller,
     Path(file_id): Path<Uuid>,
     Query(params): Query<ConvertParams>,
 ) -> Result<Json<Document>> {
-    let config = Config::from_env()?;
-    let claude_service = ClaudeService::new(&config);
-
-    let upload_dir = PathBuf::from("uploads");
-
-    let content = if params.is_multi_page {
-        let files: Vec<PathBuf> = std::fs::read_dir(&upload_dir)
-            .map_err(|e| ApiError::FileError(format!("Failed to read directory: {}", e)))?
-            .filter_map(|entry| entry.ok())
-            .filter(|entry| {
-                entry
-                    .path()
-                    .file_stem()
-                    .and_then(|stem| stem.to_str())
-                    .map(|name| name.starts_with(&file_id.to_string()))
-                    .unwrap_or(false)
-            })
-            .map(|entry| entry.path())
-            .collect();
-
-        if files.is_empty() {
+    let request = ConvertRequest {
+        is_multi_page: Some(params.is_multi_page),
+        style: params.style,
+        callback_url: None,
+    };
+    let document = run_conversion(
+        &db,
+        &storage,
+        &limits,
+        model.as_ref(),
+        &caller,
+        &file_id,
+        request,
+    )
+    .await?;
+    Ok(Json(document))
+}
+
+/// Transcribe an upload's images into a LaTeX document.
+#[utoipa::path(
+    post,
+    path = "/api/v1/documents/{file_id}/conversions",
+    tag = "conversion",
+    params(("file_id" = Uuid, Path, description = "Upload ID from `/api/v1/uploads`")),
+    request_body = ConvertRequest,
+    responses((status = 200, body = Document))
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn convert_document(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    State(limits): State<Limits>,
+    State(model): State<Option<Config>>,
+    State(webhook_config): State<WebhookConfig>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+    Json(request): Json<ConvertRequest>,
+) -> Result<Json<Document>> {
+    if let Some(url) = request.callback_url.as_deref() {
+        webhooks::validate_url(&webhook_config, "callback_url", url)?;
+    }
+    let document = run_conversion(
+        &db,
+        &storage,
+        &limits,
+        model.as_ref(),
+        &caller,
+        &file_id,
+        request,
+    )
+    .await?;
+    Ok(Json(document))
+}
+
+async fn run_conversion(
+    db: &Database,
+    storage: &Storage,
+    limits: &Limits,
+    model: Option<&Config>,
+    caller: &Caller,
+    file_id: &Uuid,
+    request: ConvertRequest,
+) -> Result<Document> {
+    let style = styles::resolve(request.style.as_deref())?;
+    let callback = request.callback_url.as_deref();
+    caller.authorize(db, file_id)?;
+    let is_multi_page = match request.is_multi_page {
+        Some(is_multi_page) => is_multi_page,
+        None => uploaded_as_pages(storage, file_id).await?,
+    };
+    let pages = match db.get_document(file_id)? {
+        Some(summary) if is_multi_page => summary.page_count.max(1) as u64,
+        _ => 1,
+    };
+    let payers = caller.payers();
+    limits.reserve_pages(db, &payers, pages)?;
+
+    let job = db
+        .start_job(file_id, JobKind::Convert)
+        .inspect_err(|_| limits.release_pages(db, &payers, pages))?;
+    let result = convert(storage, model, file_id, style, is_multi_page).await;
+    if result.is_err() {
+        limits.release_pages(db, &payers, pages);
+    }
+    db.finish_job(&job, result.as_ref().err())?;
+    let owner = db.document_owner(file_id)?.flatten();
+    let announce = |kind, error: Option<&ApiError>| {
+        let data = EventData {
+            document_id: *file_id,
+            job_id: Some(job),
+            error_code: error.map(ApiError::code),
+            error: error.map(ApiError::detail),
+        };
+        webhooks::emit(db, owner.as_ref(), callback, kind, data);
+    };
+
+    let document = result.inspect_err(|e| announce(EventKind::ConversionFailed, Some(e)))?;
+    db.save_document(&document, DocumentOrigin::Upload, None, None)?;
+    announce(EventKind::ConversionSucceeded, None);
+
+    Ok(document)
+}
+
+// `/upload` names images `{id}_{n}.ext` exactly when it treats them as pages
+async fn uploaded_as_pages(storage: &Storage, file_id: &Uuid) -> Result<bool> {
+    let id = file_id.to_string();
+    Ok(page_images(storage, file_id).await?.iter().any(|key| {
+        let name = key.rsplit('/').next().unwrap_or_default();
+        name.strip_prefix(&id)
+            .is_some_and(|rest| rest.starts_with('_'))
+    }))
+}
+
+#[tracing::instrument(
+    err,
+    skip_all,
+    fields(document.id = %file_id, document.style = style.name, multi_page = is_multi_page)
+)]
+async fn convert(
+    storage: &Storage,
+    model: Option<&Config>,
+    file_id: &Uuid,
+    style: &Style,
+    is_multi_page: bool,
+) -> Result<Document> {
+    let config = model
+        .ok_or_else(|| ApiError::InternalServerError(anyhow::anyhow!("CLAUDE_API_KEY not set")))?;
+    let claude_service = ClaudeService::new(config, *file_id);
+
+    let pages = if is_multi_page {
+        let keys = page_images(storage, file_id).await?;
+        if keys.is_empty() {
             return Err(ApiError::NotFound(format!(
                 "No files found for ID {}",
                 file_id
             )));
         }
 
-        claude_service.convert_multiple_pages(&files).await?
+        let images = load_pages(storage, &keys)
+            .instrument(tracing::info_span!(
+                "load_pages",
+                document.id = %file_id,
+                page.count = keys.len()
+            ))
+            .await?;
+        claude_service
+            .convert_multiple_pages(&images, style)
+            .await?
     } else {
-        let file_path = upload_dir.join(format!("{}.png", file_id));
-        if !file_path.exists() {
-            return Err(ApiError::NotFound(format!("File not found: {}", file_id)));
-        }
-        claude_service.convert_single_page(&file_path).await?
+        // The upload may be a JPEG or WebP as well as a PNG
+        let key = page_images(storage, file_id)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ApiError::NotFound(format!("File not found: {}", file_id)))?;
+        let image = storage.get(&key).await?;
+        vec![claude_service.convert_single_page(&image, style).await?]
     };
 
+    let content = tracing::info_span!("assemble", document.id = %file_id, page.count = pages.len())
+        .in_scope(|| style.assemble(&pages));
+
     // Store the LaTeX content for later PDF generation
-    store_latex(&file_id, &content).await?;
+    store_latex(storage, file_id, &content).await?;
 
-    Ok(Json(Document {
-        id: file_id,
+    Ok(Document {
+        id: *file_id,
         filename: format!("{}.tex", file_id),
         content,
+        style: style.name.to_string(),
         created_at: chrono::Utc::now(),
-    }))
+    })
 }
 
-pub async fn generate_pdf(Path(file_id): Path<Uuid>) -> Result<impl IntoResponse> {
-    // Get the stored LaTeX content
-    let latex_content = get_latex(&file_id).await?;
+async fn load_pages(storage: &Storage, keys: &[String]) -> Result<Vec<Vec<u8>>> {
+    let mut images = Vec::with_capacity(keys.len());
+    for key in keys {
+        images.push(storage.get(key).await?);
+    }
+    Ok(images)
+}
 
-    // Generate PDF
-    let pdf_service = PdfService::new();
-    let output_dir = PathBuf::from("pdf");
-    tokio::fs::create_dir_all(&output_dir)
-        .await
-        .map_err(|e| ApiError::FileError(format!("Failed to create PDF directory: {}", e)))?;
+// Compile a document as a job, keeping the PDF for later downloads,
+// charging the compile time to `payers` and announcing it to the owner's
+// webhooks and `callback`
+pub(crate) async fn build_pdf(
+    db: &Database,
+    storage: &Storage,
+    limits: &Limits,
+    payers: &[Payer],
+    document: &Document,
+    engine: Option<&str>,
+    callback: Option<&str>,
+) -> Result<Vec<u8>> {
+    limits.check_compile(db, payers)?;
 
-    let output_path = output_dir.join(format!("{}.pdf", file_id));
-    let pdf_data = pdf_service
-        .generate_pdf(&latex_content, &output_path)
+    let job = db.start_job(&document.id, JobKind::Pdf)?;
+    let started = Instant::now();
+    let result = compile(document, engine).await;
+    metrics::record_compile(engine.unwrap_or("pdflatex"), started.elapsed(), &result);
+    let compile_ms = started.elapsed().as_millis() as u64;
+    limits.record(
+        db,
+        payers,
+        Usage {
+            compile_ms,
+            ..Usage::default()
+        },
+    )?;
+    db.finish_job(&job, result.as_ref().err())?;
+    let pdf_data = result?;
+    storage
+        .put(&pdf_key(document, engine), pdf_data.clone())
         .await?;
 
-    // Create response headers
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        axum::http::header::CONTENT_TYPE,
-        "application/pdf".parse().unwrap(),
-    );
-    headers.insert(
-        axum::http::header::CONTENT_DISPOSITION,
-        format!("attachment; filename=\"{}.pdf\"", file_id)
-            .parse()
-            .unwrap(),
-    );
+    let owner = db.document_owner(&document.id)?.flatten();
+    let data = EventData {
+        document_id: document.id,
+        job_id: Some(job),
+        error_code: None,
+        error: None,
+    };
+    webhooks::emit(db, owner.as_ref(), callback, EventKind::PdfReady, data);
+    Ok(pdf_data)
+}
+
+/// Where the PDF of `document` as compiled by `engine` is kept.
+fn pdf_key(document: &Document, engine: Option<&str>) -> String {
+    storage::pdf_key(
+        &document.id,
+        engine.unwrap_or("pdflatex"),
+        &document.content,
+    )
+}
+
+/// The PDF an earlier build of this exact `document` left, if any.
+pub(crate) async fn cached_pdf(
+    storage: &Storage,
+    document: &Document,
+    engine: Option<&str>,
+) -> Result<Option<Vec<u8>>> {
+    let cached = match storage.get(&pdf_key(document, engine)).await {
+        Ok(pdf) => Some(pdf),
+        Err(ApiError::NotFound(_)) => None,
+        Err(e) => return Err(e),
+    };
+    let result = if cached.is_some() { "hit" } else { "miss" };
+    metrics::CACHE_REQUESTS
+        .with_label_values(&["pdf", result])
+        .inc();
+    Ok(cached)
+}
+
+#[tracing::instrument(
+    err,
+    skip_all,
+    fields(document.id = %document.id, pdf.engine = engine.unwrap_or("pdflatex"))
+)]
+async fn compile(document: &Document, engine: Option<&str>) -> Result<Vec<u8>> {
+    match engine {
+        Some("typst") => {
+            // Typst has no LaTeX front end, so go through the document model
+            let (source, _) = export::typst::render(document, &parser::parse(&document.content));
+            TypstService::new().generate_pdf(&source).await
+        }
+        _ => PdfService::new().generate_pdf(&document.content).await,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{file_id}/exports/pdf",
+    tag = "exports",
+    params(("file_id" = Uuid, Path, description = "Document ID"), PdfParams),
+    responses((status = 200, content_type = "application/pdf", body = Binary))
+)]
+pub async fn generate_pdf(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    State(limits): State<Limits>,
+    State(webhook_config): State<WebhookConfig>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+    Query(params): Query<PdfParams>,
+) -> Result<impl IntoResponse> {
+    if let Some(url) = params.callback_url.as_deref() {
+        webhooks::validate_url(&webhook_config, "callback_url", url)?;
+    }
+    caller.authorize(&db, &file_id)?;
+    // Get the stored LaTeX content, re-rendered if a different style was requested
+    let mut document = load_document(&db, &storage, &file_id).await?;
+    if let Some(name) = params.style.as_deref() {
+        let style = styles::find(name)?;
+        document.content = style.restyle(&document.content);
+        document.style = style.name.to_string();
+    }
+
+    let engine = params.engine.as_deref();
+    if !matches!(engine, None | Some("pdflatex") | Some("typst")) {
+        return Err(ApiError::invalid_field(
+            "engine",
+            format!(
+                "Unsupported PDF engine: {}. Available engines: [\"pdflatex\", \"typst\"]",
+                engine.unwrap_or_default()
+            ),
+        ));
+    }
+
+    // A callback is promised a `pdf.ready` event, which only a build sends
+    let cached = match params.callback_url {
+        Some(_) => None,
+        None => cached_pdf(&storage, &document, engine).await?,
+    };
+    let pdf_data = match cached {
+        Some(pdf) => pdf,
+        None => {
+            build_pdf(
+                &db,
+                &storage,
+                &limits,
+                &caller.payers(),
+                &document,
+                engine,
+                params.callback_url.as_deref(),
+            )
+            .await?
+        }
+    };
+
+    let headers = headers::attachment("application/pdf", &format!("{}.pdf", file_id));
 
     Ok((headers, pdf_data))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::testing::TestApi;
+    use crate::services::storage::FsStore;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn finds_single_images_of_any_type() {
+        let root = tempfile::tempdir().unwrap();
+        let storage: Storage = Arc::new(FsStore::new(root.path()));
+        let file_id = Uuid::new_v4();
+        let other = Uuid::new_v4();
+        for name in [format!("{}.jpg", file_id), format!("{}.webp", other)] {
+            storage
+                .put(&storage::upload_key(&name), b"image".to_vec())
+                .await
+                .unwrap();
+        }
+
+        let keys = page_images(&storage, &file_id).await.unwrap();
+        assert_eq!(keys, [storage::upload_key(&format!("{}.jpg", file_id))]);
+        assert!(!uploaded_as_pages(&storage, &file_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn serves_pdfs_from_earlier_builds() {
+        let api = TestApi::start().await;
+        let (owner, key) = api.owner("reader", false);
+        let document = api
+            .document(
+                &owner.id,
+                "\\documentclass{article}\\begin{document}Hi\\end{document}",
+            )
+            .await;
+        api.storage
+            .put(&pdf_key(&document, None), b"%PDF-cached".to_vec())
+            .await
+            .unwrap();
+
+        let response = api
+            .client
+            .get(api.url(&format!("/api/v1/documents/{}/exports/pdf", document.id)))
+            .bearer_auth(&key)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.bytes().await.unwrap().as_ref(), b"%PDF-cached");
+        assert!(api.db.jobs(&document.id).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+//! Headers marking the unversioned routes as deprecated aliases of `/api/v1`.
+
+use axum::extract::Request;
+use axum::http::header::LINK;
+use axum::http::{HeaderName, HeaderValue, Uri};
+use axum::middleware::Next;
+use axum::response::Response;
+
+pub const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+
+/// When `/api/v1` replaced the root routes (2026-10-19), as an RFC 9745 date.
+const DEPRECATED_SINCE: &str = "@1792368000";
+
+/// Middleware: add `Deprecation` and a `Link` to the route's successor.
+pub async fn deprecated(request: Request, next: Next) -> Response {
+    let link = format!(
+        "<{}>; rel=\"successor-version\", </docs>; rel=\"deprecation\"",
+        successor(request.uri())
+    );
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(DEPRECATION, HeaderValue::from_static(DEPRECATED_SINCE));
+    if let Ok(link) = HeaderValue::from_str(&link) {
+        headers.insert(LINK, link);
+    }
+    response
+}
+
+/// The `/api/v1` equivalent of a deprecated request's path.
+fn successor(uri: &Uri) -> String {
+    let path = uri.path();
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["upload"] => "/api/v1/uploads".to_string(),
+        ["convert", id] => format!("/api/v1/documents/{}/conversions", id),
+        ["pdf", id] => format!("/api/v1/documents/{}/exports/pdf", id),
+        ["bundle", id] => format!("/api/v1/documents/{}/exports/bundle", id),
+        ["export", id] => {
+            let format = uri
+                .query()
+                .and_then(|query| {
+                    query
+                        .split('&')
+                        .find_map(|pair| pair.strip_prefix("format="))
+                })
+                .filter(|format| format.chars().all(|c| c.is_ascii_alphanumeric()))
+                .unwrap_or("{format}");
+            format!("/api/v1/documents/{}/exports/{}", id, format)
+        }
+        _ => format!("/api/v1{}", path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::testing::TestApi;
+
+    const SOURCE: &str = "\\documentclass{article}\\begin{document}Hi\\end{document}";
+
+    #[tokio::test]
+    async fn points_legacy_routes_at_their_successors() {
+        let api = TestApi::start().await;
+        let (owner, key) = api.owner("reader", false);
+        let document = api.document(&owner.id, SOURCE).await;
+        let id = document.id;
+
+        for (path, successor) in [
+            (
+                format!("/export/{}?format=markdown", id),
+                format!("/api/v1/documents/{}/exports/markdown", id),
+            ),
+            (
+                format!("/bundle/{}", id),
+                format!("/api/v1/documents/{}/exports/bundle", id),
+            ),
+        ] {
+            let response = api
+                .client
+                .get(api.url(&path))
+                .bearer_auth(&key)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200, "{}", path);
+            assert_eq!(response.headers()["deprecation"], "@1792368000");
+            assert_eq!(
+                response.headers()["link"],
+                format!(
+                    "<{}>; rel=\"successor-version\", </docs>; rel=\"deprecation\"",
+                    successor
+                )
+            );
+        }
+
+        // The versioned routes are not deprecated
+        let response = api
+            .client
+            .get(api.url(&format!("/api/v1/documents/{}/exports/markdown", id)))
+            .bearer_auth(&key)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.headers().get("deprecation").is_none());
+        assert!(response.headers().get("link").is_none());
+    }
+}
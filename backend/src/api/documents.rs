@@ -0,0 +1,592 @@
+use axum::extract::{DefaultBodyLimit, Multipart, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::auth::Caller;
+use super::convert::{load_document, page_images, store_latex};
+use super::extract::{multipart_error, Json, Path, Query};
+use crate::db::{Database, DocumentQuery};
+use crate::errors::{ApiError, Result};
+use crate::models::document::{Document, DocumentOrigin, DocumentSummary};
+use crate::models::job::Job;
+use crate::models::page::Page;
+use crate::models::provenance::{PageOrigin, Provenance};
+use crate::services::bundle::{self, PageImage};
+use crate::services::compose::{self, SplitMode};
+use crate::services::janitor;
+use crate::services::storage::{self, Storage};
+use crate::services::{latex, styles};
+use crate::utils::headers::{self, HeaderMap};
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MergeRequest {
+    /// Documents to concatenate, in order.
+    documents: Vec<Uuid>,
+    /// Style of the merged document; defaults to the first document's.
+    style: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SplitRequest {
+    mode: SplitMode,
+    /// Pages or 1-based section numbers that start a new part; every
+    /// boundary when empty.
+    #[serde(default)]
+    at: Vec<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DocumentList {
+    documents: Vec<DocumentSummary>,
+    total: usize,
+    limit: usize,
+    offset: usize,
+}
+
+/// The `/documents/import` form, for the API docs; the handler reads the
+/// fields as they stream in.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+struct ImportForm {
+    /// A `.tex` file or a bundle from
+    /// `/api/v1/documents/{file_id}/exports/bundle`.
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+    /// Style for bare LaTeX fragments, or to restyle the import.
+    style: Option<String>,
+}
+
+/// Bundles carry page images, so imports get more room than the default body limit.
+const MAX_IMPORT_SIZE: usize = 64 * 1024 * 1024;
+
+/// Stored documents, filtered, sorted and paged by the query string.
+///
+/// Only admins see other owners' documents.
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents",
+    tag = "documents",
+    params(DocumentQuery),
+    responses((status = 200, body = DocumentList))
+)]
+async fn list_documents(
+    State(db): State<Database>,
+    caller: Caller,
+    Query(mut query): Query<DocumentQuery>,
+) -> Result<Json<DocumentList>> {
+    if !caller.owner.admin {
+        query.owner = Some(*caller.id());
+    }
+    let (documents, total) = db.list_documents(&query)?;
+    Ok(Json(DocumentList {
+        documents,
+        total,
+        limit: query.limit(),
+        offset: query.offset,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{file_id}",
+    tag = "documents",
+    params(("file_id" = Uuid, Path, description = "Document ID")),
+    responses((status = 200, body = DocumentSummary))
+)]
+async fn get_document(
+    State(db): State<Database>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<DocumentSummary>> {
+    caller.authorize(&db, &file_id)?;
+    db.get_document(&file_id)?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Document not found: {}", file_id)))
+}
+
+/// Remove a document with its page images, LaTeX and PDF.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/documents/{file_id}",
+    tag = "documents",
+    params(("file_id" = Uuid, Path, description = "Document ID")),
+    responses((status = 204, description = "Deleted"))
+)]
+async fn delete_document(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+) -> Result<StatusCode> {
+    // Documents with files but no record yet can only be removed by admins
+    match caller.authorize(&db, &file_id) {
+        Err(ApiError::NotFound(_)) if caller.owner.admin => {}
+        result => result?,
+    }
+    if janitor::remove_document(&db, &storage, &file_id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(format!(
+            "Document not found: {}",
+            file_id
+        )))
+    }
+}
+
+/// Conversions and PDF builds for a document, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{file_id}/jobs",
+    tag = "documents",
+    params(("file_id" = Uuid, Path, description = "Document ID")),
+    responses((status = 200, body = Vec<Job>))
+)]
+async fn list_jobs(
+    State(db): State<Database>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<Vec<Job>>> {
+    caller.authorize(&db, &file_id)?;
+    Ok(Json(db.jobs(&file_id)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs/{job_id}",
+    tag = "documents",
+    params(("job_id" = Uuid, Path)),
+    responses((status = 200, body = Job))
+)]
+pub async fn get_job(
+    State(db): State<Database>,
+    caller: Caller,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<Job>> {
+    let not_found = || ApiError::NotFound(format!("Job not found: {}", job_id));
+    let job = db.get_job(&job_id)?.ok_or_else(not_found)?;
+    // Don't reveal other owners' jobs
+    match caller.authorize(&db, &job.document_id) {
+        Ok(()) => Ok(Json(job)),
+        Err(ApiError::AuthorizationError) => Err(not_found()),
+        Err(e) => Err(e),
+    }
+}
+
+/// A document's LaTeX page by page, each with its origin.
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{file_id}/pages",
+    tag = "documents",
+    params(("file_id" = Uuid, Path, description = "Document ID")),
+    responses((status = 200, body = Vec<Page>))
+)]
+pub async fn list_pages(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<Vec<Page>>> {
+    caller.authorize(&db, &file_id)?;
+    Ok(Json(load_pages(&db, &storage, &file_id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{file_id}/pages/{number}",
+    tag = "documents",
+    params(
+        ("file_id" = Uuid, Path, description = "Document ID"),
+        ("number" = usize, Path, description = "1-based page number"),
+    ),
+    responses((status = 200, body = Page))
+)]
+pub async fn get_page(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    caller: Caller,
+    Path((file_id, number)): Path<(Uuid, usize)>,
+) -> Result<Json<Page>> {
+    caller.authorize(&db, &file_id)?;
+    load_pages(&db, &storage, &file_id)
+        .await?
+        .into_iter()
+        .find(|page| page.number == number)
+        .map(Json)
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("Page {} not found in document {}", number, file_id))
+        })
+}
+
+/// Create a document from a `.tex` file or an exported bundle.
+///
+/// Bare LaTeX fragments (no `\begin{document}`) are assembled with the
+/// optional `style` field, as if they had just been converted.
+#[utoipa::path(
+    post,
+    path = "/api/v1/documents/import",
+    tag = "documents",
+    request_body(content = ImportForm, content_type = "multipart/form-data"),
+    responses((status = 201, body = Document, headers(("etag" = String))))
+)]
+async fn import_document(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    caller: Caller,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse> {
+    let mut upload = None;
+    let mut style = None;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| multipart_error("Failed to process multipart form", e))?
+    {
+        if field.name() == Some("style") {
+            let value = field
+                .text()
+                .await
+                .map_err(|e| multipart_error("Failed to read field data", e))?;
+            style = Some(value);
+            continue;
+        }
+
+        let data = field
+            .bytes()
+            .await
+            .map_err(|e| multipart_error("Failed to read file data", e))?;
+        upload = Some(data);
+    }
+
+    let data = upload.ok_or_else(|| ApiError::ValidationError("No file provided".to_string()))?;
+
+    let (content, images) = if data.starts_with(b"PK\x03\x04") {
+        let unpacked = bundle::open(&data)?;
+        let content = match style.as_deref() {
+            Some(name) => styles::find(name)?.restyle(&unpacked.content),
+            None => unpacked.content,
+        };
+        (content, unpacked.images)
+    } else {
+        let text = String::from_utf8(data.to_vec()).map_err(|_| {
+            ApiError::ValidationError(
+                "Expected a UTF-8 .tex file or a noteforge bundle".to_string(),
+            )
+        })?;
+        let content = if text.contains("\\begin{document}") {
+            match style.as_deref() {
+                Some(name) => styles::find(name)?.restyle(&text),
+                None => text,
+            }
+        } else {
+            styles::resolve(style.as_deref())?.assemble(&latex::split_pages(&text))
+        };
+        (content, Vec::new())
+    };
+    // Imports are compiled like model output, so they must not read the server's files
+    if let Some(command) = latex::file_access(&content) {
+        return Err(ApiError::invalid_field(
+            "file",
+            format!("{} is not allowed in imported LaTeX", command),
+        ));
+    }
+
+    let file_id = Uuid::new_v4();
+    let images = store_images(&storage, &file_id, &images).await?;
+    store_latex(&storage, &file_id, &content).await?;
+
+    let mut headers = HeaderMap::new();
+    if let Ok(etag) = format!("\"{}\"", bundle::revision(&content)).parse() {
+        headers.insert(axum::http::header::ETAG, etag);
+    }
+
+    let document = Document {
+        id: file_id,
+        filename: format!("{}.tex", file_id),
+        style: styles::detect(&content).name.to_string(),
+        content,
+        created_at: chrono::Utc::now(),
+    };
+    let pages: Vec<PageOrigin> = images
+        .into_iter()
+        .enumerate()
+        .map(|(index, image)| PageOrigin {
+            document: file_id,
+            page: index + 1,
+            image: Some(image),
+        })
+        .collect();
+    db.save_document(
+        &document,
+        DocumentOrigin::Import,
+        (!pages.is_empty()).then_some(pages.as_slice()),
+        Some(caller.id()),
+    )?;
+
+    Ok((StatusCode::CREATED, headers, Json(document)))
+}
+
+/// Combine several documents into a new one, reconciling their preambles.
+#[utoipa::path(
+    post,
+    path = "/api/v1/documents/merge",
+    tag = "documents",
+    request_body = MergeRequest,
+    responses((
+        status = 201,
+        body = Document,
+        headers(("x-merge-warnings" = String, description = "Preamble conflicts, if any"))
+    ))
+)]
+async fn merge_documents(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    caller: Caller,
+    Json(request): Json<MergeRequest>,
+) -> Result<impl IntoResponse> {
+    if request.documents.len() < 2 {
+        return Err(ApiError::invalid_field(
+            "documents",
+            "At least two documents are needed for a merge",
+        ));
+    }
+
+    let mut sources = Vec::with_capacity(request.documents.len());
+    let mut pages = Vec::new();
+    for file_id in &request.documents {
+        caller.authorize(&db, file_id)?;
+        let document = load_document(&db, &storage, file_id).await?;
+        pages.extend(load_provenance(&db, &storage, &document).await?.pages);
+        sources.push(document);
+    }
+
+    let style = match request.style.as_deref() {
+        Some(name) => styles::find(name)?,
+        None => styles::detect(&sources[0].content),
+    };
+    let merged = compose::merge(style, &sources);
+
+    let document = create_document(
+        &db,
+        &storage,
+        &caller,
+        merged.content,
+        DocumentOrigin::Merge,
+        pages,
+    )
+    .await?;
+    let mut headers = HeaderMap::new();
+    headers::insert_warnings(&mut headers, "x-merge-warnings", &merged.warnings);
+
+    Ok((StatusCode::CREATED, headers, Json(document)))
+}
+
+/// Break a document into new ones at page or section boundaries.
+#[utoipa::path(
+    post,
+    path = "/api/v1/documents/{file_id}/split",
+    tag = "documents",
+    params(("file_id" = Uuid, Path, description = "Document ID")),
+    request_body = SplitRequest,
+    responses((status = 201, body = Vec<Document>))
+)]
+async fn split_document(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+    Json(request): Json<SplitRequest>,
+) -> Result<impl IntoResponse> {
+    caller.authorize(&db, &file_id)?;
+    let source = load_document(&db, &storage, &file_id).await?;
+    let origins = load_provenance(&db, &storage, &source).await?.pages;
+    let parts = compose::split(&source, request.mode, &request.at)?;
+
+    let mut documents = Vec::with_capacity(parts.len());
+    for part in parts {
+        let pages = part
+            .pages
+            .iter()
+            .filter_map(|page| origins.get(*page).cloned())
+            .collect();
+        documents.push(
+            create_document(
+                &db,
+                &storage,
+                &caller,
+                part.content,
+                DocumentOrigin::Split,
+                pages,
+            )
+            .await?,
+        );
+    }
+
+    Ok((StatusCode::CREATED, Json(documents)))
+}
+
+/// The original document, page and image behind each page of a document.
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{file_id}/provenance",
+    tag = "documents",
+    params(("file_id" = Uuid, Path, description = "Document ID")),
+    responses((status = 200, body = Provenance))
+)]
+async fn get_provenance(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<Provenance>> {
+    caller.authorize(&db, &file_id)?;
+    let document = load_document(&db, &storage, &file_id).await?;
+    Ok(Json(load_provenance(&db, &storage, &document).await?))
+}
+
+async fn create_document(
+    db: &Database,
+    storage: &Storage,
+    caller: &Caller,
+    content: String,
+    origin: DocumentOrigin,
+    pages: Vec<PageOrigin>,
+) -> Result<Document> {
+    let file_id = Uuid::new_v4();
+    store_latex(storage, &file_id, &content).await?;
+
+    let document = Document {
+        id: file_id,
+        filename: format!("{}.tex", file_id),
+        style: styles::detect(&content).name.to_string(),
+        content,
+        created_at: chrono::Utc::now(),
+    };
+    db.save_document(&document, origin, Some(&pages), Some(caller.id()))?;
+    Ok(document)
+}
+
+// Documents recorded before page origins were tracked are their own origin
+async fn load_provenance(
+    db: &Database,
+    storage: &Storage,
+    document: &Document,
+) -> Result<Provenance> {
+    let mut pages = db.pages(&document.id)?;
+    if pages.is_empty() {
+        let images = page_images(storage, &document.id).await.unwrap_or_default();
+        pages = (0..latex::split_pages(&document.content).len())
+            .map(|index| PageOrigin {
+                document: document.id,
+                page: index + 1,
+                image: images
+                    .get(index)
+                    .and_then(|key| key.rsplit('/').next())
+                    .map(str::to_string),
+            })
+            .collect();
+    }
+
+    Ok(Provenance {
+        document: document.id,
+        pages,
+    })
+}
+
+async fn load_pages(db: &Database, storage: &Storage, file_id: &Uuid) -> Result<Vec<Page>> {
+    let document = load_document(db, storage, file_id).await?;
+    let mut origins = load_provenance(db, storage, &document)
+        .await?
+        .pages
+        .into_iter();
+    Ok(latex::split_pages(&document.content)
+        .into_iter()
+        .enumerate()
+        .map(|(index, content)| Page {
+            number: index + 1,
+            content,
+            origin: origins.next(),
+        })
+        .collect())
+}
+
+// Same naming as `/api/v1/uploads`: `{id}.ext` for one page, `{id}_{n}.ext` for several
+async fn store_images(
+    storage: &Storage,
+    file_id: &Uuid,
+    images: &[PageImage],
+) -> Result<Vec<String>> {
+    let mut filenames = Vec::with_capacity(images.len());
+    for (index, image) in images.iter().enumerate() {
+        let filename = if images.len() > 1 {
+            format!("{}_{}.{}", file_id, index, image.extension)
+        } else {
+            format!("{}.{}", file_id, image.extension)
+        };
+        storage
+            .put(&storage::upload_key(&filename), image.data.clone())
+            .await?;
+        filenames.push(filename);
+    }
+
+    Ok(filenames)
+}
+
+pub fn routes() -> Router<super::AppState> {
+    Router::new()
+        .route("/documents", get(list_documents))
+        .route(
+            "/documents/:file_id",
+            get(get_document).delete(delete_document),
+        )
+        .route("/documents/:file_id/jobs", get(list_jobs))
+        .route(
+            "/documents/import",
+            post(import_document).layer(DefaultBodyLimit::max(MAX_IMPORT_SIZE)),
+        )
+        .route("/documents/merge", post(merge_documents))
+        .route("/documents/:file_id/split", post(split_document))
+        .route("/documents/:file_id/provenance", get(get_provenance))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::testing::{multipart, TestApi};
+
+    #[tokio::test]
+    async fn rejects_imports_that_read_files() {
+        let api = TestApi::start().await;
+        let (_, key) = api.owner("importer", false);
+        let import = |source: &'static str| {
+            let (content_type, body) = multipart(&[("file", Some("notes.tex"), source.as_bytes())]);
+            api.client
+                .post(api.url("/api/v1/documents/import"))
+                .bearer_auth(&key)
+                .header("content-type", content_type)
+                .body(body)
+                .send()
+        };
+
+        let response =
+            import("\\documentclass{article}\\begin{document}\\input{/etc/passwd}\\end{document}")
+                .await
+                .unwrap();
+        assert_eq!(response.status(), 400);
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["errors"][0]["field"], "file");
+        assert!(api
+            .db
+            .list_documents(&Default::default())
+            .unwrap()
+            .0
+            .is_empty());
+
+        let response = import("Page one: $x^2$").await.unwrap();
+        assert_eq!(response.status(), 201);
+    }
+}
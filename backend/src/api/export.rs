@@ -0,0 +1,98 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+use super::auth::Caller;
+use super::convert::load_document;
+use super::extract::{Path, Query};
+use super::openapi::Binary;
+use crate::db::Database;
+use crate::errors::Result;
+use crate::services::export::{self, ExportFormat};
+use crate::services::storage::Storage;
+use crate::utils::headers;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportParams {
+    /// `markdown`, `html`, `anki`, `docx`, `epub`, `ipynb` or `typst`.
+    format: String,
+}
+
+/// Deprecated alias of `GET /api/v1/documents/{file_id}/exports/{format}`.
+#[utoipa::path(
+    get,
+    path = "/export/{file_id}",
+    tag = "exports",
+    params(("file_id" = Uuid, Path, description = "Document ID"), ExportParams),
+    responses((
+        status = 200,
+        description = "The document in the requested format",
+        content_type = "application/octet-stream",
+        body = Binary,
+        headers(("x-export-warnings" = String, description = "Content the format cannot carry"))
+    ))
+)]
+pub async fn export_document(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+    Query(params): Query<ExportParams>,
+) -> Result<impl IntoResponse> {
+    export_as(&db, &storage, &caller, &file_id, &params.format).await
+}
+
+/// The document converted to another format; `pdf` and `bundle` have
+/// routes of their own.
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{file_id}/exports/{format}",
+    tag = "exports",
+    params(
+        ("file_id" = Uuid, Path, description = "Document ID"),
+        (
+            "format" = String,
+            Path,
+            description = "`markdown`, `html`, `anki`, `docx`, `epub`, `ipynb` or `typst`"
+        ),
+    ),
+    responses((
+        status = 200,
+        description = "The document in the requested format",
+        content_type = "application/octet-stream",
+        body = Binary,
+        headers(("x-export-warnings" = String, description = "Content the format cannot carry"))
+    ))
+)]
+pub async fn export_format(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    caller: Caller,
+    Path((file_id, format)): Path<(Uuid, String)>,
+) -> Result<impl IntoResponse> {
+    export_as(&db, &storage, &caller, &file_id, &format).await
+}
+
+async fn export_as(
+    db: &Database,
+    storage: &Storage,
+    caller: &Caller,
+    file_id: &Uuid,
+    format: &str,
+) -> Result<impl IntoResponse> {
+    caller.authorize(db, file_id)?;
+    let format = ExportFormat::parse(format)?;
+    let document = load_document(db, storage, file_id).await?;
+    let export = export::export(format, &document)?;
+
+    let mut headers = headers::attachment(
+        export.content_type,
+        &format!("{}.{}", file_id, export.extension),
+    );
+    headers::insert_warnings(&mut headers, "x-export-warnings", &export.warnings);
+
+    Ok((headers, export.body))
+}
@@ -0,0 +1,166 @@
+//! `Json`, `Query` and `Path` extractors that reject with [`ApiError`], so
+//! malformed input gets a problem response naming the offending field.
+
+use axum::async_trait;
+use axum::extract::multipart::MultipartError;
+use axum::extract::path::ErrorKind;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::errors::{ApiError, Result};
+
+/// A JSON body; also usable as a response, like `axum::Json`.
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self> {
+        let axum::Json(value) = axum::Json::from_request(request, state).await?;
+        Ok(Json(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self> {
+        let axum::extract::Query(value) =
+            axum::extract::Query::from_request_parts(parts, state).await?;
+        Ok(Query(value))
+    }
+}
+
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self> {
+        let axum::extract::Path(value) =
+            axum::extract::Path::from_request_parts(parts, state).await?;
+        Ok(Path(value))
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        if let JsonRejection::JsonDataError(e) = &rejection {
+            if let Some(e) = find::<serde_path_to_error::Error<serde_json::Error>>(e) {
+                let message = e.inner().to_string();
+                // serde_json appends the position, which means little to API clients
+                let message = message
+                    .split(" at line ")
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                let path = e.path().to_string();
+                let field = if path == "." {
+                    missing_field(&message)
+                } else {
+                    Some(path)
+                };
+                if let Some(field) = field {
+                    return ApiError::invalid_field(field, message);
+                }
+            }
+        }
+        from_status(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        let message = rejection.body_text();
+        match missing_field(&message) {
+            Some(field) => ApiError::invalid_field(field, "missing field"),
+            None => from_status(rejection.status(), message),
+        }
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        if let PathRejection::FailedToDeserializePathParams(e) = &rejection {
+            if let ErrorKind::ParseErrorAtKey {
+                key,
+                value,
+                expected_type,
+            } = e.kind()
+            {
+                return ApiError::invalid_field(
+                    key.as_str(),
+                    format!("`{}` is not a valid {}", value, expected_type),
+                );
+            }
+        }
+        from_status(rejection.status(), rejection.body_text())
+    }
+}
+
+/// A failure reading a multipart body; oversized bodies get a 413.
+pub fn multipart_error(context: &str, error: MultipartError) -> ApiError {
+    let message = format!("{}: {}", context, error.body_text());
+    if error.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        ApiError::PayloadTooLarge(message)
+    } else {
+        ApiError::FileError(message)
+    }
+}
+
+fn from_status(status: StatusCode, message: String) -> ApiError {
+    match status {
+        StatusCode::PAYLOAD_TOO_LARGE => ApiError::PayloadTooLarge(message),
+        status if status.is_server_error() => {
+            ApiError::InternalServerError(anyhow::anyhow!(message))
+        }
+        _ => ApiError::ValidationError(message),
+    }
+}
+
+/// The field serde names in "missing field `name`".
+fn missing_field(message: &str) -> Option<String> {
+    let rest = message.split("missing field `").nth(1)?;
+    rest.split('`').next().map(str::to_string)
+}
+
+fn find<'a, E: std::error::Error + 'static>(
+    error: &'a (dyn std::error::Error + 'static),
+) -> Option<&'a E> {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if let Some(found) = error.downcast_ref::<E>() {
+            return Some(found);
+        }
+        current = error.source();
+    }
+    None
+}
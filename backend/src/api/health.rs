@@ -1,7 +1,20 @@
+use axum::extract::State;
+use axum::http::StatusCode;
 use axum::routing::get;
 use axum::{Json, Router};
 use serde_json::{json, Value};
 
+use crate::models::health::Readiness;
+use crate::services::health::Health;
+
+/// The process is up and serving; says nothing about its dependencies.
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "operations",
+    security(()),
+    responses((status = 200, body = Object, example = json!({"status": "ok", "version": "0.1.0"})))
+)]
 async fn health_check() -> Json<Value> {
     Json(json!({
         "status": "ok",
@@ -9,6 +22,31 @@ async fn health_check() -> Json<Value> {
     }))
 }
 
-pub fn routes() -> Router {
-    Router::new().route("/health", get(health_check))
+/// Every dependency's status, at most a few seconds old; 503 while a
+/// required one is down.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "operations",
+    security(()),
+    responses(
+        (status = 200, body = Readiness),
+        (status = 503, description = "A required dependency is down", body = Readiness),
+    )
+)]
+async fn readiness(State(health): State<Health>) -> (StatusCode, Json<Readiness>) {
+    let report = health.readiness().await;
+    let status = if report.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
+pub fn routes() -> Router<super::AppState> {
+    Router::new()
+        .route("/health", get(health_check))
+        .route("/health/live", get(health_check))
+        .route("/health/ready", get(readiness))
 }
@@ -0,0 +1,84 @@
+//! Rate-limit middleware and `RateLimit-*` response headers.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use axum::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts, Request, State};
+use axum::http::request::Parts;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use super::auth::Caller;
+use crate::errors::ApiError;
+use crate::services::limits::{Bucket, Decision, Limits};
+
+/// The client's address, as [`limit_ip`] found it; handlers charge their
+/// work to it as well as to the caller.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, ApiError> {
+        parts.extensions.get::<ClientIp>().copied().ok_or_else(|| {
+            ApiError::InternalServerError(anyhow::anyhow!("client IP middleware not installed"))
+        })
+    }
+}
+
+/// Limit every request by client IP.
+pub async fn limit_ip(
+    State(limits): State<Limits>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let peer = peer.map_or(Ipv4Addr::UNSPECIFIED.into(), |ConnectInfo(addr)| addr.ip());
+    let ip = limits.client_ip(request.headers(), peer);
+    request.extensions_mut().insert(ClientIp(ip));
+    limited(limits.hit(Bucket::Ip(ip)), request, next).await
+}
+
+/// Limit authenticated requests by owner; runs after authentication.
+pub async fn limit_owner(
+    State(limits): State<Limits>,
+    caller: Caller,
+    request: Request,
+    next: Next,
+) -> Response {
+    limited(limits.hit(Bucket::Owner(*caller.id())), request, next).await
+}
+
+async fn limited(decision: Option<Decision>, request: Request, next: Next) -> Response {
+    let Some(decision) = decision else {
+        return next.run(request).await;
+    };
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        ApiError::TooManyRequests {
+            message: format!(
+                "Rate limit of {} requests per minute exceeded",
+                decision.limit
+            ),
+            retry_after: decision.reset,
+        }
+        .into_response()
+    };
+
+    // The owner's limit is the one that matters to signed-in clients
+    let headers = response.headers_mut();
+    if !headers.contains_key("ratelimit-limit") {
+        for (name, value) in [
+            ("ratelimit-limit", decision.limit),
+            ("ratelimit-remaining", decision.remaining),
+            ("ratelimit-reset", decision.reset),
+        ] {
+            headers.insert(name, HeaderValue::from(value));
+        }
+    }
+    response
+}
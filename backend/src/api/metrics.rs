@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use subtle::ConstantTimeEq;
+
+use crate::db::Database;
+use crate::errors::{ApiError, Result};
+use crate::services::metrics;
+use crate::services::storage::Storage;
+
+/// `METRICS_TOKEN`, read once at startup; without one `/metrics` is open.
+#[derive(Clone, Default)]
+pub struct MetricsToken(Option<Arc<str>>);
+
+impl MetricsToken {
+    pub fn new(token: Option<String>) -> Self {
+        Self(token.map(Arc::from))
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(std::env::var("METRICS_TOKEN").ok())
+    }
+
+    /// Fail unless `headers` carry the token, if there is one. The comparison
+    /// takes as long wherever the first wrong byte is.
+    fn check(&self, headers: &HeaderMap) -> Result<()> {
+        let Some(token) = &self.0 else {
+            return Ok(());
+        };
+        let presented = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if bool::from(presented.as_bytes().ct_eq(token.as_bytes())) {
+            Ok(())
+        } else {
+            Err(ApiError::AuthenticationError)
+        }
+    }
+}
+
+/// Prometheus scrape endpoint; requires `Bearer $METRICS_TOKEN` when that is set.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    security(("metrics_token" = [])),
+    responses((
+        status = 200,
+        description = "Prometheus text exposition format",
+        content_type = "text/plain; version=0.0.4",
+        body = String
+    ))
+)]
+async fn render_metrics(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    State(token): State<MetricsToken>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse> {
+    token.check(&headers)?;
+
+    let body = metrics::render(&db, &storage).await?;
+    Ok(([(CONTENT_TYPE, "text/plain; version=0.0.4")], body))
+}
+
+/// Middleware: count and time requests by their route template.
+pub async fn track(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    // Templates, not raw paths, so IDs don't explode the label set
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched".to_string(), |path| path.as_str().to_string());
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    metrics::HTTP_DURATION
+        .with_label_values(&[&method, &route])
+        .observe(started.elapsed().as_secs_f64());
+    metrics::HTTP_REQUESTS
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    response
+}
+
+pub fn routes() -> Router<super::AppState> {
+    Router::new().route("/metrics", get(render_metrics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::testing::{TestApi, METRICS_TOKEN};
+    use crate::services::storage;
+
+    impl TestApi {
+        async fn scrape(&self) -> String {
+            let response = self
+                .client
+                .get(self.url("/metrics"))
+                .bearer_auth(METRICS_TOKEN)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+            response.text().await.unwrap()
+        }
+    }
+
+    /// The value of one series in a scrape, or 0 before it first moves.
+    fn sample(scrape: &str, series: &str) -> f64 {
+        scrape
+            .lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+            .map_or(0.0, |value| value.parse().unwrap())
+    }
+
+    #[tokio::test]
+    async fn requires_the_token_when_one_is_set() {
+        let api = TestApi::start().await;
+        let scrape = |token: Option<&str>| {
+            let request = api.client.get(api.url("/metrics"));
+            match token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+            .send()
+        };
+
+        for token in [
+            None,
+            Some("metrics-toke"),
+            Some("metrics-token-2"),
+            Some(""),
+        ] {
+            let response = scrape(token).await.unwrap();
+            assert_eq!(response.status(), 401, "{:?}", token);
+        }
+        let response = scrape(Some(METRICS_TOKEN)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.text().await.unwrap().contains("noteforge_"));
+
+        assert!(MetricsToken::default().check(&HeaderMap::new()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn counts_requests_and_pdf_cache_hits() {
+        let api = TestApi::start().await;
+        let (owner, key) = api.owner("reader", false);
+        let source = "\\documentclass{article}\\begin{document}Metrics\\end{document}";
+        let document = api.document(&owner.id, source).await;
+        api.storage
+            .put(
+                &storage::pdf_key(&document.id, "pdflatex", source),
+                b"%PDF-cached".to_vec(),
+            )
+            .await
+            .unwrap();
+        let requests = "noteforge_http_requests_total{method=\"GET\",\
+            route=\"/api/v1/documents/:file_id/exports/pdf\",status=\"200\"}";
+        let hits = "noteforge_cache_requests_total{artifact=\"pdf\",result=\"hit\"}";
+
+        // Other tests move the same counters, so only look for growth
+        let before = api.scrape().await;
+        let response = api
+            .client
+            .get(api.url(&format!("/api/v1/documents/{}/exports/pdf", document.id)))
+            .bearer_auth(&key)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let after = api.scrape().await;
+
+        assert!(
+            sample(&after, requests) > sample(&before, requests),
+            "{}",
+            after
+        );
+        assert!(sample(&after, hits) > sample(&before, hits), "{}", after);
+    }
+}
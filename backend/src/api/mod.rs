@@ -1,15 +1,186 @@
+mod auth;
+mod bundle;
 mod convert;
+mod deprecation;
+mod documents;
+mod export;
+mod extract;
 mod health;
+mod limits;
+mod metrics;
+mod openapi;
+mod shares;
+mod styles;
+mod telemetry;
 mod test;
+#[cfg(test)]
+mod testing;
 mod upload;
+mod webhooks;
 
+use axum::extract::FromRef;
+use axum::http::Uri;
+use axum::middleware;
 use axum::routing::{get, post};
 use axum::Router;
 
-pub fn routes() -> Router {
+use crate::config::env::{Config, WebhookConfig};
+use crate::db::Database;
+use crate::errors::ApiError;
+use crate::services::health::Health;
+use crate::services::jwt::JwtVerifier;
+use crate::services::limits::Limits;
+use crate::services::share::ShareSigner;
+use crate::services::storage::Storage;
+
+pub use metrics::MetricsToken;
+
+/// Shared handles passed to every handler.
+#[derive(Clone)]
+pub struct AppState {
+    pub db: Database,
+    pub storage: Storage,
+    /// Set when identity provider logins are configured.
+    pub jwt: Option<JwtVerifier>,
+    pub shares: ShareSigner,
+    pub limits: Limits,
+    pub health: Health,
+    /// The model API settings; `None` without `CLAUDE_API_KEY`.
+    pub model: Option<Config>,
+    pub metrics_token: MetricsToken,
+    pub webhooks: WebhookConfig,
+}
+
+impl FromRef<AppState> for Database {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for Storage {
+    fn from_ref(state: &AppState) -> Self {
+        state.storage.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<JwtVerifier> {
+    fn from_ref(state: &AppState) -> Self {
+        state.jwt.clone()
+    }
+}
+
+impl FromRef<AppState> for ShareSigner {
+    fn from_ref(state: &AppState) -> Self {
+        state.shares.clone()
+    }
+}
+
+impl FromRef<AppState> for Limits {
+    fn from_ref(state: &AppState) -> Self {
+        state.limits.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.model.clone()
+    }
+}
+
+impl FromRef<AppState> for MetricsToken {
+    fn from_ref(state: &AppState) -> Self {
+        state.metrics_token.clone()
+    }
+}
+
+impl FromRef<AppState> for WebhookConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.webhooks.clone()
+    }
+}
+
+impl FromRef<AppState> for Health {
+    fn from_ref(state: &AppState) -> Self {
+        state.health.clone()
+    }
+}
+
+async fn no_route(uri: Uri) -> ApiError {
+    ApiError::NotFound(format!("No route for {}", uri.path()))
+}
+
+/// Every route. The API lives under `/api/v1`; the unversioned routes it
+/// replaced remain as deprecated aliases. Health checks, metrics, the API
+/// docs and share links are unversioned. All but those and the style
+/// catalogue need an API key or token.
+pub fn routes(state: AppState) -> Router<AppState> {
+    let v1 = Router::new().merge(styles::routes()).merge(authenticate(
+        &state,
+        Router::new()
+            .merge(auth::routes())
+            .merge(documents::routes())
+            .merge(shares::routes())
+            .merge(webhooks::routes())
+            .route("/uploads", post(upload::handle_upload))
+            .route(
+                "/documents/:file_id/conversions",
+                post(convert::convert_document),
+            )
+            .route("/documents/:file_id/pages", get(documents::list_pages))
+            .route(
+                "/documents/:file_id/pages/:number",
+                get(documents::get_page),
+            )
+            .route(
+                "/documents/:file_id/exports/pdf",
+                get(convert::generate_pdf),
+            )
+            .route(
+                "/documents/:file_id/exports/bundle",
+                get(bundle::download_bundle),
+            )
+            .route(
+                "/documents/:file_id/exports/:format",
+                get(export::export_format),
+            )
+            .route("/jobs/:job_id", get(documents::get_job)),
+    ));
+
+    // The root routes older clients call, from before `/api/v1`
+    let legacy = authenticate(
+        &state,
+        Router::new()
+            .route("/upload", post(upload::handle_upload))
+            .route("/convert/:file_id", get(convert::convert_to_text))
+            .route("/pdf/:file_id", get(convert::generate_pdf))
+            .route("/export/:file_id", get(export::export_document))
+            .route("/bundle/:file_id", get(bundle::download_bundle)),
+    )
+    .layer(middleware::from_fn(deprecation::deprecated));
+
     Router::new()
         .merge(health::routes())
-        .route("/upload", post(upload::handle_upload))
-        .route("/convert/:file_id", get(convert::convert_to_text))
-        .route("/pdf/:file_id", get(convert::generate_pdf))
+        .merge(metrics::routes())
+        .merge(openapi::routes())
+        .merge(shares::public_routes())
+        .nest("/api/v1", v1)
+        .merge(legacy)
+        .fallback(no_route)
+        .layer(middleware::from_fn_with_state(state, limits::limit_ip))
+        .layer(middleware::from_fn(metrics::track))
+        .layer(middleware::from_fn(telemetry::trace))
+        .layer(middleware::from_fn(telemetry::assign_request_id))
+}
+
+/// Require a key or token for `routes`, and limit requests per owner.
+fn authenticate(state: &AppState, routes: Router<AppState>) -> Router<AppState> {
+    routes
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            limits::limit_owner,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::authenticate,
+        ))
 }
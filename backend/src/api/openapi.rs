@@ -0,0 +1,226 @@
+//! The OpenAPI document, generated from the handlers' `#[utoipa::path]`
+//! annotations, and the interactive docs that render it.
+
+use axum::Router;
+use utoipa::openapi::response::ResponseBuilder;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{Content, Deprecated, RefOr};
+use utoipa::{Modify, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::errors::Problem;
+use crate::models::webhook::Event;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "noteforge",
+        description = "Turn photos of handwritten notes into LaTeX, PDFs and other formats.\n\n\
+            The unversioned routes that predate `/api/v1` still work, but are \
+            deprecated; their responses carry `Deprecation` and a `Link` to the successor."
+    ),
+    paths(
+        super::upload::handle_upload,
+        super::convert::convert_document,
+        super::styles::list_styles,
+        super::documents::list_documents,
+        super::documents::get_document,
+        super::documents::delete_document,
+        super::documents::list_jobs,
+        super::documents::get_job,
+        super::documents::list_pages,
+        super::documents::get_page,
+        super::documents::import_document,
+        super::documents::merge_documents,
+        super::documents::split_document,
+        super::documents::get_provenance,
+        super::convert::generate_pdf,
+        super::bundle::download_bundle,
+        super::export::export_format,
+        super::shares::create_share,
+        super::shares::list_shares,
+        super::shares::revoke_share,
+        super::shares::view_share,
+        super::shares::view_share_with_password,
+        super::auth::current_owner,
+        super::auth::current_usage,
+        super::auth::list_keys,
+        super::auth::create_key,
+        super::auth::revoke_key,
+        super::auth::list_owners,
+        super::auth::create_owner,
+        super::webhooks::list_webhooks,
+        super::webhooks::create_webhook,
+        super::webhooks::delete_webhook,
+        super::webhooks::get_secret,
+        super::webhooks::rotate_secret,
+        super::webhooks::list_deliveries,
+        super::webhooks::get_delivery,
+        super::webhooks::redeliver,
+        super::health::health_check,
+        super::health::readiness,
+        super::metrics::render_metrics,
+        super::convert::convert_to_text,
+        super::export::export_document,
+    ),
+    components(schemas(Problem, Event)),
+    modifiers(&Security, &Problems, &Deprecations),
+    security(("bearer" = [])),
+    tags(
+        (name = "conversion", description = "Upload note images and turn them into documents"),
+        (name = "documents", description = "Stored documents, their pages and their history"),
+        (name = "exports", description = "Documents as PDFs, bundles and other formats"),
+        (name = "shares", description = "Read-only links to documents"),
+        (name = "account", description = "The caller and their API keys"),
+        (name = "webhooks", description = "Signed event callbacks and their delivery log"),
+        (name = "admin", description = "Owner management; admins only"),
+        (name = "operations", description = "Health checks and metrics"),
+    )
+)]
+pub struct ApiDoc;
+
+/// A file in a response body; its content type says what kind.
+#[derive(ToSchema)]
+#[schema(value_type = String, format = Binary)]
+#[allow(dead_code)]
+pub struct Binary(Vec<u8>);
+
+/// API keys and identity provider JWTs both go in `Authorization: Bearer`.
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("An API key (`nf_…`) or an identity provider JWT"))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "metrics_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("`$METRICS_TOKEN`, when it is set"))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Every failure is a problem document, so say so once for every operation.
+struct Problems;
+
+impl Modify for Problems {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let problem = ResponseBuilder::new()
+            .description("An RFC 7807 problem; branch on `code`")
+            .content(
+                "application/problem+json",
+                Content::new(Some(RefOr::Ref(utoipa::openapi::Ref::from_schema_name(
+                    "Problem",
+                )))),
+            )
+            .build();
+        for item in openapi.paths.paths.values_mut() {
+            for operation in [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+                &mut item.patch,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                operation
+                    .responses
+                    .responses
+                    .entry("default".to_string())
+                    .or_insert_with(|| problem.clone().into());
+            }
+        }
+    }
+}
+
+/// The root routes from before `/api/v1`, marked deprecated. Those served by
+/// the same handler as their successor are documented as copies of it.
+struct Deprecations;
+
+impl Modify for Deprecations {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let paths = &mut openapi.paths.paths;
+        for (legacy, successor) in [
+            ("/upload", "/api/v1/uploads"),
+            ("/pdf/{file_id}", "/api/v1/documents/{file_id}/exports/pdf"),
+            (
+                "/bundle/{file_id}",
+                "/api/v1/documents/{file_id}/exports/bundle",
+            ),
+        ] {
+            if let Some(mut item) = paths.get(successor).cloned() {
+                for (method, operation) in [("GET", &mut item.get), ("POST", &mut item.post)] {
+                    if let Some(operation) = operation {
+                        operation.summary =
+                            Some(format!("Deprecated alias of `{} {}`.", method, successor));
+                    }
+                }
+                paths.insert(legacy.to_string(), item);
+            }
+        }
+        for path in [
+            "/upload",
+            "/convert/{file_id}",
+            "/pdf/{file_id}",
+            "/export/{file_id}",
+            "/bundle/{file_id}",
+        ] {
+            let Some(item) = paths.get_mut(path) else {
+                continue;
+            };
+            for operation in [&mut item.get, &mut item.post].into_iter().flatten() {
+                operation.deprecated = Some(Deprecated::True);
+                // Operation IDs must stay unique across the copies
+                if let Some(id) = &mut operation.operation_id {
+                    if !id.starts_with("legacy_") {
+                        *id = format!("legacy_{}", id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `/openapi.json` and the docs UI at `/docs`; both public.
+pub fn routes() -> Router<super::AppState> {
+    SwaggerUi::new("/docs")
+        .url("/openapi.json", ApiDoc::openapi())
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    /// The committed spec must match the code; run with `UPDATE_OPENAPI=1`
+    /// to regenerate it after changing the API.
+    #[test]
+    fn spec_is_up_to_date() {
+        let generated = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(SPEC, &generated).unwrap();
+            return;
+        }
+        let committed = std::fs::read_to_string(SPEC).unwrap_or_default();
+        assert!(
+            committed == generated,
+            "openapi.json is out of date; run `UPDATE_OPENAPI=1 cargo test spec_is_up_to_date`"
+        );
+    }
+}
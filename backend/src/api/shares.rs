@@ -0,0 +1,420 @@
+//! Read-only share links: managed by document owners, served publicly.
+
+use std::net::IpAddr;
+
+use axum::extract::State;
+use axum::http::header::{CACHE_CONTROL, CONTENT_SECURITY_POLICY, REFERRER_POLICY};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{delete, get};
+use axum::{Form, Router};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::auth::Caller;
+use super::convert::{build_pdf, cached_pdf, load_document};
+use super::extract::{Json, Path};
+use super::limits::ClientIp;
+use super::openapi::Binary;
+use crate::db::Database;
+use crate::errors::{ApiError, Result};
+use crate::models::share::{Share, ShareLink};
+use crate::models::usage::Payer;
+use crate::services::export::{self, ExportFormat};
+use crate::services::limits::Limits;
+use crate::services::share::{self, ShareSigner};
+use crate::services::storage::Storage;
+use crate::utils::headers;
+
+const DEFAULT_EXPIRY_HOURS: i64 = 7 * 24;
+const MAX_EXPIRY_HOURS: i64 = 90 * 24;
+
+/// Header carrying a share's password on `GET /shared/:token`.
+const PASSWORD_HEADER: &str = "x-share-password";
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewShare {
+    /// `pdf`, `latex` or any format `/export` accepts.
+    format: String,
+    expires_in_hours: Option<i64>,
+    password: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PasswordForm {
+    password: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/documents/{file_id}/shares",
+    tag = "shares",
+    params(("file_id" = Uuid, Path, description = "Document ID")),
+    request_body = NewShare,
+    responses((status = 201, body = ShareLink))
+)]
+async fn create_share(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    State(signer): State<ShareSigner>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+    Json(request): Json<NewShare>,
+) -> Result<impl IntoResponse> {
+    caller.authorize(&db, &file_id)?;
+    // Only converted documents have anything to show
+    load_document(&db, &storage, &file_id).await?;
+
+    let format = request.format.to_lowercase();
+    if format != "pdf" && format != "latex" && ExportFormat::parse(&format).is_err() {
+        return Err(ApiError::invalid_field(
+            "format",
+            format!(
+                "Unsupported share format: {}. Use pdf, latex or a format /export accepts",
+                request.format
+            ),
+        ));
+    }
+    let hours = request.expires_in_hours.unwrap_or(DEFAULT_EXPIRY_HOURS);
+    if !(1..=MAX_EXPIRY_HOURS).contains(&hours) {
+        return Err(ApiError::invalid_field(
+            "expires_in_hours",
+            format!("Must be between 1 and {}", MAX_EXPIRY_HOURS),
+        ));
+    }
+    let password_hash = match request.password.as_deref() {
+        Some("") | None => None,
+        Some(password) => Some(share::hash_password(password).await?),
+    };
+
+    let share = db.create_share(
+        &file_id,
+        caller.id(),
+        &format,
+        password_hash.as_deref(),
+        Utc::now() + Duration::hours(hours),
+    )?;
+    Ok((StatusCode::CREATED, Json(link(&signer, share))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{file_id}/shares",
+    tag = "shares",
+    params(("file_id" = Uuid, Path, description = "Document ID")),
+    responses((status = 200, body = Vec<ShareLink>))
+)]
+async fn list_shares(
+    State(db): State<Database>,
+    State(signer): State<ShareSigner>,
+    caller: Caller,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<Vec<ShareLink>>> {
+    caller.authorize(&db, &file_id)?;
+    let shares = db.shares(&file_id)?;
+    Ok(Json(
+        shares
+            .into_iter()
+            .map(|share| link(&signer, share))
+            .collect(),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/shares/{share_id}",
+    tag = "shares",
+    params(("share_id" = Uuid, Path)),
+    responses((status = 204, description = "Revoked"))
+)]
+async fn revoke_share(
+    State(db): State<Database>,
+    caller: Caller,
+    Path(share_id): Path<Uuid>,
+) -> Result<StatusCode> {
+    let not_found = || ApiError::NotFound(format!("Share not found: {}", share_id));
+    let (share, _) = db.get_share(&share_id)?.ok_or_else(not_found)?;
+    caller.authorize(&db, &share.document_id)?;
+    if db.revoke_share(&share_id)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(not_found())
+    }
+}
+
+/// Public: serve a share, with any password in the `x-share-password` header.
+#[utoipa::path(
+    get,
+    path = "/shared/{token}",
+    tag = "shares",
+    security(()),
+    params(
+        ("token" = String, Path, description = "Signed token from the share's URL"),
+        ("x-share-password" = Option<String>, Header),
+    ),
+    responses((
+        status = 200,
+        description = "The shared document, in the share's format",
+        content_type = "application/octet-stream",
+        body = Binary
+    ))
+)]
+async fn view_share(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    State(signer): State<ShareSigner>,
+    State(limits): State<Limits>,
+    ClientIp(ip): ClientIp,
+    Path(token): Path<String>,
+    request_headers: HeaderMap,
+) -> Result<impl IntoResponse> {
+    let password = request_headers
+        .get(PASSWORD_HEADER)
+        .and_then(|value| value.to_str().ok());
+    serve(&db, &storage, &signer, &limits, ip, &token, password).await
+}
+
+/// Public: serve a share, with the password from an HTML form.
+#[utoipa::path(
+    post,
+    path = "/shared/{token}",
+    tag = "shares",
+    security(()),
+    params(("token" = String, Path, description = "Signed token from the share's URL")),
+    request_body(content = PasswordForm, content_type = "application/x-www-form-urlencoded"),
+    responses((
+        status = 200,
+        description = "The shared document, in the share's format",
+        content_type = "application/octet-stream",
+        body = Binary
+    ))
+)]
+async fn view_share_with_password(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    State(signer): State<ShareSigner>,
+    State(limits): State<Limits>,
+    ClientIp(ip): ClientIp,
+    Path(token): Path<String>,
+    Form(form): Form<PasswordForm>,
+) -> Result<impl IntoResponse> {
+    serve(
+        &db,
+        &storage,
+        &signer,
+        &limits,
+        ip,
+        &token,
+        Some(&form.password),
+    )
+    .await
+}
+
+async fn serve(
+    db: &Database,
+    storage: &Storage,
+    signer: &ShareSigner,
+    limits: &Limits,
+    ip: IpAddr,
+    token: &str,
+    password: Option<&str>,
+) -> Result<impl IntoResponse> {
+    let share_id = signer.verify(token)?;
+    let (share, password_hash) = db
+        .get_share(&share_id)?
+        .filter(|(share, _)| share.revoked_at.is_none())
+        .ok_or_else(|| ApiError::NotFound("Share link not found or expired".to_string()))?;
+    if let Some(hash) = password_hash {
+        // 403 rather than 401: a bearer token would not help
+        let password = password.ok_or(ApiError::AuthorizationError)?;
+        limits.attempt_share_password(&share_id)?;
+        if !share::check_password(password, &hash).await {
+            return Err(ApiError::AuthorizationError);
+        }
+        limits.share_password_accepted(&share_id);
+    }
+
+    let document = load_document(db, storage, &share.document_id).await?;
+    let id = share.document_id;
+    let (mut response_headers, body) = match share.format.as_str() {
+        "pdf" => {
+            let pdf = match cached_pdf(storage, &document, None).await? {
+                Some(pdf) => pdf,
+                None => {
+                    // Viewers have no account, so the document's owner pays,
+                    // and so does the viewer's address
+                    let owner = db.document_owner(&id)?.flatten();
+                    let payers: Vec<Payer> = owner
+                        .map(Payer::Owner)
+                        .into_iter()
+                        .chain([Payer::Ip(ip)])
+                        .collect();
+                    build_pdf(db, storage, limits, &payers, &document, None, None).await?
+                }
+            };
+            (
+                headers::inline("application/pdf", &format!("{}.pdf", id)),
+                pdf,
+            )
+        }
+        "latex" => (
+            headers::inline("text/plain; charset=utf-8", &format!("{}.tex", id)),
+            document.content.into_bytes(),
+        ),
+        format => {
+            let export = export::export(ExportFormat::parse(format)?, &document)?;
+            let mut headers =
+                headers::inline(export.content_type, &format!("{}.{}", id, export.extension));
+            // Shown on the API's origin, so keep any script in it from running there
+            if export.content_type.starts_with("text/html") {
+                headers.insert(CONTENT_SECURITY_POLICY, "sandbox".parse().unwrap());
+            }
+            (headers, export.body)
+        }
+    };
+    db.record_share_view(&share_id)?;
+
+    // Keep the token out of caches and other sites' logs
+    response_headers.insert(CACHE_CONTROL, "private, no-store".parse().unwrap());
+    response_headers.insert(REFERRER_POLICY, "no-referrer".parse().unwrap());
+    Ok((response_headers, body))
+}
+
+fn link(signer: &ShareSigner, share: Share) -> ShareLink {
+    let token = signer.sign(&share.id, share.expires_at);
+    ShareLink {
+        url: format!("/shared/{}", token),
+        token,
+        share,
+    }
+}
+
+/// Routes for document owners; they need authentication.
+pub fn routes() -> Router<super::AppState> {
+    Router::new()
+        .route(
+            "/documents/:file_id/shares",
+            get(list_shares).post(create_share),
+        )
+        .route("/shares/:share_id", delete(revoke_share))
+}
+
+/// The route that serves shares to anyone with a link.
+pub fn public_routes() -> Router<super::AppState> {
+    Router::new().route(
+        "/shared/:token",
+        get(view_share).post(view_share_with_password),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::api::testing::TestApi;
+    use crate::services::limits::SHARE_PASSWORD_ATTEMPTS;
+
+    const SOURCE: &str = "\\documentclass{article}\\begin{document}Shared notes\\end{document}";
+
+    async fn share(api: &TestApi, key: &str, document: &Uuid, request: Value) -> Value {
+        let response = api
+            .client
+            .post(api.url(&format!("/api/v1/documents/{}/shares", document)))
+            .bearer_auth(key)
+            .json(&request)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 201);
+        response.json().await.unwrap()
+    }
+
+    async fn view(api: &TestApi, token: &str, password: Option<&str>) -> reqwest::Response {
+        let mut request = api.client.get(api.url(&format!("/shared/{}", token)));
+        if let Some(password) = password {
+            request = request.header(PASSWORD_HEADER, password);
+        }
+        request.send().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn turns_away_tampered_expired_and_revoked_links() {
+        let api = TestApi::start().await;
+        let (owner, key) = api.owner("sharer", false);
+        let id = api.document(&owner.id, SOURCE).await.id;
+        let link = share(&api, &key, &id, json!({ "format": "latex" })).await;
+        let token = link["token"].as_str().unwrap();
+        let response = view(&api, token, None).await;
+        assert_eq!(response.status(), 200);
+        assert!(response.text().await.unwrap().contains("Shared notes"));
+
+        // Another share's ID, or a later expiry, under this token's signature
+        let other = share(&api, &key, &id, json!({ "format": "pdf" })).await;
+        let (payload, signature) = token.split_once('.').unwrap();
+        let (other_payload, _) = other["token"].as_str().unwrap().split_once('.').unwrap();
+        let mut longer = URL_SAFE_NO_PAD.decode(payload).unwrap();
+        longer[16..].copy_from_slice(&i64::MAX.to_be_bytes());
+        for forged in [
+            format!("{}.{}", other_payload, signature),
+            format!("{}.{}", URL_SAFE_NO_PAD.encode(longer), signature),
+            format!("{}.{}x", payload, signature),
+            "garbage".to_string(),
+        ] {
+            assert_eq!(view(&api, &forged, None).await.status(), 404, "{}", forged);
+        }
+
+        // Properly signed, but expired
+        let share_id: Uuid = link["id"].as_str().unwrap().parse().unwrap();
+        let signer = ShareSigner::from_env(&api.db).unwrap();
+        let expired = signer.sign(&share_id, Utc::now() - Duration::minutes(1));
+        assert_eq!(view(&api, &expired, None).await.status(), 404);
+
+        let revoked = api
+            .client
+            .delete(api.url(&format!("/api/v1/shares/{}", share_id)))
+            .bearer_auth(&key)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(revoked.status(), 204);
+        assert_eq!(view(&api, token, None).await.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn checks_passwords_and_limits_wrong_ones() {
+        let api = TestApi::start().await;
+        let (owner, key) = api.owner("sharer", false);
+        let id = api.document(&owner.id, SOURCE).await.id;
+        let link = share(
+            &api,
+            &key,
+            &id,
+            json!({ "format": "html", "password": "hunter2" }),
+        )
+        .await;
+        let token = link["token"].as_str().unwrap();
+
+        for password in [None, Some("hunter3")] {
+            let response = view(&api, token, password).await;
+            assert_eq!(response.status(), 403, "{:?}", password);
+            assert!(!response.headers().contains_key("www-authenticate"));
+        }
+        let response = view(&api, token, Some("hunter2")).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-security-policy"], "sandbox");
+        assert!(response.text().await.unwrap().contains("Shared notes"));
+
+        // One wrong guess so far; the right password did not count
+        for _ in 1..SHARE_PASSWORD_ATTEMPTS {
+            assert_eq!(view(&api, token, Some("guess")).await.status(), 403);
+        }
+        let response = view(&api, token, Some("hunter2")).await;
+        assert_eq!(response.status(), 429);
+        assert!(response.headers().contains_key("retry-after"));
+    }
+}
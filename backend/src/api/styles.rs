@@ -0,0 +1,19 @@
+use axum::routing::get;
+use axum::{Json, Router};
+
+use crate::services::styles::{Style, STYLES};
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/styles",
+    tag = "conversion",
+    security(()),
+    responses((status = 200, body = Vec<Style>))
+)]
+async fn list_styles() -> Json<&'static [Style]> {
+    Json(STYLES)
+}
+
+pub fn routes() -> Router<super::AppState> {
+    Router::new().route("/styles", get(list_styles))
+}
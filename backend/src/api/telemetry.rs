@@ -0,0 +1,209 @@
+use axum::extract::{MatchedPath, Request};
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use tracing::field::Empty;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::services::telemetry;
+use crate::utils::request_id;
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Middleware: give each request an ID, reusing the caller's `X-Request-Id`
+/// when it is sensible, and echo it on the response.
+pub async fn assign_request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| request_id::is_valid(id))
+        .map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string);
+
+    let mut response = request_id::scope(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID, value);
+    }
+    response
+}
+
+/// Middleware: a server span per request, continuing the caller's trace when
+/// it sent a `traceparent` header.
+pub async fn trace(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched".to_string(), |path| path.as_str().to_string());
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format!("{} {}", method, route),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.request.method = %method,
+        http.route = %route,
+        url.path = %request.uri().path(),
+        request.id = %request_id::current().unwrap_or_default(),
+        http.response.status_code = Empty,
+    );
+    span.set_parent(telemetry::extract(request.headers()));
+
+    let response = next.run(request).instrument(span.clone()).await;
+
+    span.record("http.response.status_code", response.status().as_u16());
+    if response.status().is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use opentelemetry::trace::{SpanId, TraceId, TracerProvider as _};
+    use opentelemetry_sdk::export::trace::SpanData;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use serde_json::json;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::api::testing::{unlimited, TestApi};
+    use crate::config::env::Config;
+    use crate::services::storage;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const PARENT_ID: &str = "00f067aa0ba902b7";
+
+    // Stands in for the model API, answering every page with the same text
+    async fn serve_model() -> SocketAddr {
+        let app = Router::new().route(
+            "/v1/messages",
+            post(|| async {
+                Json(json!({
+                    "content": [{ "text": "The answer is $x^2$." }],
+                    "usage": { "input_tokens": 10, "output_tokens": 5 },
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        addr
+    }
+
+    fn attribute(span: &SpanData, key: &str) -> Option<String> {
+        span.attributes
+            .iter()
+            .find(|attribute| attribute.key.as_str() == key)
+            .map(|attribute| attribute.value.to_string())
+    }
+
+    #[tokio::test]
+    async fn request_spans_continue_the_callers_trace() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let model = Config {
+            claude_api_key: "test".to_string(),
+            claude_api_url: format!("http://{}", serve_model().await),
+        };
+        let api = TestApi::start_with(unlimited(), Some(model)).await;
+        let (owner, key) = api.owner("tester", false);
+        let file_id = uuid::Uuid::new_v4();
+        let pages = [format!("{}_1.png", file_id), format!("{}_2.png", file_id)];
+        for page in &pages {
+            api.storage
+                .put(&storage::upload_key(page), b"not really a png".to_vec())
+                .await
+                .unwrap();
+        }
+        api.db
+            .record_upload(&file_id, Some(&owner.id), &pages)
+            .unwrap();
+
+        let traceparent = format!("00-{}-{}-01", TRACE_ID, PARENT_ID);
+        let converted = api
+            .client
+            .post(api.url(&format!("/api/v1/documents/{}/conversions", file_id)))
+            .bearer_auth(&key)
+            .header("traceparent", &traceparent)
+            .json(&json!({ "is_multi_page": true }))
+            .send()
+            .await
+            .unwrap();
+        assert!(converted.status().is_success(), "{:?}", converted.status());
+        // The compile span is recorded whether or not typst is installed;
+        // only its status differs, so the response is not checked
+        api.client
+            .get(api.url(&format!(
+                "/api/v1/documents/{}/exports/pdf?engine=typst",
+                file_id
+            )))
+            .bearer_auth(&key)
+            .header("traceparent", &traceparent)
+            .send()
+            .await
+            .unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let by_id: HashMap<SpanId, &SpanData> = spans
+            .iter()
+            .map(|span| (span.span_context.span_id(), span))
+            .collect();
+        let requests: Vec<&SpanData> = spans
+            .iter()
+            .filter(|span| attribute(span, "http.route").is_some())
+            .collect();
+        assert_eq!(requests.len(), 2);
+        for request in &requests {
+            assert_eq!(
+                request.span_context.trace_id(),
+                TraceId::from_hex(TRACE_ID).unwrap()
+            );
+            assert_eq!(request.parent_span_id, SpanId::from_hex(PARENT_ID).unwrap());
+        }
+
+        // Walk up to the request span the work happened under
+        let request_of = |span: &SpanData| {
+            let mut current = span.parent_span_id;
+            while let Some(ancestor) = by_id.get(&current) {
+                if attribute(ancestor, "http.route").is_some() {
+                    return Some(current);
+                }
+                current = ancestor.parent_span_id;
+            }
+            None
+        };
+        let document_id = file_id.to_string();
+        for (name, count) in [
+            ("load_pages", 1),
+            ("transcribe_page", 2),
+            ("assemble", 1),
+            ("compile", 1),
+        ] {
+            let named: Vec<&SpanData> = spans.iter().filter(|span| span.name == name).collect();
+            assert_eq!(named.len(), count, "{}", name);
+            for span in named {
+                assert!(request_of(span).is_some(), "{} outside a request", name);
+                assert_eq!(
+                    attribute(span, "document.id").as_deref(),
+                    Some(document_id.as_str()),
+                    "{}",
+                    name
+                );
+            }
+        }
+    }
+}
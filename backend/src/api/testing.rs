@@ -0,0 +1,151 @@
+//! The whole API on a local port, for tests that go through the middleware.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tempfile::TempDir;
+use uuid::Uuid;
+
+use super::convert::store_latex;
+use super::{AppState, MetricsToken};
+use crate::config::env::{ClientIpSource, Config, LimitsConfig, WebhookConfig};
+use crate::db::Database;
+use crate::models::document::{Document, DocumentOrigin};
+use crate::models::owner::Owner;
+use crate::services::health::Health;
+use crate::services::limits::Limits;
+use crate::services::share::ShareSigner;
+use crate::services::storage::{FsStore, Storage};
+
+/// What `/metrics` wants as a bearer token.
+pub const METRICS_TOKEN: &str = "metrics-token";
+
+pub struct TestApi {
+    pub db: Database,
+    pub storage: Storage,
+    pub client: reqwest::Client,
+    addr: SocketAddr,
+    _root: TempDir,
+}
+
+impl TestApi {
+    /// An API with an in-memory database, storage in a temp dir and no limits.
+    pub async fn start() -> Self {
+        Self::start_with(unlimited(), None).await
+    }
+
+    pub async fn start_with(limits: LimitsConfig, model: Option<Config>) -> Self {
+        let root = tempfile::tempdir().unwrap();
+        let db = Database::open(":memory:").unwrap();
+        let storage: Storage = Arc::new(FsStore::new(root.path()));
+        let state = AppState {
+            db: db.clone(),
+            storage: storage.clone(),
+            jwt: None,
+            shares: ShareSigner::from_env(&db).unwrap(),
+            limits: Limits::new(limits),
+            health: Health::new(db.clone(), storage.clone(), model.clone()),
+            model,
+            metrics_token: MetricsToken::new(Some(METRICS_TOKEN.to_string())),
+            webhooks: WebhookConfig {
+                max_attempts: 1,
+                retry_base: Duration::from_secs(1),
+                timeout: Duration::from_secs(5),
+                allow_private_urls: false,
+            },
+        };
+        let app = super::routes(state.clone()).with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+        });
+        Self {
+            db,
+            storage,
+            client: reqwest::Client::new(),
+            addr,
+            _root: root,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// A stored LaTeX document belonging to `owner`.
+    pub async fn document(&self, owner: &Uuid, content: &str) -> Document {
+        let document = Document {
+            id: Uuid::new_v4(),
+            filename: "notes.tex".to_string(),
+            content: content.to_string(),
+            style: "article".to_string(),
+            created_at: chrono::Utc::now(),
+        };
+        store_latex(&self.storage, &document.id, &document.content)
+            .await
+            .unwrap();
+        self.db
+            .save_document(&document, DocumentOrigin::Import, None, Some(owner))
+            .unwrap();
+        document
+    }
+
+    /// A new owner and the secret of an API key for them.
+    pub fn owner(&self, name: &str, admin: bool) -> (Owner, String) {
+        let owner = self.db.create_owner(name, admin).unwrap();
+        let key = self.db.create_api_key(&owner.id, "test").unwrap();
+        (owner, key.secret)
+    }
+}
+
+pub fn unlimited() -> LimitsConfig {
+    LimitsConfig {
+        requests_per_minute: 0,
+        ip_requests_per_minute: 0,
+        pages_per_day: 0,
+        compile_seconds_per_day: 0,
+        ip_pages_per_day: 0,
+        ip_compile_seconds_per_day: 0,
+        client_ip: ClientIpSource::Peer,
+    }
+}
+
+/// A `multipart/form-data` body of `(name, filename, data)` fields, and its
+/// content type. Image files are sent with their type.
+pub fn multipart(fields: &[(&str, Option<&str>, &[u8])]) -> (String, Vec<u8>) {
+    const BOUNDARY: &str = "noteforge-test-boundary";
+    let mut body = Vec::new();
+    for (name, filename, data) in fields {
+        body.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+        let disposition = match filename {
+            Some(filename) => format!(
+                "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n",
+                name, filename
+            ),
+            None => format!("Content-Disposition: form-data; name=\"{}\"\r\n", name),
+        };
+        body.extend_from_slice(disposition.as_bytes());
+        let extension = filename.and_then(|filename| filename.rsplit_once('.'));
+        let content_type = match extension.map(|(_, extension)| extension) {
+            Some("png") => Some("image/png"),
+            Some("jpg") => Some("image/jpeg"),
+            Some("webp") => Some("image/webp"),
+            Some("gif") => Some("image/gif"),
+            _ => None,
+        };
+        if let Some(content_type) = content_type {
+            body.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+        }
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+    (format!("multipart/form-data; boundary={}", BOUNDARY), body)
+}
@@ -1,26 +1,57 @@
-use std::io::Write;
-use std::path::Path;
-
-use axum::extract::Multipart;
+use axum::extract::{Multipart, State};
 use axum::response::Json;
-use serde_json::json;
-use tracing::info;
+use serde::Serialize;
+use tracing::{info, Instrument};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
+use super::auth::Caller;
+use super::extract::multipart_error;
+use crate::db::Database;
 use crate::errors::{ApiError, Result};
+use crate::services::storage::{self, Storage};
 
-const UPLOAD_DIR: &str = "uploads";
 const MAX_FILE_SIZE: usize = 10 * 1024 * 1024; // 10MB
 const ALLOWED_TYPES: [&str; 3] = ["image/jpeg", "image/png", "image/webp"]; // MIME types
 const MAX_FILES: usize = 5;
 
-pub async fn handle_upload(mut multipart: Multipart) -> Result<Json<serde_json::Value>> {
-    // Ensure upload directory exists
-    std::fs::create_dir_all(UPLOAD_DIR)
-        .map_err(|e| ApiError::FileError(format!("Failed to create upload directory: {}", e)))?;
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UploadResponse {
+    status: &'static str,
+    /// Pass to `/api/v1/documents/{file_id}/conversions`.
+    file_id: Uuid,
+    filenames: Vec<String>,
+    is_multi_page: bool,
+}
+
+/// The `/upload` form, for the API docs; the handler reads the fields as
+/// they stream in.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+struct UploadForm {
+    /// `true` when the images are pages of one document.
+    is_multi_page: Option<bool>,
+    /// Up to 5 JPEG, PNG or WebP images of at most 10MB each, under any field name.
+    #[schema(value_type = Vec<String>, format = Binary)]
+    files: Vec<Vec<u8>>,
+}
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/uploads",
+    tag = "conversion",
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses((status = 200, body = UploadResponse))
+)]
+pub async fn handle_upload(
+    State(db): State<Database>,
+    State(storage): State<Storage>,
+    caller: Caller,
+    mut multipart: Multipart,
+) -> Result<Json<UploadResponse>> {
     // Generate a single file_id for this upload batch
     let file_id = Uuid::new_v4();
+    let mut written = Written::new(&storage);
     let mut uploaded_files = Vec::new();
     let mut is_multi_page = false;
     let mut file_counter = 0;
@@ -29,14 +60,14 @@ pub async fn handle_upload(mut multipart: Multipart) -> Result<Json<serde_json::
     while let Some(field) = multipart
         .next_field()
         .await
-        .map_err(|e| ApiError::FileError(format!("Failed to process multipart form: {}", e)))?
+        .map_err(|e| multipart_error("Failed to process multipart form", e))?
     {
         let field_name = field.name().unwrap_or("unknown").to_string();
         
         // Check if this is the is_multi_page flag
         if field_name == "is_multi_page" {
             let value = field.text().await
-                .map_err(|e| ApiError::FileError(format!("Failed to read field data: {}", e)))?;
+                .map_err(|e| multipart_error("Failed to read field data", e))?;
             is_multi_page = value == "true";
             continue;
         }
@@ -67,11 +98,11 @@ pub async fn handle_upload(mut multipart: Multipart) -> Result<Json<serde_json::
         let data = field
             .bytes()
             .await
-            .map_err(|e| ApiError::FileError(format!("Failed to read file data: {}", e)))?;
+            .map_err(|e| multipart_error("Failed to read file data", e))?;
 
         // Check file size
         if data.len() > MAX_FILE_SIZE {
-            return Err(ApiError::ValidationError(format!(
+            return Err(ApiError::PayloadTooLarge(format!(
                 "File too large. Maximum size is {} bytes",
                 MAX_FILE_SIZE
             )));
@@ -88,14 +119,18 @@ pub async fn handle_upload(mut multipart: Multipart) -> Result<Json<serde_json::
             format!("{}.{}", file_id, extension)
         };
         
-        let filepath = Path::new(UPLOAD_DIR).join(&filename);
-
         // Save file
-        let mut file = std::fs::File::create(&filepath)
-            .map_err(|e| ApiError::FileError(format!("Failed to create file: {}", e)))?;
-
-        file.write_all(&data)
-            .map_err(|e| ApiError::FileError(format!("Failed to write file: {}", e)))?;
+        let key = storage::upload_key(&filename);
+        storage
+            .put(&key, data.to_vec())
+            .instrument(tracing::info_span!(
+                "store_upload",
+                document.id = %file_id,
+                page.number = uploaded_files.len() + 1,
+                upload.bytes = data.len(),
+            ))
+            .await?;
+        written.keys.push(key);
 
         uploaded_files.push(filename.clone());
         info!("File uploaded successfully: {}", filename);
@@ -105,12 +140,53 @@ pub async fn handle_upload(mut multipart: Multipart) -> Result<Json<serde_json::
         return Err(ApiError::ValidationError("No files provided".to_string()));
     }
 
-    Ok(Json(json!({
-        "status": "success",
-        "file_id": file_id.to_string(),
-        "filenames": uploaded_files,
-        "is_multi_page": is_multi_page || uploaded_files.len() > 1
-    })))
+    db.record_upload(&file_id, Some(caller.id()), &uploaded_files)?;
+    written.keep();
+
+    Ok(Json(UploadResponse {
+        status: "success",
+        file_id,
+        is_multi_page: is_multi_page || uploaded_files.len() > 1,
+        filenames: uploaded_files,
+    }))
+}
+
+/// Blobs an upload has written so far. Unless the upload is recorded and
+/// they are kept, they are deleted when this is dropped: on an error, and
+/// when the client goes away partway through.
+struct Written {
+    storage: Storage,
+    keys: Vec<String>,
+}
+
+impl Written {
+    fn new(storage: &Storage) -> Self {
+        Self {
+            storage: storage.clone(),
+            keys: Vec::new(),
+        }
+    }
+
+    fn keep(mut self) {
+        self.keys.clear();
+    }
+}
+
+impl Drop for Written {
+    fn drop(&mut self) {
+        if self.keys.is_empty() {
+            return;
+        }
+        let storage = self.storage.clone();
+        let keys = std::mem::take(&mut self.keys);
+        tokio::spawn(async move {
+            for key in keys {
+                if let Err(e) = storage.delete(&key).await {
+                    tracing::warn!("failed to delete {} after a failed upload: {}", key, e);
+                }
+            }
+        });
+    }
 }
 
 fn mime_to_extension(content_type: &str) -> Option<&str> {
@@ -120,4 +196,50 @@ fn mime_to_extension(content_type: &str) -> Option<&str> {
         "image/webp" => Some("webp"),
         _ => None,
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::api::testing::{multipart, TestApi};
+
+    #[tokio::test]
+    async fn deletes_stored_pages_when_an_upload_fails() {
+        let api = TestApi::start().await;
+        let (_, key) = api.owner("reader", false);
+        let upload = |fields: &[(&str, Option<&str>, &[u8])]| {
+            let (content_type, body) = multipart(fields);
+            api.client
+                .post(api.url("/api/v1/uploads"))
+                .bearer_auth(&key)
+                .header("content-type", content_type)
+                .body(body)
+                .send()
+        };
+
+        // The second page is rejected after the first was stored
+        let response = upload(&[
+            ("file", Some("one.png"), b"first page"),
+            ("file", Some("two.gif"), b"second page"),
+        ])
+        .await
+        .unwrap();
+        assert_eq!(response.status(), 400);
+
+        let mut left = api.storage.list("uploads/").await.unwrap();
+        for _ in 0..100 {
+            if left.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            left = api.storage.list("uploads/").await.unwrap();
+        }
+        assert!(left.is_empty(), "{:?}", left);
+
+        let response = upload(&[("file", Some("one.png"), b"first page")])
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(api.storage.list("uploads/").await.unwrap().len(), 1);
+    }
+}
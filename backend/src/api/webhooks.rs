@@ -0,0 +1,190 @@
+//! Webhook endpoints, the signing secret and the delivery log.
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{delete, get, post};
+use axum::Router;
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use super::auth::Caller;
+use super::extract::{Json, Path, Query};
+use crate::config::env::WebhookConfig;
+use crate::db::Database;
+use crate::errors::{ApiError, Result};
+use crate::models::webhook::{Delivery, DeliveryStatus, EventKind, Webhook, WebhookSecret};
+use crate::services::webhooks;
+
+const MAX_WEBHOOKS: usize = 10;
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 200;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewWebhook {
+    url: String,
+    /// Events to send; every event when omitted or empty.
+    #[serde(default)]
+    events: Vec<EventKind>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryQuery {
+    status: Option<DeliveryStatus>,
+    /// At most 200; 50 when omitted.
+    limit: Option<usize>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    responses((status = 200, body = Vec<Webhook>))
+)]
+async fn list_webhooks(State(db): State<Database>, caller: Caller) -> Result<Json<Vec<Webhook>>> {
+    Ok(Json(db.webhooks(caller.id())?))
+}
+
+/// Send the caller's events to `url`, signed with their webhook secret.
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    request_body = NewWebhook,
+    responses((status = 201, body = Webhook))
+)]
+async fn create_webhook(
+    State(db): State<Database>,
+    State(config): State<WebhookConfig>,
+    caller: Caller,
+    Json(request): Json<NewWebhook>,
+) -> Result<impl IntoResponse> {
+    webhooks::validate_url(&config, "url", &request.url)?;
+    if db.webhooks(caller.id())?.len() >= MAX_WEBHOOKS {
+        return Err(ApiError::ValidationError(format!(
+            "At most {} webhooks per account",
+            MAX_WEBHOOKS
+        )));
+    }
+    let mut events = request.events;
+    events.sort_by_key(|event| event.as_str());
+    events.dedup();
+    let webhook = db.create_webhook(caller.id(), &request.url, &events)?;
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/webhooks/{webhook_id}",
+    tag = "webhooks",
+    params(("webhook_id" = Uuid, Path)),
+    responses((status = 204, description = "Deleted"))
+)]
+async fn delete_webhook(
+    State(db): State<Database>,
+    caller: Caller,
+    Path(webhook_id): Path<Uuid>,
+) -> Result<StatusCode> {
+    if db.delete_webhook(caller.id(), &webhook_id)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(format!(
+            "Webhook not found: {}",
+            webhook_id
+        )))
+    }
+}
+
+/// The key to verify `webhook-signature` with, created on first use.
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/secret",
+    tag = "webhooks",
+    responses((status = 200, body = WebhookSecret))
+)]
+async fn get_secret(State(db): State<Database>, caller: Caller) -> Result<Json<WebhookSecret>> {
+    let secret = db.webhook_secret(caller.id(), webhooks::new_secret)?;
+    Ok(Json(WebhookSecret { secret }))
+}
+
+/// Replace the signing secret; deliveries still pending are signed with the new one.
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks/secret",
+    tag = "webhooks",
+    responses((status = 200, body = WebhookSecret))
+)]
+async fn rotate_secret(State(db): State<Database>, caller: Caller) -> Result<Json<WebhookSecret>> {
+    let secret = webhooks::new_secret();
+    db.set_webhook_secret(caller.id(), &secret)?;
+    Ok(Json(WebhookSecret { secret }))
+}
+
+/// Deliveries to the caller's webhooks and callbacks, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/deliveries",
+    tag = "webhooks",
+    params(DeliveryQuery),
+    responses((status = 200, body = Vec<Delivery>))
+)]
+async fn list_deliveries(
+    State(db): State<Database>,
+    caller: Caller,
+    Query(query): Query<DeliveryQuery>,
+) -> Result<Json<Vec<Delivery>>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    Ok(Json(db.deliveries(caller.id(), query.status, limit)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/deliveries/{delivery_id}",
+    tag = "webhooks",
+    params(("delivery_id" = Uuid, Path)),
+    responses((status = 200, body = Delivery))
+)]
+async fn get_delivery(
+    State(db): State<Database>,
+    caller: Caller,
+    Path(delivery_id): Path<Uuid>,
+) -> Result<Json<Delivery>> {
+    db.get_delivery(caller.id(), &delivery_id)?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Delivery not found: {}", delivery_id)))
+}
+
+/// Send a delivery's event again, as a new delivery with fresh retries.
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks/deliveries/{delivery_id}/redeliver",
+    tag = "webhooks",
+    params(("delivery_id" = Uuid, Path)),
+    responses((status = 202, body = Delivery))
+)]
+async fn redeliver(
+    State(db): State<Database>,
+    caller: Caller,
+    Path(delivery_id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    let delivery = db
+        .redeliver(caller.id(), &delivery_id)?
+        .ok_or_else(|| ApiError::NotFound(format!("Delivery not found: {}", delivery_id)))?;
+    webhooks::wake();
+    Ok((StatusCode::ACCEPTED, Json(delivery)))
+}
+
+pub fn routes() -> Router<super::AppState> {
+    Router::new()
+        .route("/webhooks", get(list_webhooks).post(create_webhook))
+        .route("/webhooks/:webhook_id", delete(delete_webhook))
+        .route("/webhooks/secret", get(get_secret).post(rotate_secret))
+        .route("/webhooks/deliveries", get(list_deliveries))
+        .route("/webhooks/deliveries/:delivery_id", get(get_delivery))
+        .route(
+            "/webhooks/deliveries/:delivery_id/redeliver",
+            post(redeliver),
+        )
+}
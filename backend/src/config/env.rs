@@ -1,9 +1,12 @@
 use crate::errors::{ApiError, Result};
 use serde::Deserialize;
+use std::time::Duration;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub claude_api_key: String,
+    /// `CLAUDE_API_URL`, default `https://api.anthropic.com`.
+    pub claude_api_url: String,
     // ... other config fields
 }
 
@@ -14,10 +17,289 @@ impl Config {
         let claude_api_key = std::env::var("CLAUDE_API_KEY").map_err(|_| {
             ApiError::InternalServerError(anyhow::anyhow!("CLAUDE_API_KEY not set"))
         })?;
+        let claude_api_url = std::env::var("CLAUDE_API_URL")
+            .unwrap_or_else(|_| "https://api.anthropic.com".to_string());
 
         Ok(Config {
             claude_api_key,
+            claude_api_url: claude_api_url.trim_end_matches('/').to_string(),
             // ... other fields
         })
     }
 }
+
+/// Where blobs (page images, LaTeX, PDFs) are kept, from `STORAGE_BACKEND`.
+#[derive(Debug, Clone)]
+pub enum StorageConfig {
+    /// `STORAGE_BACKEND=fs` (default): directories under `STORAGE_PATH`.
+    Filesystem { root: String },
+    /// `STORAGE_BACKEND=s3`: any S3-compatible service, addressed path-style.
+    S3 {
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key_id: String,
+        secret_access_key: String,
+        prefix: String,
+    },
+}
+
+impl StorageConfig {
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+
+        let backend = std::env::var("STORAGE_BACKEND").unwrap_or_else(|_| "fs".to_string());
+        match backend.as_str() {
+            "fs" => Ok(StorageConfig::Filesystem {
+                root: std::env::var("STORAGE_PATH").unwrap_or_else(|_| ".".to_string()),
+            }),
+            "s3" => Ok(StorageConfig::S3 {
+                endpoint: required("S3_ENDPOINT")?,
+                bucket: required("S3_BUCKET")?,
+                region: std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                access_key_id: required("S3_ACCESS_KEY_ID")?,
+                secret_access_key: required("S3_SECRET_ACCESS_KEY")?,
+                prefix: std::env::var("S3_PREFIX").unwrap_or_default(),
+            }),
+            other => Err(ApiError::InternalServerError(anyhow::anyhow!(
+                "Unknown STORAGE_BACKEND `{}`; expected `fs` or `s3`",
+                other
+            ))),
+        }
+    }
+}
+
+/// Where to find the identity provider's signing keys.
+#[derive(Debug, Clone)]
+pub enum JwksSource {
+    Url(String),
+    /// A JWKS document on disk, for testing or offline deployments.
+    Path(String),
+}
+
+/// JWT validation for OpenID Connect logins, from `JWT_*` variables.
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    /// `JWT_JWKS_URL` or `JWT_JWKS_PATH`.
+    pub jwks: JwksSource,
+    /// `JWT_ISSUER`: the `iss` tokens must carry.
+    pub issuer: String,
+    /// `JWT_AUDIENCE`: an `aud` tokens must carry, normally this app's client
+    /// ID, so tokens the provider issues to other apps are refused.
+    pub audience: String,
+    /// `JWT_ROLES_CLAIM`: dotted path to the roles, e.g. `realm_access.roles`
+    /// (default `roles`).
+    pub roles_claim: String,
+    /// `JWT_ADMIN_ROLE`: the role that makes a user an admin (default `admin`).
+    pub admin_role: String,
+}
+
+impl JwtConfig {
+    /// `None` unless a key set is configured, in which case `JWT_ISSUER` and
+    /// `JWT_AUDIENCE` are required too.
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+
+        let jwks = match (
+            std::env::var("JWT_JWKS_URL"),
+            std::env::var("JWT_JWKS_PATH"),
+        ) {
+            (Ok(url), _) => JwksSource::Url(url),
+            (_, Ok(path)) => JwksSource::Path(path),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(JwtConfig {
+            jwks,
+            issuer: required("JWT_ISSUER")?,
+            audience: required("JWT_AUDIENCE")?,
+            roles_claim: std::env::var("JWT_ROLES_CLAIM").unwrap_or_else(|_| "roles".to_string()),
+            admin_role: std::env::var("JWT_ADMIN_ROLE").unwrap_or_else(|_| "admin".to_string()),
+        }))
+    }
+}
+
+fn required(name: &str) -> Result<String> {
+    std::env::var(name)
+        .map_err(|_| ApiError::InternalServerError(anyhow::anyhow!("{} not set", name)))
+}
+
+/// How long stored artifacts are kept, from `RETENTION_*` variables.
+///
+/// Ages are in hours; an unset or zero TTL keeps that artifact type forever.
+#[derive(Debug, Clone)]
+pub struct RetentionConfig {
+    /// `RETENTION_UPLOADS_HOURS`: page images under `uploads/`.
+    pub uploads: Option<Duration>,
+    /// `RETENTION_LATEX_HOURS`: converted documents under `latex/`.
+    pub latex: Option<Duration>,
+    /// `RETENTION_PDF_HOURS`: compiled PDFs under `pdf/` (default 24).
+    pub pdf: Option<Duration>,
+    /// `RETENTION_HIGH_WATER_MB`: once the store holds more than this, the
+    /// oldest artifacts go first until it is back under 90% of it.
+    pub high_water_bytes: Option<u64>,
+    /// `RETENTION_INTERVAL_SECS`: time between janitor runs (default 3600).
+    pub interval: Duration,
+}
+
+impl RetentionConfig {
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+
+        let hours = |name: &str, default: u64| -> Result<Option<Duration>> {
+            let hours = number(name)?.unwrap_or(default);
+            Ok((hours > 0).then(|| Duration::from_secs(hours * 3600)))
+        };
+
+        Ok(RetentionConfig {
+            uploads: hours("RETENTION_UPLOADS_HOURS", 0)?,
+            latex: hours("RETENTION_LATEX_HOURS", 0)?,
+            pdf: hours("RETENTION_PDF_HOURS", 24)?,
+            high_water_bytes: number("RETENTION_HIGH_WATER_MB")?
+                .filter(|megabytes| *megabytes > 0)
+                .map(|megabytes| megabytes * 1024 * 1024),
+            interval: Duration::from_secs(
+                number("RETENTION_INTERVAL_SECS")?.unwrap_or(3600).max(1),
+            ),
+        })
+    }
+}
+
+/// Rate limits and daily quotas, from `RATE_LIMIT_*` and `QUOTA_*`
+/// variables; zero disables a limit.
+#[derive(Debug, Clone)]
+pub struct LimitsConfig {
+    /// `RATE_LIMIT_PER_MINUTE`: requests per minute for each user (default 120).
+    pub requests_per_minute: u64,
+    /// `RATE_LIMIT_IP_PER_MINUTE`: requests per minute from each IP address,
+    /// signed in or not (default 300).
+    pub ip_requests_per_minute: u64,
+    /// `QUOTA_PAGES_PER_DAY`: pages sent to the model per user (default 200).
+    pub pages_per_day: u64,
+    /// `QUOTA_COMPILE_SECONDS_PER_DAY`: PDF compile time per user (default 900).
+    pub compile_seconds_per_day: u64,
+    /// `QUOTA_IP_PAGES_PER_DAY`: pages sent to the model for requests from
+    /// each IP address, whichever users make them (default 400).
+    pub ip_pages_per_day: u64,
+    /// `QUOTA_IP_COMPILE_SECONDS_PER_DAY`: PDF compile time for requests from
+    /// each IP address, share views included (default 1800).
+    pub ip_compile_seconds_per_day: u64,
+    /// `RATE_LIMIT_CLIENT_IP`: where per-IP limits get the client's address.
+    pub client_ip: ClientIpSource,
+}
+
+/// Where the client's address comes from, which depends on what sits in
+/// front of the backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientIpSource {
+    /// `peer`: the TCP connection, for clients connecting directly. Behind a
+    /// proxy every client would share the proxy's address.
+    Peer,
+    /// `fly`: the `Fly-Client-IP` header fly.io's proxy sets. The default when
+    /// `FLY_APP_NAME` says the backend runs on fly.io.
+    FlyClientIp,
+    /// `forwarded`: the last `X-Forwarded-For` hop, the one added by the
+    /// single trusted proxy in front; earlier hops come from the client.
+    ForwardedFor,
+}
+
+impl LimitsConfig {
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+
+        Ok(LimitsConfig {
+            requests_per_minute: number("RATE_LIMIT_PER_MINUTE")?.unwrap_or(120),
+            ip_requests_per_minute: number("RATE_LIMIT_IP_PER_MINUTE")?.unwrap_or(300),
+            pages_per_day: number("QUOTA_PAGES_PER_DAY")?.unwrap_or(200),
+            compile_seconds_per_day: number("QUOTA_COMPILE_SECONDS_PER_DAY")?.unwrap_or(900),
+            ip_pages_per_day: number("QUOTA_IP_PAGES_PER_DAY")?.unwrap_or(400),
+            ip_compile_seconds_per_day: number("QUOTA_IP_COMPILE_SECONDS_PER_DAY")?.unwrap_or(1800),
+            client_ip: match std::env::var("RATE_LIMIT_CLIENT_IP").as_deref() {
+                Ok("peer") => ClientIpSource::Peer,
+                Ok("fly") => ClientIpSource::FlyClientIp,
+                Ok("forwarded") => ClientIpSource::ForwardedFor,
+                Ok(other) => {
+                    return Err(ApiError::InternalServerError(anyhow::anyhow!(
+                        "Unknown RATE_LIMIT_CLIENT_IP `{}`; expected `peer`, `fly` or `forwarded`",
+                        other
+                    )))
+                }
+                Err(_) if std::env::var_os("FLY_APP_NAME").is_some() => ClientIpSource::FlyClientIp,
+                Err(_) => ClientIpSource::Peer,
+            },
+        })
+    }
+}
+
+/// Webhook delivery, from `WEBHOOK_*` variables.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// `WEBHOOK_MAX_ATTEMPTS`: tries before a delivery is given up (default 8).
+    pub max_attempts: u32,
+    /// `WEBHOOK_RETRY_BASE_SECS`: wait after the first failure, doubling with
+    /// each one after (default 30).
+    pub retry_base: Duration,
+    /// `WEBHOOK_TIMEOUT_SECS`: how long a receiver has to answer (default 10).
+    pub timeout: Duration,
+    /// `WEBHOOK_ALLOW_PRIVATE_URLS`: accept loopback and private network
+    /// addresses, for local receivers in development.
+    pub allow_private_urls: bool,
+}
+
+impl WebhookConfig {
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+
+        Ok(WebhookConfig {
+            max_attempts: number("WEBHOOK_MAX_ATTEMPTS")?.unwrap_or(8).clamp(1, 20) as u32,
+            retry_base: Duration::from_secs(
+                number("WEBHOOK_RETRY_BASE_SECS")?.unwrap_or(30).max(1),
+            ),
+            timeout: Duration::from_secs(number("WEBHOOK_TIMEOUT_SECS")?.unwrap_or(10).max(1)),
+            allow_private_urls: std::env::var("WEBHOOK_ALLOW_PRIVATE_URLS")
+                .is_ok_and(|value| value == "true" || value == "1"),
+        })
+    }
+}
+
+/// Trace export over OTLP/HTTP, from the standard `OTEL_*` variables.
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    /// `OTEL_SERVICE_NAME` (default `noteforge`).
+    pub service_name: String,
+    /// Where spans are sent: `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`, or
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT` (e.g. `http://localhost:4318`) plus `/v1/traces`.
+    pub endpoint: String,
+}
+
+impl TelemetryConfig {
+    /// `None` unless one of the endpoints is set; the exporter reads the other
+    /// `OTEL_EXPORTER_OTLP_*` settings (headers, timeout) itself.
+    pub fn from_env() -> Option<Self> {
+        dotenv::dotenv().ok();
+
+        let endpoint = match std::env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+            Ok(endpoint) => endpoint,
+            Err(_) => format!(
+                "{}/v1/traces",
+                std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                    .ok()?
+                    .trim_end_matches('/')
+            ),
+        };
+        Some(TelemetryConfig {
+            service_name: std::env::var("OTEL_SERVICE_NAME")
+                .unwrap_or_else(|_| "noteforge".to_string()),
+            endpoint,
+        })
+    }
+}
+
+fn number(name: &str) -> Result<Option<u64>> {
+    match std::env::var(name) {
+        Ok(value) => value.trim().parse().map(Some).map_err(|_| {
+            ApiError::InternalServerError(anyhow::anyhow!("{} must be a whole number", name))
+        }),
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,130 @@
+//! Records for files written before the database existed.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use chrono::Utc;
+
+use uuid::Uuid;
+
+use super::Database;
+use crate::errors::Result;
+use crate::models::document::{Document, DocumentOrigin};
+use crate::models::provenance::{PageOrigin, Provenance};
+use crate::services::storage::Storage;
+use crate::services::styles;
+
+impl Database {
+    /// Add a record for every document under `latex/` and `uploads/` that
+    /// has none, returning how many were added.
+    pub async fn backfill(&self, storage: &Storage) -> Result<usize> {
+        let known: HashSet<Uuid> = self.with(|connection| {
+            let mut statement = connection.prepare("SELECT id FROM documents")?;
+            let ids = statement
+                .query_map([], |row| row.get::<_, String>(0))?
+                .filter_map(|id| id.ok()?.parse().ok())
+                .collect();
+            Ok(ids)
+        })?;
+
+        let mut images = upload_images(storage.list("uploads/").await?);
+        let mut added = 0;
+
+        for key in storage.list("latex/").await? {
+            let Some(id) = key
+                .strip_prefix("latex/")
+                .and_then(|name| name.strip_suffix(".tex"))
+                .and_then(|id| id.parse::<Uuid>().ok())
+            else {
+                continue;
+            };
+            if known.contains(&id) {
+                continue;
+            }
+            let Ok(content) = String::from_utf8(storage.get(&key).await?) else {
+                continue;
+            };
+            // Stores have no reliable creation time, so order by discovery
+            let document = Document {
+                id,
+                filename: format!("{}.tex", id),
+                style: styles::detect(&content).name.to_string(),
+                content,
+                created_at: Utc::now(),
+            };
+
+            let images = images.remove(&id).unwrap_or_default();
+            let (origin, pages) = match sidecar(&id) {
+                // Written by merge and split before page origins moved here
+                Some(provenance) => {
+                    let sources: HashSet<Uuid> =
+                        provenance.pages.iter().map(|page| page.document).collect();
+                    let origin = if sources.len() > 1 {
+                        DocumentOrigin::Merge
+                    } else {
+                        DocumentOrigin::Split
+                    };
+                    (origin, provenance.pages)
+                }
+                None if !images.is_empty() => (DocumentOrigin::Upload, own_pages(&id, &images)),
+                None => (DocumentOrigin::Import, Vec::new()),
+            };
+            let pages = (!pages.is_empty()).then_some(pages.as_slice());
+            self.save_document(&document, origin, pages, None)?;
+            added += 1;
+        }
+
+        // Uploads that were never converted
+        for (id, images) in images {
+            if !known.contains(&id) {
+                self.record_upload(&id, None, &images)?;
+                added += 1;
+            }
+        }
+
+        Ok(added)
+    }
+}
+
+/// Page image filenames per upload, in page order.
+fn upload_images(keys: Vec<String>) -> BTreeMap<Uuid, Vec<String>> {
+    let mut uploads: BTreeMap<Uuid, Vec<(usize, String)>> = BTreeMap::new();
+    for key in keys {
+        let Some(name) = key.strip_prefix("uploads/").map(str::to_string) else {
+            continue;
+        };
+        let stem = name.split('.').next().unwrap_or_default();
+        // `{id}.ext` for a single page, `{id}_{n}.ext` for multi-page uploads
+        let (id, index) = match stem.split_once('_') {
+            Some((id, index)) => (id, index.parse().ok()),
+            None => (stem, Some(0)),
+        };
+        if let (Ok(id), Some(index)) = (id.parse(), index) {
+            uploads.entry(id).or_default().push((index, name));
+        }
+    }
+    uploads
+        .into_iter()
+        .map(|(id, mut pages)| {
+            pages.sort();
+            (id, pages.into_iter().map(|(_, name)| name).collect())
+        })
+        .collect()
+}
+
+fn own_pages(id: &Uuid, images: &[String]) -> Vec<PageOrigin> {
+    images
+        .iter()
+        .enumerate()
+        .map(|(index, image)| PageOrigin {
+            document: *id,
+            page: index + 1,
+            image: Some(image.clone()),
+        })
+        .collect()
+}
+
+fn sidecar(id: &Uuid) -> Option<Provenance> {
+    let data = std::fs::read(Path::new("provenance").join(format!("{}.json", id))).ok()?;
+    serde_json::from_slice(&data).ok()
+}
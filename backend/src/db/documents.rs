@@ -0,0 +1,375 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, params_from_iter, OptionalExtension, Row, ToSql};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use super::{parse_uuid, Database};
+use crate::errors::Result;
+use crate::models::document::{Document, DocumentOrigin, DocumentStatus, DocumentSummary};
+use crate::models::provenance::PageOrigin;
+use crate::services::{bundle, latex, parser};
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 200;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    Title,
+    Filename,
+    PageCount,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Filters, ordering and paging for [`Database::list_documents`].
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DocumentQuery {
+    pub status: Option<DocumentStatus>,
+    pub origin: Option<DocumentOrigin>,
+    pub style: Option<String>,
+    pub owner: Option<Uuid>,
+    /// Case-insensitive match on title or filename.
+    pub q: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub sort: SortKey,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+impl DocumentQuery {
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+
+const SUMMARY_COLUMNS: &str = "id, owner_id, filename, title, style, status, origin, \
+    page_count, revision, created_at, updated_at";
+
+impl Database {
+    /// Record freshly uploaded page images, in page order.
+    pub fn record_upload(&self, id: &Uuid, owner: Option<&Uuid>, images: &[String]) -> Result<()> {
+        let now = Utc::now();
+        self.with(|connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "INSERT INTO documents
+                     (id, owner_id, filename, status, origin, page_count, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)",
+                params![
+                    id.to_string(),
+                    owner.map(Uuid::to_string),
+                    format!("{}.tex", id),
+                    DocumentStatus::Uploaded,
+                    DocumentOrigin::Upload,
+                    images.len(),
+                    now
+                ],
+            )?;
+            let pages: Vec<PageOrigin> = images
+                .iter()
+                .enumerate()
+                .map(|(index, image)| PageOrigin {
+                    document: *id,
+                    page: index + 1,
+                    image: Some(image.clone()),
+                })
+                .collect();
+            insert_pages(&transaction, id, &pages)?;
+            transaction.commit()
+        })
+    }
+
+    /// Create or update the metadata for a document whose LaTeX was just stored.
+    ///
+    /// `pages` replaces the recorded page origins; without it, a document
+    /// that has none yet is recorded as its own origin. `owner` only applies
+    /// to new documents.
+    pub fn save_document(
+        &self,
+        document: &Document,
+        origin: DocumentOrigin,
+        pages: Option<&[PageOrigin]>,
+        owner: Option<&Uuid>,
+    ) -> Result<()> {
+        let id = document.id.to_string();
+        let title = parser::parse(&document.content).title;
+        let page_count = latex::split_pages(&document.content).len();
+        let revision = bundle::revision(&document.content);
+        let now = Utc::now();
+
+        self.with(|connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "INSERT INTO documents
+                     (id, filename, title, style, status, origin, page_count, revision, created_at, updated_at, owner_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                 ON CONFLICT (id) DO UPDATE SET
+                     title = excluded.title,
+                     style = excluded.style,
+                     status = excluded.status,
+                     page_count = excluded.page_count,
+                     revision = excluded.revision,
+                     updated_at = excluded.updated_at",
+                params![
+                    id,
+                    document.filename,
+                    title,
+                    document.style,
+                    DocumentStatus::Converted,
+                    origin,
+                    page_count,
+                    revision,
+                    document.created_at,
+                    now,
+                    owner.map(Uuid::to_string)
+                ],
+            )?;
+
+            // A new revision only when the LaTeX actually changed
+            let latest: Option<String> = transaction
+                .query_row(
+                    "SELECT revision FROM revisions WHERE document_id = ?1
+                     ORDER BY number DESC LIMIT 1",
+                    [&id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if latest.as_deref() != Some(revision.as_str()) {
+                transaction.execute(
+                    "INSERT INTO revisions (document_id, number, revision, style, size, created_at)
+                     SELECT ?1, COALESCE(MAX(number), 0) + 1, ?2, ?3, ?4, ?5
+                     FROM revisions WHERE document_id = ?1",
+                    params![id, revision, document.style, document.content.len(), now],
+                )?;
+            }
+
+            let recorded: usize = transaction.query_row(
+                "SELECT COUNT(*) FROM pages WHERE document_id = ?1",
+                [&id],
+                |row| row.get(0),
+            )?;
+            match pages {
+                Some(pages) => {
+                    transaction.execute("DELETE FROM pages WHERE document_id = ?1", [&id])?;
+                    insert_pages(&transaction, &document.id, pages)?;
+                }
+                None if recorded == 0 => {
+                    let pages: Vec<PageOrigin> = (1..=page_count)
+                        .map(|page| PageOrigin {
+                            document: document.id,
+                            page,
+                            image: None,
+                        })
+                        .collect();
+                    insert_pages(&transaction, &document.id, &pages)?;
+                }
+                None => {}
+            }
+
+            transaction.commit()
+        })
+    }
+
+    pub fn get_document(&self, id: &Uuid) -> Result<Option<DocumentSummary>> {
+        self.with(|connection| {
+            connection
+                .query_row(
+                    &format!("SELECT {} FROM documents WHERE id = ?1", SUMMARY_COLUMNS),
+                    [id.to_string()],
+                    summary,
+                )
+                .optional()
+        })
+    }
+
+    /// One page of documents matching `query`, and how many match in total.
+    pub fn list_documents(&self, query: &DocumentQuery) -> Result<(Vec<DocumentSummary>, usize)> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+        let mut condition = |sql: &str, value: Box<dyn ToSql>| {
+            values.push(value);
+            conditions.push(sql.replace('?', &format!("?{}", values.len())));
+        };
+
+        if let Some(status) = query.status {
+            condition("status = ?", Box::new(status));
+        }
+        if let Some(origin) = query.origin {
+            condition("origin = ?", Box::new(origin));
+        }
+        if let Some(style) = &query.style {
+            condition("style = ?", Box::new(style.clone()));
+        }
+        if let Some(owner) = &query.owner {
+            condition("owner_id = ?", Box::new(owner.to_string()));
+        }
+        if let Some(text) = query.q.as_deref().filter(|text| !text.trim().is_empty()) {
+            let pattern = format!(
+                "%{}%",
+                text.trim()
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            );
+            condition(
+                "(title LIKE ? ESCAPE '\\' OR filename LIKE ? ESCAPE '\\')",
+                Box::new(pattern),
+            );
+        }
+        if let Some(after) = query.created_after {
+            condition("created_at >= ?", Box::new(after));
+        }
+        if let Some(before) = query.created_before {
+            condition("created_at < ?", Box::new(before));
+        }
+
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let column = match query.sort {
+            SortKey::CreatedAt => "created_at",
+            SortKey::UpdatedAt => "updated_at",
+            SortKey::Title => "title COLLATE NOCASE",
+            SortKey::Filename => "filename",
+            SortKey::PageCount => "page_count",
+        };
+        let order = match query.order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+
+        self.with(|connection| {
+            let total: usize = connection.query_row(
+                &format!("SELECT COUNT(*) FROM documents {}", filter),
+                params_from_iter(values.iter()),
+                |row| row.get(0),
+            )?;
+
+            // The id tie-break keeps paging stable when sort values repeat
+            let sql = format!(
+                "SELECT {} FROM documents {} ORDER BY {} {}, id {} LIMIT {} OFFSET {}",
+                SUMMARY_COLUMNS,
+                filter,
+                column,
+                order,
+                order,
+                query.limit(),
+                query.offset
+            );
+            let mut statement = connection.prepare(&sql)?;
+            let documents = statement
+                .query_map(params_from_iter(values.iter()), summary)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok((documents, total))
+        })
+    }
+
+    /// Remove a document's record, with its pages, revisions and jobs.
+    pub fn delete_document(&self, id: &Uuid) -> Result<bool> {
+        self.with(|connection| {
+            connection
+                .execute("DELETE FROM documents WHERE id = ?1", [id.to_string()])
+                .map(|deleted| deleted > 0)
+        })
+    }
+
+    /// Record that a document's LaTeX is gone but its page images remain.
+    pub fn mark_uploaded(&self, id: &Uuid) -> Result<()> {
+        self.with(|connection| {
+            connection.execute(
+                "UPDATE documents SET status = ?2, revision = NULL, updated_at = ?3 WHERE id = ?1",
+                params![id.to_string(), DocumentStatus::Uploaded, Utc::now()],
+            )
+        })?;
+        Ok(())
+    }
+
+    pub fn created_at(&self, id: &Uuid) -> Result<Option<DateTime<Utc>>> {
+        self.with(|connection| {
+            connection
+                .query_row(
+                    "SELECT created_at FROM documents WHERE id = ?1",
+                    [id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+    }
+
+    /// Where each page of a document came from, in page order.
+    pub fn pages(&self, id: &Uuid) -> Result<Vec<PageOrigin>> {
+        self.with(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT image, source_document, source_page FROM pages
+                 WHERE document_id = ?1 ORDER BY number",
+            )?;
+            let pages = statement
+                .query_map([id.to_string()], |row| {
+                    Ok(PageOrigin {
+                        image: row.get(0)?,
+                        document: parse_uuid(row, 1)?,
+                        page: row.get(2)?,
+                    })
+                })?
+                .collect();
+            pages
+        })
+    }
+}
+
+fn insert_pages(
+    transaction: &rusqlite::Transaction,
+    id: &Uuid,
+    pages: &[PageOrigin],
+) -> rusqlite::Result<()> {
+    let mut statement = transaction.prepare(
+        "INSERT INTO pages (document_id, number, image, source_document, source_page)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    for (index, page) in pages.iter().enumerate() {
+        statement.execute(params![
+            id.to_string(),
+            index + 1,
+            page.image,
+            page.document.to_string(),
+            page.page
+        ])?;
+    }
+    Ok(())
+}
+
+fn summary(row: &Row) -> rusqlite::Result<DocumentSummary> {
+    let owner_id: Option<String> = row.get(1)?;
+    Ok(DocumentSummary {
+        id: parse_uuid(row, 0)?,
+        owner_id: owner_id.and_then(|owner| owner.parse().ok()),
+        filename: row.get(2)?,
+        title: row.get(3)?,
+        style: row.get(4)?,
+        status: row.get(5)?,
+        origin: row.get(6)?,
+        page_count: row.get(7)?,
+        revision: row.get(8)?,
+        created_at: row.get(9)?,
+        updated_at: row.get(10)?,
+    })
+}
@@ -0,0 +1,168 @@
+use chrono::Utc;
+use rusqlite::{params, OptionalExtension, Row};
+use uuid::Uuid;
+
+use super::{parse_uuid, Database};
+use crate::errors::{ApiError, ErrorCode, Result};
+use crate::models::job::{Job, JobKind, JobStatus};
+
+impl Database {
+    pub fn start_job(&self, document_id: &Uuid, kind: JobKind) -> Result<Uuid> {
+        let id = Uuid::new_v4();
+        self.with(|connection| {
+            connection.execute(
+                "INSERT INTO jobs (id, document_id, kind, status, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    id.to_string(),
+                    document_id.to_string(),
+                    kind,
+                    JobStatus::Running,
+                    Utc::now()
+                ],
+            )
+        })?;
+        Ok(id)
+    }
+
+    /// Mark a job as done, failed if `error` is given. Only the error's code
+    /// and client-safe detail are kept; the full message goes to the log.
+    pub fn finish_job(&self, id: &Uuid, error: Option<&ApiError>) -> Result<()> {
+        let status = if let Some(e) = error {
+            tracing::warn!("job {} failed: {}", id, e);
+            JobStatus::Failed
+        } else {
+            JobStatus::Succeeded
+        };
+        self.with(|connection| {
+            connection.execute(
+                "UPDATE jobs SET status = ?2, error_code = ?3, error = ?4, finished_at = ?5
+                 WHERE id = ?1",
+                params![
+                    id.to_string(),
+                    status,
+                    error.map(ApiError::code),
+                    error.map(ApiError::detail),
+                    Utc::now()
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Jobs for a document, newest first.
+    pub fn jobs(&self, document_id: &Uuid) -> Result<Vec<Job>> {
+        self.with(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT id, document_id, kind, status, error_code, error, created_at, finished_at
+                 FROM jobs WHERE document_id = ?1 ORDER BY created_at DESC",
+            )?;
+            let jobs = statement
+                .query_map([document_id.to_string()], job)?
+                .collect();
+            jobs
+        })
+    }
+
+    pub fn get_job(&self, id: &Uuid) -> Result<Option<Job>> {
+        self.with(|connection| {
+            connection
+                .query_row(
+                    "SELECT id, document_id, kind, status, error_code, error, created_at, finished_at
+                     FROM jobs WHERE id = ?1",
+                    [id.to_string()],
+                    job,
+                )
+                .optional()
+        })
+    }
+
+    /// How many jobs of each kind are running.
+    pub fn running_jobs(&self) -> Result<Vec<(String, u64)>> {
+        self.with(|connection| {
+            let mut statement = connection
+                .prepare("SELECT kind, COUNT(*) FROM jobs WHERE status = ?1 GROUP BY kind")?;
+            let running = statement
+                .query_map([JobStatus::Running], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect();
+            running
+        })
+    }
+
+    /// Jobs still marked running were cut short by a restart.
+    pub(super) fn fail_interrupted_jobs(&self) -> Result<usize> {
+        self.with(|connection| {
+            connection.execute(
+                "UPDATE jobs SET status = ?1, error_code = ?2,
+                     error = 'Interrupted by a server restart', finished_at = ?3
+                 WHERE status = ?4",
+                params![
+                    JobStatus::Failed,
+                    ErrorCode::InternalError,
+                    Utc::now(),
+                    JobStatus::Running
+                ],
+            )
+        })
+    }
+}
+
+fn job(row: &Row) -> rusqlite::Result<Job> {
+    Ok(Job {
+        id: parse_uuid(row, 0)?,
+        document_id: parse_uuid(row, 1)?,
+        kind: row.get(2)?,
+        status: row.get(3)?,
+        error_code: row.get(4)?,
+        error: row.get(5)?,
+        created_at: row.get(6)?,
+        finished_at: row.get(7)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::document::{Document, DocumentOrigin};
+
+    fn document(db: &Database) -> Uuid {
+        let document = Document {
+            id: Uuid::new_v4(),
+            filename: "notes.png".to_string(),
+            content: String::new(),
+            style: "article".to_string(),
+            created_at: Utc::now(),
+        };
+        db.save_document(&document, DocumentOrigin::Upload, None, None)
+            .unwrap();
+        document.id
+    }
+
+    #[test]
+    fn keeps_only_the_client_safe_part_of_errors() {
+        let db = Database::open(":memory:").unwrap();
+        let document = document(&db);
+        let finish = |error: ApiError| {
+            let job = db.start_job(&document, JobKind::Convert).unwrap();
+            db.finish_job(&job, Some(&error)).unwrap();
+            db.get_job(&job).unwrap().unwrap()
+        };
+
+        let job = finish(ApiError::ClaudeError(
+            "401 from upstream: key sk-ant-...".into(),
+        ));
+        assert_eq!(job.status, JobStatus::Failed);
+        assert_eq!(job.error_code, Some(ErrorCode::ModelError));
+        assert_eq!(job.error.as_deref(), Some(ErrorCode::ModelError.title()));
+
+        let job = finish(ApiError::LaTeXError("Undefined control sequence".into()));
+        assert_eq!(job.error_code, Some(ErrorCode::CompileFailed));
+        assert_eq!(job.error.as_deref(), Some("Undefined control sequence"));
+
+        let job = db.start_job(&document, JobKind::Pdf).unwrap();
+        db.finish_job(&job, None).unwrap();
+        let job = db.get_job(&job).unwrap().unwrap();
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!((job.error_code, job.error), (None, None));
+    }
+}
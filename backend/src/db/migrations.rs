@@ -0,0 +1,170 @@
+use rusqlite::Connection;
+
+/// Schema changes in order; `PRAGMA user_version` records how many have run.
+///
+/// Never edit a migration that has shipped: append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE owners (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE documents (
+        id TEXT PRIMARY KEY,
+        owner_id TEXT REFERENCES owners (id) ON DELETE SET NULL,
+        filename TEXT NOT NULL,
+        title TEXT,
+        style TEXT,
+        status TEXT NOT NULL,
+        origin TEXT NOT NULL,
+        page_count INTEGER NOT NULL DEFAULT 0,
+        revision TEXT,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE INDEX documents_owner ON documents (owner_id);
+    CREATE INDEX documents_created_at ON documents (created_at);
+
+    CREATE TABLE pages (
+        document_id TEXT NOT NULL REFERENCES documents (id) ON DELETE CASCADE,
+        number INTEGER NOT NULL,
+        image TEXT,
+        source_document TEXT NOT NULL,
+        source_page INTEGER NOT NULL,
+        PRIMARY KEY (document_id, number)
+    );
+
+    CREATE TABLE revisions (
+        document_id TEXT NOT NULL REFERENCES documents (id) ON DELETE CASCADE,
+        number INTEGER NOT NULL,
+        revision TEXT NOT NULL,
+        style TEXT,
+        size INTEGER NOT NULL,
+        created_at TEXT NOT NULL,
+        PRIMARY KEY (document_id, number)
+    );
+
+    CREATE TABLE jobs (
+        id TEXT PRIMARY KEY,
+        document_id TEXT NOT NULL REFERENCES documents (id) ON DELETE CASCADE,
+        kind TEXT NOT NULL,
+        status TEXT NOT NULL,
+        error TEXT,
+        created_at TEXT NOT NULL,
+        finished_at TEXT
+    );
+    CREATE INDEX jobs_document ON jobs (document_id, created_at);",
+    // 2: API keys
+    "ALTER TABLE owners ADD COLUMN admin INTEGER NOT NULL DEFAULT 0;
+
+    CREATE TABLE api_keys (
+        id TEXT PRIMARY KEY,
+        owner_id TEXT NOT NULL REFERENCES owners (id) ON DELETE CASCADE,
+        name TEXT NOT NULL,
+        prefix TEXT NOT NULL,
+        hash TEXT NOT NULL UNIQUE,
+        created_at TEXT NOT NULL,
+        last_used_at TEXT,
+        revoked_at TEXT
+    );
+    CREATE INDEX api_keys_owner ON api_keys (owner_id);",
+    // 3: identity provider users
+    "ALTER TABLE owners ADD COLUMN subject TEXT;
+    CREATE UNIQUE INDEX owners_subject ON owners (subject);",
+    // 4: share links
+    "CREATE TABLE shares (
+        id TEXT PRIMARY KEY,
+        document_id TEXT NOT NULL REFERENCES documents (id) ON DELETE CASCADE,
+        owner_id TEXT REFERENCES owners (id) ON DELETE CASCADE,
+        format TEXT NOT NULL,
+        password_hash TEXT,
+        expires_at TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        revoked_at TEXT,
+        views INTEGER NOT NULL DEFAULT 0,
+        last_viewed_at TEXT
+    );
+    CREATE INDEX shares_document ON shares (document_id, created_at);
+
+    CREATE TABLE settings (
+        name TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    // 5: daily usage for quotas
+    "CREATE TABLE usage (
+        owner_id TEXT NOT NULL REFERENCES owners (id) ON DELETE CASCADE,
+        day TEXT NOT NULL,
+        pages INTEGER NOT NULL DEFAULT 0,
+        compile_ms INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (owner_id, day)
+    );",
+    // 6: webhooks
+    "ALTER TABLE owners ADD COLUMN webhook_secret TEXT;
+
+    CREATE TABLE webhooks (
+        id TEXT PRIMARY KEY,
+        owner_id TEXT NOT NULL REFERENCES owners (id) ON DELETE CASCADE,
+        url TEXT NOT NULL,
+        events TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX webhooks_owner ON webhooks (owner_id);
+
+    CREATE TABLE webhook_deliveries (
+        id TEXT PRIMARY KEY,
+        owner_id TEXT NOT NULL REFERENCES owners (id) ON DELETE CASCADE,
+        webhook_id TEXT REFERENCES webhooks (id) ON DELETE SET NULL,
+        event_id TEXT NOT NULL,
+        event TEXT NOT NULL,
+        url TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        response_status INTEGER,
+        error TEXT,
+        next_attempt_at TEXT,
+        created_at TEXT NOT NULL,
+        delivered_at TEXT
+    );
+    CREATE INDEX webhook_deliveries_owner ON webhook_deliveries (owner_id, created_at);
+    CREATE INDEX webhook_deliveries_due ON webhook_deliveries (status, next_attempt_at);",
+    // 7: job errors as a code and a client-safe detail; older messages may
+    // carry upstream responses, so only compile errors keep their text
+    "ALTER TABLE jobs ADD COLUMN error_code TEXT;
+
+    UPDATE jobs SET
+        error_code = CASE
+            WHEN error LIKE 'LaTeX conversion error: %' THEN 'compile_failed'
+            ELSE 'internal_error'
+        END,
+        error = CASE
+            WHEN error LIKE 'LaTeX conversion error: %' THEN substr(error, 25)
+            WHEN error = 'Interrupted by a server restart' THEN error
+            ELSE 'Internal server error'
+        END
+    WHERE status = 'failed';",
+    // 8: daily usage by client address, for per-IP quotas
+    "CREATE TABLE ip_usage (
+        ip TEXT NOT NULL,
+        day TEXT NOT NULL,
+        pages INTEGER NOT NULL DEFAULT 0,
+        compile_ms INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (ip, day)
+    );",
+];
+
+pub fn run(connection: &mut Connection) -> rusqlite::Result<()> {
+    let applied: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+        tracing::info!("applied database migration {}", index + 1);
+    }
+
+    Ok(())
+}
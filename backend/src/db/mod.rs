@@ -0,0 +1,206 @@
+//! Embedded SQLite store for document metadata.
+//!
+//! Page images, LaTeX sources and PDFs live in the configured
+//! [`BlobStore`](crate::services::storage::BlobStore) under `uploads/`,
+//! `latex/` and `pdf/`; the database records what exists, who owns it, where
+//! each page came from and what has been done with it, along with API keys,
+//! jobs, shares, usage and webhooks.
+//!
+//! The database is a local file, so only one replica can serve a given
+//! `DATABASE_PATH`: a second one would not know about documents, keys or
+//! shares created through the first. Sharing an S3 bucket does not change that.
+
+mod backfill;
+mod documents;
+mod jobs;
+mod migrations;
+mod owners;
+mod shares;
+mod usage;
+mod webhooks;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{Connection, Row, ToSql};
+use tokio::runtime::{Handle, RuntimeFlavor};
+use uuid::Uuid;
+
+use crate::errors::{ApiError, ErrorCode, Result};
+use crate::models::document::{DocumentOrigin, DocumentStatus};
+use crate::models::job::{JobKind, JobStatus};
+use crate::models::webhook::{DeliveryStatus, EventKind};
+
+pub use documents::DocumentQuery;
+pub use webhooks::{Attempt, Outgoing};
+
+/// Used when `DATABASE_PATH` is not set.
+pub const DEFAULT_PATH: &str = "noteforge.db";
+
+/// Shared handle to the metadata database.
+#[derive(Clone)]
+pub struct Database {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    /// Open (creating if needed) the database at `path` and bring its schema
+    /// up to date.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut connection = Connection::open(path).map_err(database_error)?;
+        connection
+            .execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")
+            .map_err(database_error)?;
+        migrations::run(&mut connection).map_err(database_error)?;
+
+        let database = Self {
+            connection: Arc::new(Mutex::new(connection)),
+        };
+        let interrupted = database.fail_interrupted_jobs()?;
+        if interrupted > 0 {
+            tracing::warn!("marked {} interrupted jobs as failed", interrupted);
+        }
+        Ok(database)
+    }
+
+    /// Open the database named by `DATABASE_PATH`, or [`DEFAULT_PATH`].
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("DATABASE_PATH").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+        Self::open(path)
+    }
+
+    /// Run a trivial query, to check the database answers.
+    pub fn ping(&self) -> Result<()> {
+        self.with(|connection| connection.query_row("SELECT 1", [], |_| Ok(())))
+    }
+
+    /// Run `f` on the connection. Waiting for the lock and for SQLite's I/O
+    /// blocks the thread, so on a multi-threaded runtime the worker's other
+    /// tasks are handed to another thread for the duration.
+    fn with<T>(&self, f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>) -> Result<T> {
+        let run = || {
+            let mut connection = self
+                .connection
+                .lock()
+                .map_err(|_| ApiError::DatabaseError("Database connection poisoned".to_string()))?;
+            f(&mut connection).map_err(database_error)
+        };
+        match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(run)
+            }
+            _ => run(),
+        }
+    }
+}
+
+fn database_error(error: rusqlite::Error) -> ApiError {
+    ApiError::DatabaseError(error.to_string())
+}
+
+fn parse_uuid(row: &Row, index: usize) -> rusqlite::Result<Uuid> {
+    let text: String = row.get(index)?;
+    text.parse().map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+/// Store a fieldless enum as its serde name.
+macro_rules! text_enum {
+    ($type:ty { $($variant:ident => $text:literal),* $(,)? }) => {
+        impl ToSql for $type {
+            fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+                Ok(ToSqlOutput::from(match self {
+                    $(<$type>::$variant => $text,)*
+                }))
+            }
+        }
+
+        impl FromSql for $type {
+            fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+                match value.as_str()? {
+                    $($text => Ok(<$type>::$variant),)*
+                    other => Err(FromSqlError::Other(
+                        format!("unknown {} `{}`", stringify!($type), other).into(),
+                    )),
+                }
+            }
+        }
+    };
+}
+
+impl ToSql for ErrorCode {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ErrorCode {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        ErrorCode::parse(text)
+            .ok_or_else(|| FromSqlError::Other(format!("unknown ErrorCode `{}`", text).into()))
+    }
+}
+
+text_enum!(DocumentStatus {
+    Uploaded => "uploaded",
+    Converted => "converted",
+});
+text_enum!(DocumentOrigin {
+    Upload => "upload",
+    Import => "import",
+    Merge => "merge",
+    Split => "split",
+});
+text_enum!(JobKind {
+    Convert => "convert",
+    Pdf => "pdf",
+});
+text_enum!(JobStatus {
+    Running => "running",
+    Succeeded => "succeeded",
+    Failed => "failed",
+});
+text_enum!(EventKind {
+    ConversionSucceeded => "conversion.succeeded",
+    ConversionFailed => "conversion.failed",
+    PdfReady => "pdf.ready",
+    DocumentDeleted => "document.deleted",
+});
+text_enum!(DeliveryStatus {
+    Pending => "pending",
+    Succeeded => "succeeded",
+    Failed => "failed",
+});
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    /// A query that waits on another task can only finish if that task gets
+    /// to run while the query holds the runtime's only worker thread.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn queries_do_not_stall_other_tasks() {
+        let db = Database::open(":memory:").unwrap();
+        let (started, query_started) = oneshot::channel();
+        let (answer, answered) = mpsc::channel();
+        let query = tokio::spawn(async move {
+            db.with(|_| {
+                started.send(()).unwrap();
+                Ok(answered.recv_timeout(Duration::from_secs(10)).is_ok())
+            })
+            .unwrap()
+        });
+
+        query_started.await.unwrap();
+        tokio::spawn(async move { answer.send(()).unwrap() });
+        assert!(query.await.unwrap(), "the query blocked the worker thread");
+    }
+}
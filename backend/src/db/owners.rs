@@ -0,0 +1,237 @@
+use chrono::Utc;
+use rusqlite::{params, OptionalExtension, Row};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::{parse_uuid, Database};
+use crate::errors::Result;
+use crate::models::owner::{ApiKey, Identity, IssuedKey, Owner};
+
+/// Marks noteforge keys, so leaked ones are easy to spot.
+const KEY_PREFIX: &str = "nf_";
+
+/// Characters of a key kept in the clear for display.
+const DISPLAY_LENGTH: usize = 12;
+
+impl Database {
+    pub fn create_owner(&self, name: &str, admin: bool) -> Result<Owner> {
+        let owner = Owner {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            admin,
+            subject: None,
+            created_at: Utc::now(),
+        };
+        self.with(|connection| {
+            connection.execute(
+                "INSERT INTO owners (id, name, admin, created_at) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    owner.id.to_string(),
+                    owner.name,
+                    owner.admin,
+                    owner.created_at
+                ],
+            )
+        })?;
+        Ok(owner)
+    }
+
+    pub fn owners(&self) -> Result<Vec<Owner>> {
+        self.with(|connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM owners ORDER BY created_at",
+                OWNER_COLUMNS
+            ))?;
+            let owners = statement.query_map([], owner)?.collect();
+            owners
+        })
+    }
+
+    /// Issue a new key for `owner_id`.
+    pub fn create_api_key(&self, owner_id: &Uuid, name: &str) -> Result<IssuedKey> {
+        // Two v4 UUIDs give 244 random bits
+        let secret = format!(
+            "{}{}{}",
+            KEY_PREFIX,
+            Uuid::new_v4().simple(),
+            Uuid::new_v4().simple()
+        );
+        let key = self.insert_api_key(owner_id, name, &secret)?;
+        Ok(IssuedKey { key, secret })
+    }
+
+    fn insert_api_key(&self, owner_id: &Uuid, name: &str, secret: &str) -> Result<ApiKey> {
+        let key = ApiKey {
+            id: Uuid::new_v4(),
+            owner_id: *owner_id,
+            name: name.to_string(),
+            prefix: secret.chars().take(DISPLAY_LENGTH).collect(),
+            created_at: Utc::now(),
+            last_used_at: None,
+        };
+        self.with(|connection| {
+            connection.execute(
+                "INSERT INTO api_keys (id, owner_id, name, prefix, hash, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    key.id.to_string(),
+                    owner_id.to_string(),
+                    key.name,
+                    key.prefix,
+                    hash(secret),
+                    key.created_at
+                ],
+            )
+        })?;
+        Ok(key)
+    }
+
+    /// An owner's keys that have not been revoked, newest first.
+    pub fn api_keys(&self, owner_id: &Uuid) -> Result<Vec<ApiKey>> {
+        self.with(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT id, owner_id, name, prefix, created_at, last_used_at FROM api_keys
+                 WHERE owner_id = ?1 AND revoked_at IS NULL ORDER BY created_at DESC",
+            )?;
+            let keys = statement
+                .query_map([owner_id.to_string()], |row| {
+                    Ok(ApiKey {
+                        id: parse_uuid(row, 0)?,
+                        owner_id: parse_uuid(row, 1)?,
+                        name: row.get(2)?,
+                        prefix: row.get(3)?,
+                        created_at: row.get(4)?,
+                        last_used_at: row.get(5)?,
+                    })
+                })?
+                .collect();
+            keys
+        })
+    }
+
+    /// Revoke one of an owner's keys, returning whether it was live.
+    pub fn revoke_api_key(&self, owner_id: &Uuid, key_id: &Uuid) -> Result<bool> {
+        self.with(|connection| {
+            connection
+                .execute(
+                    "UPDATE api_keys SET revoked_at = ?3
+                     WHERE id = ?1 AND owner_id = ?2 AND revoked_at IS NULL",
+                    params![key_id.to_string(), owner_id.to_string(), Utc::now()],
+                )
+                .map(|revoked| revoked > 0)
+        })
+    }
+
+    /// The owner of a live key, recording that the key was used.
+    pub fn authenticate(&self, secret: &str) -> Result<Option<Owner>> {
+        let hash = hash(secret);
+        self.with(|connection| {
+            let owner = connection
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM api_keys JOIN owners ON owners.id = api_keys.owner_id
+                         WHERE api_keys.hash = ?1 AND api_keys.revoked_at IS NULL",
+                        OWNER_COLUMNS
+                    ),
+                    [&hash],
+                    owner,
+                )
+                .optional()?;
+            if owner.is_some() {
+                connection.execute(
+                    "UPDATE api_keys SET last_used_at = ?2 WHERE hash = ?1",
+                    params![hash, Utc::now()],
+                )?;
+            }
+            Ok(owner)
+        })
+    }
+
+    /// The owner for an identity provider user, created on first sign-in.
+    ///
+    /// Name and admin rights follow the latest token, so role changes at
+    /// the provider take effect on the next request.
+    pub fn sign_in(&self, identity: &Identity) -> Result<Owner> {
+        self.with(|connection| {
+            connection.execute(
+                "INSERT INTO owners (id, name, admin, subject, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (subject) DO UPDATE SET name = excluded.name, admin = excluded.admin",
+                params![
+                    Uuid::new_v4().to_string(),
+                    identity.name,
+                    identity.admin,
+                    identity.subject,
+                    Utc::now()
+                ],
+            )?;
+            connection.query_row(
+                &format!("SELECT {} FROM owners WHERE subject = ?1", OWNER_COLUMNS),
+                [&identity.subject],
+                owner,
+            )
+        })
+    }
+
+    /// Make sure `secret` is an admin key, creating an admin owner for it if
+    /// it is unknown. Returns whether anything was created.
+    pub fn bootstrap_admin(&self, secret: &str) -> Result<bool> {
+        let known: bool = self.with(|connection| {
+            connection.query_row(
+                "SELECT EXISTS (SELECT 1 FROM api_keys WHERE hash = ?1)",
+                [hash(secret)],
+                |row| row.get(0),
+            )
+        })?;
+        if known {
+            return Ok(false);
+        }
+        let owner = self.create_owner("admin", true)?;
+        self.insert_api_key(&owner.id, "bootstrap", secret)?;
+        Ok(true)
+    }
+
+    pub fn has_api_keys(&self) -> Result<bool> {
+        self.with(|connection| {
+            connection.query_row(
+                "SELECT EXISTS (SELECT 1 FROM api_keys WHERE revoked_at IS NULL)",
+                [],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    /// `None` if the document is unknown, otherwise its owner, if any.
+    pub fn document_owner(&self, id: &Uuid) -> Result<Option<Option<Uuid>>> {
+        self.with(|connection| {
+            connection
+                .query_row(
+                    "SELECT owner_id FROM documents WHERE id = ?1",
+                    [id.to_string()],
+                    |row| row.get::<_, Option<String>>(0),
+                )
+                .optional()
+        })
+        .map(|owner| owner.map(|owner| owner.and_then(|owner| owner.parse().ok())))
+    }
+}
+
+fn hash(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+const OWNER_COLUMNS: &str =
+    "owners.id, owners.name, owners.admin, owners.subject, owners.created_at";
+
+fn owner(row: &Row) -> rusqlite::Result<Owner> {
+    Ok(Owner {
+        id: parse_uuid(row, 0)?,
+        name: row.get(1)?,
+        admin: row.get(2)?,
+        subject: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
@@ -0,0 +1,131 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension, Row};
+use uuid::Uuid;
+
+use super::{parse_uuid, Database};
+use crate::errors::Result;
+use crate::models::share::Share;
+
+const SHARE_COLUMNS: &str = "id, document_id, format, password_hash IS NOT NULL, expires_at, \
+    created_at, revoked_at, views, last_viewed_at";
+
+impl Database {
+    pub fn create_share(
+        &self,
+        document_id: &Uuid,
+        owner_id: &Uuid,
+        format: &str,
+        password_hash: Option<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Share> {
+        let share = Share {
+            id: Uuid::new_v4(),
+            document_id: *document_id,
+            format: format.to_string(),
+            protected: password_hash.is_some(),
+            expires_at,
+            created_at: Utc::now(),
+            revoked_at: None,
+            views: 0,
+            last_viewed_at: None,
+        };
+        self.with(|connection| {
+            connection.execute(
+                "INSERT INTO shares
+                     (id, document_id, owner_id, format, password_hash, expires_at, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    share.id.to_string(),
+                    document_id.to_string(),
+                    owner_id.to_string(),
+                    share.format,
+                    password_hash,
+                    share.expires_at,
+                    share.created_at
+                ],
+            )
+        })?;
+        Ok(share)
+    }
+
+    /// A share and its password hash, if it has one.
+    pub fn get_share(&self, id: &Uuid) -> Result<Option<(Share, Option<String>)>> {
+        self.with(|connection| {
+            connection
+                .query_row(
+                    &format!(
+                        "SELECT {}, password_hash FROM shares WHERE id = ?1",
+                        SHARE_COLUMNS
+                    ),
+                    [id.to_string()],
+                    |row| Ok((share(row)?, row.get(9)?)),
+                )
+                .optional()
+        })
+    }
+
+    /// Shares of a document, newest first.
+    pub fn shares(&self, document_id: &Uuid) -> Result<Vec<Share>> {
+        self.with(|connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM shares WHERE document_id = ?1 ORDER BY created_at DESC",
+                SHARE_COLUMNS
+            ))?;
+            let shares = statement
+                .query_map([document_id.to_string()], share)?
+                .collect();
+            shares
+        })
+    }
+
+    /// Revoke a share, returning whether it was live.
+    pub fn revoke_share(&self, id: &Uuid) -> Result<bool> {
+        self.with(|connection| {
+            connection
+                .execute(
+                    "UPDATE shares SET revoked_at = ?2 WHERE id = ?1 AND revoked_at IS NULL",
+                    params![id.to_string(), Utc::now()],
+                )
+                .map(|revoked| revoked > 0)
+        })
+    }
+
+    pub fn record_share_view(&self, id: &Uuid) -> Result<()> {
+        self.with(|connection| {
+            connection.execute(
+                "UPDATE shares SET views = views + 1, last_viewed_at = ?2 WHERE id = ?1",
+                params![id.to_string(), Utc::now()],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// A named server setting, created with `initial` the first time it is read.
+    pub fn setting(&self, name: &str, initial: impl FnOnce() -> String) -> Result<String> {
+        self.with(|connection| {
+            connection.execute(
+                "INSERT INTO settings (name, value) VALUES (?1, ?2) ON CONFLICT (name) DO NOTHING",
+                params![name, initial()],
+            )?;
+            connection.query_row(
+                "SELECT value FROM settings WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+        })
+    }
+}
+
+fn share(row: &Row) -> rusqlite::Result<Share> {
+    Ok(Share {
+        id: parse_uuid(row, 0)?,
+        document_id: parse_uuid(row, 1)?,
+        format: row.get(2)?,
+        protected: row.get(3)?,
+        expires_at: row.get(4)?,
+        created_at: row.get(5)?,
+        revoked_at: row.get(6)?,
+        views: row.get(7)?,
+        last_viewed_at: row.get(8)?,
+    })
+}
@@ -0,0 +1,98 @@
+use chrono::NaiveDate;
+use rusqlite::{params, OptionalExtension};
+
+use super::Database;
+use crate::errors::Result;
+use crate::models::usage::{Payer, Usage};
+
+/// The table a payer's usage is kept in, its key column and its key.
+fn meter(payer: &Payer) -> (&'static str, &'static str, String) {
+    match payer {
+        Payer::Owner(id) => ("usage", "owner_id", id.to_string()),
+        Payer::Ip(ip) => ("ip_usage", "ip", ip.to_string()),
+    }
+}
+
+impl Database {
+    pub fn usage(&self, payer: &Payer, day: NaiveDate) -> Result<Usage> {
+        let (table, column, key) = meter(payer);
+        self.with(|connection| {
+            connection
+                .query_row(
+                    &format!(
+                        "SELECT pages, compile_ms FROM {table} WHERE {column} = ?1 AND day = ?2"
+                    ),
+                    params![key, day],
+                    |row| {
+                        Ok(Usage {
+                            pages: row.get(0)?,
+                            compile_ms: row.get(1)?,
+                        })
+                    },
+                )
+                .optional()
+        })
+        .map(Option::unwrap_or_default)
+    }
+
+    /// Add `pages` to a day's usage unless that would take it past `limit`,
+    /// returning whether it did. Concurrent reservations can't both squeeze
+    /// under the limit.
+    pub fn reserve_pages(
+        &self,
+        payer: &Payer,
+        day: NaiveDate,
+        pages: u64,
+        limit: u64,
+    ) -> Result<bool> {
+        let (table, column, key) = meter(payer);
+        self.with(|connection| {
+            connection.execute(
+                &format!(
+                    "INSERT INTO {table} ({column}, day, pages, compile_ms)
+                     SELECT ?1, ?2, ?3, 0 WHERE ?3 <= ?4
+                     ON CONFLICT ({column}, day) DO UPDATE SET pages = pages + excluded.pages
+                     WHERE {table}.pages + excluded.pages <= ?4"
+                ),
+                params![key, day, pages, limit],
+            )
+        })
+        .map(|changed| changed > 0)
+    }
+
+    /// Give back pages reserved for work that failed.
+    pub fn release_pages(&self, payer: &Payer, day: NaiveDate, pages: u64) -> Result<()> {
+        let (table, column, key) = meter(payer);
+        self.with(|connection| {
+            connection.execute(
+                &format!(
+                    "UPDATE {table} SET pages = MAX(pages - ?3, 0) WHERE {column} = ?1 AND day = ?2"
+                ),
+                params![key, day, pages],
+            )
+        })?;
+        Ok(())
+    }
+
+    pub fn add_usage(&self, payer: &Payer, day: NaiveDate, usage: Usage) -> Result<()> {
+        let (table, column, key) = meter(payer);
+        self.with(|connection| {
+            connection.execute(
+                &format!(
+                    "INSERT INTO {table} ({column}, day, pages, compile_ms) VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT ({column}, day) DO UPDATE SET
+                         pages = pages + excluded.pages,
+                         compile_ms = compile_ms + excluded.compile_ms"
+                ),
+                params![key, day, usage.pages, usage.compile_ms],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Drop per-IP usage from before `day`. Owners' usage is kept as their
+    /// history; addresses have none worth keeping.
+    pub fn prune_ip_usage(&self, day: NaiveDate) -> Result<usize> {
+        self.with(|connection| connection.execute("DELETE FROM ip_usage WHERE day < ?1", [day]))
+    }
+}
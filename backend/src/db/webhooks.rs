@@ -0,0 +1,297 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension, Row};
+use uuid::Uuid;
+
+use super::{parse_uuid, Database};
+use crate::errors::Result;
+use crate::models::webhook::{Delivery, DeliveryStatus, EventKind, Webhook};
+
+const DELIVERY_COLUMNS: &str = "id, event_id, event, webhook_id, url, status, attempts, \
+    response_status, error, next_attempt_at, created_at, delivered_at, payload";
+
+/// A delivery that is due, with what is needed to send it.
+#[derive(Debug)]
+pub struct Outgoing {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub event: EventKind,
+    pub url: String,
+    pub payload: String,
+    pub attempts: u32,
+    pub secret: String,
+}
+
+/// The outcome of one attempt at a delivery.
+#[derive(Debug)]
+pub struct Attempt {
+    pub status: DeliveryStatus,
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+    /// When to retry, for deliveries still pending.
+    pub next_attempt_at: Option<DateTime<Utc>>,
+}
+
+impl Database {
+    pub fn create_webhook(
+        &self,
+        owner_id: &Uuid,
+        url: &str,
+        events: &[EventKind],
+    ) -> Result<Webhook> {
+        let webhook = Webhook {
+            id: Uuid::new_v4(),
+            url: url.to_string(),
+            events: events.to_vec(),
+            created_at: Utc::now(),
+        };
+        self.with(|connection| {
+            connection.execute(
+                "INSERT INTO webhooks (id, owner_id, url, events, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    webhook.id.to_string(),
+                    owner_id.to_string(),
+                    webhook.url,
+                    join_events(events),
+                    webhook.created_at
+                ],
+            )
+        })?;
+        Ok(webhook)
+    }
+
+    /// An owner's webhooks, oldest first.
+    pub fn webhooks(&self, owner_id: &Uuid) -> Result<Vec<Webhook>> {
+        self.with(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT id, url, events, created_at FROM webhooks
+                 WHERE owner_id = ?1 ORDER BY created_at",
+            )?;
+            let webhooks = statement
+                .query_map([owner_id.to_string()], |row| {
+                    let events: String = row.get(2)?;
+                    Ok(Webhook {
+                        id: parse_uuid(row, 0)?,
+                        url: row.get(1)?,
+                        events: events.split(',').filter_map(EventKind::parse).collect(),
+                        created_at: row.get(3)?,
+                    })
+                })?
+                .collect();
+            webhooks
+        })
+    }
+
+    pub fn delete_webhook(&self, owner_id: &Uuid, id: &Uuid) -> Result<bool> {
+        self.with(|connection| {
+            connection
+                .execute(
+                    "DELETE FROM webhooks WHERE id = ?1 AND owner_id = ?2",
+                    params![id.to_string(), owner_id.to_string()],
+                )
+                .map(|deleted| deleted > 0)
+        })
+    }
+
+    /// An owner's signing secret, created with `initial` the first time it is read.
+    pub fn webhook_secret(
+        &self,
+        owner_id: &Uuid,
+        initial: impl FnOnce() -> String,
+    ) -> Result<String> {
+        self.with(|connection| {
+            connection.execute(
+                "UPDATE owners SET webhook_secret = ?2 WHERE id = ?1 AND webhook_secret IS NULL",
+                params![owner_id.to_string(), initial()],
+            )?;
+            connection.query_row(
+                "SELECT webhook_secret FROM owners WHERE id = ?1",
+                [owner_id.to_string()],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    pub fn set_webhook_secret(&self, owner_id: &Uuid, secret: &str) -> Result<()> {
+        self.with(|connection| {
+            connection.execute(
+                "UPDATE owners SET webhook_secret = ?2 WHERE id = ?1",
+                params![owner_id.to_string(), secret],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Queue one event for each `(webhook, url)` target.
+    pub fn queue_deliveries(
+        &self,
+        owner_id: &Uuid,
+        event_id: &Uuid,
+        event: EventKind,
+        payload: &str,
+        targets: &[(Option<Uuid>, String)],
+    ) -> Result<()> {
+        let now = Utc::now();
+        self.with(|connection| {
+            let transaction = connection.transaction()?;
+            for (webhook_id, url) in targets {
+                transaction.execute(
+                    "INSERT INTO webhook_deliveries
+                         (id, owner_id, webhook_id, event_id, event, url, payload, status,
+                          next_attempt_at, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9)",
+                    params![
+                        Uuid::new_v4().to_string(),
+                        owner_id.to_string(),
+                        webhook_id.map(|id| id.to_string()),
+                        event_id.to_string(),
+                        event,
+                        url,
+                        payload,
+                        DeliveryStatus::Pending,
+                        now
+                    ],
+                )?;
+            }
+            transaction.commit()
+        })
+    }
+
+    /// Pending deliveries whose next attempt is due, oldest first.
+    pub fn due_deliveries(&self, limit: usize) -> Result<Vec<Outgoing>> {
+        self.with(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT d.id, d.event_id, d.event, d.url, d.payload, d.attempts, o.webhook_secret
+                 FROM webhook_deliveries d JOIN owners o ON o.id = d.owner_id
+                 WHERE d.status = ?1 AND d.next_attempt_at <= ?2 AND o.webhook_secret IS NOT NULL
+                 ORDER BY d.next_attempt_at LIMIT ?3",
+            )?;
+            let due = statement
+                .query_map(params![DeliveryStatus::Pending, Utc::now(), limit], |row| {
+                    Ok(Outgoing {
+                        id: parse_uuid(row, 0)?,
+                        event_id: parse_uuid(row, 1)?,
+                        event: row.get(2)?,
+                        url: row.get(3)?,
+                        payload: row.get(4)?,
+                        attempts: row.get(5)?,
+                        secret: row.get(6)?,
+                    })
+                })?
+                .collect();
+            due
+        })
+    }
+
+    pub fn record_attempt(&self, id: &Uuid, attempt: &Attempt) -> Result<()> {
+        let now = Utc::now();
+        let delivered_at = (attempt.status == DeliveryStatus::Succeeded).then_some(now);
+        self.with(|connection| {
+            connection.execute(
+                "UPDATE webhook_deliveries
+                 SET attempts = attempts + 1, status = ?2, response_status = ?3, error = ?4,
+                     next_attempt_at = ?5, delivered_at = ?6
+                 WHERE id = ?1",
+                params![
+                    id.to_string(),
+                    attempt.status,
+                    attempt.response_status,
+                    attempt.error,
+                    attempt.next_attempt_at,
+                    delivered_at
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// An owner's deliveries, newest first.
+    pub fn deliveries(
+        &self,
+        owner_id: &Uuid,
+        status: Option<DeliveryStatus>,
+        limit: usize,
+    ) -> Result<Vec<Delivery>> {
+        self.with(|connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM webhook_deliveries
+                 WHERE owner_id = ?1 AND (?2 IS NULL OR status = ?2)
+                 ORDER BY created_at DESC LIMIT ?3",
+                DELIVERY_COLUMNS
+            ))?;
+            let deliveries = statement
+                .query_map(params![owner_id.to_string(), status, limit], delivery)?
+                .collect();
+            deliveries
+        })
+    }
+
+    pub fn get_delivery(&self, owner_id: &Uuid, id: &Uuid) -> Result<Option<Delivery>> {
+        self.with(|connection| {
+            connection
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM webhook_deliveries WHERE id = ?1 AND owner_id = ?2",
+                        DELIVERY_COLUMNS
+                    ),
+                    params![id.to_string(), owner_id.to_string()],
+                    delivery,
+                )
+                .optional()
+        })
+    }
+
+    /// Queue a delivery's event again, to the same URL, as a new delivery.
+    pub fn redeliver(&self, owner_id: &Uuid, id: &Uuid) -> Result<Option<Delivery>> {
+        let copy = Uuid::new_v4();
+        let copied = self.with(|connection| {
+            connection.execute(
+                "INSERT INTO webhook_deliveries
+                     (id, owner_id, webhook_id, event_id, event, url, payload, status,
+                      next_attempt_at, created_at)
+                 SELECT ?1, owner_id, webhook_id, event_id, event, url, payload, ?2, ?3, ?3
+                 FROM webhook_deliveries WHERE id = ?4 AND owner_id = ?5",
+                params![
+                    copy.to_string(),
+                    DeliveryStatus::Pending,
+                    Utc::now(),
+                    id.to_string(),
+                    owner_id.to_string()
+                ],
+            )
+        })?;
+        if copied == 0 {
+            return Ok(None);
+        }
+        self.get_delivery(owner_id, &copy)
+    }
+}
+
+fn join_events(events: &[EventKind]) -> String {
+    events
+        .iter()
+        .map(|event| event.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn delivery(row: &Row) -> rusqlite::Result<Delivery> {
+    let payload: String = row.get(12)?;
+    Ok(Delivery {
+        id: parse_uuid(row, 0)?,
+        event_id: parse_uuid(row, 1)?,
+        event: row.get(2)?,
+        webhook_id: row
+            .get::<_, Option<String>>(3)?
+            .and_then(|id| id.parse().ok()),
+        url: row.get(4)?,
+        status: row.get(5)?,
+        attempts: row.get(6)?,
+        response_status: row.get(7)?,
+        error: row.get(8)?,
+        next_attempt_at: row.get(9)?,
+        created_at: row.get(10)?,
+        delivered_at: row.get(11)?,
+        payload: serde_json::from_str(&payload).unwrap_or_default(),
+    })
+}
@@ -1,8 +1,12 @@
-use axum::http::StatusCode;
+use axum::http::header::{CONTENT_TYPE, RETRY_AFTER, WWW_AUTHENTICATE};
+use axum::http::HeaderValue;
 use axum::response::{IntoResponse, Response};
-use axum::Json;
-use serde_json::json;
+use serde::Serialize;
 use thiserror::Error;
+use utoipa::ToSchema;
+
+use super::ErrorCode;
+use crate::utils::request_id;
 
 #[derive(Error, Debug)]
 pub enum ApiError {
@@ -18,15 +22,36 @@ pub enum ApiError {
     #[error("Invalid input: {0}")]
     ValidationError(String),
 
+    /// One input field, named as the client sent it, is invalid.
+    #[error("Invalid input: {field}: {message}")]
+    InvalidField { field: String, message: String },
+
+    /// A malformed upload or multipart body.
     #[error("File error: {0}")]
     FileError(String),
 
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    /// Reading or writing blob storage failed on our side.
+    #[error("Storage error: {0}")]
+    StorageError(String),
+
     #[error("Claude API error: {0}")]
     ClaudeError(String),
 
+    /// The document did not compile; the message carries the engine's errors,
+    /// without the rest of its log.
     #[error("LaTeX conversion error: {0}")]
     LaTeXError(String),
 
+    #[error("Too many requests: {message}")]
+    TooManyRequests {
+        message: String,
+        /// Seconds until the limit resets.
+        retry_after: u64,
+    },
+
     #[error("Database error: {0}")]
     DatabaseError(String),
 
@@ -34,35 +59,122 @@ pub enum ApiError {
     InternalServerError(#[from] anyhow::Error),
 }
 
+impl ApiError {
+    pub fn invalid_field(field: impl Into<String>, message: impl Into<String>) -> Self {
+        ApiError::InvalidField {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::AuthenticationError => ErrorCode::AuthenticationRequired,
+            ApiError::AuthorizationError => ErrorCode::Forbidden,
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::ValidationError(_) | ApiError::InvalidField { .. } => {
+                ErrorCode::ValidationFailed
+            }
+            ApiError::FileError(_) => ErrorCode::InvalidUpload,
+            ApiError::PayloadTooLarge(_) => ErrorCode::PayloadTooLarge,
+            ApiError::StorageError(_) => ErrorCode::StorageError,
+            ApiError::ClaudeError(_) => ErrorCode::ModelError,
+            ApiError::LaTeXError(_) => ErrorCode::CompileFailed,
+            ApiError::TooManyRequests { .. } => ErrorCode::RateLimited,
+            ApiError::DatabaseError(_) => ErrorCode::DatabaseError,
+            ApiError::InternalServerError(_) => ErrorCode::InternalError,
+        }
+    }
+
+    /// What the client is told; internal failures only get their title.
+    pub fn detail(&self) -> String {
+        match self {
+            _ if self.code().is_internal() => self.code().title().to_string(),
+            ApiError::NotFound(message)
+            | ApiError::ValidationError(message)
+            | ApiError::FileError(message)
+            | ApiError::PayloadTooLarge(message)
+            | ApiError::LaTeXError(message)
+            | ApiError::TooManyRequests { message, .. } => message.clone(),
+            ApiError::InvalidField { field, message } => format!("{}: {}", field, message),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// One rejected input, in a problem's `errors`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FieldError<'a> {
+    field: &'a str,
+    message: &'a str,
+}
+
+/// An RFC 7807 problem details body, with `code` and `request_id` extensions.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Problem<'a> {
+    /// `urn:noteforge:error:{code}`.
+    #[serde(rename = "type")]
+    kind: String,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    code: ErrorCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError<'a>>,
+}
+
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            ApiError::AuthenticationError => (StatusCode::UNAUTHORIZED, self.to_string()),
-            ApiError::AuthorizationError => (StatusCode::FORBIDDEN, self.to_string()),
-            ApiError::NotFound(ref message) => (StatusCode::NOT_FOUND, message.to_owned()),
-            ApiError::ValidationError(ref message) => (StatusCode::BAD_REQUEST, message.to_owned()),
-            ApiError::FileError(ref message) => (StatusCode::BAD_REQUEST, message.to_owned()),
-            ApiError::ClaudeError(ref message) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, message.to_owned())
-            }
-            ApiError::LaTeXError(ref message) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, message.to_owned())
-            }
-            ApiError::DatabaseError(ref message) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, message.to_owned())
-            }
-            ApiError::InternalServerError(ref e) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-            }
+        let code = self.code();
+        let status = code.status();
+        let request_id = request_id::current();
+
+        if code.is_internal() {
+            let message = match &self {
+                // Keep the whole anyhow chain
+                ApiError::InternalServerError(e) => format!("{:#}", e),
+                e => e.to_string(),
+            };
+            tracing::error!(
+                request_id = request_id.as_deref().unwrap_or("-"),
+                code = code.as_str(),
+                "{}",
+                message
+            );
+        }
+
+        let errors = match &self {
+            ApiError::InvalidField { field, message } => vec![FieldError { field, message }],
+            _ => Vec::new(),
         };
+        let problem = Problem {
+            kind: format!("urn:noteforge:error:{}", code.as_str()),
+            title: code.title(),
+            status: status.as_u16(),
+            detail: self.detail(),
+            code,
+            request_id,
+            errors,
+        };
+        let body = serde_json::to_vec(&problem).unwrap_or_default();
 
-        let body = Json(json!({
-            "error": {
-                "message": error_message,
-                "code": status.as_u16()
+        let mut response = (status, body).into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        match &self {
+            ApiError::TooManyRequests { retry_after, .. } => {
+                headers.insert(RETRY_AFTER, HeaderValue::from(*retry_after));
             }
-        }));
-
-        (status, body).into_response()
+            ApiError::AuthenticationError => {
+                headers.insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            }
+            _ => {}
+        }
+        response
     }
 }
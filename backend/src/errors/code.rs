@@ -0,0 +1,107 @@
+use axum::http::StatusCode;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Declares [`ErrorCode`] with each code's text given once, for serde, the
+/// API docs, [`ErrorCode::as_str`] and the database.
+macro_rules! error_codes {
+    ($(#[$meta:meta])* $($variant:ident => $text:literal),* $(,)?) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+        pub enum ErrorCode {
+            $(#[serde(rename = $text)] #[schema(rename = $text)] $variant,)*
+        }
+
+        impl ErrorCode {
+            #[cfg(test)]
+            const ALL: &[ErrorCode] = &[$(ErrorCode::$variant),*];
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$variant => $text,)*
+                }
+            }
+
+            pub fn parse(text: &str) -> Option<Self> {
+                match text {
+                    $($text => Some(ErrorCode::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// Stable, machine-readable error codes; clients should branch on these
+    /// rather than on messages, which may change.
+    AuthenticationRequired => "authentication_required",
+    Forbidden => "forbidden",
+    NotFound => "not_found",
+    ValidationFailed => "validation_failed",
+    InvalidUpload => "invalid_upload",
+    PayloadTooLarge => "payload_too_large",
+    CompileFailed => "compile_failed",
+    RateLimited => "rate_limited",
+    ModelError => "model_error",
+    StorageError => "storage_error",
+    DatabaseError => "database_error",
+    InternalError => "internal_error",
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::AuthenticationRequired => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::ValidationFailed | ErrorCode::InvalidUpload => StatusCode::BAD_REQUEST,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            // The request was fine; the document in it does not compile
+            ErrorCode::CompileFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::ModelError => StatusCode::BAD_GATEWAY,
+            ErrorCode::StorageError | ErrorCode::DatabaseError | ErrorCode::InternalError => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// Short summary for the problem `title`; the same for every occurrence.
+    pub fn title(self) -> &'static str {
+        match self {
+            ErrorCode::AuthenticationRequired => "Authentication required",
+            ErrorCode::Forbidden => "Not allowed",
+            ErrorCode::NotFound => "Not found",
+            ErrorCode::ValidationFailed => "Invalid request",
+            ErrorCode::InvalidUpload => "Invalid upload",
+            ErrorCode::PayloadTooLarge => "Payload too large",
+            ErrorCode::CompileFailed => "PDF compilation failed",
+            ErrorCode::RateLimited => "Too many requests",
+            ErrorCode::ModelError => "Transcription service error",
+            ErrorCode::StorageError => "Storage error",
+            ErrorCode::DatabaseError => "Database error",
+            ErrorCode::InternalError => "Internal server error",
+        }
+    }
+
+    /// Server-side failures, whose details are logged rather than returned.
+    pub fn is_internal(self) -> bool {
+        self.status().is_server_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_each_code_the_same_everywhere() {
+        for code in ErrorCode::ALL {
+            let json = serde_json::to_value(code).unwrap();
+            assert_eq!(json, code.as_str());
+            assert_eq!(ErrorCode::parse(code.as_str()), Some(*code));
+        }
+        assert_eq!(ErrorCode::parse("NotFound"), None);
+    }
+}
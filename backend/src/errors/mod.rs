@@ -1,5 +1,7 @@
 mod api_error;
+mod code;
 
-pub use api_error::ApiError;
+pub use api_error::{ApiError, Problem};
+pub use code::ErrorCode;
 
 pub type Result<T> = std::result::Result<T, ApiError>;
@@ -1,5 +1,6 @@
 mod api;
 mod config;
+mod db;
 mod errors;
 mod models;
 mod services;
@@ -8,34 +9,92 @@ mod utils;
 use std::net::SocketAddr;
 
 use axum::Router;
-use http::header::{AUTHORIZATION, CONTENT_TYPE};
-use http::Method;
+use http::header::{AUTHORIZATION, CONTENT_TYPE, LINK};
+use http::{HeaderName, Method};
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
-
-    // Create necessary directories
-    for dir in ["uploads", "latex", "pdf"] {
-        tokio::fs::create_dir_all(dir)
-            .await
-            .expect(&format!("Failed to create {} directory", dir));
-    }
+    let telemetry = services::telemetry::init(config::env::TelemetryConfig::from_env())
+        .expect("Failed to set up tracing");
 
     // Load environment variables
     dotenv::dotenv().ok();
 
+    let storage = config::env::StorageConfig::from_env()
+        .and_then(|config| services::storage::from_config(&config))
+        .expect("Failed to configure storage");
+    let db = db::Database::from_env().expect("Failed to open database");
+    match db.backfill(&storage).await {
+        Ok(0) => {}
+        Ok(added) => tracing::info!("recorded {} existing documents", added),
+        Err(e) => tracing::warn!("failed to record existing documents: {}", e),
+    }
+
+    let retention =
+        config::env::RetentionConfig::from_env().expect("Failed to read retention settings");
+    services::janitor::Janitor::new(db.clone(), storage.clone(), retention).spawn();
+    let webhooks = config::env::WebhookConfig::from_env().expect("Failed to read webhook settings");
+    services::webhooks::Dispatcher::new(db.clone(), webhooks.clone())
+        .expect("Failed to set up webhook delivery")
+        .spawn();
+
+    if let Ok(key) = std::env::var("ADMIN_API_KEY") {
+        if db
+            .bootstrap_admin(&key)
+            .expect("Failed to create admin key")
+        {
+            tracing::info!("created admin owner for ADMIN_API_KEY");
+        }
+    } else if !db.has_api_keys().unwrap_or(true) {
+        tracing::warn!("no API keys exist; set ADMIN_API_KEY to create one");
+    }
+
+    let jwt = match config::env::JwtConfig::from_env().expect("Failed to read JWT settings") {
+        Some(config) => Some(services::jwt::JwtVerifier::new(config).await),
+        None => None,
+    };
+
+    let shares = services::share::ShareSigner::from_env(&db).expect("Failed to set up share links");
+
+    let limits = services::limits::Limits::new(
+        config::env::LimitsConfig::from_env().expect("Failed to read rate limits"),
+    );
+
+    let model = match config::env::Config::from_env() {
+        Ok(config) => Some(config),
+        Err(_) => {
+            tracing::warn!("CLAUDE_API_KEY is not set; conversions will fail");
+            None
+        }
+    };
+
+    let health = services::health::Health::new(db.clone(), storage.clone(), model.clone());
+
+    let state = api::AppState {
+        db,
+        storage,
+        jwt,
+        shares,
+        limits,
+        health,
+        model,
+        metrics_token: api::MetricsToken::from_env(),
+        webhooks,
+    };
     let app = Router::new()
-        .merge(api::routes())
+        .merge(api::routes(state.clone()))
+        .with_state(state)
         .layer(
             CorsLayer::new()
                 .allow_origin([
                     "http://localhost:5173".parse().unwrap(),
                     "http://localhost:3000".parse().unwrap(),
                     "https://noteforge-nu.vercel.app".parse().unwrap(),
-                    "https://noteforge-2oepmnj85-g4titans-projects.vercel.app".parse().unwrap(),
+                    "https://noteforge-2oepmnj85-g4titans-projects.vercel.app"
+                        .parse()
+                        .unwrap(),
                 ])
                 .allow_methods([
                     Method::GET,
@@ -44,14 +103,53 @@ async fn main() {
                     Method::DELETE,
                     Method::OPTIONS,
                 ])
-                .allow_headers([CONTENT_TYPE, AUTHORIZATION])
+                .allow_headers([
+                    CONTENT_TYPE,
+                    AUTHORIZATION,
+                    HeaderName::from_static("x-share-password"),
+                ])
+                .expose_headers([
+                    HeaderName::from_static("x-request-id"),
+                    HeaderName::from_static("deprecation"),
+                    LINK,
+                ])
                 .allow_credentials(true),
         )
         .layer(TraceLayer::new_for_http());
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
     tracing::info!("listening on {}", addr);
-    axum::serve(tokio::net::TcpListener::bind(addr).await.unwrap(), app)
-        .await
-        .unwrap();
+    axum::serve(
+        tokio::net::TcpListener::bind(addr).await.unwrap(),
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .unwrap();
+
+    telemetry.shutdown();
+}
+
+/// Resolves on Ctrl-C or SIGTERM, so buffered traces are flushed on the way out.
+async fn shutdown_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl-C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {},
+        _ = terminate => {},
+    }
+    tracing::info!("shutting down");
 }
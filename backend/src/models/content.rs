@@ -0,0 +1,72 @@
+use serde::Serialize;
+
+/// Structured view of a stored LaTeX document, shared by the exporters.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Content {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Heading {
+        level: u8,
+        text: Vec<Inline>,
+    },
+    Paragraph(Vec<Inline>),
+    Math {
+        kind: MathKind,
+        tex: String,
+    },
+    List {
+        ordered: bool,
+        items: Vec<ListItem>,
+    },
+    /// Theorem-like and other text environments (`theorem`, `proof`, `solution`, ...).
+    Environment {
+        name: String,
+        title: Option<Vec<Inline>>,
+        blocks: Vec<Block>,
+    },
+    Code {
+        language: Option<String>,
+        text: String,
+    },
+    /// An environment no exporter knows how to translate, kept verbatim.
+    Unsupported {
+        name: String,
+        raw: String,
+    },
+    PageBreak,
+}
+
+/// How the rows of a display equation relate to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MathKind {
+    /// A single equation (`equation`, `\[ \]`, `$$ $$`).
+    Single,
+    /// Rows aligned on `&` (`align`, `eqnarray`, ...).
+    Aligned,
+    /// Rows centred independently (`gather`, `multline`).
+    Gathered,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ListItem {
+    pub label: Option<Vec<Inline>>,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Inline {
+    Text(String),
+    Math(String),
+    Emph(Vec<Inline>),
+    Strong(Vec<Inline>),
+    Code(String),
+    LineBreak,
+}
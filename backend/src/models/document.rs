@@ -1,11 +1,50 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct Document {
     pub id: Uuid,
     pub filename: String,
     pub content: String,
+    pub style: String,
     pub created_at: DateTime<Utc>,
 }
+
+/// Whether a document has been through the model yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentStatus {
+    /// Page images only, waiting for `/convert`.
+    Uploaded,
+    /// LaTeX is stored under `latex/`.
+    Converted,
+}
+
+/// How a document came to exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentOrigin {
+    Upload,
+    Import,
+    Merge,
+    Split,
+}
+
+/// Stored metadata for a document, without its LaTeX.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DocumentSummary {
+    pub id: Uuid,
+    pub owner_id: Option<Uuid>,
+    pub filename: String,
+    pub title: Option<String>,
+    pub style: Option<String>,
+    pub status: DocumentStatus,
+    pub origin: DocumentOrigin,
+    pub page_count: usize,
+    /// SHA-256 of the current LaTeX, as in a bundle's manifest.
+    pub revision: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
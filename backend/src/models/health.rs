@@ -0,0 +1,55 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Up,
+    Down,
+    /// The dependency could not say, e.g. free space on object storage.
+    Unknown,
+}
+
+/// The state of one dependency. What was found and what went wrong are
+/// logged, not served.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Check {
+    pub status: CheckStatus,
+    /// Whether the service is unready while this is down.
+    pub required: bool,
+    /// What was found, e.g. an engine's version or the bytes free.
+    #[serde(skip)]
+    pub detail: Option<serde_json::Value>,
+    #[serde(skip)]
+    pub error: Option<String>,
+}
+
+/// Every dependency check, keyed by name.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Readiness {
+    pub ready: bool,
+    pub version: &'static str,
+    pub checks: BTreeMap<&'static str, Check>,
+}
+
+impl Check {
+    pub fn up(required: bool, detail: Option<serde_json::Value>) -> Self {
+        Self {
+            status: CheckStatus::Up,
+            required,
+            detail,
+            error: None,
+        }
+    }
+
+    pub fn down(required: bool, error: impl Into<String>) -> Self {
+        Self {
+            status: CheckStatus::Down,
+            required,
+            detail: None,
+            error: Some(error.into()),
+        }
+    }
+}
@@ -1 +1,10 @@
+pub mod content;
 pub mod document;
+pub mod health;
+pub mod job;
+pub mod owner;
+pub mod page;
+pub mod provenance;
+pub mod share;
+pub mod usage;
+pub mod webhook;
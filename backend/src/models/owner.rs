@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Someone who owns documents and authenticates with API keys or, when
+/// `subject` is set, identity provider tokens.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Owner {
+    pub id: Uuid,
+    pub name: String,
+    /// Admins can see every document and create owners.
+    pub admin: bool,
+    /// The identity provider's `sub` claim.
+    pub subject: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A user as described by a validated identity provider token.
+#[derive(Debug)]
+pub struct Identity {
+    pub subject: String,
+    pub name: String,
+    pub admin: bool,
+}
+
+/// An API key's metadata; the key itself is only stored hashed.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    pub name: String,
+    /// The first characters of the key, to tell keys apart.
+    pub prefix: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// A newly created key, the only time the secret is shown.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IssuedKey {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    pub secret: String,
+}
@@ -0,0 +1,14 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::provenance::PageOrigin;
+
+/// One page of a document's LaTeX, with where it came from.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Page {
+    /// 1-based.
+    pub number: usize,
+    /// The page's body, without the preamble or the style's layout wrapper.
+    pub content: String,
+    pub origin: Option<PageOrigin>,
+}
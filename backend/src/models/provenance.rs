@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Where each page of a document came from, in page order.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Provenance {
+    pub document: Uuid,
+    pub pages: Vec<PageOrigin>,
+}
+
+/// The originally converted page behind a page of a merged or split document.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PageOrigin {
+    pub document: Uuid,
+    /// 1-based page number within `document`.
+    pub page: usize,
+    /// Upload the page was transcribed from, if it still has one.
+    pub image: Option<String>,
+}
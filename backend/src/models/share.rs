@@ -0,0 +1,30 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// A read-only link to one rendering of a document.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Share {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    /// `pdf`, `latex` or an export format such as `html`.
+    pub format: String,
+    /// Whether viewers need a password.
+    pub protected: bool,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub views: u64,
+    pub last_viewed_at: Option<DateTime<Utc>>,
+}
+
+/// A share as its creator sees it, with the link to hand out.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ShareLink {
+    #[serde(flatten)]
+    pub share: Share,
+    pub token: String,
+    /// Path of the public route serving the share.
+    pub url: String,
+}
@@ -0,0 +1,38 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Who work is charged to. Each has its own daily quotas, and work counts
+/// against every one involved: the owner whose key asked, and the address
+/// the request came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Payer {
+    Owner(Uuid),
+    Ip(IpAddr),
+}
+
+/// What a payer has used on one (UTC) day.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Usage {
+    pub pages: u64,
+    pub compile_ms: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Meter {
+    pub used: u64,
+    /// `None` when unlimited.
+    pub limit: Option<u64>,
+}
+
+/// Today's usage against the configured quotas.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UsageReport {
+    pub day: NaiveDate,
+    pub pages: Meter,
+    pub compile_seconds: Meter,
+    pub resets_at: DateTime<Utc>,
+}
@@ -0,0 +1,116 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::errors::ErrorCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum EventKind {
+    #[serde(rename = "conversion.succeeded")]
+    ConversionSucceeded,
+    #[serde(rename = "conversion.failed")]
+    ConversionFailed,
+    #[serde(rename = "pdf.ready")]
+    PdfReady,
+    #[serde(rename = "document.deleted")]
+    DocumentDeleted,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 4] = [
+        EventKind::ConversionSucceeded,
+        EventKind::ConversionFailed,
+        EventKind::PdfReady,
+        EventKind::DocumentDeleted,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::ConversionSucceeded => "conversion.succeeded",
+            EventKind::ConversionFailed => "conversion.failed",
+            EventKind::PdfReady => "pdf.ready",
+            EventKind::DocumentDeleted => "document.deleted",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name)
+    }
+}
+
+/// The JSON body POSTed to webhook URLs.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Event {
+    /// Also sent as `webhook-id`; the same for retries and redeliveries.
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub kind: EventKind,
+    pub created_at: DateTime<Utc>,
+    pub data: EventData,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EventData {
+    pub document_id: Uuid,
+    /// The conversion or PDF build behind the event.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<Uuid>,
+    /// Why a conversion failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+    /// What went wrong, as a problem's `detail` would say it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// An account-wide endpoint that receives the owner's events.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    /// Events sent to this URL; every event when empty.
+    pub events: Vec<EventKind>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The key deliveries are signed with, shared by an owner's webhooks and
+/// per-request callbacks.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookSecret {
+    /// `whsec_` and the base64 key, as Standard Webhooks libraries expect.
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Not yet sent, or waiting for its next retry.
+    Pending,
+    Succeeded,
+    /// Out of retries.
+    Failed,
+}
+
+/// One event on its way to one URL.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Delivery {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub event: EventKind,
+    /// The webhook it was sent for; unset for per-request callbacks.
+    pub webhook_id: Option<Uuid>,
+    pub url: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// Status code of the last attempt, if the receiver answered.
+    pub response_status: Option<u16>,
+    /// Why the last attempt failed.
+    pub error: Option<String>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    /// The event as sent.
+    #[schema(value_type = Object)]
+    pub payload: serde_json::Value,
+}
@@ -0,0 +1,61 @@
+use std::io::{Cursor, Write};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+use crate::errors::{ApiError, Result};
+
+/// In-memory zip writer with fixed timestamps, so the same entries always
+/// produce byte-identical archives.
+pub struct Archive {
+    writer: ZipWriter<Cursor<Vec<u8>>>,
+}
+
+impl Archive {
+    pub fn new() -> Self {
+        Self {
+            writer: ZipWriter::new(Cursor::new(Vec::new())),
+        }
+    }
+
+    fn options(method: CompressionMethod) -> SimpleFileOptions {
+        SimpleFileOptions::default()
+            .compression_method(method)
+            .last_modified_time(DateTime::default())
+            .unix_permissions(0o644)
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.write(name, data, CompressionMethod::Deflated)
+    }
+
+    /// Add an uncompressed entry, for formats that require one (EPUB's `mimetype`).
+    pub fn add_stored(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.write(name, data, CompressionMethod::Stored)
+    }
+
+    fn write(&mut self, name: &str, data: &[u8], method: CompressionMethod) -> Result<()> {
+        self.writer
+            .start_file(name, Self::options(method))
+            .map_err(|e| {
+                ApiError::InternalServerError(anyhow::anyhow!(
+                    "Failed to add {} to archive: {}",
+                    name,
+                    e
+                ))
+            })?;
+        self.writer.write_all(data).map_err(|e| {
+            ApiError::InternalServerError(anyhow::anyhow!(
+                "Failed to write {} to archive: {}",
+                name,
+                e
+            ))
+        })
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>> {
+        self.writer.finish().map(Cursor::into_inner).map_err(|e| {
+            ApiError::InternalServerError(anyhow::anyhow!("Failed to finish archive: {}", e))
+        })
+    }
+}
@@ -0,0 +1,302 @@
+//! Self-contained source bundles for compiling a document with a local TeX setup.
+//!
+//! Bundles are built from fixed inputs only (no clocks, sorted manifest keys,
+//! fixed zip timestamps), so the same revision always yields the same bytes.
+
+use std::io::{Cursor, Read};
+
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::errors::{ApiError, Result};
+use crate::models::document::Document;
+use crate::services::archive::Archive;
+use crate::services::latex;
+
+const MAIN: &str = "main.tex";
+
+/// Upper bound on the unpacked size of an imported bundle.
+const MAX_UNPACKED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Image types accepted back from a bundle, matching what `/upload` allows.
+const IMAGE_EXTENSIONS: [&str; 3] = ["jpg", "png", "webp"];
+
+const LATEXMKRC: &str = "$pdf_mode = 1;
+$pdflatex = 'pdflatex -interaction=nonstopmode -halt-on-error -no-shell-escape %O %S';
+";
+
+const MAKEFILE: &str = "all: main.pdf
+
+main.pdf: main.tex
+\tlatexmk -pdf main.tex
+
+clean:
+\tlatexmk -C
+
+.PHONY: all clean
+";
+
+/// An uploaded page image, in page order.
+pub struct PageImage {
+    pub extension: String,
+    pub data: Vec<u8>,
+}
+
+pub struct Bundle {
+    /// SHA-256 of the archive. The bytes only change with the LaTeX or the
+    /// images, so this is what caches should key on.
+    pub etag: String,
+    pub body: Vec<u8>,
+}
+
+/// The parts of a bundle needed to recreate its document.
+pub struct Unpacked {
+    pub content: String,
+    pub images: Vec<PageImage>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    revision: String,
+    main: String,
+    pages: Vec<ManifestPage>,
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Deserialize)]
+struct ManifestPage {
+    image: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ManifestFile {
+    path: String,
+    sha256: String,
+}
+
+pub fn build(document: &Document, images: &[PageImage]) -> Result<Bundle> {
+    let revision = revision(&document.content);
+    let mut files: Vec<(String, Vec<u8>)> = vec![
+        (MAIN.to_string(), document.content.clone().into_bytes()),
+        ("latexmkrc".to_string(), LATEXMKRC.as_bytes().to_vec()),
+        ("Makefile".to_string(), MAKEFILE.as_bytes().to_vec()),
+    ];
+
+    let fragments = latex::split_pages(&document.content);
+    let page_count = fragments.len().max(images.len());
+    let mut pages = Vec::new();
+    for number in 1..=page_count {
+        let mut page = json!({ "number": number });
+        if let Some(fragment) = fragments.get(number - 1) {
+            let path = format!("pages/page-{}.tex", number);
+            page["source"] = json!(path);
+            files.push((path, format!("{}\n", fragment).into_bytes()));
+        }
+        if let Some(image) = images.get(number - 1) {
+            let path = format!("images/page-{}.{}", number, image.extension);
+            page["image"] = json!(path);
+            files.push((path, image.data.clone()));
+        }
+        pages.push(page);
+    }
+
+    let manifest = json!({
+        "document": document.id,
+        "revision": revision,
+        "style": document.style,
+        "generator": format!("noteforge {}", env!("CARGO_PKG_VERSION")),
+        "main": MAIN,
+        "build": "latexmk -pdf main.tex",
+        "pages": pages,
+        "notes": [
+            "pages/*.tex are the per-page body fragments of main.tex and do not compile on their own",
+            "images/ holds the uploads exactly as sent to the model; there is no separate preprocessing step",
+        ],
+        "files": files
+            .iter()
+            .map(|(path, data)| json!({
+                "path": path,
+                "size": data.len(),
+                "sha256": sha256(data),
+            }))
+            .collect::<Vec<_>>(),
+    });
+    let mut manifest = serde_json::to_string_pretty(&manifest).unwrap_or_default();
+    manifest.push('\n');
+
+    let mut archive = Archive::new();
+    archive.add("manifest.json", manifest.as_bytes())?;
+    for (path, data) in &files {
+        archive.add(path, data)?;
+    }
+
+    let body = archive.finish()?;
+    Ok(Bundle {
+        etag: sha256(&body),
+        body,
+    })
+}
+
+/// Read a bundle produced by [`build`], checking every file against the manifest.
+pub fn open(data: &[u8]) -> Result<Unpacked> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))
+        .map_err(|e| ApiError::ValidationError(format!("Invalid bundle archive: {}", e)))?;
+
+    let mut read = |path: &str| -> Result<Vec<u8>> {
+        let entry = archive
+            .by_name(path)
+            .map_err(|_| ApiError::ValidationError(format!("Bundle is missing {}", path)))?;
+        let mut data = Vec::new();
+        entry
+            .take(MAX_UNPACKED_SIZE + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| ApiError::ValidationError(format!("Failed to read {}: {}", path, e)))?;
+        if data.len() as u64 > MAX_UNPACKED_SIZE {
+            return Err(ApiError::ValidationError(format!("{} is too large", path)));
+        }
+        Ok(data)
+    };
+
+    let manifest: Manifest = serde_json::from_slice(&read("manifest.json")?)
+        .map_err(|e| ApiError::ValidationError(format!("Invalid bundle manifest: {}", e)))?;
+    let mut verified = |path: &str| -> Result<Vec<u8>> {
+        let expected = manifest
+            .files
+            .iter()
+            .find(|file| file.path == path)
+            .ok_or_else(|| {
+                ApiError::ValidationError(format!("{} is not listed in the manifest", path))
+            })?;
+        let data = read(path)?;
+        if sha256(&data) != expected.sha256 {
+            return Err(ApiError::ValidationError(format!(
+                "Checksum mismatch for {}",
+                path
+            )));
+        }
+        Ok(data)
+    };
+
+    let content = String::from_utf8(verified(&manifest.main)?)
+        .map_err(|_| ApiError::ValidationError(format!("{} is not UTF-8", manifest.main)))?;
+    if revision(&content) != manifest.revision {
+        return Err(ApiError::ValidationError(
+            "Bundle revision does not match its main file".to_string(),
+        ));
+    }
+
+    let mut images = Vec::new();
+    let mut unpacked_size = content.len() as u64;
+    for path in manifest
+        .pages
+        .iter()
+        .filter_map(|page| page.image.as_deref())
+    {
+        let extension = path.rsplit_once('.').map(|(_, extension)| extension);
+        let Some(extension) = extension.filter(|extension| IMAGE_EXTENSIONS.contains(extension))
+        else {
+            return Err(ApiError::ValidationError(format!(
+                "Unsupported image in bundle: {}",
+                path
+            )));
+        };
+        let data = verified(path)?;
+        unpacked_size += data.len() as u64;
+        if unpacked_size > MAX_UNPACKED_SIZE {
+            return Err(ApiError::ValidationError("Bundle is too large".to_string()));
+        }
+        images.push(PageImage {
+            extension: extension.to_string(),
+            data,
+        });
+    }
+
+    Ok(Unpacked { content, images })
+}
+
+/// Revision identifier for a document's LaTeX.
+pub fn revision(content: &str) -> String {
+    sha256(content.as_bytes())
+}
+
+fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    use super::*;
+
+    const SOURCE: &str = "\\documentclass{article}
+\\begin{document}
+First page
+\\newpage
+Second page
+\\end{document}
+";
+
+    fn document(created_at: chrono::DateTime<Utc>) -> Document {
+        Document {
+            id: Uuid::from_u128(1),
+            filename: "notes.tex".to_string(),
+            content: SOURCE.to_string(),
+            style: "article".to_string(),
+            created_at,
+        }
+    }
+
+    fn images(second: &[u8]) -> Vec<PageImage> {
+        [b"first".as_slice(), second]
+            .into_iter()
+            .map(|data| PageImage {
+                extension: "png".to_string(),
+                data: data.to_vec(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn builds_the_same_bytes_every_time() {
+        let first = build(&document(Utc::now()), &images(b"second")).unwrap();
+        let later = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let second = build(&document(later), &images(b"second")).unwrap();
+
+        assert!(first.body == second.body, "bundles differ between builds");
+        assert_eq!(first.etag, second.etag);
+    }
+
+    #[test]
+    fn changes_the_etag_with_any_page_image() {
+        let original = build(&document(Utc::now()), &images(b"second")).unwrap();
+        let replaced = build(&document(Utc::now()), &images(b"retaken")).unwrap();
+
+        assert_ne!(original.etag, replaced.etag);
+        // The LaTeX, and so the document's revision, is unchanged
+        assert_eq!(
+            open(&original.body).unwrap().content,
+            open(&replaced.body).unwrap().content
+        );
+    }
+
+    #[test]
+    fn opens_what_it_builds() {
+        let bundle = build(&document(Utc::now()), &images(b"second")).unwrap();
+        let unpacked = open(&bundle.body).unwrap();
+
+        assert_eq!(unpacked.content, SOURCE);
+        let pages: Vec<&[u8]> = unpacked
+            .images
+            .iter()
+            .map(|image| &image.data[..])
+            .collect();
+        assert_eq!(pages, [b"first".as_slice(), b"second".as_slice()]);
+        assert!(unpacked.images.iter().all(|image| image.extension == "png"));
+    }
+}
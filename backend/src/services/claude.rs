@@ -1,10 +1,14 @@
 use crate::config::env::Config;
 use crate::errors::{ApiError, Result};
+use crate::services::styles::Style;
+use crate::services::{latex, metrics};
 use base64::{engine::general_purpose::STANDARD as base64, Engine};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use std::path::PathBuf;
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+use uuid::Uuid;
 
 #[derive(Debug, Serialize)]
 struct ClaudeRequest {
@@ -39,17 +43,19 @@ struct ImageSource {
 
 #[derive(Debug, Deserialize)]
 struct ClaudeResponse {
-    id: String,
-    #[serde(rename = "type")]
-    message_type: String,
-    role: String,
     content: Vec<ContentItem>,
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenUsage {
+    input_tokens: u64,
+    output_tokens: u64,
 }
 
 #[derive(Debug, Deserialize)]
 struct ContentItem {
-    #[serde(rename = "type")]
-    content_type: String,
     text: String,
 }
 
@@ -63,116 +69,115 @@ enum PageType {
 pub struct ClaudeService {
     client: Client,
     api_key: String,
+    api_url: String,
     model: String,
+    document_id: Uuid,
 }
 
 impl ClaudeService {
-    pub fn new(config: &Config) -> Self {
+    /// A client transcribing the pages of document `document_id`.
+    pub fn new(config: &Config, document_id: Uuid) -> Self {
         Self {
             client: Client::new(),
             api_key: config.claude_api_key.clone(),
+            api_url: config.claude_api_url.clone(),
+            document_id,
             model: "claude-3-5-sonnet-20241022".to_string(),
         }
     }
 
-    pub async fn convert_single_page(&self, image_path: &Path) -> Result<String> {
-        self.process_with_prompt(image_path, PageType::Single).await
+    /// Transcribe one page and return its body fragment.
+    pub async fn convert_single_page(&self, image: &[u8], style: &Style) -> Result<String> {
+        self.process_with_prompt(image, PageType::Single, style)
+            .instrument(self.page_span(1, 1))
+            .await
     }
 
-    pub async fn convert_multiple_pages(&self, image_paths: &[PathBuf]) -> Result<String> {
-        let mut combined_latex = String::new();
-        let paths_len = image_paths.len();
-    
-        for (index, path) in image_paths.iter().enumerate() {
+    /// Transcribe pages in order and return one body fragment per page.
+    pub async fn convert_multiple_pages(
+        &self,
+        images: &[Vec<u8>],
+        style: &Style,
+    ) -> Result<Vec<String>> {
+        let mut pages = Vec::with_capacity(images.len());
+        let images_len = images.len();
+
+        for (index, image) in images.iter().enumerate() {
             let page_type = match index {
                 0 => PageType::First,
-                i if i == paths_len - 1 => PageType::Last,
+                i if i == images_len - 1 => PageType::Last,
                 _ => PageType::Middle,
             };
-    
-            let content = self.process_with_prompt(path, page_type).await?;
-            
-            // For the first page, keep everything
-            if index == 0 {
-                combined_latex.push_str(&content);
-            } 
-            // For middle pages, strip preamble and end document tags
-            else if index < paths_len - 1 {
-                // Extract only the content between \begin{document} and \end{document}
-                if let Some(begin_idx) = content.find("\\begin{document}") {
-                    if let Some(content_start) = content[begin_idx..].find('\n') {
-                        let start_idx = begin_idx + content_start + 1;
-                        combined_latex.push_str("\\newpage\n");
-                        combined_latex.push_str(&content[start_idx..].replace("\\end{document}", ""));
-                    }
-                }
-            } 
-            // For last page, strip preamble but keep end document tag
-            else {
-                // Extract only the content between \begin{document} and \end{document}
-                if let Some(begin_idx) = content.find("\\begin{document}") {
-                    if let Some(content_start) = content[begin_idx..].find('\n') {
-                        let start_idx = begin_idx + content_start + 1;
-                        combined_latex.push_str("\\newpage\n");
-                        combined_latex.push_str(&content[start_idx..]);
-                    }
-                }
-            }
+
+            pages.push(
+                self.process_with_prompt(image, page_type, style)
+                    .instrument(self.page_span(index + 1, images_len))
+                    .await?,
+            );
         }
-    
-        Ok(combined_latex)
+
+        Ok(pages)
     }
 
-    async fn process_with_prompt(&self, image_path: &Path, page_type: PageType) -> Result<String> {
-        let image_data = tokio::fs::read(image_path)
-            .await
-            .map_err(|e| ApiError::FileError(format!("Failed to read image: {}", e)))?;
-
-        let base64_image = base64.encode(image_data);
-
-        let prompt = match page_type {
-            PageType::Single => {
-                "Convert this mathematical content to a complete LaTeX document:
-                1. Document Structure:
-                   - Must start with \\documentclass{article}
-                   - Include necessary packages (amsmath, amssymb)
-                   - Must have \\begin{document} and \\end{document}
-                2. Mathematical Content:
-                   - Use align* for equations
-                   - Format all special symbols correctly
-                   - Preserve spacing and layout
-                Do not include ```latex or ``` markers. Return only the raw LaTeX code."
-            }
-            PageType::First => {
-                "Return ONLY raw LaTeX code for the beginning of a document:
-                1. Document Structure:
-                   - Must start with \\documentclass{article}
-                   - Include necessary packages
-                   - Begin with \\begin{document}
-                2. Mathematical Content:
-                   - Format equations properly
-                Do not include \\end{document}.
-                Do not include ```latex or ``` markers. Return only the raw LaTeX code."
-            }
-            PageType::Middle => {
-                "Return ONLY raw LaTeX content for a middle page:
-                1. Format the mathematical content
-                2. Do NOT include \\documentclass, \\begin{document}, or \\end{document}
-                3. Just return the formatted content that would go inside a document"
-            }
-            PageType::Last => {
-                "Return ONLY raw LaTeX content for the final page:
-                Format all equations and end with \\end{document}.
-                Do not include ```latex or ``` markers. Return only the raw LaTeX code."
-            }
+    fn page_span(&self, page: usize, pages: usize) -> Span {
+        tracing::info_span!(
+            "transcribe_page",
+            document.id = %self.document_id,
+            page.number = page,
+            page.count = pages,
+            gen_ai.request.model = %self.model,
+            gen_ai.usage.input_tokens = Empty,
+            gen_ai.usage.output_tokens = Empty,
+            error.type = Empty,
+            otel.status_code = Empty,
+        )
+    }
+
+    async fn process_with_prompt(
+        &self,
+        image: &[u8],
+        page_type: PageType,
+        style: &Style,
+    ) -> Result<String> {
+        let base64_image = base64.encode(image);
+
+        let position = match page_type {
+            PageType::Single => "This is the only page of the notes.",
+            PageType::First => "This is the first page of a multi-page document.",
+            PageType::Middle => "This is a middle page; continue from the previous page.",
+            PageType::Last => "This is the final page of a multi-page document.",
         };
 
+        // The preamble is supplied by the style during assembly, so every page
+        // is transcribed as bare body content.
+        let prompt = format!(
+            "Convert this handwritten mathematical content to LaTeX.
+            {}
+            1. Document Structure:
+               - Return ONLY the content that goes between \\begin{{document}} and \\end{{document}}
+               - Do NOT include \\documentclass, \\usepackage, \\begin{{document}} or \\end{{document}}
+               - The document is typeset with this preamble:
+{}
+            2. Mathematical Content:
+               - Format all special symbols correctly
+               - Preserve spacing and layout
+            3. Code:
+               - Put pseudocode and algorithms in \\begin{{lstlisting}} ... \\end{{lstlisting}}
+               - Put runnable code or numerical examples written as code in
+                 \\begin{{lstlisting}}[language=Python] ... \\end{{lstlisting}}, naming the actual language
+            4. Style: {}
+            Do not include ```latex or ``` markers. Return only the raw LaTeX code.",
+            position,
+            style.header(),
+            style.rules
+        );
+
         let messages = vec![Message {
             role: "user".to_string(),
             content: vec![
                 MessageContent {
                     content_type: "text".to_string(),
-                    text: Some(prompt.to_string()),
+                    text: Some(prompt),
                     source: None,
                 },
                 MessageContent {
@@ -193,40 +198,91 @@ impl ClaudeService {
             messages,
         };
 
+        let started = Instant::now();
+        let result = self.send(&request).await;
+        metrics::MODEL_DURATION
+            .with_label_values(&[&self.model])
+            .observe(started.elapsed().as_secs_f64());
+        let claude_response = match result {
+            Ok(response) => response,
+            Err((reason, e)) => {
+                Span::current()
+                    .record("error.type", reason.as_str())
+                    .record("otel.status_code", "ERROR");
+                metrics::MODEL_ERRORS
+                    .with_label_values(&[&self.model, &reason])
+                    .inc();
+                return Err(e);
+            }
+        };
+
+        if let Some(usage) = &claude_response.usage {
+            Span::current()
+                .record("gen_ai.usage.input_tokens", usage.input_tokens)
+                .record("gen_ai.usage.output_tokens", usage.output_tokens);
+            for (direction, tokens) in [
+                ("input", usage.input_tokens),
+                ("output", usage.output_tokens),
+            ] {
+                metrics::MODEL_TOKENS
+                    .with_label_values(&[&self.model, direction])
+                    .inc_by(tokens);
+            }
+        }
+
+        let latex_content = claude_response
+            .content
+            .first()
+            .ok_or_else(|| {
+                metrics::MODEL_ERRORS
+                    .with_label_values(&[&self.model, "empty"])
+                    .inc();
+                ApiError::ClaudeError("No content in response".to_string())
+            })?
+            .text
+            .as_str();
+
+        Ok(latex::extract_body(latex_content).to_string())
+    }
+
+    /// Call the API; errors carry a short reason for metrics.
+    async fn send(
+        &self,
+        request: &ClaudeRequest,
+    ) -> std::result::Result<ClaudeResponse, (String, ApiError)> {
         let response = self
             .client
-            .post("https://api.anthropic.com/v1/messages")
+            .post(format!("{}/v1/messages", self.api_url))
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header("content-type", "application/json")
-            .json(&request)
+            .json(request)
             .send()
             .await
-            .map_err(|e| ApiError::ClaudeError(format!("Failed to send request: {}", e)))?;
+            .map_err(|e| {
+                (
+                    "transport".to_string(),
+                    ApiError::ClaudeError(format!("Failed to send request: {}", e)),
+                )
+            })?;
 
-        if !response.status().is_success() {
+        let status = response.status();
+        if !status.is_success() {
             let error_text = response
                 .text()
                 .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(ApiError::ClaudeError(format!(
-                "API request failed: {}",
-                error_text
-            )));
+            return Err((
+                format!("http_{}", status.as_u16()),
+                ApiError::ClaudeError(format!("API request failed: {}", error_text)),
+            ));
         }
 
-        let claude_response: ClaudeResponse = response
-            .json()
-            .await
-            .map_err(|e| ApiError::ClaudeError(format!("Failed to parse response: {}", e)))?;
-
-        let latex_content = claude_response
-            .content
-            .first()
-            .ok_or_else(|| ApiError::ClaudeError("No content in response".to_string()))?
-            .text
-            .clone();
-
-        Ok(latex_content)
+        response.json().await.map_err(|e| {
+            (
+                "parse".to_string(),
+                ApiError::ClaudeError(format!("Failed to parse response: {}", e)),
+            )
+        })
     }
 }
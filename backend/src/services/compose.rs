@@ -0,0 +1,458 @@
+//! Merging documents into one and splitting them apart.
+//!
+//! Both work on per-page fragments, so callers can map every resulting page
+//! back to the page (and image) it came from.
+
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::errors::{ApiError, Result};
+use crate::models::document::Document;
+use crate::services::styles::{self, Layout, Style};
+use crate::services::{latex, parser};
+
+/// Environments the beamer class already defines.
+const BEAMER_THEOREMS: [&str; 11] = [
+    "theorem",
+    "corollary",
+    "definition",
+    "definitions",
+    "fact",
+    "example",
+    "examples",
+    "lemma",
+    "proof",
+    "problem",
+    "solution",
+];
+
+/// Preamble commands that only make sense in a beamer document.
+const BEAMER_COMMANDS: [&str; 4] = [
+    "\\usetheme",
+    "\\usecolortheme",
+    "\\usefonttheme",
+    "\\setbeamertemplate",
+];
+
+pub struct Merged {
+    pub content: String,
+    pub warnings: Vec<String>,
+}
+
+/// Concatenate documents in order under `style`.
+///
+/// Packages and settings from each source's preamble are carried over unless
+/// the target already has them. Macros defined by several sources with
+/// different bodies are renamed in the later sources, and all definitions
+/// are hoisted into the preamble so none is defined twice.
+pub fn merge(style: &Style, sources: &[Document]) -> Merged {
+    let header = style.header();
+    let mut preamble = Preamble::new(style, &header);
+    let mut warnings = Vec::new();
+    let mut macros: Vec<(String, String)> = Vec::new();
+    let mut pages = Vec::new();
+
+    for (index, source) in sources.iter().enumerate() {
+        let mut source_preamble = latex::extract_preamble(&source.content).to_string();
+        let mut source_pages = latex::split_pages(&source.content);
+
+        // Rename macros that clash with an earlier, different definition
+        let found = definitions(&source_preamble, &source_pages);
+        let mut taken: HashSet<String> = macros.iter().map(|(name, _)| name.clone()).collect();
+        taken.extend(found.iter().map(|(name, _)| name.clone()));
+        let mut renamed_any = false;
+        for (name, text) in &found {
+            let clashes = macros
+                .iter()
+                .any(|(existing, body)| existing == name && body != text);
+            if !clashes {
+                continue;
+            }
+            let renamed = unique_name(name, &taken);
+            warnings.push(format!(
+                "\\{} is defined differently in document {}; renamed to \\{} there",
+                name,
+                index + 1,
+                renamed
+            ));
+            // The new name exists nowhere yet, so it cannot be redefined
+            source_preamble = define_new(
+                &parser::rename_command(&source_preamble, name, &renamed),
+                &renamed,
+            );
+            for page in &mut source_pages {
+                *page = define_new(&parser::rename_command(page, name, &renamed), &renamed);
+            }
+            taken.insert(renamed);
+            renamed_any = true;
+        }
+        let found = if renamed_any {
+            definitions(&source_preamble, &source_pages)
+        } else {
+            found
+        };
+
+        for (name, text) in found {
+            if !macros.iter().any(|(existing, _)| *existing == name) {
+                macros.push((name, text));
+            }
+        }
+        preamble.absorb(&strip_definitions(&source_preamble), index == 0);
+        pages.extend(source_pages.iter().map(|page| strip_definitions(page)));
+    }
+
+    let extra = preamble.statements;
+    let mut header = header;
+    for statement in extra {
+        header.push_str(&statement);
+        header.push('\n');
+    }
+    for (_, text) in &macros {
+        header.push_str(text);
+        header.push('\n');
+    }
+
+    Merged {
+        content: style.assemble_with(&header, &pages),
+        warnings,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitMode {
+    Page,
+    Section,
+}
+
+/// One part of a split document.
+pub struct Part {
+    pub content: String,
+    /// 0-based indices of the source pages each page of this part came from.
+    pub pages: Vec<usize>,
+}
+
+/// Split a document before the given pages or sections (1-based), or at
+/// every boundary when `at` is empty. The original preamble is kept.
+pub fn split(document: &Document, mode: SplitMode, at: &[usize]) -> Result<Vec<Part>> {
+    let style = styles::detect(&document.content);
+    let header = format!("{}\n", latex::extract_preamble(&document.content));
+    let header = if header.trim().is_empty() {
+        style.header()
+    } else {
+        header
+    };
+
+    // Units of text with the boundary number they start, if any
+    let mut units: Vec<(usize, String, Option<usize>)> = Vec::new();
+    let mut boundaries = 0;
+    for (index, page) in latex::split_pages(&document.content)
+        .into_iter()
+        .enumerate()
+    {
+        match mode {
+            SplitMode::Page => units.push((index, page, Some(index + 1))),
+            SplitMode::Section => {
+                let mut starts = parser::section_starts(&page);
+                if starts.first() != Some(&0) {
+                    starts.insert(0, 0);
+                }
+                starts.push(page.len());
+                for window in starts.windows(2) {
+                    let text = page[window[0]..window[1]].trim();
+                    if text.is_empty() {
+                        continue;
+                    }
+                    let boundary = if parser::section_starts(text).first() == Some(&0) {
+                        boundaries += 1;
+                        Some(boundaries)
+                    } else {
+                        None
+                    };
+                    units.push((index, text.to_string(), boundary));
+                }
+            }
+        }
+    }
+
+    let available = match mode {
+        SplitMode::Page => units.len(),
+        SplitMode::Section => boundaries,
+    };
+    if let Some(missing) = at
+        .iter()
+        .find(|number| **number == 0 || **number > available)
+    {
+        let unit = match mode {
+            SplitMode::Page => "page",
+            SplitMode::Section => "section",
+        };
+        return Err(ApiError::invalid_field(
+            "at",
+            format!(
+                "Cannot split at {} {}: the document has {}",
+                unit, missing, available
+            ),
+        ));
+    }
+
+    let mut parts: Vec<Vec<(usize, String)>> = Vec::new();
+    for (page, text, boundary) in units {
+        let starts_part = boundary.is_some_and(|number| at.is_empty() || at.contains(&number));
+        if starts_part || parts.is_empty() {
+            if parts.last().is_some_and(|part| part.is_empty()) {
+                parts.pop();
+            }
+            parts.push(Vec::new());
+        }
+        let part = parts.last_mut().expect("a part was just pushed");
+        // Pieces of the same source page stay on one page
+        match part.last_mut() {
+            Some((last, existing)) if *last == page => {
+                existing.push_str("\n\n");
+                existing.push_str(&text);
+            }
+            _ => part.push((page, text)),
+        }
+    }
+
+    if parts.len() < 2 {
+        return Err(ApiError::ValidationError(
+            "Nothing to split: the document has a single part".to_string(),
+        ));
+    }
+
+    Ok(parts
+        .into_iter()
+        .map(|part| {
+            let (pages, fragments): (Vec<usize>, Vec<String>) = part.into_iter().unzip();
+            Part {
+                content: style.assemble_with(&header, &fragments),
+                pages,
+            }
+        })
+        .collect())
+}
+
+/// Preamble statements carried over from merged sources.
+struct Preamble<'a> {
+    style: &'a Style,
+    header: &'a str,
+    statements: Vec<String>,
+}
+
+impl<'a> Preamble<'a> {
+    fn new(style: &'a Style, header: &'a str) -> Self {
+        Self {
+            style,
+            header,
+            statements: Vec::new(),
+        }
+    }
+
+    fn absorb(&mut self, preamble: &str, first: bool) {
+        for statement in parser::statements(preamble) {
+            if self.has(statement) {
+                continue;
+            }
+            let keep = if statement.starts_with("\\documentclass") {
+                None
+            } else if statement.starts_with("\\title")
+                || statement.starts_with("\\author")
+                || statement.starts_with("\\date")
+            {
+                // The first document names the merged one
+                first.then(|| statement.to_string())
+            } else if let Some(packages) = argument(statement, "\\usepackage") {
+                // Only load what the merged preamble lacks, with the same options
+                let missing: Vec<&str> = packages
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|package| !package.is_empty() && !self.loads(package))
+                    .collect();
+                let options = &statement[..statement.find('{').unwrap_or(statement.len())];
+                (!missing.is_empty()).then(|| format!("{}{{{}}}", options, missing.join(",")))
+            } else if let Some(name) = argument(statement, "\\newtheorem") {
+                (!self.defines_theorem(name.trim())).then(|| statement.to_string())
+            } else if BEAMER_COMMANDS
+                .iter()
+                .any(|command| statement.starts_with(command))
+            {
+                (self.style.layout == Layout::Slides).then(|| statement.to_string())
+            } else {
+                Some(statement.to_string())
+            };
+            if let Some(statement) = keep {
+                self.statements.push(statement);
+            }
+        }
+    }
+
+    fn has(&self, statement: &str) -> bool {
+        self.all_statements().any(|existing| existing == statement)
+    }
+
+    fn all_statements(&self) -> impl Iterator<Item = &str> {
+        parser::statements(self.header)
+            .into_iter()
+            .chain(self.statements.iter().map(String::as_str))
+    }
+
+    fn loads(&self, package: &str) -> bool {
+        self.all_statements().any(|statement| {
+            argument(statement, "\\usepackage")
+                .is_some_and(|packages| packages.split(',').any(|loaded| loaded.trim() == package))
+        })
+    }
+
+    fn defines_theorem(&self, name: &str) -> bool {
+        (self.style.layout == Layout::Slides && BEAMER_THEOREMS.contains(&name))
+            || self.all_statements().any(|statement| {
+                argument(statement, "\\newtheorem").is_some_and(|defined| defined == name)
+            })
+    }
+}
+
+/// The first mandatory argument of `command` at the start of `line`.
+fn argument<'a>(line: &'a str, command: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(command)?;
+    let rest = rest.strip_prefix('*').unwrap_or(rest);
+    let mut scanner = parser::Scanner::new(rest);
+    scanner.optional();
+    scanner.group()
+}
+
+/// Macro definitions in a preamble and its pages, as `(name, statement)`.
+fn definitions(preamble: &str, pages: &[String]) -> Vec<(String, String)> {
+    std::iter::once(preamble)
+        .chain(pages.iter().map(String::as_str))
+        .flat_map(|source| {
+            parser::definitions(source)
+                .into_iter()
+                .map(|definition| {
+                    (
+                        definition.name,
+                        source[definition.start..definition.end].to_string(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn strip_definitions(source: &str) -> String {
+    let mut out = String::new();
+    let mut copied = 0;
+    for definition in parser::definitions(source) {
+        out.push_str(&source[copied..definition.start]);
+        copied = definition.end;
+    }
+    out.push_str(&source[copied..]);
+    out.trim().to_string()
+}
+
+/// Turn `\renewcommand`s of `name` into `\newcommand`s.
+fn define_new(source: &str, name: &str) -> String {
+    const RENEW: &str = "\\renewcommand";
+    let mut out = String::with_capacity(source.len());
+    let mut copied = 0;
+    for definition in parser::definitions(source) {
+        if definition.name == name && source[definition.start..].starts_with(RENEW) {
+            out.push_str(&source[copied..definition.start]);
+            out.push_str("\\newcommand");
+            copied = definition.start + RENEW.len();
+        }
+    }
+    out.push_str(&source[copied..]);
+    out
+}
+
+/// `name` with a letter suffix that no other macro uses (`\R` -> `\RB`).
+fn unique_name(name: &str, taken: &HashSet<String>) -> String {
+    ('B'..='Z')
+        .map(|suffix| format!("{}{}", name, suffix))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap_or_else(|| format!("{}Merged", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn document(preamble: &str, body: &str) -> Document {
+        Document {
+            id: Uuid::new_v4(),
+            filename: "notes.tex".to_string(),
+            content: format!(
+                "\\documentclass{{article}}\n{}\n\\begin{{document}}\n{}\n\\end{{document}}\n",
+                preamble, body
+            ),
+            style: "article".to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn preamble(merged: &Merged) -> &str {
+        latex::extract_preamble(&merged.content)
+    }
+
+    #[test]
+    fn carries_multi_line_statements_over_whole() {
+        let environments = "\\newenvironment{boxed}{%
+  \\begin{center}
+}{%
+  \\end{center}
+}
+\\newenvironment{aside}
+  {\\itshape}
+  {\\par}";
+        let sources = [
+            document(environments, "One"),
+            document(&format!("{}\n\\usepackage{{tikz}}", environments), "Two"),
+        ];
+        let merged = merge(styles::find("article").unwrap(), &sources);
+        let preamble = preamble(&merged);
+
+        assert_eq!(preamble.matches("\\newenvironment{boxed}").count(), 1);
+        assert_eq!(preamble.matches("\\newenvironment{aside}").count(), 1);
+        assert!(preamble.contains(environments));
+        assert!(preamble.contains("\\usepackage{tikz}"));
+        assert_eq!(preamble.matches('{').count(), preamble.matches('}').count());
+    }
+
+    #[test]
+    fn renames_clashing_macros() {
+        let sources = [
+            document("\\newcommand{\\R}{\\mathbb{R}}", "$\\R$"),
+            document("\\newcommand{\\R}{\\mathcal{R}}", "$\\R$"),
+        ];
+        let merged = merge(styles::find("article").unwrap(), &sources);
+
+        assert!(preamble(&merged).contains("\\newcommand{\\R}{\\mathbb{R}}"));
+        assert!(preamble(&merged).contains("\\newcommand{\\RB}{\\mathcal{R}}"));
+        assert!(merged.content.contains("$\\R$\n\\newpage\n$\\RB$"));
+        assert_eq!(
+            merged.warnings,
+            ["\\R is defined differently in document 2; renamed to \\RB there"]
+        );
+    }
+
+    #[test]
+    fn renamed_redefinitions_become_definitions() {
+        let sources = [
+            document("\\renewcommand{\\vec}[1]{\\mathbf{#1}}", "$\\vec{x}$"),
+            document("\\renewcommand*{\\vec}[1]{\\boldsymbol{#1}}", "$\\vec{y}$"),
+        ];
+        let merged = merge(styles::find("article").unwrap(), &sources);
+
+        assert!(preamble(&merged).contains("\\renewcommand{\\vec}[1]{\\mathbf{#1}}"));
+        assert!(preamble(&merged).contains("\\newcommand*{\\vecB}[1]{\\boldsymbol{#1}}"));
+        assert!(!preamble(&merged).contains("\\renewcommand*{\\vecB}"));
+        assert!(merged.content.contains("$\\vecB{y}$"));
+    }
+}
@@ -0,0 +1,501 @@
+//! Anki `.apkg` decks: one note per definition, theorem or named formula.
+//!
+//! A package is a zip holding a legacy (schema 11) `collection.anki2` SQLite
+//! database and a `media` manifest. Math is written with MathJax delimiters,
+//! which every Anki client renders natively.
+
+use rusqlite::{params, Connection, DatabaseName};
+use serde_json::json;
+
+use crate::errors::{ApiError, Result};
+use crate::models::content::{Block, Content, Inline, ListItem};
+use crate::models::document::Document;
+use crate::services::archive::Archive;
+use crate::services::export::environment_title;
+use crate::services::export::markdown::wrap_math;
+use crate::services::math::escape_xml as escape;
+
+/// Environments that become one card each.
+const CARD_ENVIRONMENTS: [&str; 5] = ["definition", "theorem", "lemma", "proposition", "corollary"];
+
+/// Fixed so re-imported decks keep using the same note type.
+const MODEL_ID: i64 = 1_718_000_000_001;
+
+const SCHEMA: &str = "
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+    ver integer not null, dty integer not null, usn integer not null, ls integer not null,
+    conf text not null, models text not null, decks text not null, dconf text not null,
+    tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null, mod integer not null,
+    usn integer not null, tags text not null, flds text not null, sfld integer not null,
+    csum integer not null, flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null, ord integer not null,
+    mod integer not null, usn integer not null, type integer not null, queue integer not null,
+    due integer not null, ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null, odid integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+    ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null,
+    type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn ON notes (usn);
+CREATE INDEX ix_cards_usn ON cards (usn);
+CREATE INDEX ix_revlog_usn ON revlog (usn);
+CREATE INDEX ix_cards_nid ON cards (nid);
+CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+CREATE INDEX ix_revlog_cid ON revlog (cid);
+CREATE INDEX ix_notes_csum ON notes (csum);
+";
+
+const CARD_CSS: &str = ".card { font-family: Georgia, serif; font-size: 20px; text-align: left; }
+.kind { font-size: 0.8em; text-transform: uppercase; letter-spacing: 0.05em; opacity: 0.7; }
+.source { font-size: 0.7em; opacity: 0.6; margin-top: 1em; }
+pre { font-size: 0.8em; }";
+
+struct Card {
+    kind: &'static str,
+    page: usize,
+    front: String,
+    back: String,
+}
+
+pub fn render(document: &Document, content: &Content) -> Result<(Vec<u8>, Vec<String>)> {
+    let mut collector = Collector::default();
+    collector.collect(&content.blocks);
+
+    if collector.cards.is_empty() {
+        collector.warnings.push(
+            "No definitions, theorems or named formulas found; the deck is empty".to_string(),
+        );
+    }
+    if collector.skipped > 0 {
+        collector.warnings.push(format!(
+            "{} statement(s) had no formula or proof for the back of a card and were skipped",
+            collector.skipped
+        ));
+    }
+
+    let title = content
+        .title
+        .clone()
+        .unwrap_or_else(|| document.filename.trim_end_matches(".tex").to_string());
+    let collection = build_collection(document, &title, &collector.cards).map_err(|e| {
+        ApiError::InternalServerError(anyhow::anyhow!("Failed to build Anki collection: {}", e))
+    })?;
+
+    let mut archive = Archive::new();
+    archive.add("collection.anki2", &collection)?;
+    archive.add("media", b"{}")?;
+    Ok((archive.finish()?, collector.warnings))
+}
+
+/// Write the cards into a fresh in-memory collection and return it as a
+/// database file, so exporting never touches the disk.
+fn build_collection(
+    document: &Document,
+    title: &str,
+    cards: &[Card],
+) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let connection = Connection::open_in_memory()?;
+    connection.execute_batch(SCHEMA)?;
+
+    // Ids and times derive from the document, so exports are reproducible and
+    // re-importing a deck updates it in place
+    let modified = document.created_at.timestamp();
+    let base_id = document.created_at.timestamp_millis();
+    let deck_id = (document.id.as_u128() as i64 & 0x000f_ffff_ffff_ffff) | 1;
+
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id, "name": name, "mod": modified, "usn": -1, "desc": "", "dyn": 0, "conf": 1,
+            "collapsed": false, "extendNew": 10, "extendRev": 50,
+            "newToday": [0, 0], "revToday": [0, 0], "lrnToday": [0, 0], "timeToday": [0, 0],
+        })
+    };
+    let decks = json!({
+        "1": deck(1, "Default"),
+        deck_id.to_string(): deck(deck_id, title),
+    });
+    let field = |name: &str, ord: u32| {
+        json!({
+            "name": name, "ord": ord, "sticky": false, "rtl": false,
+            "font": "Arial", "size": 20, "media": [],
+        })
+    };
+    let models = json!({
+        MODEL_ID.to_string(): {
+            "id": MODEL_ID, "name": "Noteforge", "type": 0, "mod": modified, "usn": -1,
+            "sortf": 0, "did": deck_id, "tags": [], "vers": [], "css": CARD_CSS,
+            "flds": [field("Front", 0), field("Back", 1), field("Kind", 2), field("Source", 3)],
+            "tmpls": [{
+                "name": "Card 1", "ord": 0, "did": null, "bqfmt": "", "bafmt": "",
+                "qfmt": "<div class=\"kind\">{{Kind}}</div>\n{{Front}}",
+                "afmt": "{{FrontSide}}\n<hr id=answer>\n{{Back}}\n<div class=\"source\">{{Source}}</div>",
+            }],
+            "req": [[0, "all", [0]]],
+            "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+        }
+    });
+    let conf = json!({
+        "activeDecks": [deck_id], "curDeck": deck_id, "curModel": MODEL_ID.to_string(),
+        "newSpread": 0, "collapseTime": 1200, "timeLim": 0, "estTimes": true,
+        "dueCounts": true, "nextPos": cards.len() + 1, "sortType": "noteFld",
+        "sortBackwards": false, "addToCur": true,
+    });
+    let dconf = json!({
+        "1": {
+            "id": 1, "name": "Default", "mod": 0, "usn": 0, "maxTaken": 60, "autoplay": true,
+            "timer": 0, "replayq": true,
+            "new": {
+                "bury": true, "delays": [1, 10], "initialFactor": 2500, "ints": [1, 4, 7],
+                "order": 1, "perDay": 20, "separate": true,
+            },
+            "lapse": { "delays": [10], "leechAction": 0, "leechFails": 8, "minInt": 1, "mult": 0 },
+            "rev": {
+                "bury": true, "ease4": 1.3, "fuzz": 0.05, "ivlFct": 1, "maxIvl": 36500,
+                "minSpace": 1, "perDay": 100,
+            },
+        }
+    });
+
+    connection.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?3, 11, 0, 0, 0, ?4, ?5, ?6, ?7, '{}')",
+        params![
+            modified,
+            modified,
+            modified * 1000,
+            conf.to_string(),
+            models.to_string(),
+            decks.to_string(),
+            dconf.to_string()
+        ],
+    )?;
+
+    let document_tag = tag(title);
+    for (index, card) in cards.iter().enumerate() {
+        let id = base_id + index as i64;
+        let source = format!("{}, page {}", escape(title), card.page);
+        let fields = [
+            card.front.as_str(),
+            card.back.as_str(),
+            &environment_title(card.kind),
+            source.as_str(),
+        ]
+        .join("\x1f");
+        let sort_field = strip_html(&card.front);
+        let tags = format!(
+            " noteforge doc::{} page::{} {} ",
+            document_tag, card.page, card.kind
+        );
+
+        connection.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+            params![
+                id,
+                guid(document, index),
+                MODEL_ID,
+                modified,
+                tags,
+                fields,
+                sort_field,
+                checksum(&sort_field)
+            ],
+        )?;
+        connection.execute(
+            "INSERT INTO cards VALUES (?1, ?2, ?3, 0, ?4, -1, 0, 0, ?5, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+            params![id, id, deck_id, modified, index as i64 + 1],
+        )?;
+    }
+
+    Ok(connection.serialize(DatabaseName::Main)?.to_vec())
+}
+
+#[derive(Default)]
+struct Collector {
+    cards: Vec<Card>,
+    warnings: Vec<String>,
+    skipped: usize,
+    page: usize,
+}
+
+impl Collector {
+    fn collect(&mut self, blocks: &[Block]) {
+        self.page = 1;
+        for (index, block) in blocks.iter().enumerate() {
+            let next = blocks.get(index + 1);
+            match block {
+                Block::PageBreak => self.page += 1,
+                Block::Environment {
+                    name,
+                    title,
+                    blocks,
+                } => {
+                    if let Some(kind) = CARD_ENVIRONMENTS.iter().find(|kind| *kind == name) {
+                        self.statement(kind, title.as_deref(), blocks, next);
+                    }
+                }
+                // A lead-in ending in a colon names the equation after it
+                Block::Paragraph(inlines) => {
+                    if let Some(Block::Math { .. }) = next {
+                        let front = self.inlines(inlines);
+                        if let Some(front) = front.trim_end().strip_suffix(':') {
+                            let back = self.block(next.unwrap());
+                            self.push("formula", front.trim_end().to_string(), back);
+                        }
+                    }
+                }
+                // Description lists (`\item[term] ...`) read as glossaries
+                Block::List { items, .. } => {
+                    for ListItem { label, blocks } in items {
+                        if let Some(label) = label {
+                            let front = self.inlines(label);
+                            let back = self.blocks(blocks);
+                            self.push("term", front, back);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Titled statements ask for the title; untitled ones show the prose and
+    /// hide the displayed math, plus the proof that follows if there is one.
+    fn statement(
+        &mut self,
+        kind: &'static str,
+        title: Option<&[Inline]>,
+        blocks: &[Block],
+        next: Option<&Block>,
+    ) {
+        if let Some(title) = title {
+            let front = self.inlines(title);
+            let back = self.blocks(blocks);
+            self.push(kind, front, back);
+            return;
+        }
+
+        let split = blocks
+            .iter()
+            .position(|block| matches!(block, Block::Math { .. }))
+            .unwrap_or(blocks.len());
+        let front = self.blocks(&blocks[..split]);
+        let mut back = self.blocks(&blocks[split..]);
+        if let Some(Block::Environment { name, blocks, .. }) = next {
+            if name == "proof" {
+                back.push_str(&format!("<p><i>Proof.</i></p>{}", self.blocks(blocks)));
+            }
+        }
+        if back.is_empty() {
+            self.skipped += 1;
+        } else {
+            self.push(kind, front, back);
+        }
+    }
+
+    fn push(&mut self, kind: &'static str, front: String, back: String) {
+        if front.is_empty() || back.is_empty() {
+            return;
+        }
+        self.cards.push(Card {
+            kind,
+            page: self.page,
+            front,
+            back,
+        });
+    }
+
+    fn blocks(&mut self, blocks: &[Block]) -> String {
+        blocks.iter().map(|block| self.block(block)).collect()
+    }
+
+    fn block(&mut self, block: &Block) -> String {
+        match block {
+            Block::Heading { text, .. } => format!("<p><b>{}</b></p>", self.inlines(text)),
+            Block::Paragraph(inlines) => format!("<p>{}</p>", self.inlines(inlines)),
+            Block::Math { kind, tex } => {
+                format!("<div>\\[{}\\]</div>", escape(&wrap_math(*kind, tex)))
+            }
+            Block::List { ordered, items } => {
+                let tag = if *ordered { "ol" } else { "ul" };
+                let mut out = format!("<{}>", tag);
+                for item in items {
+                    out.push_str("<li>");
+                    if let Some(label) = &item.label {
+                        out.push_str(&format!("<b>{}</b> ", self.inlines(label)));
+                    }
+                    out.push_str(&self.blocks(&item.blocks));
+                    out.push_str("</li>");
+                }
+                out.push_str(&format!("</{}>", tag));
+                out
+            }
+            Block::Environment {
+                name,
+                title,
+                blocks,
+            } => {
+                let mut heading = environment_title(name);
+                if let Some(title) = title {
+                    heading.push_str(&format!(" ({})", self.inlines(title)));
+                }
+                format!("<div><i>{}.</i> {}</div>", heading, self.blocks(blocks))
+            }
+            Block::Code { text, .. } => format!("<pre>{}</pre>", escape(text)),
+            Block::Unsupported { name, raw } => {
+                let warning = format!("Unsupported environment `{}` kept as LaTeX source", name);
+                if !self.warnings.contains(&warning) {
+                    self.warnings.push(warning);
+                }
+                format!("<pre>{}</pre>", escape(raw))
+            }
+            Block::PageBreak => String::new(),
+        }
+    }
+
+    fn inlines(&mut self, inlines: &[Inline]) -> String {
+        let mut out = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => out.push_str(&escape(text)),
+                Inline::Math(tex) => out.push_str(&format!("\\({}\\)", escape(tex.trim()))),
+                Inline::Emph(children) => {
+                    out.push_str(&format!("<i>{}</i>", self.inlines(children)))
+                }
+                Inline::Strong(children) => {
+                    out.push_str(&format!("<b>{}</b>", self.inlines(children)))
+                }
+                Inline::Code(code) => out.push_str(&format!("<code>{}</code>", escape(code))),
+                Inline::LineBreak => out.push_str("<br>"),
+            }
+        }
+        out
+    }
+}
+
+/// Anki tags cannot contain spaces.
+fn tag(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join("_")
+        .replace('"', "")
+}
+
+/// Stable per-document note GUID, so re-imports update notes instead of duplicating them.
+fn guid(document: &Document, index: usize) -> String {
+    sha1_smol::Sha1::from(format!("noteforge:{}:{}", document.id, index))
+        .digest()
+        .to_string()[..16]
+        .to_string()
+}
+
+/// Anki's duplicate check: the first 32 bits of the SHA-1 of the sort field.
+fn checksum(text: &str) -> i64 {
+    let digest = sha1_smol::Sha1::from(text).digest().bytes();
+    i64::from(u32::from_be_bytes([
+        digest[0], digest[1], digest[2], digest[3],
+    ]))
+}
+
+fn strip_html(html: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::services::parser;
+
+    #[test]
+    fn packages_a_collection() {
+        let source = "\\documentclass{article}
+\\title{Analysis}
+\\begin{document}
+\\begin{definition}[Limit]
+$L$ is the limit of $a_n$ if $|a_n - L| \\to 0$.
+\\end{definition}
+\\newpage
+The geometric series:
+\\[ \\sum_{n=0}^\\infty x^n = \\frac{1}{1-x} \\]
+\\end{document}";
+        let document = Document {
+            id: Uuid::new_v4(),
+            filename: "analysis.tex".to_string(),
+            content: source.to_string(),
+            style: "article".to_string(),
+            created_at: Utc::now(),
+        };
+        let (package, warnings) = render(&document, &parser::parse(source)).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(package)).unwrap();
+        let mut collection = Vec::new();
+        archive
+            .by_name("collection.anki2")
+            .unwrap()
+            .read_to_end(&mut collection)
+            .unwrap();
+        assert!(collection.starts_with(b"SQLite format 3\0"));
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), &collection).unwrap();
+        let connection = Connection::open(file.path()).unwrap();
+        let version: i64 = connection
+            .query_row("SELECT ver FROM col", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 11);
+
+        let mut statement = connection
+            .prepare("SELECT sfld, tags FROM notes ORDER BY id")
+            .unwrap();
+        let notes: Vec<(String, String)> = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            notes,
+            [
+                (
+                    "Limit".to_string(),
+                    " noteforge doc::Analysis page::1 definition ".to_string()
+                ),
+                (
+                    "The geometric series".to_string(),
+                    " noteforge doc::Analysis page::2 formula ".to_string()
+                ),
+            ]
+        );
+        let cards: i64 = connection
+            .query_row("SELECT COUNT(*) FROM cards", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(cards, 2);
+    }
+}
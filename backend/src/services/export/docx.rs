@@ -0,0 +1,450 @@
+//! Word documents with equations as native Office Math, so they stay editable.
+
+use crate::errors::Result;
+use crate::models::content::{Block, Content, Inline, ListItem};
+use crate::models::document::Document;
+use crate::services::archive::Archive;
+use crate::services::export::environment_title;
+use crate::services::math::{self, escape_xml as escape};
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+<Default Extension="xml" ContentType="application/xml"/>
+<Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/>
+<Override PartName="/word/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/>
+<Override PartName="/word/numbering.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml"/>
+<Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/>
+</Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/>
+<Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/>
+</Relationships>"#;
+
+const DOCUMENT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>
+<Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering" Target="numbering.xml"/>
+</Relationships>"#;
+
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:docDefaults><w:rPrDefault><w:rPr><w:rFonts w:ascii="Cambria" w:hAnsi="Cambria"/><w:sz w:val="22"/></w:rPr></w:rPrDefault>
+<w:pPrDefault><w:pPr><w:spacing w:after="120" w:line="276" w:lineRule="auto"/></w:pPr></w:pPrDefault></w:docDefaults>
+<w:style w:type="paragraph" w:default="1" w:styleId="Normal"><w:name w:val="Normal"/><w:qFormat/></w:style>
+<w:style w:type="paragraph" w:styleId="Title"><w:name w:val="Title"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:spacing w:after="240"/></w:pPr><w:rPr><w:sz w:val="48"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Subtitle"><w:name w:val="Subtitle"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:rPr><w:i/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="360"/><w:outlineLvl w:val="0"/></w:pPr><w:rPr><w:b/><w:sz w:val="32"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading2"><w:name w:val="heading 2"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="240"/><w:outlineLvl w:val="1"/></w:pPr><w:rPr><w:b/><w:sz w:val="28"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading3"><w:name w:val="heading 3"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="200"/><w:outlineLvl w:val="2"/></w:pPr><w:rPr><w:b/><w:sz w:val="24"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading4"><w:name w:val="heading 4"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:outlineLvl w:val="3"/></w:pPr><w:rPr><w:b/><w:i/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="ListParagraph"><w:name w:val="List Paragraph"/><w:basedOn w:val="Normal"/><w:qFormat/><w:pPr><w:spacing w:after="60"/><w:ind w:left="720"/><w:contextualSpacing/></w:pPr></w:style>
+<w:style w:type="paragraph" w:styleId="Theorem"><w:name w:val="Theorem"/><w:basedOn w:val="Normal"/><w:qFormat/><w:pPr><w:pBdr><w:left w:val="single" w:sz="12" w:space="8" w:color="999999"/></w:pBdr><w:ind w:left="284"/></w:pPr></w:style>
+<w:style w:type="paragraph" w:styleId="Code"><w:name w:val="Code"/><w:basedOn w:val="Normal"/><w:qFormat/><w:pPr><w:spacing w:after="0" w:line="240" w:lineRule="auto"/><w:shd w:val="clear" w:color="auto" w:fill="F2F2F2"/></w:pPr><w:rPr><w:rFonts w:ascii="Consolas" w:hAnsi="Consolas"/><w:sz w:val="20"/></w:rPr></w:style>
+<w:style w:type="character" w:styleId="CodeChar"><w:name w:val="Code Char"/><w:rPr><w:rFonts w:ascii="Consolas" w:hAnsi="Consolas"/></w:rPr></w:style>
+</w:styles>"#;
+
+const DOCUMENT_NAMESPACES: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:m="http://schemas.openxmlformats.org/officeDocument/2006/math" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships""#;
+
+pub fn render(document: &Document, content: &Content) -> Result<(Vec<u8>, Vec<String>)> {
+    let mut writer = Writer::default();
+
+    let mut body = String::new();
+    if let Some(title) = &content.title {
+        body.push_str(&paragraph(Some("Title"), &text_run(title, "")));
+    }
+    if let Some(author) = &content.author {
+        body.push_str(&paragraph(Some("Subtitle"), &text_run(author, "")));
+    }
+    for block in &content.blocks {
+        body.push_str(&writer.block(block, None, 0));
+    }
+
+    let document_xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:document {}><w:body>{}<w:sectPr><w:pgSz w:w=\"11906\" w:h=\"16838\"/><w:pgMar w:top=\"1440\" w:right=\"1440\" w:bottom=\"1440\" w:left=\"1440\" w:header=\"708\" w:footer=\"708\" w:gutter=\"0\"/></w:sectPr></w:body></w:document>",
+        DOCUMENT_NAMESPACES, body
+    );
+
+    let title = content
+        .title
+        .clone()
+        .unwrap_or_else(|| document.filename.trim_end_matches(".tex").to_string());
+    let core_xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<cp:coreProperties xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:dcterms=\"http://purl.org/dc/terms/\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\"><dc:title>{}</dc:title><dc:creator>{}</dc:creator><dc:identifier>urn:uuid:{}</dc:identifier><dcterms:created xsi:type=\"dcterms:W3CDTF\">{}</dcterms:created></cp:coreProperties>",
+        escape(&title),
+        escape(content.author.as_deref().unwrap_or("noteforge")),
+        document.id,
+        document.created_at.format("%Y-%m-%dT%H:%M:%SZ")
+    );
+
+    let mut archive = Archive::new();
+    archive.add("[Content_Types].xml", CONTENT_TYPES.as_bytes())?;
+    archive.add("_rels/.rels", ROOT_RELS.as_bytes())?;
+    archive.add("docProps/core.xml", core_xml.as_bytes())?;
+    archive.add("word/_rels/document.xml.rels", DOCUMENT_RELS.as_bytes())?;
+    archive.add("word/document.xml", document_xml.as_bytes())?;
+    archive.add("word/styles.xml", STYLES.as_bytes())?;
+    archive.add("word/numbering.xml", writer.numbering().as_bytes())?;
+
+    Ok((archive.finish()?, writer.warnings))
+}
+
+#[derive(Default)]
+struct Writer {
+    /// One entry per list instance (true if ordered), so numbering restarts per list.
+    lists: Vec<bool>,
+    warnings: Vec<String>,
+}
+
+impl Writer {
+    /// `style` overrides the paragraph style (inside environments), `level` is the list depth.
+    fn block(&mut self, block: &Block, style: Option<&str>, level: usize) -> String {
+        match block {
+            Block::Heading { level, text } => {
+                let style = format!("Heading{}", (*level).clamp(1, 4));
+                paragraph(Some(&style), &self.inlines(text, ""))
+            }
+            Block::Paragraph(inlines) => paragraph(style, &self.inlines(inlines, "")),
+            Block::Math { kind, tex } => {
+                let node = math::parse_display(*kind, tex);
+                self.check_math(&node);
+                paragraph(style, &math::to_omml(&node, true))
+            }
+            Block::List { ordered, items } => self.list(*ordered, items, level),
+            Block::Environment {
+                name,
+                title,
+                blocks,
+            } => {
+                let emphasis = if matches!(name.as_str(), "proof" | "solution") {
+                    "<w:i/>"
+                } else {
+                    "<w:b/>"
+                };
+                let mut label = text_run(&environment_title(name), emphasis);
+                if let Some(title) = title {
+                    label.push_str(&text_run(" (", emphasis));
+                    label.push_str(&self.inlines(title, emphasis));
+                    label.push_str(&text_run(")", emphasis));
+                }
+                label.push_str(&text_run(". ", emphasis));
+
+                let mut out = String::new();
+                match blocks.split_first() {
+                    Some((Block::Paragraph(inlines), rest)) => {
+                        label.push_str(&self.inlines(inlines, ""));
+                        out.push_str(&paragraph(Some("Theorem"), &label));
+                        for block in rest {
+                            out.push_str(&self.block(block, Some("Theorem"), level));
+                        }
+                    }
+                    _ => {
+                        out.push_str(&paragraph(Some("Theorem"), &label));
+                        for block in blocks {
+                            out.push_str(&self.block(block, Some("Theorem"), level));
+                        }
+                    }
+                }
+                out
+            }
+            Block::Code { text, .. } => code_paragraphs(text),
+            Block::Unsupported { name, raw } => {
+                self.warnings.push(format!(
+                    "Unsupported environment `{}` kept as LaTeX source",
+                    name
+                ));
+                let note = text_run(
+                    &format!(
+                        "The {} environment could not be converted; LaTeX source:",
+                        name
+                    ),
+                    "<w:i/>",
+                );
+                format!("{}{}", paragraph(style, &note), code_paragraphs(raw))
+            }
+            Block::PageBreak => "<w:p><w:r><w:br w:type=\"page\"/></w:r></w:p>".to_string(),
+        }
+    }
+
+    fn list(&mut self, ordered: bool, items: &[ListItem], level: usize) -> String {
+        self.lists.push(ordered);
+        let num_id = self.lists.len();
+        let level = level.min(8);
+        let mut out = String::new();
+
+        for item in items {
+            let numbering = format!(
+                "<w:pStyle w:val=\"ListParagraph\"/><w:numPr><w:ilvl w:val=\"{}\"/><w:numId w:val=\"{}\"/></w:numPr>",
+                level, num_id
+            );
+            let mut label = String::new();
+            if let Some(text) = &item.label {
+                label = self.inlines(text, "<w:b/>");
+                label.push_str(&text_run(" ", ""));
+            }
+
+            // The first paragraph carries the bullet; the rest are indented continuations
+            let (first, rest) = match item.blocks.split_first() {
+                Some((Block::Paragraph(inlines), rest)) => (self.inlines(inlines, ""), rest),
+                _ => (String::new(), item.blocks.as_slice()),
+            };
+            out.push_str(&format!(
+                "<w:p><w:pPr>{}</w:pPr>{}{}</w:p>",
+                numbering, label, first
+            ));
+            for block in rest {
+                out.push_str(&self.block(block, Some("ListParagraph"), level + 1));
+            }
+        }
+        out
+    }
+
+    fn inlines(&mut self, inlines: &[Inline], properties: &str) -> String {
+        let mut out = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => out.push_str(&text_run(text, properties)),
+                Inline::Math(tex) => {
+                    let node = math::parse(tex);
+                    self.check_math(&node);
+                    out.push_str(&math::to_omml(&node, false));
+                }
+                Inline::Emph(children) => {
+                    out.push_str(&self.inlines(children, &format!("{}<w:i/>", properties)))
+                }
+                Inline::Strong(children) => {
+                    out.push_str(&self.inlines(children, &format!("<w:b/>{}", properties)))
+                }
+                Inline::Code(code) => out.push_str(&text_run(
+                    code,
+                    &format!("<w:rStyle w:val=\"CodeChar\"/>{}", properties),
+                )),
+                Inline::LineBreak => out.push_str("<w:r><w:br/></w:r>"),
+            }
+        }
+        out
+    }
+
+    fn check_math(&mut self, node: &math::MathNode) {
+        for command in node.unknown_commands() {
+            let warning = format!("Unknown math command `{}` left unconverted", command);
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
+        }
+    }
+
+    /// Abstract bullet and decimal definitions plus one concrete list per instance.
+    fn numbering(&self) -> String {
+        let mut out = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:numbering xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">",
+        );
+        for (abstract_id, ordered) in [(0, false), (1, true)] {
+            out.push_str(&format!(
+                "<w:abstractNum w:abstractNumId=\"{}\"><w:multiLevelType w:val=\"hybridMultilevel\"/>",
+                abstract_id
+            ));
+            for level in 0..9 {
+                let (format, text) = if ordered {
+                    let format = ["decimal", "lowerLetter", "lowerRoman"][level % 3];
+                    (format, format!("%{}.", level + 1))
+                } else {
+                    (
+                        "bullet",
+                        ["\u{2022}", "\u{25e6}", "\u{25aa}"][level % 3].to_string(),
+                    )
+                };
+                out.push_str(&format!(
+                    "<w:lvl w:ilvl=\"{0}\"><w:start w:val=\"1\"/><w:numFmt w:val=\"{1}\"/><w:lvlText w:val=\"{2}\"/><w:lvlJc w:val=\"left\"/><w:pPr><w:ind w:left=\"{3}\" w:hanging=\"360\"/></w:pPr></w:lvl>",
+                    level,
+                    format,
+                    text,
+                    720 * (level + 1)
+                ));
+            }
+            out.push_str("</w:abstractNum>");
+        }
+        for (index, ordered) in self.lists.iter().enumerate() {
+            out.push_str(&format!(
+                "<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"{}\"/>",
+                index + 1,
+                u8::from(*ordered)
+            ));
+            if *ordered {
+                out.push_str(
+                    "<w:lvlOverride w:ilvl=\"0\"><w:startOverride w:val=\"1\"/></w:lvlOverride>",
+                );
+            }
+            out.push_str("</w:num>");
+        }
+        out.push_str("</w:numbering>");
+        out
+    }
+}
+
+fn paragraph(style: Option<&str>, runs: &str) -> String {
+    match style {
+        Some(style) => format!(
+            "<w:p><w:pPr><w:pStyle w:val=\"{}\"/></w:pPr>{}</w:p>",
+            style, runs
+        ),
+        None => format!("<w:p>{}</w:p>", runs),
+    }
+}
+
+fn text_run(text: &str, properties: &str) -> String {
+    let properties = if properties.is_empty() {
+        String::new()
+    } else {
+        format!("<w:rPr>{}</w:rPr>", properties)
+    };
+    format!(
+        "<w:r>{}<w:t xml:space=\"preserve\">{}</w:t></w:r>",
+        properties,
+        escape(text)
+    )
+}
+
+fn code_paragraphs(text: &str) -> String {
+    text.lines()
+        .map(|line| paragraph(Some("Code"), &text_run(line, "")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::services::parser;
+
+    const SOURCE: &str = "\\documentclass{article}
+\\title{Week 3\u{1}}
+\\author{A. Student}
+\\begin{document}
+\\section{Limits \\& continuity}
+A \\textbf{bold} claim with $x^2 < 1$ and a stray \u{b}control.
+\\begin{align*}
+a &= b \\\\
+c &= \\foo{d}
+\\end{align*}
+\\begin{enumerate}
+\\item First
+\\begin{itemize}\\item Nested\\end{itemize}
+\\item Second
+\\end{enumerate}
+\\begin{enumerate}\\item Again\\end{enumerate}
+\\begin{theorem}[Squeeze]
+If $f \\le g \\le h$ then it holds.
+\\end{theorem}
+\\begin{verbatim}
+tab\there\u{7}
+\\end{verbatim}
+\\begin{tikzpicture}\\draw (0,0) -- (1,1);\\end{tikzpicture}
+\\end{document}";
+
+    fn render_source(source: &str) -> (Vec<(String, String)>, Vec<String>) {
+        let document = Document {
+            id: Uuid::new_v4(),
+            filename: "week3.tex".to_string(),
+            content: source.to_string(),
+            style: "article".to_string(),
+            created_at: Utc::now(),
+        };
+        let (bytes, warnings) = render(&document, &parser::parse(source)).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let parts = (0..archive.len())
+            .map(|index| {
+                let mut file = archive.by_index(index).unwrap();
+                let mut xml = String::new();
+                file.read_to_string(&mut xml).unwrap();
+                (file.name().to_string(), xml)
+            })
+            .collect();
+        (parts, warnings)
+    }
+
+    fn part<'a>(parts: &'a [(String, String)], name: &str) -> &'a str {
+        &parts.iter().find(|(part, _)| part == name).unwrap().1
+    }
+
+    #[test]
+    fn writes_well_formed_parts() {
+        let (parts, _) = render_source(SOURCE);
+        let names: Vec<&str> = parts.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "[Content_Types].xml",
+                "_rels/.rels",
+                "docProps/core.xml",
+                "word/_rels/document.xml.rels",
+                "word/document.xml",
+                "word/styles.xml",
+                "word/numbering.xml",
+            ]
+        );
+        for (name, xml) in &parts {
+            if let Err(e) = roxmltree::Document::parse(xml) {
+                panic!("{} is not well-formed: {}", name, e);
+            }
+        }
+        assert!(part(&parts, "docProps/core.xml").contains("<dc:title>Week 3</dc:title>"));
+    }
+
+    #[test]
+    fn renders_blocks() {
+        let (parts, warnings) = render_source(SOURCE);
+        let xml = part(&parts, "word/document.xml");
+        let document = roxmltree::Document::parse(xml).unwrap();
+        let styles: Vec<&str> = document
+            .descendants()
+            .filter(|node| node.tag_name().name() == "pStyle")
+            .filter_map(|node| node.attributes().next().map(|attribute| attribute.value()))
+            .collect();
+        assert_eq!(
+            styles,
+            [
+                "Title",
+                "Subtitle",
+                "Heading1",
+                "ListParagraph",
+                "ListParagraph",
+                "ListParagraph",
+                "ListParagraph",
+                "Theorem",
+                "Code",
+                "Code",
+            ]
+        );
+
+        assert!(xml.contains("<w:t xml:space=\"preserve\">Limits &amp; continuity</w:t>"));
+        assert!(xml.contains("<w:rPr><w:b/></w:rPr><w:t xml:space=\"preserve\">bold</w:t>"));
+        assert!(xml.contains("a stray control."));
+        assert!(xml.contains("<m:oMath><m:sSup>"));
+        assert!(xml.contains("<m:oMathPara><m:oMath><m:eqArr>"));
+        assert!(xml.contains("<w:t xml:space=\"preserve\">tab\there</w:t>"));
+        assert!(xml.contains("\\draw (0,0) -- (1,1);"));
+
+        // Each list gets its own numbering instance, so the second one restarts
+        let numbering = part(&parts, "word/numbering.xml");
+        assert_eq!(numbering.matches("<w:num w:numId=").count(), 3);
+        assert_eq!(
+            numbering.matches("<w:startOverride w:val=\"1\"/>").count(),
+            2
+        );
+
+        assert_eq!(
+            warnings,
+            [
+                "Unknown math command `\\foo` left unconverted",
+                "Unsupported environment `tikzpicture` kept as LaTeX source",
+            ]
+        );
+    }
+}
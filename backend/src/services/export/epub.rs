@@ -0,0 +1,330 @@
+//! EPUB 3 with MathML equations: one XHTML chapter per section or page.
+
+use crate::errors::Result;
+use crate::models::content::{Block, Content};
+use crate::models::document::Document;
+use crate::services::archive::Archive;
+use crate::services::export::html::{self, TocEntry, Writer};
+use crate::services::math::escape_xml as escape;
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+struct Chapter {
+    file: String,
+    xhtml: String,
+    has_math: bool,
+}
+
+pub fn render(document: &Document, content: &Content) -> Result<(Vec<u8>, Vec<String>)> {
+    let title = content
+        .title
+        .clone()
+        .unwrap_or_else(|| document.filename.trim_end_matches(".tex").to_string());
+
+    let mut writer = Writer::default();
+    let mut chapters = Vec::new();
+    let mut nav = Vec::new();
+
+    if content.title.is_some() || content.author.is_some() {
+        let mut body = format!("<h1>{}</h1>\n", escape(&title));
+        if let Some(author) = &content.author {
+            body.push_str(&format!("<p class=\"author\">{}</p>\n", escape(author)));
+        }
+        chapters.push(Chapter {
+            file: "title.xhtml".to_string(),
+            xhtml: page(&title, "titlepage", &body),
+            has_math: false,
+        });
+    }
+
+    for (index, (number, blocks)) in split_chapters(&content.blocks).into_iter().enumerate() {
+        let file = format!("chapter-{}.xhtml", index + 1);
+        let first_entry = writer.toc.len();
+        let body = writer.blocks(&blocks);
+
+        // Point the chapter's headings at its own file
+        for entry in &mut writer.toc[first_entry..] {
+            entry.href = format!("{}{}", file, entry.href);
+        }
+        let entries = writer.toc.drain(first_entry..).collect::<Vec<_>>();
+        if entries.is_empty() {
+            nav.push(TocEntry {
+                level: 1,
+                href: file.clone(),
+                html: format!("Page {}", number),
+            });
+        }
+        nav.extend(entries);
+
+        chapters.push(Chapter {
+            has_math: body.contains("<math"),
+            xhtml: page(&title, "chapter", &body),
+            file,
+        });
+    }
+
+    let mut archive = Archive::new();
+    // The mimetype must come first and uncompressed so readers can sniff it
+    archive.add_stored("mimetype", b"application/epub+zip")?;
+    archive.add("META-INF/container.xml", CONTAINER.as_bytes())?;
+    archive.add(
+        "OEBPS/content.opf",
+        package(document, content, &title, &chapters).as_bytes(),
+    )?;
+    archive.add("OEBPS/nav.xhtml", navigation(&title, &nav).as_bytes())?;
+    archive.add("OEBPS/style.css", html::STYLESHEET.trim_start().as_bytes())?;
+    for chapter in &chapters {
+        archive.add(&format!("OEBPS/{}", chapter.file), chapter.xhtml.as_bytes())?;
+    }
+
+    Ok((archive.finish()?, writer.warnings))
+}
+
+/// Group top-level blocks into chapters at page breaks and top-level headings,
+/// keeping the page each chapter starts on.
+fn split_chapters(blocks: &[Block]) -> Vec<(usize, Vec<Block>)> {
+    let mut chapters = Vec::new();
+    let mut current = Vec::new();
+    let mut page = 1;
+    let mut start_page = 1;
+
+    for block in blocks {
+        match block {
+            Block::PageBreak => {
+                if !current.is_empty() {
+                    chapters.push((start_page, std::mem::take(&mut current)));
+                }
+                page += 1;
+                start_page = page;
+            }
+            Block::Heading { level: 1, .. } => {
+                if !current.is_empty() {
+                    chapters.push((start_page, std::mem::take(&mut current)));
+                }
+                start_page = page;
+                current.push(block.clone());
+            }
+            block => current.push(block.clone()),
+        }
+    }
+    if !current.is_empty() {
+        chapters.push((start_page, current));
+    }
+    chapters
+}
+
+fn page(title: &str, kind: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE html>
+<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"en\" xml:lang=\"en\">
+<head>
+<meta charset=\"utf-8\"/>
+<title>{}</title>
+<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>
+</head>
+<body>
+<section epub:type=\"{}\">
+{}</section>
+</body>
+</html>
+",
+        escape(title),
+        kind,
+        body
+    )
+}
+
+fn navigation(title: &str, entries: &[TocEntry]) -> String {
+    page(
+        title,
+        "frontmatter",
+        &format!(
+            "<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n{}</nav>\n",
+            html::toc(entries)
+        ),
+    )
+}
+
+fn package(document: &Document, content: &Content, title: &str, chapters: &[Chapter]) -> String {
+    let modified = document.created_at.format("%Y-%m-%dT%H:%M:%SZ");
+
+    let mut metadata = format!(
+        "    <dc:identifier id=\"book-id\">urn:uuid:{}</dc:identifier>
+    <dc:title>{}</dc:title>
+    <dc:language>en</dc:language>
+    <dc:date>{}</dc:date>
+    <meta property=\"dcterms:modified\">{}</meta>
+    <meta name=\"generator\" content=\"noteforge {}\"/>
+",
+        document.id,
+        escape(title),
+        document.created_at.format("%Y-%m-%d"),
+        modified,
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some(author) = &content.author {
+        metadata.push_str(&format!(
+            "    <dc:creator>{}</dc:creator>\n",
+            escape(author)
+        ));
+    }
+
+    let mut manifest = String::from(
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>
+    <item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>
+",
+    );
+    let mut spine = String::new();
+    for (index, chapter) in chapters.iter().enumerate() {
+        let properties = if chapter.has_math {
+            " properties=\"mathml\""
+        } else {
+            ""
+        };
+        manifest.push_str(&format!(
+            "    <item id=\"item-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"{}/>\n",
+            index + 1,
+            chapter.file,
+            properties
+        ));
+        spine.push_str(&format!("    <itemref idref=\"item-{}\"/>\n", index + 1));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"en\">
+  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+{}  </metadata>
+  <manifest>
+{}  </manifest>
+  <spine>
+{}  </spine>
+</package>
+",
+        metadata, manifest, spine
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use chrono::Utc;
+    use uuid::Uuid;
+    use zip::CompressionMethod;
+
+    use super::*;
+    use crate::services::parser;
+
+    const SOURCE: &str = "\\documentclass{article}
+\\title{Week 3}
+\\author{A. Student}
+\\begin{document}
+\\section{Limits}
+Where $x \\to 0$.
+\\newpage
+No heading on this page.
+\\end{document}";
+
+    #[test]
+    fn writes_a_valid_package() {
+        let document = Document {
+            id: Uuid::new_v4(),
+            filename: "week3.tex".to_string(),
+            content: SOURCE.to_string(),
+            style: "article".to_string(),
+            created_at: Utc::now(),
+        };
+        let (bytes, _) = render(&document, &parser::parse(SOURCE)).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut files = Vec::new();
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).unwrap();
+            let mut text = String::new();
+            file.read_to_string(&mut text).unwrap();
+            files.push((file.name().to_string(), file.compression(), text));
+        }
+        let file = |name: &str| {
+            let found = files.iter().find(|(file, ..)| file == name);
+            &found.unwrap_or_else(|| panic!("{} is missing", name)).2
+        };
+
+        // Readers sniff the type from the first, uncompressed entry
+        let (name, compression, text) = &files[0];
+        assert_eq!(name, "mimetype");
+        assert_eq!(*compression, CompressionMethod::Stored);
+        assert_eq!(text, "application/epub+zip");
+
+        for (name, _, text) in &files[1..] {
+            if name.ends_with(".css") {
+                continue;
+            }
+            // The XHTML pages start with `<!DOCTYPE html>`
+            let options = roxmltree::ParsingOptions {
+                allow_dtd: true,
+                ..Default::default()
+            };
+            if let Err(e) = roxmltree::Document::parse_with_options(text, options) {
+                panic!("{} is not well-formed: {}", name, e);
+            }
+        }
+
+        let container = roxmltree::Document::parse(file("META-INF/container.xml")).unwrap();
+        let rootfile = container
+            .descendants()
+            .find(|node| node.has_tag_name("rootfile"))
+            .unwrap();
+        assert_eq!(rootfile.attribute("full-path"), Some("OEBPS/content.opf"));
+
+        // Every manifest item is in the archive, and the spine reads the pages in order
+        let opf = roxmltree::Document::parse(file("OEBPS/content.opf")).unwrap();
+        let title = opf.descendants().find(|node| node.has_tag_name("title"));
+        assert_eq!(title.and_then(|node| node.text()), Some("Week 3"));
+        let items: Vec<_> = opf
+            .descendants()
+            .filter(|node| node.has_tag_name("item"))
+            .map(|node| {
+                let href = node.attribute("href").unwrap();
+                file(&format!("OEBPS/{}", href));
+                (
+                    node.attribute("id").unwrap(),
+                    href,
+                    node.attribute("properties"),
+                )
+            })
+            .collect();
+        assert!(items.contains(&("nav", "nav.xhtml", Some("nav"))));
+        let spine: Vec<_> = opf
+            .descendants()
+            .filter(|node| node.has_tag_name("itemref"))
+            .map(|node| {
+                let id = node.attribute("idref").unwrap();
+                let (_, href, properties) = items.iter().find(|item| item.0 == id).unwrap();
+                (*href, *properties)
+            })
+            .collect();
+        assert_eq!(
+            spine,
+            [
+                ("title.xhtml", None),
+                ("chapter-1.xhtml", Some("mathml")),
+                ("chapter-2.xhtml", None),
+            ]
+        );
+
+        let nav = file("OEBPS/nav.xhtml");
+        assert!(nav.contains("href=\"chapter-1.xhtml#"), "{}", nav);
+        assert!(
+            nav.contains("<a href=\"chapter-2.xhtml\">Page 2</a>"),
+            "{}",
+            nav
+        );
+    }
+}
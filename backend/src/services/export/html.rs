@@ -0,0 +1,241 @@
+//! Standalone HTML5 with native MathML; no scripts, fonts or CDN assets.
+
+use crate::models::content::{Block, Content, Inline, ListItem};
+use crate::models::document::Document;
+use crate::services::export::environment_title;
+use crate::services::math::{self, escape_xml as escape};
+
+pub(crate) const STYLESHEET: &str = "
+:root { color-scheme: light dark; }
+body { font-family: Georgia, 'Times New Roman', serif; line-height: 1.6; max-width: 46rem; margin: 2rem auto; padding: 0 1rem; }
+header h1 { margin-bottom: 0.25rem; }
+.author { font-style: italic; margin-top: 0; }
+nav { border: 1px solid #8884; border-radius: 4px; padding: 0.5rem 1rem; margin-bottom: 2rem; }
+nav h2 { font-size: 1rem; margin: 0.5rem 0; }
+nav ol { padding-left: 1.25rem; margin: 0.25rem 0; }
+section.page + section.page { border-top: 1px dashed #8886; margin-top: 2rem; padding-top: 1rem; }
+math[display=block] { margin: 1rem 0; overflow-x: auto; }
+.env { border-left: 3px solid #8888; padding: 0.25rem 1rem; margin: 1rem 0; }
+.env-name { font-weight: bold; }
+.env.proof .env-name, .env.solution .env-name { font-weight: normal; font-style: italic; }
+.env.proof > :last-child::after { content: ' \\220E'; float: right; }
+pre { background: #8881; padding: 0.75rem; overflow-x: auto; }
+.unsupported { border: 1px solid #c60; padding: 0.5rem; }
+";
+
+pub fn render(document: &Document, content: &Content) -> (String, Vec<String>) {
+    let mut writer = Writer::default();
+    let main = writer.pages(&content.blocks);
+
+    let title = content
+        .title
+        .clone()
+        .unwrap_or_else(|| document.filename.trim_end_matches(".tex").to_string());
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+    html.push_str(&format!("<title>{}</title>\n", escape(&title)));
+    html.push_str(&format!(
+        "<meta name=\"generator\" content=\"noteforge {}\">\n",
+        env!("CARGO_PKG_VERSION")
+    ));
+    html.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", STYLESHEET));
+
+    html.push_str(&format!("<header>\n<h1>{}</h1>\n", escape(&title)));
+    if let Some(author) = &content.author {
+        html.push_str(&format!("<p class=\"author\">{}</p>\n", escape(author)));
+    }
+    html.push_str("</header>\n");
+
+    if !writer.toc.is_empty() {
+        html.push_str(
+            "<nav aria-labelledby=\"toc-heading\">\n<h2 id=\"toc-heading\">Contents</h2>\n",
+        );
+        html.push_str(&toc(&writer.toc));
+        html.push_str("</nav>\n");
+    }
+
+    html.push_str("<main>\n");
+    html.push_str(&main);
+    html.push_str("</main>\n</body>\n</html>\n");
+
+    (html, writer.warnings)
+}
+
+pub(crate) struct TocEntry {
+    pub level: u8,
+    pub href: String,
+    pub html: String,
+}
+
+/// Shared with the EPUB exporter, so output must also be well-formed XHTML.
+#[derive(Default)]
+pub(crate) struct Writer {
+    pub toc: Vec<TocEntry>,
+    pub warnings: Vec<String>,
+}
+
+impl Writer {
+    /// Wrap each page of notes in its own labelled section.
+    fn pages(&mut self, blocks: &[Block]) -> String {
+        let mut out = String::new();
+        let pages: Vec<&[Block]> = blocks
+            .split(|block| matches!(block, Block::PageBreak))
+            .filter(|page| !page.is_empty())
+            .collect();
+        for (index, page) in pages.iter().enumerate() {
+            out.push_str(&format!(
+                "<section class=\"page\" id=\"page-{0}\" aria-label=\"Page {0}\">\n",
+                index + 1
+            ));
+            out.push_str(&self.blocks(page));
+            out.push_str("</section>\n");
+        }
+        out
+    }
+
+    pub fn blocks(&mut self, blocks: &[Block]) -> String {
+        blocks.iter().map(|block| self.block(block)).collect()
+    }
+
+    fn block(&mut self, block: &Block) -> String {
+        match block {
+            Block::Heading { level, text } => {
+                let tag = format!("h{}", (level + 1).min(6));
+                let id = format!("section-{}", self.toc.len() + 1);
+                let html = self.inlines(text);
+                self.toc.push(TocEntry {
+                    level: *level,
+                    href: format!("#{}", id),
+                    html: html.clone(),
+                });
+                format!("<{0} id=\"{1}\">{2}</{0}>\n", tag, id, html)
+            }
+            Block::Paragraph(inlines) => format!("<p>{}</p>\n", self.inlines(inlines)),
+            Block::Math { kind, tex } => {
+                let node = math::parse_display(*kind, tex);
+                self.check_math(&node);
+                format!("{}\n", math::to_mathml(&node, tex, true))
+            }
+            Block::List { ordered, items } => self.list(*ordered, items),
+            Block::Environment {
+                name,
+                title,
+                blocks,
+            } => {
+                let mut heading = environment_title(name);
+                if let Some(title) = title {
+                    heading.push_str(&format!(" ({})", self.inlines(title)));
+                }
+                format!(
+                    "<div class=\"env {0}\" role=\"group\" aria-label=\"{1}\">\n<p><span class=\"env-name\">{2}.</span></p>\n{3}</div>\n",
+                    escape(name),
+                    escape(&environment_title(name)),
+                    heading,
+                    self.blocks(blocks)
+                )
+            }
+            Block::Code { language, text } => {
+                let class = language
+                    .as_deref()
+                    .map(|language| format!(" class=\"language-{}\"", escape(language)))
+                    .unwrap_or_default();
+                format!("<pre><code{}>{}</code></pre>\n", class, escape(text))
+            }
+            Block::Unsupported { name, raw } => {
+                self.warnings.push(format!(
+                    "Unsupported environment `{}` kept as LaTeX source",
+                    name
+                ));
+                format!(
+                    "<figure class=\"unsupported\">\n<figcaption>The <code>{0}</code> environment could not be converted; LaTeX source:</figcaption>\n<pre><code class=\"language-latex\">{1}</code></pre>\n</figure>\n",
+                    escape(name),
+                    escape(raw)
+                )
+            }
+            Block::PageBreak => String::new(),
+        }
+    }
+
+    fn list(&mut self, ordered: bool, items: &[ListItem]) -> String {
+        let tag = if ordered { "ol" } else { "ul" };
+        let mut out = format!("<{}>\n", tag);
+        for item in items {
+            out.push_str("<li>");
+            if let Some(label) = &item.label {
+                out.push_str(&format!("<strong>{}</strong> ", self.inlines(label)));
+            }
+            // A lone paragraph is inlined so list items don't get extra margins
+            match item.blocks.as_slice() {
+                [Block::Paragraph(inlines)] => out.push_str(&self.inlines(inlines)),
+                blocks => {
+                    out.push('\n');
+                    out.push_str(&self.blocks(blocks));
+                }
+            }
+            out.push_str("</li>\n");
+        }
+        out.push_str(&format!("</{}>\n", tag));
+        out
+    }
+
+    fn inlines(&mut self, inlines: &[Inline]) -> String {
+        let mut out = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => out.push_str(&escape(text)),
+                Inline::Math(tex) => {
+                    let node = math::parse(tex);
+                    self.check_math(&node);
+                    out.push_str(&math::to_mathml(&node, tex, false));
+                }
+                Inline::Emph(children) => {
+                    out.push_str(&format!("<em>{}</em>", self.inlines(children)))
+                }
+                Inline::Strong(children) => {
+                    out.push_str(&format!("<strong>{}</strong>", self.inlines(children)))
+                }
+                Inline::Code(code) => out.push_str(&format!("<code>{}</code>", escape(code))),
+                Inline::LineBreak => out.push_str("<br/>"),
+            }
+        }
+        out
+    }
+
+    fn check_math(&mut self, node: &math::MathNode) {
+        for command in node.unknown_commands() {
+            let warning = format!("Unknown math command `{}` left unconverted", command);
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
+        }
+    }
+}
+
+/// Nested ordered lists following the heading levels.
+pub(crate) fn toc(entries: &[TocEntry]) -> String {
+    let mut out = String::new();
+    let mut stack: Vec<u8> = Vec::new();
+
+    for entry in entries {
+        while stack.last().is_some_and(|level| *level > entry.level) {
+            out.push_str("</li>\n</ol>\n");
+            stack.pop();
+        }
+        if stack.last() == Some(&entry.level) {
+            out.push_str("</li>\n");
+        } else {
+            out.push_str("<ol>\n");
+            stack.push(entry.level);
+        }
+        out.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>",
+            entry.href, entry.html
+        ));
+    }
+    for _ in stack {
+        out.push_str("</li>\n</ol>\n");
+    }
+    out
+}
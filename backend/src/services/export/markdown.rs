@@ -0,0 +1,309 @@
+//! Markdown with `$...$` / `$$...$$` math, as rendered by KaTeX and MathJax.
+
+use crate::models::content::{Block, Content, Inline, ListItem, MathKind};
+use crate::services::export::environment_title;
+
+/// Math commands KaTeX rejects; equations using them are flagged.
+const KATEX_UNSUPPORTED: [&str; 6] = [
+    "\\intertext",
+    "\\shortintertext",
+    "\\xymatrix",
+    "\\begin{tikzcd}",
+    "\\newcommand",
+    "\\DeclareMathOperator",
+];
+
+pub fn render(content: &Content) -> (String, Vec<String>) {
+    let mut writer = Writer {
+        heading_offset: u8::from(content.title.is_some()),
+        warnings: Vec::new(),
+    };
+
+    let mut parts = Vec::new();
+    if let Some(title) = &content.title {
+        parts.push(format!("# {}", escape(title)));
+    }
+    if let Some(author) = &content.author {
+        parts.push(format!("*{}*", escape(author)));
+    }
+    parts.push(writer.blocks(&content.blocks));
+
+    let mut markdown = parts
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    markdown.push('\n');
+    (markdown, writer.warnings)
+}
+
+/// Render a run of blocks on its own, with headings shifted down by `heading_offset`.
+pub(crate) fn fragment(blocks: &[Block], heading_offset: u8) -> (String, Vec<String>) {
+    let mut writer = Writer {
+        heading_offset,
+        warnings: Vec::new(),
+    };
+    (writer.blocks(blocks), writer.warnings)
+}
+
+struct Writer {
+    heading_offset: u8,
+    warnings: Vec<String>,
+}
+
+impl Writer {
+    fn blocks(&mut self, blocks: &[Block]) -> String {
+        blocks
+            .iter()
+            .map(|block| self.block(block))
+            .filter(|rendered| !rendered.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn block(&mut self, block: &Block) -> String {
+        match block {
+            Block::Heading { level, text } => {
+                let level = (level + self.heading_offset).min(6) as usize;
+                format!("{} {}", "#".repeat(level), self.inlines(text))
+            }
+            Block::Paragraph(inlines) => self.inlines(inlines),
+            Block::Math { kind, tex } => {
+                self.check_math(tex);
+                format!("$$\n{}\n$$", wrap_math(*kind, tex))
+            }
+            Block::List { ordered, items } => self.list(*ordered, items),
+            Block::Environment {
+                name,
+                title,
+                blocks,
+            } => {
+                let mut heading = environment_title(name);
+                if let Some(title) = title {
+                    heading.push_str(&format!(" ({})", self.inlines(title)));
+                }
+                let heading = if matches!(name.as_str(), "proof" | "solution") {
+                    format!("*{}.*", heading)
+                } else {
+                    format!("**{}.**", heading)
+                };
+
+                let body = self.blocks(blocks);
+                let rendered = if body.starts_with(['$', '-', '`', '>', '#']) || body.is_empty() {
+                    format!("{}\n\n{}", heading, body)
+                } else {
+                    format!("{} {}", heading, body)
+                };
+                prefix_lines(rendered.trim_end(), "> ", ">")
+            }
+            Block::Code { language, text } => {
+                let fence = if text.contains("```") { "````" } else { "```" };
+                format!(
+                    "{}{}\n{}\n{}",
+                    fence,
+                    language.as_deref().unwrap_or(""),
+                    text,
+                    fence
+                )
+            }
+            Block::Unsupported { name, raw } => {
+                self.warnings.push(format!(
+                    "Unsupported environment `{}` kept as LaTeX source",
+                    name
+                ));
+                format!(
+                    "> **Unsupported:** the `{}` environment could not be converted and is kept as LaTeX.\n\n```latex\n{}\n```",
+                    name, raw
+                )
+            }
+            Block::PageBreak => "---".to_string(),
+        }
+    }
+
+    fn list(&mut self, ordered: bool, items: &[ListItem]) -> String {
+        items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let marker = if ordered {
+                    format!("{}. ", index + 1)
+                } else {
+                    "- ".to_string()
+                };
+                let mut body = self.blocks(&item.blocks);
+                if let Some(label) = &item.label {
+                    body = format!("**{}** {}", self.inlines(label), body);
+                }
+                let indent = " ".repeat(marker.len());
+                let body = prefix_lines(body.trim_end(), &indent, "");
+                format!("{}{}", marker, &body[indent.len().min(body.len())..])
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn inlines(&mut self, inlines: &[Inline]) -> String {
+        let mut out = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => out.push_str(&escape(text)),
+                Inline::Math(tex) => {
+                    self.check_math(tex);
+                    out.push('$');
+                    out.push_str(tex.trim());
+                    out.push('$');
+                }
+                Inline::Emph(children) => {
+                    out.push('*');
+                    out.push_str(self.inlines(children).trim());
+                    out.push('*');
+                }
+                Inline::Strong(children) => {
+                    out.push_str("**");
+                    out.push_str(self.inlines(children).trim());
+                    out.push_str("**");
+                }
+                Inline::Code(code) => {
+                    out.push('`');
+                    out.push_str(code);
+                    out.push('`');
+                }
+                Inline::LineBreak => out.push_str("\\\n"),
+            }
+        }
+        out
+    }
+
+    fn check_math(&mut self, tex: &str) {
+        for command in KATEX_UNSUPPORTED {
+            if tex.contains(command) {
+                let warning = format!("`{}` is not supported by KaTeX", command);
+                if !self.warnings.contains(&warning) {
+                    self.warnings.push(warning);
+                }
+            }
+        }
+    }
+}
+
+/// Multi-row environments become their KaTeX-compatible inner forms.
+pub(crate) fn wrap_math(kind: MathKind, tex: &str) -> String {
+    match kind {
+        MathKind::Single => tex.to_string(),
+        MathKind::Aligned => format!("\\begin{{aligned}}\n{}\n\\end{{aligned}}", tex),
+        MathKind::Gathered => format!("\\begin{{gathered}}\n{}\n\\end{{gathered}}", tex),
+    }
+}
+
+/// Backslash the characters Markdown (and KaTeX's `$`) would interpret.
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '$' | '[' | ']' | '<' | '>') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Prefix every line, using `blank` for empty lines so blockquotes stay joined.
+fn prefix_lines(text: &str, prefix: &str, blank: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                blank.to_string()
+            } else {
+                format!("{}{}", prefix, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::parser;
+
+    fn markdown(body: &str) -> (String, Vec<String>) {
+        let source = format!(
+            "\\documentclass{{article}}\n\\title{{Notes}}\n\\begin{{document}}\n{}\n\\end{{document}}\n",
+            body
+        );
+        render(&parser::parse(&source))
+    }
+
+    #[test]
+    fn renders_blocks() {
+        let (text, warnings) = markdown(
+            "\\section{Limits}
+A \\emph{limit} of $f$ costs 5\\$.
+
+\\begin{theorem}[Squeeze]
+If $g \\le f \\le h$ then
+\\[ \\lim f = L \\]
+\\end{theorem}
+
+\\begin{enumerate}
+\\item First
+\\item Second
+\\end{enumerate}
+
+\\begin{align}
+a &= b \\\\
+c &= d
+\\end{align}
+
+\\newpage
+\\begin{tikzpicture}
+\\draw (0,0) -- (1,1);
+\\end{tikzpicture}",
+        );
+        assert_eq!(
+            text,
+            r"# Notes
+
+## Limits
+
+A *limit* of $f$ costs 5\$.
+
+> **Theorem (Squeeze).** If $g \le f \le h$ then
+>
+> $$
+> \lim f = L
+> $$
+
+1. First
+2. Second
+
+$$
+\begin{aligned}
+a &= b \\
+c &= d
+\end{aligned}
+$$
+
+---
+
+> **Unsupported:** the `tikzpicture` environment could not be converted and is kept as LaTeX.
+
+```latex
+\begin{tikzpicture}
+\draw (0,0) -- (1,1);
+\end{tikzpicture}
+```
+"
+        );
+        assert_eq!(
+            warnings,
+            ["Unsupported environment `tikzpicture` kept as LaTeX source"]
+        );
+    }
+
+    #[test]
+    fn warns_about_math_katex_rejects() {
+        let (_, warnings) = markdown("\\[ \\xymatrix{A \\ar[r] & B} \\]");
+        assert_eq!(warnings, ["`\\xymatrix` is not supported by KaTeX"]);
+    }
+}
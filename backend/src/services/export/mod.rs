@@ -0,0 +1,143 @@
+pub mod anki;
+pub mod docx;
+pub mod epub;
+pub mod html;
+pub mod markdown;
+pub mod notebook;
+pub mod typst;
+
+use crate::errors::{ApiError, Result};
+use crate::models::document::Document;
+use crate::services::parser;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Anki,
+    Docx,
+    Epub,
+    Notebook,
+    Typst,
+}
+
+impl ExportFormat {
+    const ALL: [(&'static str, ExportFormat); 13] = [
+        ("markdown", ExportFormat::Markdown),
+        ("md", ExportFormat::Markdown),
+        ("html", ExportFormat::Html),
+        ("htm", ExportFormat::Html),
+        ("anki", ExportFormat::Anki),
+        ("apkg", ExportFormat::Anki),
+        ("docx", ExportFormat::Docx),
+        ("epub", ExportFormat::Epub),
+        ("ipynb", ExportFormat::Notebook),
+        ("notebook", ExportFormat::Notebook),
+        ("jupyter", ExportFormat::Notebook),
+        ("typst", ExportFormat::Typst),
+        ("typ", ExportFormat::Typst),
+    ];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+            .map(|(_, format)| *format)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|(alias, _)| *alias).collect();
+                ApiError::ValidationError(format!(
+                    "Unsupported export format: {}. Available formats: {:?}",
+                    name, names
+                ))
+            })
+    }
+}
+
+/// A rendered export, ready to be served as an attachment.
+pub struct Export {
+    pub body: Vec<u8>,
+    pub content_type: &'static str,
+    pub extension: &'static str,
+    /// Parts of the source that could not be translated faithfully.
+    pub warnings: Vec<String>,
+}
+
+pub fn export(format: ExportFormat, document: &Document) -> Result<Export> {
+    let content = parser::parse(&document.content);
+
+    match format {
+        ExportFormat::Markdown => {
+            let (markdown, warnings) = markdown::render(&content);
+            Ok(Export {
+                body: markdown.into_bytes(),
+                content_type: "text/markdown; charset=utf-8",
+                extension: "md",
+                warnings,
+            })
+        }
+        ExportFormat::Html => {
+            let (html, warnings) = html::render(document, &content);
+            Ok(Export {
+                body: html.into_bytes(),
+                content_type: "text/html; charset=utf-8",
+                extension: "html",
+                warnings,
+            })
+        }
+        ExportFormat::Anki => {
+            let (deck, warnings) = anki::render(document, &content)?;
+            Ok(Export {
+                body: deck,
+                content_type: "application/apkg",
+                extension: "apkg",
+                warnings,
+            })
+        }
+        ExportFormat::Docx => {
+            let (docx, warnings) = docx::render(document, &content)?;
+            Ok(Export {
+                body: docx,
+                content_type:
+                    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+                extension: "docx",
+                warnings,
+            })
+        }
+        ExportFormat::Epub => {
+            let (epub, warnings) = epub::render(document, &content)?;
+            Ok(Export {
+                body: epub,
+                content_type: "application/epub+zip",
+                extension: "epub",
+                warnings,
+            })
+        }
+        ExportFormat::Notebook => {
+            let (notebook, warnings) = notebook::render(document, &content);
+            Ok(Export {
+                body: notebook.into_bytes(),
+                content_type: "application/x-ipynb+json",
+                extension: "ipynb",
+                warnings,
+            })
+        }
+        ExportFormat::Typst => {
+            let (typst, warnings) = typst::render(document, &content);
+            Ok(Export {
+                body: typst.into_bytes(),
+                content_type: "text/vnd.typst; charset=utf-8",
+                extension: "typ",
+                warnings,
+            })
+        }
+    }
+}
+
+/// Display name for a theorem-like environment (`lemma` -> `Lemma`).
+pub(crate) fn environment_title(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,164 @@
+//! Jupyter notebooks (nbformat 4): a Markdown cell per section or page, with
+//! runnable listings split out into code cells.
+
+use serde_json::{json, Value};
+
+use crate::models::content::{Block, Content};
+use crate::models::document::Document;
+use crate::services::export::markdown;
+
+/// Listing languages that run in the notebook's Python kernel.
+const KERNEL_LANGUAGES: [&str; 3] = ["python", "python3", "py"];
+
+pub fn render(document: &Document, content: &Content) -> (String, Vec<String>) {
+    let mut notebook = Notebook::default();
+    let heading_offset = u8::from(content.title.is_some());
+
+    let mut title = Vec::new();
+    if let Some(text) = &content.title {
+        title.push(format!("# {}", markdown::escape(text)));
+    }
+    if let Some(author) = &content.author {
+        title.push(format!("*{}*", markdown::escape(author)));
+    }
+    if !title.is_empty() {
+        notebook.markdown(title.join("\n\n"), 1);
+    }
+
+    let mut page = 1;
+    let mut section: Vec<Block> = Vec::new();
+    for block in &content.blocks {
+        match block {
+            Block::PageBreak => {
+                notebook.section(&section, heading_offset, page);
+                section.clear();
+                page += 1;
+            }
+            Block::Heading { level: 1, .. } => {
+                notebook.section(&section, heading_offset, page);
+                section.clear();
+                section.push(block.clone());
+            }
+            Block::Code { language, text } if is_runnable(language.as_deref()) => {
+                notebook.section(&section, heading_offset, page);
+                section.clear();
+                notebook.code(text, page);
+            }
+            block => section.push(block.clone()),
+        }
+    }
+    notebook.section(&section, heading_offset, page);
+
+    let title = content
+        .title
+        .clone()
+        .unwrap_or_else(|| document.filename.trim_end_matches(".tex").to_string());
+    let ipynb = json!({
+        "cells": notebook.cells,
+        "metadata": {
+            "kernelspec": {
+                "display_name": "Python 3",
+                "language": "python",
+                "name": "python3",
+            },
+            "language_info": { "name": "python" },
+            "title": title,
+        },
+        "nbformat": 4,
+        "nbformat_minor": 5,
+    });
+
+    let mut out = serde_json::to_string_pretty(&ipynb).unwrap_or_default();
+    out.push('\n');
+    (out, notebook.warnings)
+}
+
+#[derive(Default)]
+struct Notebook {
+    cells: Vec<Value>,
+    warnings: Vec<String>,
+}
+
+impl Notebook {
+    fn section(&mut self, blocks: &[Block], heading_offset: u8, page: usize) {
+        let (text, warnings) = markdown::fragment(blocks, heading_offset);
+        for warning in warnings {
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
+        }
+        if !text.trim().is_empty() {
+            self.markdown(text, page);
+        }
+    }
+
+    fn markdown(&mut self, text: String, page: usize) {
+        let cell = json!({
+            "cell_type": "markdown",
+            "id": format!("cell-{}", self.cells.len() + 1),
+            "metadata": { "noteforge": { "page": page } },
+            "source": source_lines(&text),
+        });
+        self.cells.push(cell);
+    }
+
+    fn code(&mut self, text: &str, page: usize) {
+        let cell = json!({
+            "cell_type": "code",
+            "execution_count": null,
+            "id": format!("cell-{}", self.cells.len() + 1),
+            "metadata": { "noteforge": { "page": page } },
+            "outputs": [],
+            "source": source_lines(text),
+        });
+        self.cells.push(cell);
+    }
+}
+
+/// Untagged listings are pseudocode or numerical examples; both go in code cells.
+fn is_runnable(language: Option<&str>) -> bool {
+    language.is_none_or(|language| KERNEL_LANGUAGES.contains(&language.to_lowercase().as_str()))
+}
+
+/// Notebook sources are stored as lines, each keeping its newline except the last.
+fn source_lines(text: &str) -> Vec<String> {
+    let text = text.trim_end();
+    let mut lines: Vec<String> = text.split_inclusive('\n').map(str::to_string).collect();
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn escapes_the_title_page() {
+        let document = Document {
+            id: Uuid::new_v4(),
+            filename: "notes.tex".to_string(),
+            content: String::new(),
+            style: "article".to_string(),
+            created_at: Utc::now(),
+        };
+        let content = Content {
+            title: Some("Costs in $ and *stars*".to_string()),
+            author: Some("A_B".to_string()),
+            blocks: Vec::new(),
+        };
+
+        let (json, _) = render(&document, &content);
+        let notebook: Value = serde_json::from_str(&json).unwrap();
+        let source: String = notebook["cells"][0]["source"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(Value::as_str)
+            .collect();
+        assert_eq!(source, "# Costs in \\$ and \\*stars\\*\n\n*A\\_B*");
+    }
+}
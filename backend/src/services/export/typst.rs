@@ -0,0 +1,266 @@
+//! Typst markup, compiled by `TypstService` as a lighter alternative to pdflatex.
+
+use crate::models::content::{Block, Content, Inline, ListItem};
+use crate::models::document::Document;
+use crate::services::export::environment_title;
+use crate::services::math;
+use crate::services::styles::{self, Layout};
+
+/// Theorem-like environments and proofs, shared by every layout.
+const DEFINITIONS: &str = "#let env(name, title: none, body) = block(
+  width: 100%,
+  inset: (left: 8pt, y: 4pt),
+  stroke: (left: 1.5pt + gray),
+)[*#name*#if title != none [ (#title)]. #body]
+#let proof(name: \"Proof\", body) = block(width: 100%)[_#name._ #body #h(1fr) $square$]
+";
+
+pub fn render(document: &Document, content: &Content) -> (String, Vec<String>) {
+    let layout = styles::find(&document.style)
+        .map(|style| style.layout)
+        .unwrap_or(Layout::Paged);
+
+    let mut writer = Writer {
+        layout,
+        warnings: Vec::new(),
+    };
+    let body = writer.blocks(&content.blocks, 0);
+
+    let mut out = String::new();
+    let title = content
+        .title
+        .clone()
+        .unwrap_or_else(|| document.filename.trim_end_matches(".tex").to_string());
+    out.push_str(&format!("#set document(title: {}", string(&title)));
+    if let Some(author) = &content.author {
+        out.push_str(&format!(", author: {}", string(author)));
+    }
+    out.push_str(")\n");
+
+    match layout {
+        Layout::Paged => {
+            out.push_str("#set page(paper: \"a4\", margin: 2.5cm)\n#set text(size: 11pt)\n");
+        }
+        Layout::Slides => {
+            out.push_str(
+                "#set page(paper: \"presentation-16-9\", margin: 1.5cm)\n#set text(size: 20pt)\n",
+            );
+        }
+        Layout::Columns(columns) => {
+            out.push_str(&format!(
+                "#set page(paper: \"a4\", flipped: true, margin: 1cm, columns: {})\n#set text(size: 8pt)\n",
+                columns
+            ));
+        }
+    }
+    out.push_str("#set heading(numbering: \"1.1\")\n#set par(justify: true)\n");
+    out.push_str(DEFINITIONS);
+    out.push('\n');
+
+    if content.title.is_some() || content.author.is_some() {
+        out.push_str(&format!(
+            "#align(center)[#text(size: 1.6em, weight: \"bold\")[{}]",
+            escape(&title)
+        ));
+        if let Some(author) = &content.author {
+            out.push_str(&format!(" \\\n{}", escape(author)));
+        }
+        out.push_str("]\n\n");
+    }
+
+    out.push_str(&body);
+    (out, writer.warnings)
+}
+
+struct Writer {
+    layout: Layout,
+    warnings: Vec<String>,
+}
+
+impl Writer {
+    fn blocks(&mut self, blocks: &[Block], indent: usize) -> String {
+        let mut out = String::new();
+        for block in blocks {
+            out.push_str(&self.block(block, indent));
+        }
+        out
+    }
+
+    fn block(&mut self, block: &Block, indent: usize) -> String {
+        let pad = " ".repeat(indent);
+        match block {
+            Block::Heading { level, text } => format!(
+                "{}{} {}\n\n",
+                pad,
+                "=".repeat(*level as usize),
+                self.inlines(text)
+            ),
+            Block::Paragraph(inlines) => {
+                let text = self.inlines(inlines);
+                format!("{}{}\n\n", pad, text.replace('\n', &format!("\n{}", pad)))
+            }
+            Block::Math { kind, tex } => {
+                let node = math::parse_display(*kind, tex);
+                self.check_math(&node);
+                let equation = math::to_typst(&node).replace('\n', &format!("\n{}  ", pad));
+                format!("{}$ {} $\n\n", pad, equation)
+            }
+            Block::List { ordered, items } => self.list(*ordered, items, indent),
+            Block::Environment {
+                name,
+                title,
+                blocks,
+            } => {
+                let body = self.blocks(blocks, indent + 2);
+                let body = body.trim_end();
+                match name.as_str() {
+                    "proof" | "solution" => {
+                        let label = match title {
+                            Some(title) => self.inlines(title),
+                            None => environment_title(name),
+                        };
+                        format!("{0}#proof(name: [{1}])[\n{2}\n{0}]\n\n", pad, label, body)
+                    }
+                    _ => {
+                        let title = title
+                            .as_ref()
+                            .map(|title| format!(", title: [{}]", self.inlines(title)))
+                            .unwrap_or_default();
+                        format!(
+                            "{0}#env(\"{1}\"{2})[\n{3}\n{0}]\n\n",
+                            pad,
+                            environment_title(name),
+                            title,
+                            body
+                        )
+                    }
+                }
+            }
+            Block::Code { language, text } => format!(
+                "{0}{1}{2}\n{3}\n{0}{1}\n\n",
+                pad,
+                fence(text),
+                language.as_deref().unwrap_or(""),
+                text.trim_end()
+            ),
+            Block::Unsupported { name, raw } => {
+                self.warnings.push(format!(
+                    "Unsupported environment `{}` kept as LaTeX source",
+                    name
+                ));
+                format!(
+                    "{0}// The {1} environment could not be converted\n{0}{2}latex\n{3}\n{0}{2}\n\n",
+                    pad,
+                    name,
+                    fence(raw),
+                    raw.trim_end()
+                )
+            }
+            Block::PageBreak => match self.layout {
+                // Multi-column sheets flow continuously
+                Layout::Columns(_) => String::new(),
+                Layout::Paged | Layout::Slides => format!("{}#pagebreak(weak: true)\n\n", pad),
+            },
+        }
+    }
+
+    fn list(&mut self, ordered: bool, items: &[ListItem], indent: usize) -> String {
+        let pad = " ".repeat(indent);
+        let marker = if ordered { "+" } else { "-" };
+        let mut out = String::new();
+        for item in items {
+            out.push_str(&format!("{}{} ", pad, marker));
+            if let Some(label) = &item.label {
+                out.push_str(&format!("*{}* ", self.inlines(label)));
+            }
+            // The first paragraph shares the marker line, the rest are indented under it
+            let mut blocks = item.blocks.as_slice();
+            if let [Block::Paragraph(inlines), rest @ ..] = blocks {
+                out.push_str(&self.inlines(inlines));
+                out.push('\n');
+                blocks = rest;
+            } else {
+                out.push('\n');
+            }
+            let nested = self.blocks(blocks, indent + 2);
+            out.push_str(nested.trim_end_matches('\n'));
+            if !nested.is_empty() {
+                out.push('\n');
+            }
+        }
+        out.push('\n');
+        out
+    }
+
+    fn inlines(&mut self, inlines: &[Inline]) -> String {
+        let mut out = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => out.push_str(&escape(text)),
+                Inline::Math(tex) => {
+                    let node = math::parse(tex);
+                    self.check_math(&node);
+                    out.push_str(&format!("${}$", math::to_typst(&node)));
+                }
+                Inline::Emph(children) => {
+                    out.push_str(&format!("#emph[{}]", self.inlines(children)))
+                }
+                Inline::Strong(children) => {
+                    out.push_str(&format!("#strong[{}]", self.inlines(children)))
+                }
+                Inline::Code(code) => out.push_str(&format!("#raw({})", string(code))),
+                Inline::LineBreak => out.push_str(" \\\n"),
+            }
+        }
+        out
+    }
+
+    fn check_math(&mut self, node: &math::MathNode) {
+        for command in node.unknown_commands() {
+            let warning = format!("Unknown math command `{}` left unconverted", command);
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
+        }
+    }
+}
+
+/// Escape markup characters in running text.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '*'
+                | '_'
+                | '#'
+                | '$'
+                | '['
+                | ']'
+                | '<'
+                | '>'
+                | '@'
+                | '`'
+                | '~'
+                | '='
+                | '-'
+                | '+'
+                | '/'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// A Typst string literal.
+fn string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A raw-block fence longer than any backtick run in the text.
+fn fence(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
@@ -0,0 +1,254 @@
+//! Dependency checks behind `/health/ready`.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+use crate::config::env::Config;
+use crate::db::Database;
+use crate::models::health::{Check, CheckStatus, Readiness};
+use crate::services::storage::Storage;
+
+/// Below this much free space, storage counts as full.
+const MIN_FREE_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Upper bound for any one check, so a hung dependency fails the probe
+/// instead of stalling it.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a report is reused, so that frequent probes do not each start
+/// the engines and write to storage.
+const REPORT_TTL: Duration = Duration::from_secs(5);
+
+/// Runs the checks at most once per [`REPORT_TTL`], logging what they find.
+#[derive(Clone)]
+pub struct Health {
+    db: Database,
+    storage: Storage,
+    model: Option<Config>,
+    last: Arc<Mutex<Option<(Instant, Readiness)>>>,
+}
+
+impl Health {
+    pub fn new(db: Database, storage: Storage, model: Option<Config>) -> Self {
+        Self {
+            db,
+            storage,
+            model,
+            last: Arc::default(),
+        }
+    }
+
+    /// The latest report; concurrent callers wait for one run of the checks.
+    pub async fn readiness(&self) -> Readiness {
+        let mut last = self.last.lock().await;
+        if let Some((checked, report)) = last.as_ref() {
+            if checked.elapsed() < REPORT_TTL {
+                return report.clone();
+            }
+        }
+        let report = readiness(&self.db, &self.storage, self.model.as_ref()).await;
+        log(&report);
+        *last = Some((Instant::now(), report.clone()));
+        report
+    }
+}
+
+fn log(report: &Readiness) {
+    for (name, check) in &report.checks {
+        match (&check.error, &check.detail) {
+            (Some(error), _) => tracing::warn!("readiness check {} is down: {}", name, error),
+            (None, Some(detail)) => tracing::debug!("readiness check {}: {}", name, detail),
+            (None, None) => {}
+        }
+    }
+}
+
+/// Check every dependency; ready unless a required one is down.
+async fn readiness(db: &Database, storage: &Storage, model: Option<&Config>) -> Readiness {
+    let (pdflatex, typst, writable, space) = tokio::join!(
+        engine("pdflatex", true),
+        engine("typst", false),
+        writable(storage),
+        space(storage),
+    );
+
+    let checks = BTreeMap::from([
+        ("pdflatex", pdflatex),
+        ("typst", typst),
+        ("storage", writable),
+        ("disk_space", space),
+        ("database", database(db)),
+        ("credentials", credentials(model)),
+        ("queue", queue(db)),
+    ]);
+    let ready = checks
+        .values()
+        .all(|check| !check.required || check.status != CheckStatus::Down);
+
+    Readiness {
+        ready,
+        version: env!("CARGO_PKG_VERSION"),
+        checks,
+    }
+}
+
+/// A TeX engine on `PATH`, with the first line of its `--version`.
+async fn engine(command: &str, required: bool) -> Check {
+    let output = match within(
+        Command::new(command)
+            .arg("--version")
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Check::down(required, format!("{} not found on PATH", command))
+        }
+        Ok(Err(e)) => return Check::down(required, format!("Failed to run {}: {}", command, e)),
+        Err(e) => return Check::down(required, e),
+    };
+    if !output.status.success() {
+        return Check::down(
+            required,
+            format!("{} --version exited with {}", command, output.status),
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout.lines().next().unwrap_or_default().trim();
+    Check::up(required, Some(json!({ "version": version })))
+}
+
+/// Write, read back and remove a probe blob.
+async fn writable(storage: &Storage) -> Check {
+    let key = format!("health/{}", uuid::Uuid::new_v4());
+    let probe = async {
+        storage.put(&key, b"ok".to_vec()).await?;
+        let read = storage.get(&key).await;
+        storage.delete(&key).await?;
+        read
+    };
+    match within(probe).await {
+        Ok(Ok(data)) if data == b"ok" => Check::up(true, None),
+        Ok(Ok(_)) => Check::down(true, "Storage returned different data than was written"),
+        Ok(Err(e)) => Check::down(true, e.to_string()),
+        Err(e) => Check::down(true, e),
+    }
+}
+
+async fn space(storage: &Storage) -> Check {
+    match within(storage.available_space()).await {
+        Ok(Ok(Some(available))) => {
+            let detail = json!({ "available_bytes": available, "minimum_bytes": MIN_FREE_BYTES });
+            if available < MIN_FREE_BYTES {
+                Check {
+                    detail: Some(detail),
+                    ..Check::down(true, format!("Only {} MB free", available / (1024 * 1024)))
+                }
+            } else {
+                Check::up(true, Some(detail))
+            }
+        }
+        Ok(Ok(None)) => Check {
+            status: CheckStatus::Unknown,
+            ..Check::up(true, None)
+        },
+        Ok(Err(e)) => Check::down(true, e.to_string()),
+        Err(e) => Check::down(true, e),
+    }
+}
+
+fn database(db: &Database) -> Check {
+    match db.ping() {
+        Ok(()) => Check::up(true, None),
+        Err(e) => Check::down(true, e.to_string()),
+    }
+}
+
+/// The model API key is set; whether it is valid only shows on first use.
+fn credentials(model: Option<&Config>) -> Check {
+    match model {
+        Some(config) if !config.claude_api_key.trim().is_empty() => Check::up(true, None),
+        Some(_) => Check::down(true, "CLAUDE_API_KEY is empty"),
+        None => Check::down(true, "CLAUDE_API_KEY not set"),
+    }
+}
+
+/// Conversions and PDF builds in progress, by kind.
+fn queue(db: &Database) -> Check {
+    match db.running_jobs() {
+        Ok(running) => {
+            let backlog: u64 = running.iter().map(|(_, count)| count).sum();
+            let by_kind: BTreeMap<String, u64> = running.into_iter().collect();
+            Check::up(
+                false,
+                Some(json!({ "running": backlog, "by_kind": by_kind })),
+            )
+        }
+        Err(e) => Check::down(false, e.to_string()),
+    }
+}
+
+async fn within<T>(check: impl Future<Output = T>) -> std::result::Result<T, String> {
+    tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .map_err(|_| format!("Timed out after {} seconds", CHECK_TIMEOUT.as_secs()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::document::{Document, DocumentOrigin};
+    use crate::models::job::JobKind;
+    use crate::services::storage::FsStore;
+
+    #[tokio::test]
+    async fn reuses_reports_and_serves_only_statuses() {
+        let root = tempfile::tempdir().unwrap();
+        let db = Database::open(":memory:").unwrap();
+        let health = Health::new(db.clone(), Arc::new(FsStore::new(root.path())), None);
+        let running =
+            |report: &Readiness| report.checks["queue"].detail.clone().unwrap()["running"].clone();
+
+        let first = health.readiness().await;
+        assert_eq!(running(&first), 0);
+        assert_eq!(first.checks["storage"].status, CheckStatus::Up);
+
+        // Within the TTL the same report comes back, without running the checks
+        let document = Document {
+            id: uuid::Uuid::new_v4(),
+            filename: "notes.png".to_string(),
+            content: String::new(),
+            style: "article".to_string(),
+            created_at: chrono::Utc::now(),
+        };
+        db.save_document(&document, DocumentOrigin::Upload, None, None)
+            .unwrap();
+        db.start_job(&document.id, JobKind::Convert).unwrap();
+        assert_eq!(running(&health.readiness().await), 0);
+
+        *health.last.lock().await = None;
+        let report = health.readiness().await;
+        assert_eq!(running(&report), 1);
+
+        let served = serde_json::to_value(&report).unwrap();
+        for (name, check) in served["checks"].as_object().unwrap() {
+            let mut fields: Vec<&str> = check
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(String::as_str)
+                .collect();
+            fields.sort();
+            assert_eq!(fields, ["required", "status"], "{}", name);
+        }
+    }
+}
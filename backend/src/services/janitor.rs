@@ -0,0 +1,425 @@
+//! Background cleanup: expired artifacts, storage over its high-water mark,
+//! and compiler scratch directories left behind by crashes.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::config::env::RetentionConfig;
+use crate::db::Database;
+use crate::errors::Result;
+use crate::models::webhook::{EventData, EventKind};
+use crate::services::pdf::TEMP_DIR_PREFIX;
+use crate::services::storage::{self, Entry, Storage};
+use crate::services::webhooks;
+
+/// Scratch directories older than this belong to no running compile.
+const TEMP_DIR_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Top-level prefixes the janitor manages; nothing else in the store is touched.
+const PREFIXES: [&str; 3] = ["uploads/", "latex/", "pdf/"];
+
+/// What one janitor run removed.
+#[derive(Debug, Default)]
+pub struct Sweep {
+    /// Blobs past their type's TTL.
+    pub expired: usize,
+    /// Blobs removed to get under the high-water mark.
+    pub evicted: usize,
+    pub freed_bytes: u64,
+    pub temp_dirs: usize,
+}
+
+pub struct Janitor {
+    db: Database,
+    storage: Storage,
+    config: RetentionConfig,
+}
+
+impl Janitor {
+    pub fn new(db: Database, storage: Storage, config: RetentionConfig) -> Self {
+        Self {
+            db,
+            storage,
+            config,
+        }
+    }
+
+    /// Run every `config.interval`, starting now.
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.interval);
+            loop {
+                ticker.tick().await;
+                match self.run().await {
+                    Ok(sweep) if sweep.expired + sweep.evicted + sweep.temp_dirs == 0 => {}
+                    Ok(sweep) => tracing::info!(
+                        "janitor removed {} expired and {} evicted blobs ({} bytes) and {} temp dirs",
+                        sweep.expired,
+                        sweep.evicted,
+                        sweep.freed_bytes,
+                        sweep.temp_dirs
+                    ),
+                    Err(e) => tracing::warn!("janitor run failed: {}", e),
+                }
+            }
+        })
+    }
+
+    pub async fn run(&self) -> Result<Sweep> {
+        // Per-IP quotas only look at today
+        self.db.prune_ip_usage(Utc::now().date_naive())?;
+
+        let mut sweep = Sweep {
+            temp_dirs: sweep_temp_dirs(TEMP_DIR_MAX_AGE).await,
+            ..Sweep::default()
+        };
+
+        let mut entries = Vec::new();
+        for prefix in PREFIXES {
+            entries.extend(self.storage.entries(prefix).await?);
+        }
+        let mut touched = BTreeSet::new();
+
+        // Expire by age
+        let now = Utc::now();
+        let mut kept = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let age = (now - entry.modified).to_std().unwrap_or_default();
+            if self.ttl(&entry.key).is_some_and(|ttl| age > ttl) {
+                self.remove(&entry, &mut touched).await?;
+                sweep.expired += 1;
+                sweep.freed_bytes += entry.size;
+            } else {
+                kept.push(entry);
+            }
+        }
+
+        // Evict down to 90% of the high-water mark: PDFs first, since they
+        // can be rebuilt, then whole documents, least recently touched first
+        if let Some(limit) = self.config.high_water_bytes {
+            let mut total: u64 = kept.iter().map(|entry| entry.size).sum();
+            if total > limit {
+                let target = limit / 10 * 9;
+                for group in eviction_order(kept) {
+                    if total <= target {
+                        break;
+                    }
+                    for entry in group {
+                        self.remove(&entry, &mut touched).await?;
+                        sweep.evicted += 1;
+                        sweep.freed_bytes += entry.size;
+                        total = total.saturating_sub(entry.size);
+                    }
+                }
+            }
+        }
+
+        for id in touched {
+            self.reconcile(&id).await?;
+        }
+        Ok(sweep)
+    }
+
+    fn ttl(&self, key: &str) -> Option<Duration> {
+        match key.split('/').next() {
+            Some("uploads") => self.config.uploads,
+            Some("latex") => self.config.latex,
+            Some("pdf") => self.config.pdf,
+            _ => None,
+        }
+    }
+
+    async fn remove(&self, entry: &Entry, touched: &mut BTreeSet<Uuid>) -> Result<()> {
+        self.storage.delete(&entry.key).await?;
+        if let Some(id) = storage::document_id(&entry.key) {
+            touched.insert(id);
+        }
+        Ok(())
+    }
+
+    /// Bring a document's record in line with what is left of it.
+    async fn reconcile(&self, id: &Uuid) -> Result<()> {
+        let has_latex = !self.storage.list(&storage::latex_key(id)).await?.is_empty();
+        if has_latex {
+            return Ok(());
+        }
+        if upload_keys(&self.storage, id).await?.is_empty() {
+            let owner = self.db.document_owner(id)?.flatten();
+            if self.db.delete_document(id)? {
+                document_deleted(&self.db, owner.as_ref(), id);
+            }
+        } else {
+            self.db.mark_uploaded(id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Delete everything stored for a document, returning whether anything was.
+pub async fn remove_document(db: &Database, storage: &Storage, id: &Uuid) -> Result<bool> {
+    let mut keys = upload_keys(storage, id).await?;
+    keys.extend(storage.list(&storage::latex_key(id)).await?);
+    keys.extend(storage.list(&storage::pdf_prefix(id)).await?);
+
+    for key in &keys {
+        storage.delete(key).await?;
+    }
+    let owner = db.document_owner(id)?.flatten();
+    let recorded = db.delete_document(id)?;
+    if recorded {
+        document_deleted(db, owner.as_ref(), id);
+    }
+    Ok(recorded || !keys.is_empty())
+}
+
+fn document_deleted(db: &Database, owner: Option<&Uuid>, id: &Uuid) {
+    let data = EventData {
+        document_id: *id,
+        job_id: None,
+        error_code: None,
+        error: None,
+    };
+    webhooks::emit(db, owner, None, EventKind::DocumentDeleted, data);
+}
+
+async fn upload_keys(storage: &Storage, id: &Uuid) -> Result<Vec<String>> {
+    let keys = storage
+        .list(&storage::upload_key(&id.to_string()))
+        .await?
+        .into_iter()
+        .filter(|key| storage::document_id(key) == Some(*id))
+        .collect();
+    Ok(keys)
+}
+
+/// Groups of blobs to evict together, in eviction order.
+fn eviction_order(entries: Vec<Entry>) -> Vec<Vec<Entry>> {
+    let (mut pdfs, rest): (Vec<Entry>, Vec<Entry>) = entries
+        .into_iter()
+        .partition(|entry| entry.key.starts_with("pdf/"));
+    pdfs.sort_by_key(|entry| entry.modified);
+
+    // Blobs no document owns are left alone rather than evicted as one lot
+    let mut documents: BTreeMap<Uuid, Vec<Entry>> = BTreeMap::new();
+    for entry in rest {
+        if let Some(id) = storage::document_id(&entry.key) {
+            documents.entry(id).or_default().push(entry);
+        }
+    }
+    let mut documents: Vec<Vec<Entry>> = documents.into_values().collect();
+    documents.sort_by_key(|group| group.iter().map(|entry| entry.modified).max());
+
+    pdfs.into_iter()
+        .map(|entry| vec![entry])
+        .chain(documents)
+        .collect()
+}
+
+/// Remove compiler scratch directories older than `max_age`.
+async fn sweep_temp_dirs(max_age: Duration) -> usize {
+    let Ok(mut entries) = tokio::fs::read_dir(std::env::temp_dir()).await else {
+        return 0;
+    };
+    let mut removed = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(TEMP_DIR_PREFIX)
+        {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+        let stale = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > max_age);
+        if metadata.is_dir() && stale && tokio::fs::remove_dir_all(entry.path()).await.is_ok() {
+            removed += 1;
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::models::document::{Document, DocumentOrigin, DocumentStatus};
+    use crate::services::storage::FsStore;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    struct Store {
+        root: TempDir,
+        storage: Storage,
+    }
+
+    impl Store {
+        fn new() -> Self {
+            let root = tempfile::tempdir().unwrap();
+            let storage: Storage = Arc::new(FsStore::new(root.path()));
+            Self { root, storage }
+        }
+
+        /// Store `size` bytes at `key`, last written `age` ago.
+        async fn put(&self, key: &str, size: usize, age: Duration) {
+            self.storage.put(key, vec![b'x'; size]).await.unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(self.root.path().join(key))
+                .unwrap()
+                .set_modified(SystemTime::now() - age)
+                .unwrap();
+        }
+
+        async fn has(&self, key: &str) -> bool {
+            self.storage.get(key).await.is_ok()
+        }
+    }
+
+    fn config() -> RetentionConfig {
+        RetentionConfig {
+            uploads: None,
+            latex: None,
+            pdf: None,
+            high_water_bytes: None,
+            interval: HOUR,
+        }
+    }
+
+    fn upload(db: &Database, id: &Uuid) -> String {
+        let key = storage::upload_key(&format!("{}.png", id));
+        db.record_upload(id, None, std::slice::from_ref(&key))
+            .unwrap();
+        key
+    }
+
+    #[tokio::test]
+    async fn expires_blobs_past_their_ttl() {
+        let db = Database::open(":memory:").unwrap();
+        let store = Store::new();
+        let (old, new) = (Uuid::new_v4(), Uuid::new_v4());
+        let old_pdf = storage::pdf_key(&old, "pdflatex", "old");
+        let new_pdf = storage::pdf_key(&new, "pdflatex", "new");
+        store.put(&old_pdf, 10, 2 * HOUR).await;
+        store.put(&new_pdf, 10, Duration::ZERO).await;
+        // Without a TTL of their own, documents stay however old they are
+        store.put(&storage::latex_key(&old), 10, 48 * HOUR).await;
+
+        let janitor = Janitor::new(
+            db,
+            store.storage.clone(),
+            RetentionConfig {
+                pdf: Some(HOUR),
+                ..config()
+            },
+        );
+        let sweep = janitor.run().await.unwrap();
+
+        assert_eq!(
+            (sweep.expired, sweep.evicted, sweep.freed_bytes),
+            (1, 0, 10)
+        );
+        assert!(!store.has(&old_pdf).await);
+        assert!(store.has(&new_pdf).await);
+        assert!(store.has(&storage::latex_key(&old)).await);
+    }
+
+    #[tokio::test]
+    async fn evicts_pdfs_before_documents() {
+        let db = Database::open(":memory:").unwrap();
+        let store = Store::new();
+        let (older, newer) = (Uuid::new_v4(), Uuid::new_v4());
+        let older_upload = upload(&db, &older);
+        let newer_upload = upload(&db, &newer);
+        store.put(&older_upload, 100, 3 * HOUR).await;
+        store.put(&storage::latex_key(&older), 100, 3 * HOUR).await;
+        store.put(&newer_upload, 100, 2 * HOUR).await;
+        store.put(&storage::latex_key(&newer), 100, 2 * HOUR).await;
+        // The most recent blob, but one that can be rebuilt
+        let pdf = storage::pdf_key(&newer, "pdflatex", "source");
+        store.put(&pdf, 100, Duration::ZERO).await;
+        // No document owns this, so it isn't evicted however old it is
+        store.put("uploads/stray.png", 100, 48 * HOUR).await;
+
+        // 600 bytes against a mark of 500: down to 450 takes the PDF and then
+        // the older document
+        let janitor = Janitor::new(
+            db.clone(),
+            store.storage.clone(),
+            RetentionConfig {
+                high_water_bytes: Some(500),
+                ..config()
+            },
+        );
+        let sweep = janitor.run().await.unwrap();
+
+        assert_eq!(
+            (sweep.expired, sweep.evicted, sweep.freed_bytes),
+            (0, 3, 300)
+        );
+        assert!(!store.has(&pdf).await);
+        assert!(!store.has(&older_upload).await);
+        assert!(!store.has(&storage::latex_key(&older)).await);
+        assert!(store.has(&newer_upload).await);
+        assert!(store.has(&storage::latex_key(&newer)).await);
+        assert!(store.has("uploads/stray.png").await);
+        assert!(db.get_document(&older).unwrap().is_none());
+        assert!(db.get_document(&newer).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn keeps_the_record_until_latex_and_uploads_are_gone() {
+        let db = Database::open(":memory:").unwrap();
+        let store = Store::new();
+        let id = Uuid::new_v4();
+        let upload = upload(&db, &id);
+        let document = Document {
+            id,
+            filename: format!("{}.tex", id),
+            content: "\\documentclass{article}\\begin{document}Hi\\end{document}".to_string(),
+            style: "article".to_string(),
+            created_at: Utc::now(),
+        };
+        db.save_document(&document, DocumentOrigin::Upload, None, None)
+            .unwrap();
+        store.put(&upload, 10, 2 * HOUR).await;
+        store.put(&storage::latex_key(&id), 10, 2 * HOUR).await;
+        let janitor = |config| Janitor::new(db.clone(), store.storage.clone(), config);
+
+        // The LaTeX goes, but the pages could still be converted again
+        let sweep = janitor(RetentionConfig {
+            latex: Some(HOUR),
+            ..config()
+        })
+        .run()
+        .await
+        .unwrap();
+        assert_eq!(sweep.expired, 1);
+        assert!(store.has(&upload).await);
+        let summary = db.get_document(&id).unwrap().unwrap();
+        assert_eq!(summary.status, DocumentStatus::Uploaded);
+
+        let sweep = janitor(RetentionConfig {
+            uploads: Some(HOUR),
+            ..config()
+        })
+        .run()
+        .await
+        .unwrap();
+        assert_eq!(sweep.expired, 1);
+        assert!(db.get_document(&id).unwrap().is_none());
+    }
+}
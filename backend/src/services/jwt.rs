@@ -0,0 +1,362 @@
+//! Validation of OpenID Connect tokens against the identity provider's JWKS.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use crate::config::env::{JwksSource, JwtConfig};
+use crate::errors::{ApiError, Result};
+use crate::models::owner::Identity;
+
+/// Keys are refetched at least this often, so revoked ones stop working.
+const KEYS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// An unknown `kid` triggers a refetch at most this often, so forged tokens
+/// cannot hammer the identity provider.
+const MIN_REFRESH: Duration = Duration::from_secs(60);
+
+/// Shared, cheaply cloned verifier.
+#[derive(Clone)]
+pub struct JwtVerifier {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    config: JwtConfig,
+    client: reqwest::Client,
+    keys: RwLock<Keys>,
+}
+
+struct Keys {
+    set: JwkSet,
+    fetched: Option<Instant>,
+}
+
+impl JwtVerifier {
+    /// Set up a verifier, loading the key set now if it is reachable.
+    pub async fn new(config: JwtConfig) -> Self {
+        let verifier = Self {
+            inner: Arc::new(Inner {
+                config,
+                client: reqwest::Client::new(),
+                keys: RwLock::new(Keys {
+                    set: JwkSet { keys: Vec::new() },
+                    fetched: None,
+                }),
+            }),
+        };
+        if let Err(e) = verifier.refresh().await {
+            tracing::warn!("failed to load JWKS, will retry on first login: {}", e);
+        }
+        verifier
+    }
+
+    /// Check a token's signature and claims, and map them to a user.
+    pub async fn verify(&self, token: &str) -> Result<Identity> {
+        let config = &self.inner.config;
+        let header = jsonwebtoken::decode_header(token).map_err(rejected)?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(rejected("symmetric algorithms are not accepted"));
+        }
+
+        let jwk = self.key(header.kid.as_deref()).await?;
+        if jwk
+            .common
+            .key_algorithm
+            .is_some_and(|algorithm| algorithm.to_string().parse().ok() != Some(header.alg))
+        {
+            return Err(rejected("algorithm does not match the key"));
+        }
+        let key = DecodingKey::from_jwk(&jwk).map_err(rejected)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_required_spec_claims(&["exp", "sub", "iss", "aud"]);
+        validation.set_issuer(&[&config.issuer]);
+        validation.set_audience(&[&config.audience]);
+
+        let claims = jsonwebtoken::decode::<Value>(token, &key, &validation)
+            .map_err(rejected)?
+            .claims;
+        Ok(identity(&claims, config))
+    }
+
+    async fn key(&self, kid: Option<&str>) -> Result<Jwk> {
+        {
+            let keys = self.inner.keys.read().await;
+            let fresh = keys
+                .fetched
+                .is_some_and(|fetched| fetched.elapsed() < KEYS_TTL);
+            if fresh {
+                if let Some(jwk) = find(&keys.set, kid) {
+                    return Ok(jwk);
+                }
+                if keys
+                    .fetched
+                    .is_some_and(|fetched| fetched.elapsed() < MIN_REFRESH)
+                {
+                    return Err(rejected("unknown signing key"));
+                }
+            }
+        }
+
+        // Stale, or the provider may have rotated its keys
+        self.refresh().await?;
+        let keys = self.inner.keys.read().await;
+        find(&keys.set, kid).ok_or_else(|| rejected("unknown signing key"))
+    }
+
+    async fn refresh(&self) -> Result<()> {
+        let set: JwkSet = match &self.inner.config.jwks {
+            JwksSource::Url(url) => self
+                .inner
+                .client
+                .get(url)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| jwks_error(url, e))?
+                .json()
+                .await
+                .map_err(|e| jwks_error(url, e))?,
+            JwksSource::Path(path) => {
+                let text = tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|e| jwks_error(path, e))?;
+                serde_json::from_str(&text).map_err(|e| jwks_error(path, e))?
+            }
+        };
+
+        let mut keys = self.inner.keys.write().await;
+        keys.set = set;
+        keys.fetched = Some(Instant::now());
+        Ok(())
+    }
+}
+
+/// Whether a bearer token is a JWT rather than an API key.
+pub fn is_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+/// The key named by `kid`, or the only key when the token names none.
+fn find(set: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => set.find(kid).cloned(),
+        None if set.keys.len() == 1 => set.keys.first().cloned(),
+        None => None,
+    }
+}
+
+fn identity(claims: &Value, config: &JwtConfig) -> Identity {
+    let subject = claims["sub"].as_str().unwrap_or_default().to_string();
+    let name = ["name", "preferred_username", "email"]
+        .iter()
+        .find_map(|claim| claims[claim].as_str())
+        .unwrap_or(&subject)
+        .to_string();
+
+    let roles = config
+        .roles_claim
+        .split('.')
+        .fold(claims, |value, part| &value[part]);
+    // Either a list of roles or OAuth-style space-separated scopes
+    let admin = match roles {
+        Value::Array(roles) => roles
+            .iter()
+            .any(|role| role.as_str() == Some(config.admin_role.as_str())),
+        Value::String(roles) => roles
+            .split_whitespace()
+            .any(|role| role == config.admin_role),
+        _ => false,
+    };
+
+    Identity {
+        subject,
+        name,
+        admin,
+    }
+}
+
+fn rejected(reason: impl std::fmt::Display) -> ApiError {
+    tracing::debug!("rejected token: {}", reason);
+    ApiError::AuthenticationError
+}
+
+fn jwks_error(source: &str, error: impl std::fmt::Display) -> ApiError {
+    ApiError::InternalServerError(anyhow::anyhow!(
+        "Failed to load JWKS from {}: {}",
+        source,
+        error
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    const ISSUER: &str = "https://login.example.edu/realms/uni";
+    const AUDIENCE: &str = "noteforge";
+
+    /// A throwaway RSA key pair, generated for these tests only.
+    const PRIVATE_KEY: &[u8] = include_bytes!("../../testdata/jwt-rsa.der");
+    const PUBLIC_JWK: &str = include_str!("../../testdata/jwt-rsa.jwk.json");
+
+    /// The public key as a set whose keys have the given IDs.
+    fn write_jwks(file: &NamedTempFile, kids: &[&str]) {
+        let keys: Vec<Value> = kids
+            .iter()
+            .map(|kid| {
+                let mut jwk: Value = serde_json::from_str(PUBLIC_JWK).unwrap();
+                jwk["kid"] = json!(kid);
+                jwk
+            })
+            .collect();
+        std::fs::write(file.path(), json!({ "keys": keys }).to_string()).unwrap();
+    }
+
+    async fn verifier(file: &NamedTempFile, roles_claim: &str) -> JwtVerifier {
+        JwtVerifier::new(JwtConfig {
+            jwks: JwksSource::Path(file.path().to_str().unwrap().to_string()),
+            issuer: ISSUER.to_string(),
+            audience: AUDIENCE.to_string(),
+            roles_claim: roles_claim.to_string(),
+            admin_role: "admin".to_string(),
+        })
+        .await
+    }
+
+    fn claims() -> Value {
+        json!({
+            "sub": "u-123",
+            "name": "Ada",
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "exp": chrono::Utc::now().timestamp() + 600,
+        })
+    }
+
+    fn sign(kid: &str, claims: &Value) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+        let key = EncodingKey::from_rsa_der(PRIVATE_KEY);
+        jsonwebtoken::encode(&header, claims, &key).unwrap()
+    }
+
+    #[tokio::test]
+    async fn accepts_tokens_for_this_app() {
+        let jwks = NamedTempFile::new().unwrap();
+        write_jwks(&jwks, &["a"]);
+        let identity = verifier(&jwks, "roles")
+            .await
+            .verify(&sign("a", &claims()))
+            .await
+            .unwrap();
+        assert_eq!(identity.subject, "u-123");
+        assert_eq!(identity.name, "Ada");
+        assert!(!identity.admin);
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_audience_issuer_and_expired_tokens() {
+        let jwks = NamedTempFile::new().unwrap();
+        write_jwks(&jwks, &["a"]);
+        let verifier = verifier(&jwks, "roles").await;
+
+        let mut other_app = claims();
+        other_app["aud"] = json!("another-client");
+        let mut other_issuer = claims();
+        other_issuer["iss"] = json!("https://evil.example.com");
+        let mut expired = claims();
+        expired["exp"] = json!(chrono::Utc::now().timestamp() - 3600);
+        let mut no_audience = claims();
+        no_audience.as_object_mut().unwrap().remove("aud");
+
+        for claims in [other_app, other_issuer, expired, no_audience] {
+            assert!(
+                matches!(
+                    verifier.verify(&sign("a", &claims)).await,
+                    Err(ApiError::AuthenticationError)
+                ),
+                "accepted {}",
+                claims
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_symmetric_algorithms() {
+        let jwks = NamedTempFile::new().unwrap();
+        write_jwks(&jwks, &["a"]);
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("a".to_string());
+        // Keyed with the public key, the classic algorithm confusion attack
+        let key = EncodingKey::from_secret(PUBLIC_JWK.as_bytes());
+        let token = jsonwebtoken::encode(&header, &claims(), &key).unwrap();
+        assert!(matches!(
+            verifier(&jwks, "roles").await.verify(&token).await,
+            Err(ApiError::AuthenticationError)
+        ));
+    }
+
+    #[tokio::test]
+    async fn refetches_keys_for_an_unknown_kid() {
+        let jwks = NamedTempFile::new().unwrap();
+        write_jwks(&jwks, &["a"]);
+        let verifier = verifier(&jwks, "roles").await;
+
+        // The provider rotates to a new key ID
+        write_jwks(&jwks, &["b"]);
+        let token = sign("b", &claims());
+        // Just fetched, so an unknown kid alone doesn't refetch yet
+        assert!(verifier.verify(&token).await.is_err());
+
+        verifier.inner.keys.write().await.fetched = Instant::now().checked_sub(MIN_REFRESH);
+        assert!(verifier.verify(&token).await.is_ok());
+        let keys = verifier.inner.keys.read().await;
+        assert!(keys.set.find("b").is_some() && keys.set.find("a").is_none());
+    }
+
+    #[tokio::test]
+    async fn maps_roles_from_the_configured_claim() {
+        let jwks = NamedTempFile::new().unwrap();
+        write_jwks(&jwks, &["a"]);
+        let with = |path: &str, roles: Value| {
+            let mut claims = claims();
+            let (parent, leaf) = path.rsplit_once('.').unwrap_or(("", path));
+            let target = if parent.is_empty() {
+                &mut claims
+            } else {
+                claims[parent] = json!({});
+                &mut claims[parent]
+            };
+            target[leaf] = roles;
+            sign("a", &claims)
+        };
+
+        let nested = verifier(&jwks, "realm_access.roles").await;
+        let admin = with("realm_access.roles", json!(["user", "admin"]));
+        assert!(nested.verify(&admin).await.unwrap().admin);
+        let user = with("realm_access.roles", json!(["user"]));
+        assert!(!nested.verify(&user).await.unwrap().admin);
+        // The same role under a claim that isn't configured counts for nothing
+        let elsewhere = with("roles", json!(["admin"]));
+        assert!(!nested.verify(&elsewhere).await.unwrap().admin);
+
+        let scopes = verifier(&jwks, "scope").await;
+        let admin = with("scope", json!("openid profile admin"));
+        assert!(scopes.verify(&admin).await.unwrap().admin);
+        let user = with("scope", json!("openid administrator"));
+        assert!(!scopes.verify(&user).await.unwrap().admin);
+    }
+}
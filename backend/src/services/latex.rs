@@ -0,0 +1,180 @@
+//! Helpers for picking apart LaTeX returned by the model or stored on disk.
+
+const BEGIN_DOCUMENT: &str = "\\begin{document}";
+const END_DOCUMENT: &str = "\\end{document}";
+
+/// Remove ```latex fences the model sometimes adds despite being told not to.
+pub fn strip_code_fences(content: &str) -> &str {
+    let trimmed = content.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    // Drop the info string (e.g. "latex") on the opening fence line
+    let rest = rest.split_once('\n').map(|(_, body)| body).unwrap_or("");
+    rest.trim_end().strip_suffix("```").unwrap_or(rest).trim()
+}
+
+/// The body of a document, between `\begin{document}` and `\end{document}`.
+///
+/// Fragments without those markers are returned as-is so that both full
+/// documents and bare page content can be fed through the same path.
+pub fn extract_body(content: &str) -> &str {
+    let content = strip_code_fences(content);
+    let start = content
+        .find(BEGIN_DOCUMENT)
+        .map(|idx| idx + BEGIN_DOCUMENT.len())
+        .unwrap_or(0);
+    let end = content[start..]
+        .find(END_DOCUMENT)
+        .map(|idx| start + idx)
+        .unwrap_or(content.len());
+    content[start..end].trim()
+}
+
+/// Everything before `\begin{document}`; empty for bare fragments.
+pub fn extract_preamble(content: &str) -> &str {
+    let content = strip_code_fences(content);
+    content
+        .find(BEGIN_DOCUMENT)
+        .map(|idx| content[..idx].trim_end())
+        .unwrap_or("")
+}
+
+/// Primitives that read or write files, or run commands through `\write18`.
+const FILE_ACCESS: [&str; 10] = [
+    "input",
+    "include",
+    "InputIfFileExists",
+    "openin",
+    "openout",
+    "read",
+    "readline",
+    "@input",
+    "@@input",
+    "@iinput",
+];
+
+/// The first command in `content` that touches the filesystem or the shell,
+/// outside comments. Uploaded LaTeX is compiled on the server, where such
+/// commands could read its secrets.
+pub fn file_access(content: &str) -> Option<String> {
+    let mut chars = content.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '%' => {
+                // A comment runs to the end of the line
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '\\' => {
+                let mut end = start + 1;
+                while let Some(&(index, c)) = chars.peek() {
+                    if !(c.is_ascii_alphabetic() || c == '@') {
+                        break;
+                    }
+                    end = index + c.len_utf8();
+                    chars.next();
+                }
+                if end == start + 1 {
+                    // A control symbol such as `\%`; its character is not special
+                    chars.next();
+                    continue;
+                }
+                let name = &content[start + 1..end];
+                if FILE_ACCESS.contains(&name) {
+                    return Some(format!("\\{}", name));
+                }
+                if name == "write" && content[end..].trim_start().starts_with("18") {
+                    return Some("\\write18".to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split a document back into per-page fragments, undoing whatever layout
+/// wrapper (page breaks, frames, multicols) the style that produced it added.
+pub fn split_pages(content: &str) -> Vec<String> {
+    let body = extract_body(content);
+    let body = unwrap_environment(body, "multicols*");
+
+    let pages: Vec<String> = if body.contains("\\begin{frame}") {
+        body.split("\\end{frame}")
+            .filter_map(|frame| {
+                let start = frame.find("\\begin{frame}")?;
+                let frame = &frame[start + "\\begin{frame}".len()..];
+                Some(skip_optional_argument(frame).trim().to_string())
+            })
+            .collect()
+    } else {
+        body.split("\\newpage")
+            .flat_map(|page| page.split("\\clearpage"))
+            .map(|page| page.trim().to_string())
+            .collect()
+    };
+
+    pages.into_iter().filter(|page| !page.is_empty()).collect()
+}
+
+/// Strip a `\begin{name}{arg}` ... `\end{name}` wrapper if the body has one.
+fn unwrap_environment<'a>(body: &'a str, name: &str) -> &'a str {
+    let begin = format!("\\begin{{{}}}", name);
+    let end = format!("\\end{{{}}}", name);
+    let Some(begin_idx) = body.find(&begin) else {
+        return body;
+    };
+    let rest = &body[begin_idx + begin.len()..];
+    // Skip the column count argument
+    let rest = match rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+        Some((_, after)) => after,
+        None => rest,
+    };
+    match rest.rfind(&end) {
+        Some(end_idx) => rest[..end_idx].trim(),
+        None => rest.trim(),
+    }
+}
+
+fn skip_optional_argument(text: &str) -> &str {
+    match text.strip_prefix('[').and_then(|r| r.split_once(']')) {
+        Some((_, after)) => after,
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_file_access() {
+        assert_eq!(
+            file_access("Notes \\input{/etc/passwd}").as_deref(),
+            Some("\\input")
+        );
+        assert_eq!(
+            file_access("\\immediate\\write18{ls}").as_deref(),
+            Some("\\write18")
+        );
+        assert_eq!(
+            file_access("\\makeatletter\\@@input .env").as_deref(),
+            Some("\\@@input")
+        );
+        assert_eq!(
+            file_access("\\openin5=secret \\read5 to\\x").as_deref(),
+            Some("\\openin")
+        );
+    }
+
+    #[test]
+    fn allows_ordinary_documents() {
+        let content = "\\documentclass{article}\n\\usepackage{graphicx}\n\\begin{document}\n\
+            \\includegraphics{fig} \\readme 50\\% % \\input{x}\n\\write16{note}\n\\end{document}";
+        assert_eq!(file_access(content), None);
+    }
+}
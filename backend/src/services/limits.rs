@@ -0,0 +1,395 @@
+//! Per-minute rate limits (in memory) and daily quotas per owner and per IP
+//! address (in the database).
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::http::HeaderMap;
+use chrono::{DateTime, NaiveDate, Utc};
+use uuid::Uuid;
+
+use crate::config::env::{ClientIpSource, LimitsConfig};
+use crate::db::Database;
+use crate::errors::{ApiError, Result};
+use crate::models::usage::{Meter, Payer, Usage, UsageReport};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Expired windows are dropped once this many are tracked.
+const PRUNE_AT: usize = 10_000;
+
+/// Wrong passwords a share accepts per minute, whoever sends them.
+pub const SHARE_PASSWORD_ATTEMPTS: u64 = 5;
+
+/// Who a rate limit applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bucket {
+    Owner(Uuid),
+    Ip(IpAddr),
+    /// Password attempts on a share.
+    SharePassword(Uuid),
+}
+
+/// The state of a bucket after counting a request, for `RateLimit-*` headers.
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u64,
+    pub remaining: u64,
+    /// Seconds until the window resets.
+    pub reset: u64,
+}
+
+struct Window {
+    started: Instant,
+    count: u64,
+}
+
+#[derive(Clone)]
+pub struct Limits {
+    config: Arc<LimitsConfig>,
+    windows: Arc<Mutex<HashMap<Bucket, Window>>>,
+}
+
+impl Limits {
+    pub fn new(config: LimitsConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            windows: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Count a request against `bucket`; `None` when it has no limit.
+    pub fn hit(&self, bucket: Bucket) -> Option<Decision> {
+        let limit = match bucket {
+            Bucket::Owner(_) => self.config.requests_per_minute,
+            Bucket::Ip(_) => self.config.ip_requests_per_minute,
+            Bucket::SharePassword(_) => SHARE_PASSWORD_ATTEMPTS,
+        };
+        if limit == 0 {
+            return None;
+        }
+        Some(self.count(bucket, limit))
+    }
+
+    /// Count a password attempt on a share before paying for the hash check,
+    /// so guesses sent in parallel are limited too. Attempts with the right
+    /// password are given back with [`Limits::share_password_accepted`].
+    pub fn attempt_share_password(&self, share_id: &Uuid) -> Result<()> {
+        let decision = self.count(Bucket::SharePassword(*share_id), SHARE_PASSWORD_ATTEMPTS);
+        if decision.allowed {
+            return Ok(());
+        }
+        Err(ApiError::TooManyRequests {
+            message: "Too many wrong passwords for this share; try again later".to_string(),
+            retry_after: decision.reset,
+        })
+    }
+
+    pub fn share_password_accepted(&self, share_id: &Uuid) {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(window) = windows.get_mut(&Bucket::SharePassword(*share_id)) {
+            window.count = window.count.saturating_sub(1);
+        }
+    }
+
+    fn count(&self, bucket: Bucket, limit: u64) -> Decision {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        if windows.len() >= PRUNE_AT {
+            windows.retain(|_, window| now.duration_since(window.started) < WINDOW);
+        }
+        let window = windows.entry(bucket).or_insert(Window {
+            started: now,
+            count: 0,
+        });
+        if now.duration_since(window.started) >= WINDOW {
+            window.started = now;
+            window.count = 0;
+        }
+        window.count += 1;
+
+        let reset = WINDOW.saturating_sub(now.duration_since(window.started));
+        Decision {
+            allowed: window.count <= limit,
+            limit,
+            remaining: limit.saturating_sub(window.count),
+            reset: reset.as_secs().max(1),
+        }
+    }
+
+    /// The client's address, from wherever `RATE_LIMIT_CLIENT_IP` says the
+    /// proxy in front puts it; the connection's when there is none.
+    pub fn client_ip(&self, headers: &HeaderMap, peer: IpAddr) -> IpAddr {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let ip = match self.config.client_ip {
+            ClientIpSource::Peer => None,
+            ClientIpSource::FlyClientIp => header("fly-client-ip"),
+            // Only the hop our proxy appended can be trusted
+            ClientIpSource::ForwardedFor => headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .next_back()
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next()),
+        };
+        ip.and_then(|ip| ip.trim().parse().ok()).unwrap_or(peer)
+    }
+
+    /// Charge `pages` against each payer's quota for today before converting
+    /// them, failing if that would exceed any of them. Pages for a conversion
+    /// that then fails are given back with [`Limits::release_pages`].
+    pub fn reserve_pages(&self, db: &Database, payers: &[Payer], pages: u64) -> Result<()> {
+        let day = today();
+        for (charged, payer) in payers.iter().enumerate() {
+            let limit = self.quotas(payer).pages;
+            if limit == 0 {
+                db.add_usage(
+                    payer,
+                    day,
+                    Usage {
+                        pages,
+                        ..Usage::default()
+                    },
+                )?;
+                continue;
+            }
+            if db.reserve_pages(payer, day, pages, limit)? {
+                continue;
+            }
+            self.release_pages(db, &payers[..charged], pages);
+            let used = db.usage(payer, day)?.pages;
+            return Err(quota_exceeded(format!(
+                "Daily page quota{} reached: {} of {} pages used, {} more requested",
+                whose(payer),
+                used,
+                limit,
+                pages
+            )));
+        }
+        Ok(())
+    }
+
+    /// Give back pages reserved today. Failures are logged: the caller is
+    /// already reporting an error of its own.
+    pub fn release_pages(&self, db: &Database, payers: &[Payer], pages: u64) {
+        for payer in payers {
+            if let Err(e) = db.release_pages(payer, today(), pages) {
+                tracing::warn!("failed to release {} reserved pages: {}", pages, e);
+            }
+        }
+    }
+
+    /// Fail if any payer's compile time for today is used up.
+    pub fn check_compile(&self, db: &Database, payers: &[Payer]) -> Result<()> {
+        for payer in payers {
+            let limit = self.quotas(payer).compile_seconds;
+            let used = db.usage(payer, today())?.compile_ms / 1000;
+            if limit > 0 && used >= limit {
+                return Err(quota_exceeded(format!(
+                    "Daily compile quota{} reached: {} of {} seconds used",
+                    whose(payer),
+                    used,
+                    limit
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn record(&self, db: &Database, payers: &[Payer], usage: Usage) -> Result<()> {
+        for payer in payers {
+            db.add_usage(payer, today(), usage)?;
+        }
+        Ok(())
+    }
+
+    pub fn report(&self, db: &Database, owner_id: &Uuid) -> Result<UsageReport> {
+        let day = today();
+        let usage = db.usage(&Payer::Owner(*owner_id), day)?;
+        let limit = |limit: u64| (limit > 0).then_some(limit);
+        Ok(UsageReport {
+            day,
+            pages: Meter {
+                used: usage.pages,
+                limit: limit(self.config.pages_per_day),
+            },
+            compile_seconds: Meter {
+                used: usage.compile_ms / 1000,
+                limit: limit(self.config.compile_seconds_per_day),
+            },
+            resets_at: midnight(),
+        })
+    }
+
+    fn quotas(&self, payer: &Payer) -> Quotas {
+        match payer {
+            Payer::Owner(_) => Quotas {
+                pages: self.config.pages_per_day,
+                compile_seconds: self.config.compile_seconds_per_day,
+            },
+            Payer::Ip(_) => Quotas {
+                pages: self.config.ip_pages_per_day,
+                compile_seconds: self.config.ip_compile_seconds_per_day,
+            },
+        }
+    }
+}
+
+/// A payer's daily limits; zero is none.
+struct Quotas {
+    pages: u64,
+    compile_seconds: u64,
+}
+
+/// Names the quota in messages when it isn't the caller's own.
+fn whose(payer: &Payer) -> &'static str {
+    match payer {
+        Payer::Owner(_) => "",
+        Payer::Ip(_) => " for this IP address",
+    }
+}
+
+fn today() -> NaiveDate {
+    Utc::now().date_naive()
+}
+
+/// The next UTC midnight, when quotas reset.
+fn midnight() -> DateTime<Utc> {
+    (today() + chrono::Days::new(1))
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight exists")
+        .and_utc()
+}
+
+fn quota_exceeded(message: String) -> ApiError {
+    ApiError::TooManyRequests {
+        message,
+        retry_after: (midnight() - Utc::now()).num_seconds().max(1) as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(client_ip: ClientIpSource) -> Limits {
+        Limits::new(LimitsConfig {
+            requests_per_minute: 0,
+            ip_requests_per_minute: 0,
+            pages_per_day: 0,
+            compile_seconds_per_day: 0,
+            ip_pages_per_day: 0,
+            ip_compile_seconds_per_day: 0,
+            client_ip,
+        })
+    }
+
+    #[test]
+    fn reserves_pages_up_to_the_quota() {
+        let db = Database::open(":memory:").unwrap();
+        let owner = [Payer::Owner(db.create_owner("reader", false).unwrap().id)];
+        let limits = Limits::new(LimitsConfig {
+            pages_per_day: 5,
+            ..limits(ClientIpSource::Peer).config.as_ref().clone()
+        });
+        let used = || db.usage(&owner[0], today()).unwrap().pages;
+
+        assert!(limits.reserve_pages(&db, &owner, 6).is_err());
+        assert_eq!(used(), 0);
+        limits.reserve_pages(&db, &owner, 3).unwrap();
+        // A second conversion running alongside can't take it past the quota
+        assert!(matches!(
+            limits.reserve_pages(&db, &owner, 3),
+            Err(ApiError::TooManyRequests { .. })
+        ));
+        limits.reserve_pages(&db, &owner, 2).unwrap();
+        assert_eq!(used(), 5);
+
+        // A failed conversion gives its pages back
+        limits.release_pages(&db, &owner, 2);
+        assert_eq!(used(), 3);
+        limits.reserve_pages(&db, &owner, 2).unwrap();
+        assert_eq!(used(), 5);
+    }
+
+    #[test]
+    fn shares_an_ip_quota_between_owners() {
+        let db = Database::open(":memory:").unwrap();
+        let [first, second] =
+            ["first", "second"].map(|name| db.create_owner(name, false).unwrap().id);
+        let ip = Payer::Ip("203.0.113.7".parse().unwrap());
+        let limits = Limits::new(LimitsConfig {
+            pages_per_day: 5,
+            ip_pages_per_day: 6,
+            ip_compile_seconds_per_day: 10,
+            ..limits(ClientIpSource::Peer).config.as_ref().clone()
+        });
+        let used = |payer| db.usage(&payer, today()).unwrap();
+
+        limits
+            .reserve_pages(&db, &[Payer::Owner(first), ip], 4)
+            .unwrap();
+        // Within the second owner's quota, but not the address's
+        let error = limits
+            .reserve_pages(&db, &[Payer::Owner(second), ip], 4)
+            .unwrap_err();
+        assert!(
+            error.detail().contains("for this IP address"),
+            "{}",
+            error.detail()
+        );
+        assert_eq!(used(Payer::Owner(second)).pages, 0);
+        assert_eq!(used(ip).pages, 4);
+        limits
+            .reserve_pages(&db, &[Payer::Owner(second), ip], 2)
+            .unwrap();
+        assert_eq!(used(ip).pages, 6);
+
+        // Compile time too, whichever owner's documents it went on
+        let compile = Usage {
+            compile_ms: 10_000,
+            ..Usage::default()
+        };
+        limits
+            .check_compile(&db, &[Payer::Owner(second), ip])
+            .unwrap();
+        limits
+            .record(&db, &[Payer::Owner(first), ip], compile)
+            .unwrap();
+        assert!(limits.check_compile(&db, &[Payer::Owner(second)]).is_ok());
+        assert!(matches!(
+            limits.check_compile(&db, &[Payer::Owner(second), ip]),
+            Err(ApiError::TooManyRequests { .. })
+        ));
+
+        // The usage report is the owner's own
+        assert_eq!(limits.report(&db, &first).unwrap().pages.used, 4);
+    }
+
+    #[test]
+    fn takes_the_client_ip_from_the_trusted_hop() {
+        let peer: IpAddr = "10.0.0.2".parse().unwrap();
+        let ip = |source, headers: &HeaderMap| limits(source).client_ip(headers, peer).to_string();
+
+        let mut headers = HeaderMap::new();
+        // A client claiming to be someone else, then what the proxy added
+        headers.append("x-forwarded-for", "1.1.1.1, 2.2.2.2".parse().unwrap());
+        headers.append("x-forwarded-for", "3.3.3.3".parse().unwrap());
+        headers.insert("fly-client-ip", "4.4.4.4".parse().unwrap());
+        assert_eq!(ip(ClientIpSource::Peer, &headers), "10.0.0.2");
+        assert_eq!(ip(ClientIpSource::ForwardedFor, &headers), "3.3.3.3");
+        assert_eq!(ip(ClientIpSource::FlyClientIp, &headers), "4.4.4.4");
+
+        headers.insert("x-forwarded-for", "1.1.1.1,5.5.5.5 ".parse().unwrap());
+        assert_eq!(ip(ClientIpSource::ForwardedFor, &headers), "5.5.5.5");
+        headers.insert("x-forwarded-for", "garbage".parse().unwrap());
+        assert_eq!(ip(ClientIpSource::ForwardedFor, &headers), "10.0.0.2");
+        assert_eq!(
+            ip(ClientIpSource::FlyClientIp, &HeaderMap::new()),
+            "10.0.0.2"
+        );
+    }
+}
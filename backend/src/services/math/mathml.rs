@@ -0,0 +1,278 @@
+use super::{escape_xml, MathNode, TableAlign};
+
+/// Render a tree as a MathML `<math>` element, keeping the TeX source as an annotation.
+pub fn to_mathml(node: &MathNode, tex: &str, display: bool) -> String {
+    let mut out = String::new();
+    out.push_str(if display {
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\">"
+    } else {
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">"
+    });
+    out.push_str("<semantics><mrow>");
+    write_mathml(node, display, &mut out);
+    out.push_str("</mrow><annotation encoding=\"application/x-tex\">");
+    out.push_str(&escape_xml(tex));
+    out.push_str("</annotation></semantics></math>");
+    out
+}
+
+fn write_mathml(node: &MathNode, display: bool, out: &mut String) {
+    match node {
+        MathNode::Ident { text, upright } => {
+            if *upright && text.chars().count() == 1 {
+                out.push_str("<mi mathvariant=\"normal\">");
+            } else {
+                out.push_str("<mi>");
+            }
+            out.push_str(&escape_xml(text));
+            out.push_str("</mi>");
+        }
+        MathNode::Number(text) => {
+            out.push_str("<mn>");
+            out.push_str(&escape_xml(text));
+            out.push_str("</mn>");
+        }
+        MathNode::Operator(text) => {
+            out.push_str("<mo>");
+            out.push_str(&escape_xml(text));
+            out.push_str("</mo>");
+        }
+        MathNode::LargeOperator { text, limits } => {
+            if text.chars().count() > 1 {
+                out.push_str("<mi>");
+                out.push_str(&escape_xml(text));
+                out.push_str("</mi>");
+            } else {
+                out.push_str(if *limits {
+                    "<mo largeop=\"true\" movablelimits=\"true\">"
+                } else {
+                    "<mo largeop=\"true\">"
+                });
+                out.push_str(&escape_xml(text));
+                out.push_str("</mo>");
+            }
+        }
+        MathNode::Text(text) => {
+            out.push_str("<mtext>");
+            out.push_str(&escape_xml(text));
+            out.push_str("</mtext>");
+        }
+        MathNode::Row(children) => {
+            out.push_str("<mrow>");
+            for child in children {
+                write_mathml(child, display, out);
+            }
+            out.push_str("</mrow>");
+        }
+        MathNode::Frac {
+            numerator,
+            denominator,
+            line,
+        } => {
+            out.push_str(if *line {
+                "<mfrac>"
+            } else {
+                "<mfrac linethickness=\"0\">"
+            });
+            write_mathml(numerator, display, out);
+            write_mathml(denominator, display, out);
+            out.push_str("</mfrac>");
+        }
+        MathNode::Sqrt { radicand, index } => match index {
+            Some(index) => {
+                out.push_str("<mroot>");
+                write_mathml(radicand, display, out);
+                write_mathml(index, display, out);
+                out.push_str("</mroot>");
+            }
+            None => {
+                out.push_str("<msqrt>");
+                write_mathml(radicand, display, out);
+                out.push_str("</msqrt>");
+            }
+        },
+        MathNode::Scripts { base, sub, sup } => {
+            let (tag_both, tag_sub, tag_sup) = if base.takes_limits() && display {
+                ("munderover", "munder", "mover")
+            } else {
+                ("msubsup", "msub", "msup")
+            };
+            let tag = match (sub, sup) {
+                (Some(_), Some(_)) => tag_both,
+                (Some(_), None) => tag_sub,
+                _ => tag_sup,
+            };
+            out.push_str(&format!("<{}>", tag));
+            write_mathml(base, display, out);
+            for script in [sub, sup].into_iter().flatten() {
+                write_mathml(script, display, out);
+            }
+            out.push_str(&format!("</{}>", tag));
+        }
+        MathNode::UnderOver { base, under, over } => {
+            let tag = match (under, over) {
+                (Some(_), Some(_)) => "munderover",
+                (Some(_), None) => "munder",
+                _ => "mover",
+            };
+            out.push_str(&format!("<{}>", tag));
+            write_mathml(base, display, out);
+            for script in [under, over].into_iter().flatten() {
+                write_mathml(script, display, out);
+            }
+            out.push_str(&format!("</{}>", tag));
+        }
+        MathNode::Accent {
+            base,
+            accent,
+            under,
+        } => {
+            let (tag, attribute) = if *under {
+                ("munder", "accentunder")
+            } else {
+                ("mover", "accent")
+            };
+            out.push_str(&format!("<{} {}=\"true\">", tag, attribute));
+            write_mathml(base, display, out);
+            out.push_str("<mo stretchy=\"true\">");
+            out.push_str(&escape_xml(&accent.to_string()));
+            out.push_str("</mo>");
+            out.push_str(&format!("</{}>", tag));
+        }
+        MathNode::Fenced { open, close, body } => {
+            out.push_str("<mrow>");
+            if !open.is_empty() {
+                out.push_str("<mo fence=\"true\" stretchy=\"true\">");
+                out.push_str(&escape_xml(open));
+                out.push_str("</mo>");
+            }
+            write_mathml(body, display, out);
+            if !close.is_empty() {
+                out.push_str("<mo fence=\"true\" stretchy=\"true\">");
+                out.push_str(&escape_xml(close));
+                out.push_str("</mo>");
+            }
+            out.push_str("</mrow>");
+        }
+        MathNode::Table { rows, align } => {
+            let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+            let column_align = (0..columns)
+                .map(|column| match align {
+                    TableAlign::Center => "center",
+                    TableAlign::Left => "left",
+                    TableAlign::Aligned if column % 2 == 0 => "right",
+                    TableAlign::Aligned => "left",
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            out.push_str(&format!("<mtable columnalign=\"{}\">", column_align));
+            for cells in rows {
+                out.push_str("<mtr>");
+                for cell in cells {
+                    out.push_str("<mtd>");
+                    write_mathml(cell, true, out);
+                    out.push_str("</mtd>");
+                }
+                out.push_str("</mtr>");
+            }
+            out.push_str("</mtable>");
+        }
+        MathNode::Space(em) => {
+            out.push_str(&format!("<mspace width=\"{:.4}em\"/>", em));
+        }
+        MathNode::Unknown(name) => {
+            out.push_str("<merror><mtext>");
+            out.push_str(&escape_xml(name));
+            out.push_str("</mtext></merror>");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse;
+    use super::*;
+
+    fn mathml(tex: &str, display: bool) -> String {
+        let mut out = String::new();
+        write_mathml(&parse(tex), display, &mut out);
+        out
+    }
+
+    #[test]
+    fn renders_constructs() {
+        let cases = [
+            ("\\frac{a}{b}", "<mfrac><mi>a</mi><mi>b</mi></mfrac>"),
+            ("\\tfrac12", "<mfrac><mn>1</mn><mn>2</mn></mfrac>"),
+            (
+                "\\binom{n}{k}",
+                "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mfrac linethickness=\"0\"><mi>n</mi><mi>k</mi></mfrac><mo fence=\"true\" stretchy=\"true\">)</mo></mrow>",
+            ),
+            (
+                "x_i^2",
+                "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>",
+            ),
+            (
+                "x^{n+1}",
+                "<msup><mi>x</mi><mrow><mi>n</mi><mo>+</mo><mn>1</mn></mrow></msup>",
+            ),
+            (
+                "\\int_0^1 f",
+                "<mrow><msubsup><mo largeop=\"true\">∫</mo><mn>0</mn><mn>1</mn></msubsup><mi>f</mi></mrow>",
+            ),
+            ("\\sqrt[3]{x}", "<mroot><mi>x</mi><mn>3</mn></mroot>"),
+            (
+                "\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}",
+                "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mtable columnalign=\"center center\"><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable><mo fence=\"true\" stretchy=\"true\">)</mo></mrow>",
+            ),
+            (
+                "\\begin{cases} 1 & x > 0 \\\\ 0 & \\text{else} \\end{cases}",
+                "<mrow><mo fence=\"true\" stretchy=\"true\">{</mo><mtable columnalign=\"left left\"><mtr><mtd><mn>1</mn></mtd><mtd><mrow><mi>x</mi><mo>&gt;</mo><mn>0</mn></mrow></mtd></mtr><mtr><mtd><mn>0</mn></mtd><mtd><mtext>else</mtext></mtd></mtr></mtable></mrow>",
+            ),
+            (
+                "\\left( \\frac{1}{2} \\right)",
+                "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mfrac><mn>1</mn><mn>2</mn></mfrac><mo fence=\"true\" stretchy=\"true\">)</mo></mrow>",
+            ),
+            (
+                "\\left\\{ x \\right.",
+                "<mrow><mo fence=\"true\" stretchy=\"true\">{</mo><mi>x</mi></mrow>",
+            ),
+            (
+                "\\sin x",
+                "<mrow><mi>sin</mi><mi>x</mi></mrow>",
+            ),
+            (
+                "\\foo{y}",
+                "<mrow><merror><mtext>\\foo</mtext></merror><mi>y</mi></mrow>",
+            ),
+        ];
+        for (tex, expected) in cases {
+            assert_eq!(mathml(tex, false), expected, "{}", tex);
+        }
+    }
+
+    #[test]
+    fn puts_limits_above_and_below_in_display_math() {
+        let sum = "\\sum_{i=1}^n i";
+        let scripts = "<mo largeop=\"true\" movablelimits=\"true\">∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi>";
+        assert_eq!(
+            mathml(sum, false),
+            format!("<mrow><msubsup>{}</msubsup><mi>i</mi></mrow>", scripts)
+        );
+        assert_eq!(
+            mathml(sum, true),
+            format!(
+                "<mrow><munderover>{}</munderover><mi>i</mi></mrow>",
+                scripts
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_the_source_as_an_annotation() {
+        assert_eq!(
+            to_mathml(&parse("a<b"), "a<b", true),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\"><semantics><mrow><mrow><mi>a</mi><mo>&lt;</mo><mi>b</mi></mrow></mrow><annotation encoding=\"application/x-tex\">a&lt;b</annotation></semantics></math>"
+        );
+    }
+}
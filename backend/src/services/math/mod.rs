@@ -0,0 +1,999 @@
+//! Parses TeX math into a small expression tree that the exporters render
+//! as MathML, Office Math or Typst.
+
+mod mathml;
+mod omml;
+mod typst;
+
+pub use mathml::to_mathml;
+pub use omml::to_omml;
+pub use typst::to_typst;
+
+use crate::models::content::MathKind;
+use crate::services::parser::Scanner;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MathNode {
+    /// Identifier; `upright` is set for function names and `\mathrm` text.
+    Ident {
+        text: String,
+        upright: bool,
+    },
+    Number(String),
+    Operator(String),
+    /// Big operator such as `\sum`; `limits` puts scripts above and below.
+    LargeOperator {
+        text: String,
+        limits: bool,
+    },
+    Text(String),
+    Row(Vec<MathNode>),
+    Frac {
+        numerator: Box<MathNode>,
+        denominator: Box<MathNode>,
+        line: bool,
+    },
+    Sqrt {
+        radicand: Box<MathNode>,
+        index: Option<Box<MathNode>>,
+    },
+    Scripts {
+        base: Box<MathNode>,
+        sub: Option<Box<MathNode>>,
+        sup: Option<Box<MathNode>>,
+    },
+    /// Content set above or below a base (`\overset`, `\underbrace`, ...).
+    UnderOver {
+        base: Box<MathNode>,
+        under: Option<Box<MathNode>>,
+        over: Option<Box<MathNode>>,
+    },
+    /// Accent over (or under) a base, such as `\hat` or `\underline`.
+    Accent {
+        base: Box<MathNode>,
+        accent: char,
+        under: bool,
+    },
+    Fenced {
+        open: String,
+        close: String,
+        body: Box<MathNode>,
+    },
+    Table {
+        rows: Vec<Vec<MathNode>>,
+        align: TableAlign,
+    },
+    /// Horizontal space, in ems.
+    Space(f32),
+    /// A command we do not understand, kept as its TeX name.
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableAlign {
+    Center,
+    Left,
+    /// Alternating right/left columns, as in `align`.
+    Aligned,
+}
+
+/// Parse a display equation, turning multi-row environments into a table.
+pub fn parse_display(kind: MathKind, tex: &str) -> MathNode {
+    let align = match kind {
+        MathKind::Aligned => TableAlign::Aligned,
+        MathKind::Single | MathKind::Gathered => TableAlign::Center,
+    };
+    MathParser::new(tex).parse_top(align)
+}
+
+/// Parse inline math.
+pub fn parse(tex: &str) -> MathNode {
+    MathParser::new(tex).parse_top(TableAlign::Center)
+}
+
+impl MathNode {
+    /// Every `Unknown` command in the tree, for reporting.
+    pub fn unknown_commands(&self) -> Vec<String> {
+        let mut unknown = Vec::new();
+        self.visit(&mut |node| {
+            if let MathNode::Unknown(name) = node {
+                if !unknown.contains(name) {
+                    unknown.push(name.clone());
+                }
+            }
+        });
+        unknown
+    }
+
+    fn visit(&self, f: &mut impl FnMut(&MathNode)) {
+        f(self);
+        match self {
+            MathNode::Row(children) => children.iter().for_each(|child| child.visit(f)),
+            MathNode::Frac {
+                numerator,
+                denominator,
+                ..
+            } => {
+                numerator.visit(f);
+                denominator.visit(f);
+            }
+            MathNode::Sqrt { radicand, index } => {
+                radicand.visit(f);
+                if let Some(index) = index {
+                    index.visit(f);
+                }
+            }
+            MathNode::Scripts { base, sub, sup } => {
+                base.visit(f);
+                sub.iter()
+                    .chain(sup.iter())
+                    .for_each(|child| child.visit(f));
+            }
+            MathNode::UnderOver { base, under, over } => {
+                base.visit(f);
+                under
+                    .iter()
+                    .chain(over.iter())
+                    .for_each(|child| child.visit(f));
+            }
+            MathNode::Accent { base, .. } => base.visit(f),
+            MathNode::Fenced { body, .. } => body.visit(f),
+            MathNode::Table { rows, .. } => rows.iter().flatten().for_each(|cell| cell.visit(f)),
+            _ => {}
+        }
+    }
+
+    /// True for operators whose scripts go above and below in display style.
+    pub fn takes_limits(&self) -> bool {
+        matches!(self, MathNode::LargeOperator { limits: true, .. })
+    }
+}
+
+enum Symbol {
+    Ident(&'static str),
+    Operator(&'static str),
+    Large(&'static str, bool),
+    Function(&'static str, bool),
+    Space(f32),
+}
+
+fn symbol(name: &str) -> Option<Symbol> {
+    use Symbol::*;
+    Some(match name {
+        // Greek
+        "alpha" => Ident("\u{3b1}"),
+        "beta" => Ident("\u{3b2}"),
+        "gamma" => Ident("\u{3b3}"),
+        "delta" => Ident("\u{3b4}"),
+        "epsilon" => Ident("\u{3f5}"),
+        "varepsilon" => Ident("\u{3b5}"),
+        "zeta" => Ident("\u{3b6}"),
+        "eta" => Ident("\u{3b7}"),
+        "theta" => Ident("\u{3b8}"),
+        "vartheta" => Ident("\u{3d1}"),
+        "iota" => Ident("\u{3b9}"),
+        "kappa" => Ident("\u{3ba}"),
+        "lambda" => Ident("\u{3bb}"),
+        "mu" => Ident("\u{3bc}"),
+        "nu" => Ident("\u{3bd}"),
+        "xi" => Ident("\u{3be}"),
+        "pi" => Ident("\u{3c0}"),
+        "varpi" => Ident("\u{3d6}"),
+        "rho" => Ident("\u{3c1}"),
+        "varrho" => Ident("\u{3f1}"),
+        "sigma" => Ident("\u{3c3}"),
+        "varsigma" => Ident("\u{3c2}"),
+        "tau" => Ident("\u{3c4}"),
+        "upsilon" => Ident("\u{3c5}"),
+        "phi" => Ident("\u{3d5}"),
+        "varphi" => Ident("\u{3c6}"),
+        "chi" => Ident("\u{3c7}"),
+        "psi" => Ident("\u{3c8}"),
+        "omega" => Ident("\u{3c9}"),
+        "Gamma" => Ident("\u{393}"),
+        "Delta" => Ident("\u{394}"),
+        "Theta" => Ident("\u{398}"),
+        "Lambda" => Ident("\u{39b}"),
+        "Xi" => Ident("\u{39e}"),
+        "Pi" => Ident("\u{3a0}"),
+        "Sigma" => Ident("\u{3a3}"),
+        "Upsilon" => Ident("\u{3a5}"),
+        "Phi" => Ident("\u{3a6}"),
+        "Psi" => Ident("\u{3a8}"),
+        "Omega" => Ident("\u{3a9}"),
+        // Letter-like
+        "infty" => Ident("\u{221e}"),
+        "partial" => Ident("\u{2202}"),
+        "nabla" => Ident("\u{2207}"),
+        "emptyset" | "varnothing" => Ident("\u{2205}"),
+        "ell" => Ident("\u{2113}"),
+        "hbar" => Ident("\u{210f}"),
+        "Re" => Ident("\u{211c}"),
+        "Im" => Ident("\u{2111}"),
+        "aleph" => Ident("\u{2135}"),
+        "wp" => Ident("\u{2118}"),
+        "angle" => Ident("\u{2220}"),
+        "triangle" => Ident("\u{25b3}"),
+        "top" => Ident("\u{22a4}"),
+        "bot" => Ident("\u{22a5}"),
+        // Relations
+        "le" | "leq" => Operator("\u{2264}"),
+        "ge" | "geq" => Operator("\u{2265}"),
+        "leqslant" => Operator("\u{2a7d}"),
+        "geqslant" => Operator("\u{2a7e}"),
+        "ne" | "neq" => Operator("\u{2260}"),
+        "approx" => Operator("\u{2248}"),
+        "equiv" => Operator("\u{2261}"),
+        "sim" => Operator("\u{223c}"),
+        "simeq" => Operator("\u{2243}"),
+        "cong" => Operator("\u{2245}"),
+        "propto" => Operator("\u{221d}"),
+        "ll" => Operator("\u{226a}"),
+        "gg" => Operator("\u{226b}"),
+        "prec" => Operator("\u{227a}"),
+        "succ" => Operator("\u{227b}"),
+        "in" => Operator("\u{2208}"),
+        "notin" => Operator("\u{2209}"),
+        "ni" => Operator("\u{220b}"),
+        "subset" => Operator("\u{2282}"),
+        "subseteq" => Operator("\u{2286}"),
+        "subsetneq" => Operator("\u{228a}"),
+        "supset" => Operator("\u{2283}"),
+        "supseteq" => Operator("\u{2287}"),
+        "perp" => Operator("\u{22a5}"),
+        "parallel" => Operator("\u{2225}"),
+        "mid" => Operator("\u{2223}"),
+        "nmid" => Operator("\u{2224}"),
+        "models" => Operator("\u{22a8}"),
+        "vdash" => Operator("\u{22a2}"),
+        "coloneqq" => Operator("\u{2254}"),
+        // Binary operators
+        "pm" => Operator("\u{b1}"),
+        "mp" => Operator("\u{2213}"),
+        "times" => Operator("\u{d7}"),
+        "div" => Operator("\u{f7}"),
+        "cdot" => Operator("\u{22c5}"),
+        "ast" => Operator("\u{2217}"),
+        "star" => Operator("\u{22c6}"),
+        "circ" => Operator("\u{2218}"),
+        "bullet" => Operator("\u{2219}"),
+        "cup" => Operator("\u{222a}"),
+        "cap" => Operator("\u{2229}"),
+        "setminus" => Operator("\u{2216}"),
+        "wedge" | "land" => Operator("\u{2227}"),
+        "vee" | "lor" => Operator("\u{2228}"),
+        "oplus" => Operator("\u{2295}"),
+        "ominus" => Operator("\u{2296}"),
+        "otimes" => Operator("\u{2297}"),
+        "odot" => Operator("\u{2299}"),
+        "neg" | "lnot" => Operator("\u{ac}"),
+        "forall" => Operator("\u{2200}"),
+        "exists" => Operator("\u{2203}"),
+        "nexists" => Operator("\u{2204}"),
+        // Arrows
+        "to" | "rightarrow" => Operator("\u{2192}"),
+        "gets" | "leftarrow" => Operator("\u{2190}"),
+        "leftrightarrow" => Operator("\u{2194}"),
+        "Rightarrow" => Operator("\u{21d2}"),
+        "Leftarrow" => Operator("\u{21d0}"),
+        "Leftrightarrow" => Operator("\u{21d4}"),
+        "implies" | "Longrightarrow" => Operator("\u{27f9}"),
+        "impliedby" | "Longleftarrow" => Operator("\u{27f8}"),
+        "iff" | "Longleftrightarrow" => Operator("\u{27fa}"),
+        "longrightarrow" => Operator("\u{27f6}"),
+        "longleftarrow" => Operator("\u{27f5}"),
+        "mapsto" => Operator("\u{21a6}"),
+        "longmapsto" => Operator("\u{27fc}"),
+        "hookrightarrow" => Operator("\u{21aa}"),
+        "uparrow" => Operator("\u{2191}"),
+        "downarrow" => Operator("\u{2193}"),
+        "rightharpoonup" => Operator("\u{21c0}"),
+        // Delimiters and punctuation
+        "{" | "lbrace" => Operator("{"),
+        "}" | "rbrace" => Operator("}"),
+        "langle" => Operator("\u{27e8}"),
+        "rangle" => Operator("\u{27e9}"),
+        "lfloor" => Operator("\u{230a}"),
+        "rfloor" => Operator("\u{230b}"),
+        "lceil" => Operator("\u{2308}"),
+        "rceil" => Operator("\u{2309}"),
+        "|" | "Vert" | "lVert" | "rVert" => Operator("\u{2016}"),
+        "vert" | "lvert" | "rvert" => Operator("|"),
+        "colon" => Operator(":"),
+        "ldots" | "dots" | "dotsc" | "dotsb" => Operator("\u{2026}"),
+        "cdots" => Operator("\u{22ef}"),
+        "vdots" => Operator("\u{22ee}"),
+        "ddots" => Operator("\u{22f1}"),
+        "prime" => Operator("\u{2032}"),
+        "%" => Operator("%"),
+        "#" => Operator("#"),
+        "&" => Operator("&"),
+        "_" => Operator("_"),
+        "$" => Operator("$"),
+        // Large operators
+        "sum" => Large("\u{2211}", true),
+        "prod" => Large("\u{220f}", true),
+        "coprod" => Large("\u{2210}", true),
+        "bigcup" => Large("\u{22c3}", true),
+        "bigcap" => Large("\u{22c2}", true),
+        "bigoplus" => Large("\u{2a01}", true),
+        "bigotimes" => Large("\u{2a02}", true),
+        "bigvee" => Large("\u{22c1}", true),
+        "bigwedge" => Large("\u{22c0}", true),
+        "int" => Large("\u{222b}", false),
+        "iint" => Large("\u{222c}", false),
+        "iiint" => Large("\u{222d}", false),
+        "oint" => Large("\u{222e}", false),
+        // Named functions
+        "lim" => Function("lim", true),
+        "limsup" => Function("lim sup", true),
+        "liminf" => Function("lim inf", true),
+        "max" => Function("max", true),
+        "min" => Function("min", true),
+        "sup" => Function("sup", true),
+        "inf" => Function("inf", true),
+        "det" => Function("det", true),
+        "gcd" => Function("gcd", true),
+        "Pr" => Function("Pr", true),
+        "sin" => Function("sin", false),
+        "cos" => Function("cos", false),
+        "tan" => Function("tan", false),
+        "cot" => Function("cot", false),
+        "sec" => Function("sec", false),
+        "csc" => Function("csc", false),
+        "arcsin" => Function("arcsin", false),
+        "arccos" => Function("arccos", false),
+        "arctan" => Function("arctan", false),
+        "sinh" => Function("sinh", false),
+        "cosh" => Function("cosh", false),
+        "tanh" => Function("tanh", false),
+        "log" => Function("log", false),
+        "ln" => Function("ln", false),
+        "lg" => Function("lg", false),
+        "exp" => Function("exp", false),
+        "deg" => Function("deg", false),
+        "dim" => Function("dim", false),
+        "ker" => Function("ker", false),
+        "hom" => Function("hom", false),
+        "arg" => Function("arg", false),
+        "rank" => Function("rank", false),
+        "tr" => Function("tr", false),
+        // Spacing
+        "," | "thinspace" => Space(0.1667),
+        ":" | ">" | "medspace" => Space(0.2222),
+        ";" | "thickspace" => Space(0.2778),
+        "!" | "negthinspace" => Space(-0.1667),
+        " " => Space(0.25),
+        "quad" => Space(1.0),
+        "qquad" => Space(2.0),
+        _ => return None,
+    })
+}
+
+fn accent(name: &str) -> Option<(char, bool)> {
+    Some(match name {
+        "hat" | "widehat" => ('^', false),
+        "bar" | "overline" => ('\u{203e}', false),
+        "vec" | "overrightarrow" => ('\u{2192}', false),
+        "overleftarrow" => ('\u{2190}', false),
+        "dot" => ('\u{2d9}', false),
+        "ddot" => ('\u{a8}', false),
+        "tilde" | "widetilde" => ('~', false),
+        "check" => ('\u{2c7}', false),
+        "breve" => ('\u{2d8}', false),
+        "acute" => ('\u{b4}', false),
+        "grave" => ('`', false),
+        "underline" => ('_', true),
+        _ => return None,
+    })
+}
+
+/// Alphabet variants selected with `\mathbb`, `\mathbf` and friends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variant {
+    Normal,
+    Bold,
+    Italic,
+    DoubleStruck,
+    Script,
+    Fraktur,
+    SansSerif,
+    Monospace,
+}
+
+fn variant(name: &str) -> Option<Variant> {
+    Some(match name {
+        "mathrm" | "mathup" | "textrm" | "rm" => Variant::Normal,
+        "mathbf" | "boldsymbol" | "bm" | "textbf" | "mathbfup" => Variant::Bold,
+        "mathit" | "textit" => Variant::Italic,
+        "mathbb" | "Bbb" => Variant::DoubleStruck,
+        "mathcal" | "mathscr" => Variant::Script,
+        "mathfrak" => Variant::Fraktur,
+        "mathsf" | "textsf" => Variant::SansSerif,
+        "mathtt" | "texttt" => Variant::Monospace,
+        _ => return None,
+    })
+}
+
+/// Map a letter or digit to its Unicode mathematical alphanumeric form.
+fn styled_char(c: char, variant: Variant) -> char {
+    let exception = match (variant, c) {
+        (Variant::DoubleStruck, 'C') => Some('\u{2102}'),
+        (Variant::DoubleStruck, 'H') => Some('\u{210d}'),
+        (Variant::DoubleStruck, 'N') => Some('\u{2115}'),
+        (Variant::DoubleStruck, 'P') => Some('\u{2119}'),
+        (Variant::DoubleStruck, 'Q') => Some('\u{211a}'),
+        (Variant::DoubleStruck, 'R') => Some('\u{211d}'),
+        (Variant::DoubleStruck, 'Z') => Some('\u{2124}'),
+        (Variant::Script, 'B') => Some('\u{212c}'),
+        (Variant::Script, 'E') => Some('\u{2130}'),
+        (Variant::Script, 'F') => Some('\u{2131}'),
+        (Variant::Script, 'H') => Some('\u{210b}'),
+        (Variant::Script, 'I') => Some('\u{2110}'),
+        (Variant::Script, 'L') => Some('\u{2112}'),
+        (Variant::Script, 'M') => Some('\u{2133}'),
+        (Variant::Script, 'R') => Some('\u{211b}'),
+        (Variant::Script, 'e') => Some('\u{212f}'),
+        (Variant::Script, 'g') => Some('\u{210a}'),
+        (Variant::Script, 'o') => Some('\u{2134}'),
+        (Variant::Fraktur, 'C') => Some('\u{212d}'),
+        (Variant::Fraktur, 'H') => Some('\u{210c}'),
+        (Variant::Fraktur, 'I') => Some('\u{2111}'),
+        (Variant::Fraktur, 'R') => Some('\u{211c}'),
+        (Variant::Fraktur, 'Z') => Some('\u{2128}'),
+        (Variant::Italic, 'h') => Some('\u{210e}'),
+        _ => None,
+    };
+    if let Some(exception) = exception {
+        return exception;
+    }
+
+    let (upper, lower, digit) = match variant {
+        Variant::Normal => return c,
+        Variant::Bold => (0x1d400, 0x1d41a, Some(0x1d7ce)),
+        Variant::Italic => (0x1d434, 0x1d44e, None),
+        Variant::DoubleStruck => (0x1d538, 0x1d552, Some(0x1d7d8)),
+        Variant::Script => (0x1d49c, 0x1d4b6, None),
+        Variant::Fraktur => (0x1d504, 0x1d51e, None),
+        Variant::SansSerif => (0x1d5a0, 0x1d5ba, Some(0x1d7e2)),
+        Variant::Monospace => (0x1d670, 0x1d68a, Some(0x1d7f6)),
+    };
+    let code = match c {
+        'A'..='Z' => upper + (c as u32 - 'A' as u32),
+        'a'..='z' => lower + (c as u32 - 'a' as u32),
+        '0'..='9' => match digit {
+            Some(base) => base + (c as u32 - '0' as u32),
+            None => return c,
+        },
+        _ => return c,
+    };
+    char::from_u32(code).unwrap_or(c)
+}
+
+struct MathParser<'a> {
+    scanner: Scanner<'a>,
+}
+
+impl<'a> MathParser<'a> {
+    fn new(tex: &'a str) -> Self {
+        Self {
+            scanner: Scanner::new(tex),
+        }
+    }
+
+    fn parse_top(&mut self, align: TableAlign) -> MathNode {
+        let mut rows = Vec::new();
+        loop {
+            rows.extend(self.parse_rows());
+            self.scanner.skip_whitespace();
+            if self.scanner.peek().is_none() {
+                break;
+            }
+            // Stray `}`, `\right` or `\end`: skip it and keep going
+            let before = self.scanner.pos;
+            self.scanner.command_name();
+            if self.scanner.pos == before {
+                self.scanner.bump();
+            }
+        }
+        table_or_row(rows, align)
+    }
+
+    /// Parse `&`/`\\` separated cells up to a closing token.
+    fn parse_rows(&mut self) -> Vec<Vec<MathNode>> {
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+        loop {
+            cells.push(row(self.parse_sequence()));
+            self.scanner.skip_whitespace();
+            if self.scanner.eat("&") {
+                continue;
+            }
+            if self.scanner.eat("\\\\") {
+                self.scanner.optional();
+                rows.push(std::mem::take(&mut cells));
+                continue;
+            }
+            break;
+        }
+        let trailing_empty = cells.iter().all(|cell| *cell == MathNode::Row(Vec::new()));
+        if !trailing_empty || rows.is_empty() {
+            rows.push(cells);
+        }
+        rows
+    }
+
+    fn parse_sequence(&mut self) -> Vec<MathNode> {
+        let mut nodes: Vec<MathNode> = Vec::new();
+        loop {
+            self.scanner.skip_whitespace();
+            let rest = self.scanner.rest();
+            let Some(c) = self.scanner.peek() else {
+                break;
+            };
+            if c == '}'
+                || c == '&'
+                || rest.starts_with("\\\\")
+                || at_command(rest, "right")
+                || at_command(rest, "end")
+            {
+                break;
+            }
+            match c {
+                '^' | '_' => {
+                    self.scanner.bump();
+                    let script = self.argument();
+                    let base = nodes.pop().unwrap_or(MathNode::Row(Vec::new()));
+                    nodes.push(attach_script(base, script, c == '^'));
+                }
+                '\'' => {
+                    let mut primes = String::new();
+                    while self.scanner.eat("'") {
+                        primes.push('\u{2032}');
+                    }
+                    let base = nodes.pop().unwrap_or(MathNode::Row(Vec::new()));
+                    nodes.push(attach_script(base, MathNode::Operator(primes), true));
+                }
+                _ => {
+                    if let Some(node) = self.atom() {
+                        nodes.push(node);
+                    }
+                }
+            }
+        }
+        nodes
+    }
+
+    /// A script or command argument: a group or a single token.
+    fn argument(&mut self) -> MathNode {
+        self.scanner.skip_whitespace();
+        match self.scanner.peek() {
+            Some('{') => self.group(),
+            Some('\\') => self.atom().unwrap_or(MathNode::Row(Vec::new())),
+            Some(c) => {
+                self.scanner.bump();
+                single_char(c)
+            }
+            None => MathNode::Row(Vec::new()),
+        }
+    }
+
+    fn group(&mut self) -> MathNode {
+        self.scanner.eat("{");
+        let mut nodes = Vec::new();
+        loop {
+            nodes.extend(self.parse_sequence());
+            if self.scanner.eat("}") || self.scanner.peek().is_none() {
+                break;
+            }
+            // `&` or `\\` inside a plain group; keep them literally
+            if !self.scanner.eat("&") {
+                let before = self.scanner.pos;
+                self.scanner.command_name();
+                if self.scanner.pos == before {
+                    self.scanner.bump();
+                }
+            }
+        }
+        row(nodes)
+    }
+
+    fn atom(&mut self) -> Option<MathNode> {
+        let c = self.scanner.peek()?;
+        match c {
+            '{' => Some(self.group()),
+            '\\' => self.command(),
+            '0'..='9' | '.' => {
+                let rest = self.scanner.rest();
+                let len = rest
+                    .char_indices()
+                    .find(|(_, c)| !(c.is_ascii_digit() || *c == '.'))
+                    .map(|(idx, _)| idx)
+                    .unwrap_or(rest.len());
+                let number = &rest[..len];
+                self.scanner.pos += len;
+                if number == "." {
+                    Some(MathNode::Operator(".".to_string()))
+                } else {
+                    Some(MathNode::Number(number.to_string()))
+                }
+            }
+            '~' => {
+                self.scanner.bump();
+                Some(MathNode::Space(0.25))
+            }
+            _ => {
+                self.scanner.bump();
+                Some(single_char(c))
+            }
+        }
+    }
+
+    fn command(&mut self) -> Option<MathNode> {
+        let name = self.scanner.command_name();
+        if let Some(symbol) = symbol(name) {
+            return Some(match symbol {
+                Symbol::Ident(text) => MathNode::Ident {
+                    text: text.to_string(),
+                    upright: false,
+                },
+                Symbol::Operator(text) => MathNode::Operator(text.to_string()),
+                Symbol::Large(text, limits) => MathNode::LargeOperator {
+                    text: text.to_string(),
+                    limits,
+                },
+                Symbol::Function(text, limits) if limits => MathNode::LargeOperator {
+                    text: text.to_string(),
+                    limits,
+                },
+                Symbol::Function(text, _) => MathNode::Ident {
+                    text: text.to_string(),
+                    upright: true,
+                },
+                Symbol::Space(em) => MathNode::Space(em),
+            });
+        }
+        if let Some((accent, under)) = accent(name) {
+            let base = self.argument();
+            return Some(MathNode::Accent {
+                base: Box::new(base),
+                accent,
+                under,
+            });
+        }
+        if let Some(variant) = variant(name) {
+            let argument = self.argument();
+            return Some(apply_variant(argument, variant));
+        }
+
+        let node = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => MathNode::Frac {
+                numerator: Box::new(self.argument()),
+                denominator: Box::new(self.argument()),
+                line: true,
+            },
+            "binom" | "dbinom" | "tbinom" => MathNode::Fenced {
+                open: "(".to_string(),
+                close: ")".to_string(),
+                body: Box::new(MathNode::Frac {
+                    numerator: Box::new(self.argument()),
+                    denominator: Box::new(self.argument()),
+                    line: false,
+                }),
+            },
+            "sqrt" => {
+                let index = self.scanner.optional().map(|index| Box::new(parse(index)));
+                MathNode::Sqrt {
+                    radicand: Box::new(self.argument()),
+                    index,
+                }
+            }
+            "text" | "mbox" | "textnormal" | "hbox" => {
+                let text = self.scanner.group().unwrap_or_default();
+                MathNode::Text(text.to_string())
+            }
+            "operatorname" | "operatorname*" => {
+                let text = self.scanner.group().unwrap_or_default();
+                let text = text.trim().to_string();
+                if name.ends_with('*') {
+                    MathNode::LargeOperator { text, limits: true }
+                } else {
+                    MathNode::Ident {
+                        text,
+                        upright: true,
+                    }
+                }
+            }
+            "overset" | "stackrel" => {
+                let over = self.argument();
+                let base = self.argument();
+                MathNode::UnderOver {
+                    base: Box::new(base),
+                    under: None,
+                    over: Some(Box::new(over)),
+                }
+            }
+            "underset" => {
+                let under = self.argument();
+                let base = self.argument();
+                MathNode::UnderOver {
+                    base: Box::new(base),
+                    under: Some(Box::new(under)),
+                    over: None,
+                }
+            }
+            "overbrace" | "underbrace" => {
+                let base = self.argument();
+                let brace = if name == "overbrace" {
+                    '\u{23de}'
+                } else {
+                    '\u{23df}'
+                };
+                MathNode::Accent {
+                    base: Box::new(base),
+                    accent: brace,
+                    under: name == "underbrace",
+                }
+            }
+            "left" => {
+                let open = self.delimiter();
+                let body = row(self.parse_sequence());
+                let close = if at_command(self.scanner.rest(), "right") {
+                    self.scanner.command_name();
+                    self.delimiter()
+                } else {
+                    String::new()
+                };
+                MathNode::Fenced {
+                    open,
+                    close,
+                    body: Box::new(body),
+                }
+            }
+            "begin" => {
+                let env = self.scanner.group().unwrap_or_default();
+                return Some(self.environment(env));
+            }
+            "not" => {
+                let negated = self.argument();
+                match negated {
+                    MathNode::Operator(op) => MathNode::Operator(format!("{}\u{338}", op)),
+                    other => other,
+                }
+            }
+            "phantom" | "hphantom" | "vphantom" | "label" | "tag" => {
+                self.argument();
+                return None;
+            }
+            "mathop" | "mathrel" | "mathbin" | "mathord" => self.argument(),
+            "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" | "nonumber"
+            | "notag" | "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr"
+            | "biggl" | "biggr" | "Biggl" | "Biggr" | "middle" => return None,
+            _ => MathNode::Unknown(format!("\\{}", name)),
+        };
+        Some(node)
+    }
+
+    /// The delimiter token after `\left` or `\right`; `.` means none.
+    fn delimiter(&mut self) -> String {
+        self.scanner.skip_whitespace();
+        match self.scanner.peek() {
+            Some('\\') => match self.command() {
+                Some(MathNode::Operator(text)) => text,
+                _ => String::new(),
+            },
+            Some('.') => {
+                self.scanner.bump();
+                String::new()
+            }
+            Some(c) => {
+                self.scanner.bump();
+                c.to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    fn environment(&mut self, env: &str) -> MathNode {
+        if env == "array" || env.starts_with("alignat") {
+            self.scanner.group();
+        }
+        let rows = self.parse_rows();
+        let end = format!("\\end{{{}}}", env);
+        self.scanner.skip_whitespace();
+        if !self.scanner.eat(&end) {
+            // Mismatched end; consume whatever closes the environment
+            if self.scanner.eat("\\end") {
+                self.scanner.group();
+            }
+        }
+
+        let (open, close, align) = match env.trim_end_matches('*') {
+            "pmatrix" => ("(", ")", TableAlign::Center),
+            "bmatrix" => ("[", "]", TableAlign::Center),
+            "Bmatrix" => ("{", "}", TableAlign::Center),
+            "vmatrix" => ("|", "|", TableAlign::Center),
+            "Vmatrix" => ("\u{2016}", "\u{2016}", TableAlign::Center),
+            "cases" | "dcases" => ("{", "", TableAlign::Left),
+            "rcases" => ("", "}", TableAlign::Left),
+            "aligned" | "align" | "split" | "alignat" | "alignedat" | "eqnarray" | "flalign" => {
+                ("", "", TableAlign::Aligned)
+            }
+            _ => ("", "", TableAlign::Center),
+        };
+        let table = MathNode::Table { rows, align };
+        if open.is_empty() && close.is_empty() {
+            table
+        } else {
+            MathNode::Fenced {
+                open: open.to_string(),
+                close: close.to_string(),
+                body: Box::new(table),
+            }
+        }
+    }
+}
+
+/// True if `rest` starts with `\name` as a whole command, not a prefix of a longer one.
+fn at_command(rest: &str, name: &str) -> bool {
+    rest.strip_prefix('\\')
+        .and_then(|rest| rest.strip_prefix(name))
+        .is_some_and(|after| !after.starts_with(|c: char| c.is_ascii_alphabetic()))
+}
+
+fn single_char(c: char) -> MathNode {
+    if c.is_alphabetic() {
+        MathNode::Ident {
+            text: c.to_string(),
+            upright: false,
+        }
+    } else if c.is_ascii_digit() {
+        MathNode::Number(c.to_string())
+    } else if c == '-' {
+        MathNode::Operator("\u{2212}".to_string())
+    } else {
+        MathNode::Operator(c.to_string())
+    }
+}
+
+fn attach_script(base: MathNode, script: MathNode, is_sup: bool) -> MathNode {
+    match base {
+        MathNode::Scripts { base, sub, sup }
+            if (is_sup && sup.is_none()) || (!is_sup && sub.is_none()) =>
+        {
+            if is_sup {
+                MathNode::Scripts {
+                    base,
+                    sub,
+                    sup: Some(Box::new(script)),
+                }
+            } else {
+                MathNode::Scripts {
+                    base,
+                    sub: Some(Box::new(script)),
+                    sup,
+                }
+            }
+        }
+        base => {
+            let script = Some(Box::new(script));
+            if is_sup {
+                MathNode::Scripts {
+                    base: Box::new(base),
+                    sub: None,
+                    sup: script,
+                }
+            } else {
+                MathNode::Scripts {
+                    base: Box::new(base),
+                    sub: script,
+                    sup: None,
+                }
+            }
+        }
+    }
+}
+
+fn apply_variant(node: MathNode, variant: Variant) -> MathNode {
+    match node {
+        MathNode::Ident { text, .. } => MathNode::Ident {
+            text: text.chars().map(|c| styled_char(c, variant)).collect(),
+            upright: variant == Variant::Normal,
+        },
+        MathNode::Number(text) => {
+            MathNode::Number(text.chars().map(|c| styled_char(c, variant)).collect())
+        }
+        MathNode::Row(children) => {
+            // `\mathrm{d}` style words read as one upright identifier
+            let all_letters = children.iter().all(
+                |child| matches!(child, MathNode::Ident { text, .. } if text.chars().count() == 1),
+            );
+            if all_letters && variant == Variant::Normal && children.len() > 1 {
+                let text = children
+                    .iter()
+                    .filter_map(|child| match child {
+                        MathNode::Ident { text, .. } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                return MathNode::Ident {
+                    text,
+                    upright: true,
+                };
+            }
+            MathNode::Row(
+                children
+                    .into_iter()
+                    .map(|child| apply_variant(child, variant))
+                    .collect(),
+            )
+        }
+        MathNode::Scripts { base, sub, sup } => MathNode::Scripts {
+            base: Box::new(apply_variant(*base, variant)),
+            sub,
+            sup,
+        },
+        other => other,
+    }
+}
+
+fn row(mut nodes: Vec<MathNode>) -> MathNode {
+    if nodes.len() == 1 {
+        nodes.pop().unwrap()
+    } else {
+        MathNode::Row(nodes)
+    }
+}
+
+fn table_or_row(mut rows: Vec<Vec<MathNode>>, align: TableAlign) -> MathNode {
+    if rows.len() == 1 && rows[0].len() == 1 {
+        return rows.pop().unwrap().pop().unwrap();
+    }
+    MathNode::Table { rows, align }
+}
+
+/// Escape text for XML, dropping the characters XML 1.0 does not allow at
+/// all: C0 controls other than tab and newlines, and U+FFFE/U+FFFF.
+pub fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\t' | '\n' | '\r' => out.push(c),
+            '\0'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {}
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_xml() {
+        let cases = [
+            (
+                "a < b && c > \"d\" 'e'",
+                "a &lt; b &amp;&amp; c &gt; &quot;d&quot; &#39;e&#39;",
+            ),
+            ("tab\tnew\nline\r", "tab\tnew\nline\r"),
+            (
+                "nul\0 bell\u{7} esc\u{1b} \u{fffe}\u{ffff}",
+                "nul bell esc ",
+            ),
+            (
+                "del\u{7f} nbsp\u{a0} \u{1d400}",
+                "del\u{7f} nbsp\u{a0} \u{1d400}",
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(escape_xml(text), expected, "{:?}", text);
+        }
+    }
+}
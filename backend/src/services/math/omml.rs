@@ -0,0 +1,469 @@
+//! Office Math Markup Language, the native equation format of Word.
+
+use super::{escape_xml, MathNode, TableAlign};
+
+/// Render a tree as an `<m:oMath>` element; display equations get an `<m:oMathPara>`.
+pub fn to_omml(node: &MathNode, display: bool) -> String {
+    let mut out = String::new();
+    if display {
+        out.push_str("<m:oMathPara>");
+    }
+    out.push_str("<m:oMath>");
+    match node {
+        // Aligned rows become an equation array aligned on the relation after `&`
+        MathNode::Table {
+            rows,
+            align: TableAlign::Aligned,
+        } if display => {
+            out.push_str("<m:eqArr>");
+            for cells in rows {
+                out.push_str("<m:e>");
+                for (index, cell) in cells.iter().enumerate() {
+                    write_omml(cell, index % 2 == 1, &mut out);
+                }
+                out.push_str("</m:e>");
+            }
+            out.push_str("</m:eqArr>");
+        }
+        node => write_omml(node, false, &mut out),
+    }
+    out.push_str("</m:oMath>");
+    if display {
+        out.push_str("</m:oMathPara>");
+    }
+    out
+}
+
+fn run(text: &str, properties: &str, out: &mut String) {
+    out.push_str("<m:r>");
+    if !properties.is_empty() {
+        out.push_str("<m:rPr>");
+        out.push_str(properties);
+        out.push_str("</m:rPr>");
+    }
+    out.push_str("<m:t xml:space=\"preserve\">");
+    out.push_str(&escape_xml(text));
+    out.push_str("</m:t></m:r>");
+}
+
+fn element(tag: &str, node: &MathNode, out: &mut String) {
+    out.push_str(&format!("<m:{}>", tag));
+    write_omml(node, false, out);
+    out.push_str(&format!("</m:{}>", tag));
+}
+
+/// `align_first` marks the first run as an alignment point inside `m:eqArr`.
+///
+/// `m:aln` must come last in `m:rPr`, after `m:nor`/`m:sty`.
+fn write_omml(node: &MathNode, align_first: bool, out: &mut String) {
+    let aln = if align_first { "<m:aln/>" } else { "" };
+    match node {
+        MathNode::Ident { text, upright } => {
+            let style = if *upright { "<m:sty m:val=\"p\"/>" } else { "" };
+            run(text, &format!("{}{}", style, aln), out);
+        }
+        MathNode::Number(text) | MathNode::Operator(text) => run(text, aln, out),
+        MathNode::LargeOperator { text, .. } => {
+            run(text, &format!("<m:sty m:val=\"p\"/>{}", aln), out)
+        }
+        MathNode::Text(text) => run(text, &format!("<m:nor/>{}", aln), out),
+        MathNode::Row(children) => write_row(children, align_first, out),
+        MathNode::Frac {
+            numerator,
+            denominator,
+            line,
+        } => {
+            out.push_str("<m:f>");
+            if !line {
+                out.push_str("<m:fPr><m:type m:val=\"noBar\"/></m:fPr>");
+            }
+            element("num", numerator, out);
+            element("den", denominator, out);
+            out.push_str("</m:f>");
+        }
+        MathNode::Sqrt { radicand, index } => {
+            out.push_str("<m:rad>");
+            match index {
+                Some(index) => element("deg", index, out),
+                None => out.push_str("<m:radPr><m:degHide m:val=\"1\"/></m:radPr><m:deg/>"),
+            }
+            element("e", radicand, out);
+            out.push_str("</m:rad>");
+        }
+        MathNode::Scripts { base, sub, sup } => {
+            if let MathNode::LargeOperator { text, limits } = base.as_ref() {
+                if text.chars().count() == 1 {
+                    write_nary(text, *limits, sub, sup, None, out);
+                    return;
+                }
+                // Named operators with limits (lim, max) put the limit below
+                if *limits && sup.is_none() {
+                    if let Some(sub) = sub {
+                        out.push_str("<m:limLow><m:e>");
+                        write_omml(base, align_first, out);
+                        out.push_str("</m:e>");
+                        element("lim", sub, out);
+                        out.push_str("</m:limLow>");
+                        return;
+                    }
+                }
+            }
+            let tag = match (sub, sup) {
+                (Some(_), Some(_)) => "sSubSup",
+                (Some(_), None) => "sSub",
+                _ => "sSup",
+            };
+            out.push_str(&format!("<m:{}><m:e>", tag));
+            write_omml(base, align_first, out);
+            out.push_str("</m:e>");
+            if let Some(sub) = sub {
+                element("sub", sub, out);
+            }
+            if let Some(sup) = sup {
+                element("sup", sup, out);
+            }
+            out.push_str(&format!("</m:{}>", tag));
+        }
+        MathNode::UnderOver { base, under, over } => {
+            let mut inner = String::new();
+            write_omml(base, align_first, &mut inner);
+            if let Some(under) = under {
+                let mut wrapped = String::from("<m:limLow><m:e>");
+                wrapped.push_str(&inner);
+                wrapped.push_str("</m:e>");
+                element("lim", under, &mut wrapped);
+                wrapped.push_str("</m:limLow>");
+                inner = wrapped;
+            }
+            if let Some(over) = over {
+                let mut wrapped = String::from("<m:limUpp><m:e>");
+                wrapped.push_str(&inner);
+                wrapped.push_str("</m:e>");
+                element("lim", over, &mut wrapped);
+                wrapped.push_str("</m:limUpp>");
+                inner = wrapped;
+            }
+            out.push_str(&inner);
+        }
+        MathNode::Accent {
+            base,
+            accent,
+            under,
+        } => match accent {
+            '\u{203e}' | '_' => {
+                let position = if *under { "bot" } else { "top" };
+                out.push_str(&format!(
+                    "<m:bar><m:barPr><m:pos m:val=\"{}\"/></m:barPr>",
+                    position
+                ));
+                element("e", base, out);
+                out.push_str("</m:bar>");
+            }
+            '\u{23de}' | '\u{23df}' => {
+                let position = if *under { "bot" } else { "top" };
+                out.push_str(&format!(
+                    "<m:groupChr><m:groupChrPr><m:chr m:val=\"{}\"/><m:pos m:val=\"{}\"/></m:groupChrPr>",
+                    accent, position
+                ));
+                element("e", base, out);
+                out.push_str("</m:groupChr>");
+            }
+            _ => {
+                out.push_str(&format!(
+                    "<m:acc><m:accPr><m:chr m:val=\"{}\"/></m:accPr>",
+                    escape_xml(&combining(*accent).to_string())
+                ));
+                element("e", base, out);
+                out.push_str("</m:acc>");
+            }
+        },
+        MathNode::Fenced { open, close, body } => {
+            out.push_str(&format!(
+                "<m:d><m:dPr><m:begChr m:val=\"{}\"/><m:endChr m:val=\"{}\"/></m:dPr>",
+                escape_xml(open),
+                escape_xml(close)
+            ));
+            element("e", body, out);
+            out.push_str("</m:d>");
+        }
+        MathNode::Table { rows, align } => {
+            let justification = match align {
+                TableAlign::Center => "center",
+                TableAlign::Left | TableAlign::Aligned => "left",
+            };
+            let columns = rows.iter().map(Vec::len).max().unwrap_or(1);
+            out.push_str(&format!(
+                "<m:m><m:mPr><m:mcs><m:mc><m:mcPr><m:count m:val=\"{}\"/><m:mcJc m:val=\"{}\"/></m:mcPr></m:mc></m:mcs></m:mPr>",
+                columns, justification
+            ));
+            for cells in rows {
+                out.push_str("<m:mr>");
+                for column in 0..columns {
+                    match cells.get(column) {
+                        Some(cell) => element("e", cell, out),
+                        None => out.push_str("<m:e/>"),
+                    }
+                }
+                out.push_str("</m:mr>");
+            }
+            out.push_str("</m:m>");
+        }
+        MathNode::Space(em) => {
+            let space = if *em >= 2.0 {
+                "\u{2003}\u{2003}"
+            } else if *em >= 1.0 {
+                "\u{2003}"
+            } else if *em > 0.2 {
+                "\u{2005}"
+            } else if *em > 0.0 {
+                "\u{2009}"
+            } else {
+                return;
+            };
+            run(space, aln, out);
+        }
+        MathNode::Unknown(name) => run(name, &format!("<m:nor/>{}", aln), out),
+    }
+}
+
+/// Write a row, folding each n-ary operator together with the term after it.
+fn write_row(children: &[MathNode], align_first: bool, out: &mut String) {
+    let mut index = 0;
+    while index < children.len() {
+        let child = &children[index];
+        let align = align_first && index == 0;
+        match child {
+            MathNode::Scripts { base, sub, sup } if is_nary(base) => {
+                let MathNode::LargeOperator { text, limits } = base.as_ref() else {
+                    unreachable!();
+                };
+                let operand = children.get(index + 1);
+                write_nary(text, *limits, sub, sup, operand, out);
+                index += if operand.is_some() { 2 } else { 1 };
+            }
+            MathNode::LargeOperator { text, limits } if is_nary(child) => {
+                let operand = children.get(index + 1);
+                write_nary(text, *limits, &None, &None, operand, out);
+                index += if operand.is_some() { 2 } else { 1 };
+            }
+            _ => {
+                write_omml(child, align, out);
+                index += 1;
+            }
+        }
+    }
+}
+
+/// Word expects combining marks for `m:acc`.
+fn combining(accent: char) -> char {
+    match accent {
+        '^' => '\u{302}',
+        '~' => '\u{303}',
+        '\u{2192}' => '\u{20d7}',
+        '\u{2190}' => '\u{20d6}',
+        '\u{2d9}' => '\u{307}',
+        '\u{a8}' => '\u{308}',
+        '\u{2c7}' => '\u{30c}',
+        '\u{2d8}' => '\u{306}',
+        '\u{b4}' => '\u{301}',
+        '`' => '\u{300}',
+        other => other,
+    }
+}
+
+fn is_nary(node: &MathNode) -> bool {
+    matches!(node, MathNode::LargeOperator { text, .. } if text.chars().count() == 1)
+}
+
+fn write_nary(
+    symbol: &str,
+    limits: bool,
+    sub: &Option<Box<MathNode>>,
+    sup: &Option<Box<MathNode>>,
+    operand: Option<&MathNode>,
+    out: &mut String,
+) {
+    out.push_str("<m:nary><m:naryPr>");
+    out.push_str(&format!("<m:chr m:val=\"{}\"/>", escape_xml(symbol)));
+    out.push_str(if limits {
+        "<m:limLoc m:val=\"undOvr\"/>"
+    } else {
+        "<m:limLoc m:val=\"subSup\"/>"
+    });
+    if sub.is_none() {
+        out.push_str("<m:subHide m:val=\"1\"/>");
+    }
+    if sup.is_none() {
+        out.push_str("<m:supHide m:val=\"1\"/>");
+    }
+    out.push_str("</m:naryPr>");
+    match sub {
+        Some(sub) => element("sub", sub, out),
+        None => out.push_str("<m:sub/>"),
+    }
+    match sup {
+        Some(sup) => element("sup", sup, out),
+        None => out.push_str("<m:sup/>"),
+    }
+    match operand {
+        Some(operand) => element("e", operand, out),
+        None => out.push_str("<m:e/>"),
+    }
+    out.push_str("</m:nary>");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{parse, parse_display};
+    use super::*;
+    use crate::models::content::MathKind;
+
+    fn omml(tex: &str) -> String {
+        let mut out = String::new();
+        write_omml(&parse(tex), false, &mut out);
+        out
+    }
+
+    fn r(text: &str) -> String {
+        format!("<m:r><m:t xml:space=\"preserve\">{}</m:t></m:r>", text)
+    }
+
+    fn styled(properties: &str, text: &str) -> String {
+        format!(
+            "<m:r><m:rPr>{}</m:rPr><m:t xml:space=\"preserve\">{}</m:t></m:r>",
+            properties, text
+        )
+    }
+
+    #[test]
+    fn renders_constructs() {
+        let cases = [
+            (
+                "\\frac{a}{b}",
+                format!("<m:f><m:num>{}</m:num><m:den>{}</m:den></m:f>", r("a"), r("b")),
+            ),
+            (
+                "\\binom{n}{k}",
+                format!(
+                    "<m:d><m:dPr><m:begChr m:val=\"(\"/><m:endChr m:val=\")\"/></m:dPr><m:e><m:f><m:fPr><m:type m:val=\"noBar\"/></m:fPr><m:num>{}</m:num><m:den>{}</m:den></m:f></m:e></m:d>",
+                    r("n"),
+                    r("k")
+                ),
+            ),
+            (
+                "x_i^2",
+                format!(
+                    "<m:sSubSup><m:e>{}</m:e><m:sub>{}</m:sub><m:sup>{}</m:sup></m:sSubSup>",
+                    r("x"),
+                    r("i"),
+                    r("2")
+                ),
+            ),
+            (
+                "x^{n+1}",
+                format!(
+                    "<m:sSup><m:e>{}</m:e><m:sup>{}{}{}</m:sup></m:sSup>",
+                    r("x"),
+                    r("n"),
+                    r("+"),
+                    r("1")
+                ),
+            ),
+            (
+                "\\sum_{i=1}^n i",
+                format!(
+                    "<m:nary><m:naryPr><m:chr m:val=\"∑\"/><m:limLoc m:val=\"undOvr\"/></m:naryPr><m:sub>{}{}{}</m:sub><m:sup>{}</m:sup><m:e>{}</m:e></m:nary>",
+                    r("i"),
+                    r("="),
+                    r("1"),
+                    r("n"),
+                    r("i")
+                ),
+            ),
+            (
+                "\\int_0^1 f",
+                format!(
+                    "<m:nary><m:naryPr><m:chr m:val=\"∫\"/><m:limLoc m:val=\"subSup\"/></m:naryPr><m:sub>{}</m:sub><m:sup>{}</m:sup><m:e>{}</m:e></m:nary>",
+                    r("0"),
+                    r("1"),
+                    r("f")
+                ),
+            ),
+            (
+                "\\lim_{x \\to 0} x",
+                format!(
+                    "<m:limLow><m:e>{}</m:e><m:lim>{}{}{}</m:lim></m:limLow>{}",
+                    styled("<m:sty m:val=\"p\"/>", "lim"),
+                    r("x"),
+                    r("→"),
+                    r("0"),
+                    r("x")
+                ),
+            ),
+            (
+                "\\sqrt[3]{x}",
+                format!("<m:rad><m:deg>{}</m:deg><m:e>{}</m:e></m:rad>", r("3"), r("x")),
+            ),
+            (
+                "\\begin{bmatrix} 1 \\\\ 2 \\end{bmatrix}",
+                format!(
+                    "<m:d><m:dPr><m:begChr m:val=\"[\"/><m:endChr m:val=\"]\"/></m:dPr><m:e><m:m><m:mPr><m:mcs><m:mc><m:mcPr><m:count m:val=\"1\"/><m:mcJc m:val=\"center\"/></m:mcPr></m:mc></m:mcs></m:mPr><m:mr><m:e>{}</m:e></m:mr><m:mr><m:e>{}</m:e></m:mr></m:m></m:e></m:d>",
+                    r("1"),
+                    r("2")
+                ),
+            ),
+            (
+                "\\left| x \\right|",
+                format!(
+                    "<m:d><m:dPr><m:begChr m:val=\"|\"/><m:endChr m:val=\"|\"/></m:dPr><m:e>{}</m:e></m:d>",
+                    r("x")
+                ),
+            ),
+            (
+                "\\left\\{ x \\right.",
+                format!(
+                    "<m:d><m:dPr><m:begChr m:val=\"{{\"/><m:endChr m:val=\"\"/></m:dPr><m:e>{}</m:e></m:d>",
+                    r("x")
+                ),
+            ),
+            (
+                "\\text{if } a<b",
+                format!(
+                    "{}{}{}{}",
+                    styled("<m:nor/>", "if "),
+                    r("a"),
+                    r("&lt;"),
+                    r("b")
+                ),
+            ),
+            (
+                "\\foo x",
+                format!("{}{}", styled("<m:nor/>", "\\foo"), r("x")),
+            ),
+        ];
+        for (tex, expected) in cases {
+            assert_eq!(omml(tex), expected, "{}", tex);
+        }
+    }
+
+    #[test]
+    fn aligns_display_rows_on_the_relation() {
+        let node = parse_display(MathKind::Aligned, "a &= b \\\\ c &= d");
+        let aligned = |left: &str, right: &str| {
+            format!(
+                "<m:e>{}{}{}</m:e>",
+                r(left),
+                styled("<m:aln/>", "="),
+                r(right)
+            )
+        };
+        assert_eq!(
+            to_omml(&node, true),
+            format!(
+                "<m:oMathPara><m:oMath><m:eqArr>{}{}</m:eqArr></m:oMath></m:oMathPara>",
+                aligned("a", "b"),
+                aligned("c", "d")
+            )
+        );
+    }
+}
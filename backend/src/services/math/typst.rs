@@ -0,0 +1,303 @@
+//! Typst math syntax.
+
+use super::{MathNode, TableAlign};
+
+/// Functions Typst predefines in math mode; other names go through `op()`.
+const TYPST_OPERATORS: [&str; 33] = [
+    "arccos", "arcsin", "arctan", "arg", "cos", "cosh", "cot", "coth", "csc", "deg", "det", "dim",
+    "exp", "gcd", "hom", "inf", "ker", "lg", "lim", "liminf", "limsup", "ln", "log", "max", "min",
+    "Pr", "sec", "sin", "sinh", "sup", "tan", "tanh", "tr",
+];
+
+/// Render a tree as the body of a Typst `$...$` equation.
+pub fn to_typst(node: &MathNode) -> String {
+    let mut out = String::new();
+    write_typst(node, &mut out);
+    out.trim().to_string()
+}
+
+fn write_typst(node: &MathNode, out: &mut String) {
+    match node {
+        MathNode::Ident { text, upright } => {
+            if *upright {
+                operator_name(text, false, out);
+            } else if text.chars().count() > 1 {
+                // Multi-letter identifiers would be read as variables
+                out.push_str(&format!("{} ", quote(text)));
+            } else {
+                out.push_str(&format!("{} ", text));
+            }
+        }
+        MathNode::Number(text) => out.push_str(&format!("{} ", text)),
+        MathNode::Operator(text) => out.push_str(&format!("{} ", escape_operator(text))),
+        MathNode::LargeOperator { text, limits } => {
+            if text.chars().count() > 1 {
+                operator_name(text, *limits, out);
+            } else {
+                out.push_str(&format!("{} ", text));
+            }
+        }
+        MathNode::Text(text) => out.push_str(&format!("{} ", quote(text))),
+        MathNode::Row(children) => {
+            if children.is_empty() {
+                out.push_str("zws ");
+            }
+            for child in children {
+                write_typst(child, out);
+            }
+        }
+        MathNode::Frac {
+            numerator,
+            denominator,
+            line,
+        } => {
+            let function = if *line { "frac" } else { "binom" };
+            out.push_str(&format!(
+                "{}({}, {}) ",
+                function,
+                to_typst(numerator),
+                to_typst(denominator)
+            ));
+        }
+        MathNode::Sqrt { radicand, index } => match index {
+            Some(index) => out.push_str(&format!(
+                "root({}, {}) ",
+                to_typst(index),
+                to_typst(radicand)
+            )),
+            None => out.push_str(&format!("sqrt({}) ", to_typst(radicand))),
+        },
+        MathNode::Scripts { base, sub, sup } => {
+            out.push_str(&attachment(base, false));
+            scripts(sub, sup, out);
+        }
+        MathNode::UnderOver { base, under, over } => {
+            out.push_str(&attachment(base, true));
+            scripts(under, over, out);
+        }
+        MathNode::Accent {
+            base,
+            accent,
+            under,
+        } => {
+            let function = match (accent, under) {
+                ('_', true) => "underline",
+                ('\u{23df}', _) => "underbrace",
+                ('\u{23de}', _) => "overbrace",
+                ('\u{203e}', _) => "overline",
+                ('^', _) => "hat",
+                ('~', _) => "tilde",
+                ('\u{2192}', _) => "arrow",
+                ('\u{2190}', _) => "arrow.l",
+                ('\u{2d9}', _) => "dot",
+                ('\u{a8}', _) => "dot.double",
+                ('\u{2c7}', _) => "caron",
+                ('\u{2d8}', _) => "breve",
+                ('\u{b4}', _) => "acute",
+                ('`', _) => "grave",
+                _ => "overline",
+            };
+            out.push_str(&format!("{}({}) ", function, to_typst(base)));
+        }
+        MathNode::Fenced { open, close, body } => match body.as_ref() {
+            MathNode::Frac {
+                numerator,
+                denominator,
+                line: false,
+            } if open == "(" && close == ")" => {
+                out.push_str(&format!(
+                    "binom({}, {}) ",
+                    to_typst(numerator),
+                    to_typst(denominator)
+                ));
+            }
+            MathNode::Table { rows, .. } if open == "{" && close.is_empty() => {
+                let cases: Vec<String> = rows
+                    .iter()
+                    .map(|cells| cells.iter().map(to_typst).collect::<Vec<_>>().join(" & "))
+                    .collect();
+                out.push_str(&format!("cases({}) ", cases.join(", ")));
+            }
+            MathNode::Table { rows, .. } => {
+                let delimiter = match (open.as_str(), close.as_str()) {
+                    ("(", ")") => "\"(\"",
+                    ("[", "]") => "\"[\"",
+                    ("{", "}") => "\"{\"",
+                    ("|", "|") => "\"|\"",
+                    ("\u{2016}", "\u{2016}") => "\"||\"",
+                    _ => "#none",
+                };
+                out.push_str(&format!(
+                    "mat(delim: {}, {}) ",
+                    delimiter,
+                    matrix_rows(rows)
+                ));
+            }
+            _ => {
+                out.push_str(&format!(
+                    "lr({} {} {}) ",
+                    delimiter(open),
+                    to_typst(body),
+                    delimiter(close)
+                ));
+            }
+        },
+        MathNode::Table { rows, align } => {
+            // Top-level rows use Typst's own `&` alignment and `\` line breaks
+            let lines: Vec<String> = rows
+                .iter()
+                .map(|cells| {
+                    let separator = if *align == TableAlign::Aligned {
+                        " & "
+                    } else {
+                        " "
+                    };
+                    cells
+                        .iter()
+                        .map(to_typst)
+                        .collect::<Vec<_>>()
+                        .join(separator)
+                })
+                .collect();
+            out.push_str(&lines.join(" \\\n"));
+            out.push(' ');
+        }
+        MathNode::Space(em) => {
+            let space = if *em >= 2.0 {
+                "wide"
+            } else if *em >= 1.0 {
+                "quad"
+            } else if *em > 0.25 {
+                "thick"
+            } else if *em > 0.2 {
+                "med"
+            } else if *em > 0.0 {
+                "thin"
+            } else {
+                return;
+            };
+            out.push_str(&format!("{} ", space));
+        }
+        MathNode::Unknown(name) => out.push_str(&format!("{} ", quote(name))),
+    }
+}
+
+fn operator_name(text: &str, limits: bool, out: &mut String) {
+    let compact: String = text.split_whitespace().collect();
+    if TYPST_OPERATORS.contains(&compact.as_str()) {
+        out.push_str(&format!("{} ", compact));
+    } else if text.chars().count() == 1 {
+        out.push_str(&format!("upright({}) ", text));
+    } else if limits {
+        out.push_str(&format!("op({}, limits: #true) ", quote(text)));
+    } else {
+        out.push_str(&format!("op({}) ", quote(text)));
+    }
+}
+
+fn matrix_rows(rows: &[Vec<MathNode>]) -> String {
+    rows.iter()
+        .map(|cells| cells.iter().map(to_typst).collect::<Vec<_>>().join(", "))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// The base of an attachment, with `limits()` for under/over placement.
+///
+/// Typst attaches scripts to the last item only, so a multi-item base is wrapped.
+fn attachment(base: &MathNode, limits: bool) -> String {
+    let base = match base {
+        MathNode::Row(children) if children.len() > 1 => format!("attach({})", to_typst(base)),
+        base => to_typst(base),
+    };
+    if limits {
+        format!("limits({})", base)
+    } else {
+        base
+    }
+}
+
+/// Scripts in parentheses, which Typst strips after `_` and `^`.
+fn scripts(sub: &Option<Box<MathNode>>, sup: &Option<Box<MathNode>>, out: &mut String) {
+    if let Some(sub) = sub {
+        out.push_str(&format!("_({})", to_typst(sub)));
+    }
+    if let Some(sup) = sup {
+        out.push_str(&format!("^({})", to_typst(sup)));
+    }
+    out.push(' ');
+}
+
+fn delimiter(text: &str) -> String {
+    match text {
+        "" => String::new(),
+        "(" | ")" | "[" | "]" | "|" => text.to_string(),
+        "{" | "}" => format!("\\{}", text),
+        other => other.to_string(),
+    }
+}
+
+fn escape_operator(text: &str) -> String {
+    match text {
+        // Commas and semicolons would otherwise split function arguments
+        "/" | "_" | "^" | "&" | "$" | "#" | "\\" | "\"" | "'" | "[" | "]" | "{" | "}" | "("
+        | ")" | "," | ";" => {
+            format!("\\{}", text)
+        }
+        _ => text.to_string(),
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{parse, parse_display};
+    use super::*;
+    use crate::models::content::MathKind;
+
+    #[test]
+    fn renders_constructs() {
+        let cases = [
+            ("\\frac{a}{b}", "frac(a, b)"),
+            ("\\binom{n}{k}", "binom(n, k)"),
+            ("x_i^2", "x_(i)^(2)"),
+            ("x^{n+1}", "x^(n + 1)"),
+            ("\\sum_{i=1}^n i", "∑_(i = 1)^(n) i"),
+            ("\\int_0^1 f", "∫_(0)^(1) f"),
+            ("\\lim_{x \\to 0} x", "lim_(x → 0) x"),
+            ("\\sqrt[3]{x}", "root(3, x)"),
+            ("\\hat{x}", "hat(x)"),
+            (
+                "\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}",
+                "mat(delim: \"(\", a, b; c, d)",
+            ),
+            (
+                "\\begin{vmatrix} a & b \\\\ c & d \\end{vmatrix}",
+                "mat(delim: \"|\", a, b; c, d)",
+            ),
+            (
+                "\\begin{cases} 1 & x > 0 \\\\ 0 & \\text{otherwise} \\end{cases}",
+                "cases(1 & x > 0, 0 & \"otherwise\")",
+            ),
+            ("\\left( \\frac{1}{2} \\right)", "lr(( frac(1, 2) ))"),
+            ("\\left\\{ x \\right.", "lr(\\{ x )"),
+            ("\\left| x \\right|", "lr(| x |)"),
+            ("\\sin x", "sin x"),
+            ("\\operatorname{rank} A", "op(\"rank\") A"),
+            ("f(a, b)", "f \\( a \\, b \\)"),
+            ("\\foo x", "\"\\\\foo\" x"),
+        ];
+        for (tex, expected) in cases {
+            assert_eq!(to_typst(&parse(tex)), expected, "{}", tex);
+        }
+    }
+
+    #[test]
+    fn aligns_display_rows() {
+        let node = parse_display(MathKind::Aligned, "a &= b \\\\ c &= d");
+        assert_eq!(to_typst(&node), "a & = b \\\nc & = d");
+    }
+}
@@ -0,0 +1,228 @@
+//! Prometheus metrics, served from `/metrics`.
+//!
+//! Counters and histograms are updated where things happen; gauges that
+//! describe stored state (running jobs, stored bytes) are read at scrape
+//! time instead.
+
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, Encoder, HistogramVec, IntCounterVec,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::db::Database;
+use crate::errors::{ApiError, Result};
+use crate::services::storage::Storage;
+
+/// Artifact directories reported by `noteforge_storage_*`.
+const PREFIXES: [&str; 3] = ["uploads/", "latex/", "pdf/"];
+
+/// Listing a large bucket on every scrape would be wasteful.
+const STORAGE_TTL: Duration = Duration::from_secs(60);
+
+pub static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "noteforge_http_requests_total",
+        "HTTP requests by route and status",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+pub static HTTP_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "noteforge_http_request_duration_seconds",
+        "HTTP request latency by route",
+        &["method", "route"],
+        vec![0.005, 0.025, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]
+    )
+    .unwrap()
+});
+
+pub static MODEL_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "noteforge_model_request_duration_seconds",
+        "Latency of calls to the model API",
+        &["model"],
+        vec![0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0]
+    )
+    .unwrap()
+});
+
+pub static MODEL_TOKENS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "noteforge_model_tokens_total",
+        "Tokens used by the model API, by direction (input or output)",
+        &["model", "direction"]
+    )
+    .unwrap()
+});
+
+pub static MODEL_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "noteforge_model_errors_total",
+        "Failed calls to the model API",
+        &["model", "reason"]
+    )
+    .unwrap()
+});
+
+pub static COMPILE_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "noteforge_pdf_compile_duration_seconds",
+        "PDF compile time by engine and outcome",
+        &["engine", "outcome"],
+        vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]
+    )
+    .unwrap()
+});
+
+pub static COMPILE_FAILURES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "noteforge_pdf_compile_failures_total",
+        "Failed PDF compiles by engine and reason",
+        &["engine", "reason"]
+    )
+    .unwrap()
+});
+
+pub static CACHE_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "noteforge_cache_requests_total",
+        "Lookups of stored artifacts that can be rebuilt, by result (hit or miss)",
+        &["artifact", "result"]
+    )
+    .unwrap()
+});
+
+pub static WEBHOOK_ATTEMPTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "noteforge_webhook_attempts_total",
+        "Webhook delivery attempts by event and outcome (success, retry or failure)",
+        &["event", "outcome"]
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Copy)]
+struct DirectoryUsage {
+    prefix: &'static str,
+    bytes: u64,
+    objects: u64,
+}
+
+/// The last storage listing and when it was taken.
+static STORAGE: Mutex<Option<(Instant, Vec<DirectoryUsage>)>> = Mutex::new(None);
+
+pub fn record_compile(engine: &str, elapsed: Duration, result: &Result<Vec<u8>>) {
+    let outcome = if result.is_ok() { "success" } else { "failure" };
+    COMPILE_DURATION
+        .with_label_values(&[engine, outcome])
+        .observe(elapsed.as_secs_f64());
+    if let Err(e) = result {
+        let message = match e {
+            // The display form of these hides the cause
+            ApiError::InternalServerError(e) => e.to_string(),
+            e => e.to_string(),
+        };
+        COMPILE_FAILURES
+            .with_label_values(&[engine, failure_reason(&message)])
+            .inc();
+    }
+}
+
+/// A short, bounded label for why a compile failed.
+fn failure_reason(message: &str) -> &'static str {
+    const REASONS: [(&str, &str); 7] = [
+        ("timed out", "timeout"),
+        ("Failed to run", "engine_unavailable"),
+        ("Undefined control sequence", "undefined_control_sequence"),
+        (".sty' not found", "missing_package"),
+        ("Missing $ inserted", "missing_dollar"),
+        ("Emergency stop", "emergency_stop"),
+        ("error:", "compile_error"),
+    ];
+    REASONS
+        .iter()
+        .find(|(needle, _)| message.contains(needle))
+        .map_or("other", |(_, reason)| reason)
+}
+
+/// Everything registered, in the Prometheus text format.
+pub async fn render(db: &Database, storage: &Storage) -> Result<String> {
+    let registry = Registry::new();
+    let jobs = IntGaugeVec::new(
+        Opts::new(
+            "noteforge_jobs_running",
+            "Conversions and PDF builds in progress",
+        ),
+        &["kind"],
+    )
+    .map_err(metrics_error)?;
+    for (kind, running) in db.running_jobs()? {
+        jobs.with_label_values(&[&kind]).set(running as i64);
+    }
+    let bytes = IntGaugeVec::new(
+        Opts::new(
+            "noteforge_storage_bytes",
+            "Size of stored artifacts by directory",
+        ),
+        &["prefix"],
+    )
+    .map_err(metrics_error)?;
+    let objects = IntGaugeVec::new(
+        Opts::new(
+            "noteforge_storage_objects",
+            "Number of stored artifacts by directory",
+        ),
+        &["prefix"],
+    )
+    .map_err(metrics_error)?;
+    for usage in storage_usage(storage).await? {
+        bytes
+            .with_label_values(&[usage.prefix])
+            .set(usage.bytes as i64);
+        objects
+            .with_label_values(&[usage.prefix])
+            .set(usage.objects as i64);
+    }
+    registry
+        .register(Box::new(jobs))
+        .and_then(|_| registry.register(Box::new(bytes)))
+        .and_then(|_| registry.register(Box::new(objects)))
+        .map_err(metrics_error)?;
+
+    let mut families = prometheus::gather();
+    families.extend(registry.gather());
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&families, &mut buffer)
+        .map_err(metrics_error)?;
+    String::from_utf8(buffer).map_err(metrics_error)
+}
+
+async fn storage_usage(storage: &Storage) -> Result<Vec<DirectoryUsage>> {
+    if let Some((taken, usage)) = STORAGE.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        if taken.elapsed() < STORAGE_TTL {
+            return Ok(usage.clone());
+        }
+    }
+
+    let mut usage = Vec::with_capacity(PREFIXES.len());
+    for prefix in PREFIXES {
+        let entries = storage.entries(prefix).await?;
+        usage.push(DirectoryUsage {
+            prefix,
+            bytes: entries.iter().map(|entry| entry.size).sum(),
+            objects: entries.len() as u64,
+        });
+    }
+    *STORAGE.lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), usage.clone()));
+    Ok(usage)
+}
+
+fn metrics_error(error: impl std::fmt::Display) -> ApiError {
+    ApiError::InternalServerError(anyhow::anyhow!("Failed to render metrics: {}", error))
+}
@@ -1,2 +1,20 @@
+pub mod archive;
+pub mod bundle;
 pub mod claude;
+pub mod compose;
+pub mod export;
+pub mod health;
+pub mod janitor;
+pub mod jwt;
+pub mod latex;
+pub mod limits;
+pub mod math;
+pub mod metrics;
+pub mod parser;
 pub mod pdf;
+pub mod share;
+pub mod storage;
+pub mod styles;
+pub mod telemetry;
+pub mod typst;
+pub mod webhooks;
@@ -0,0 +1,1351 @@
+//! Parses the LaTeX the model produces into the `Content` document model.
+//!
+//! This is not a TeX engine: it understands the subset of LaTeX our prompts
+//! ask for (sectioning, lists, theorem-like environments, display math) and
+//! keeps anything else verbatim as `Block::Unsupported`.
+
+use std::collections::HashMap;
+
+use crate::models::content::{Block, Content, Inline, ListItem, MathKind};
+use crate::services::latex;
+
+const MATH_ENVIRONMENTS: [(&str, MathKind); 14] = [
+    ("equation", MathKind::Single),
+    ("equation*", MathKind::Single),
+    ("displaymath", MathKind::Single),
+    ("align", MathKind::Aligned),
+    ("align*", MathKind::Aligned),
+    ("flalign", MathKind::Aligned),
+    ("flalign*", MathKind::Aligned),
+    ("alignat", MathKind::Aligned),
+    ("alignat*", MathKind::Aligned),
+    ("eqnarray", MathKind::Aligned),
+    ("eqnarray*", MathKind::Aligned),
+    ("gather", MathKind::Gathered),
+    ("gather*", MathKind::Gathered),
+    ("multline*", MathKind::Gathered),
+];
+
+const LIST_ENVIRONMENTS: [(&str, bool); 4] = [
+    ("itemize", false),
+    ("enumerate", true),
+    ("description", false),
+    ("problems", true),
+];
+
+const TEXT_ENVIRONMENTS: [&str; 14] = [
+    "theorem",
+    "lemma",
+    "proposition",
+    "corollary",
+    "definition",
+    "example",
+    "remark",
+    "proof",
+    "solution",
+    "claim",
+    "note",
+    "exercise",
+    "problem",
+    "conjecture",
+];
+
+/// Environments whose content is parsed as if the wrapper were not there.
+const TRANSPARENT_ENVIRONMENTS: [&str; 8] = [
+    "center",
+    "flushleft",
+    "flushright",
+    "quote",
+    "quotation",
+    "minipage",
+    "multicols",
+    "multicols*",
+];
+
+const CODE_ENVIRONMENTS: [&str; 6] = [
+    "verbatim",
+    "lstlisting",
+    "minted",
+    "algorithmic",
+    "algorithm",
+    "pseudocode",
+];
+
+/// Commands that only affect layout and are dropped along with their arguments.
+const IGNORED_COMMANDS: [(&str, usize); 20] = [
+    ("maketitle", 0),
+    ("tableofcontents", 0),
+    ("noindent", 0),
+    ("centering", 0),
+    ("par", 0),
+    ("medskip", 0),
+    ("bigskip", 0),
+    ("smallskip", 0),
+    ("hrule", 0),
+    ("hfill", 0),
+    ("vfill", 0),
+    ("indent", 0),
+    ("vspace", 1),
+    ("vspace*", 1),
+    ("hspace", 1),
+    ("hspace*", 1),
+    ("label", 1),
+    ("setlength", 2),
+    ("thispagestyle", 1),
+    ("pagestyle", 1),
+];
+
+pub fn parse(source: &str) -> Content {
+    let source = latex::strip_code_fences(source);
+    let preamble = source
+        .find("\\begin{document}")
+        .map(|idx| &source[..idx])
+        .unwrap_or("");
+
+    let mut parser = Parser {
+        macros: parse_macros(preamble),
+        title: find_command_argument(preamble, "title"),
+        author: find_command_argument(preamble, "author"),
+    };
+    let blocks = parser.parse_blocks(latex::extract_body(source));
+
+    Content {
+        title: parser.title.map(|title| collapse_whitespace(&title)),
+        author: parser.author.map(|author| collapse_whitespace(&author)),
+        blocks,
+    }
+}
+
+struct Macro {
+    arguments: usize,
+    /// Makes the first argument optional, in brackets, defaulting to this.
+    default: Option<String>,
+    body: String,
+}
+
+struct Parser {
+    macros: HashMap<String, Macro>,
+    title: Option<String>,
+    author: Option<String>,
+}
+
+impl Parser {
+    fn parse_blocks(&mut self, src: &str) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut paragraph = String::new();
+        let mut scanner = Scanner::new(src);
+
+        while let Some(c) = scanner.peek() {
+            match c {
+                '%' => scanner.skip_line(),
+                '\n' => {
+                    scanner.bump();
+                    if scanner.at_blank_line() {
+                        self.flush_paragraph(&mut paragraph, &mut blocks);
+                    } else {
+                        paragraph.push(' ');
+                    }
+                }
+                '$' if scanner.eat("$$") => {
+                    self.flush_paragraph(&mut paragraph, &mut blocks);
+                    let tex = scanner.take_until("$$");
+                    blocks.push(self.math_block(MathKind::Single, tex));
+                }
+                '\\' if scanner.eat("\\[") => {
+                    self.flush_paragraph(&mut paragraph, &mut blocks);
+                    let tex = scanner.take_until("\\]");
+                    blocks.push(self.math_block(MathKind::Single, tex));
+                }
+                // Inline math is copied through untouched so that environments
+                // inside it (pmatrix, cases) are not mistaken for blocks
+                '$' => {
+                    scanner.bump();
+                    let tex = scanner.take_until("$");
+                    paragraph.push('$');
+                    paragraph.push_str(tex);
+                    paragraph.push('$');
+                }
+                '\\' if scanner.eat("\\(") => {
+                    let tex = scanner.take_until("\\)");
+                    paragraph.push_str("\\(");
+                    paragraph.push_str(tex);
+                    paragraph.push_str("\\)");
+                }
+                '\\' => {
+                    let start = scanner.pos;
+                    let name = scanner.command_name();
+                    match name {
+                        "begin" => {
+                            self.flush_paragraph(&mut paragraph, &mut blocks);
+                            let env = scanner.group().unwrap_or_default();
+                            let (body, raw) = scanner.environment(env, start);
+                            self.environment(env, body, raw, &mut blocks);
+                        }
+                        "section" | "section*" | "chapter" | "chapter*" | "subsection"
+                        | "subsection*" | "subsubsection" | "subsubsection*" | "paragraph"
+                        | "paragraph*" | "frametitle" => {
+                            self.flush_paragraph(&mut paragraph, &mut blocks);
+                            scanner.optional();
+                            let text = scanner.group().unwrap_or_default();
+                            blocks.push(Block::Heading {
+                                level: heading_level(name),
+                                text: self.parse_inlines(text),
+                            });
+                        }
+                        "newpage" | "clearpage" | "pagebreak" => {
+                            self.flush_paragraph(&mut paragraph, &mut blocks);
+                            push_page_break(&mut blocks);
+                        }
+                        "title" | "author" | "date" => {
+                            let value = scanner.group().map(str::to_string);
+                            match name {
+                                "title" => self.title = value.or(self.title.take()),
+                                "author" => self.author = value.or(self.author.take()),
+                                _ => {}
+                            }
+                        }
+                        _ => {
+                            if let Some((_, arguments)) = IGNORED_COMMANDS
+                                .iter()
+                                .find(|(command, _)| *command == name)
+                            {
+                                for _ in 0..*arguments {
+                                    scanner.group();
+                                }
+                            } else {
+                                paragraph.push_str(&src[start..scanner.pos]);
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    paragraph.push(c);
+                    scanner.bump();
+                }
+            }
+        }
+
+        self.flush_paragraph(&mut paragraph, &mut blocks);
+        blocks
+    }
+
+    fn environment(&mut self, env: &str, body: &str, raw: &str, blocks: &mut Vec<Block>) {
+        if let Some((_, kind)) = MATH_ENVIRONMENTS.iter().find(|(name, _)| *name == env) {
+            // alignat takes the number of columns as its first argument
+            let body = if env.starts_with("alignat") {
+                let mut scanner = Scanner::new(body);
+                scanner.group();
+                &body[scanner.pos..]
+            } else {
+                body
+            };
+            blocks.push(self.math_block(*kind, body));
+        } else if env == "multline" {
+            blocks.push(self.math_block(MathKind::Gathered, body));
+        } else if let Some((_, ordered)) = LIST_ENVIRONMENTS.iter().find(|(name, _)| *name == env) {
+            let mut scanner = Scanner::new(body);
+            scanner.optional();
+            blocks.push(Block::List {
+                ordered: *ordered,
+                items: self.list_items(&body[scanner.pos..]),
+            });
+        } else if TEXT_ENVIRONMENTS.contains(&env) {
+            let mut scanner = Scanner::new(body);
+            let title = scanner.optional().map(|title| self.parse_inlines(title));
+            blocks.push(Block::Environment {
+                name: env.to_string(),
+                title,
+                blocks: self.parse_blocks(&body[scanner.pos..]),
+            });
+        } else if TRANSPARENT_ENVIRONMENTS.contains(&env) {
+            let mut scanner = Scanner::new(body);
+            scanner.group_if(env.starts_with("multicols"));
+            blocks.extend(self.parse_blocks(&body[scanner.pos..]));
+        } else if env == "frame" {
+            // Each Beamer frame is one page of notes
+            let mut scanner = Scanner::new(body);
+            scanner.optional();
+            if let Some(title) = scanner.group() {
+                blocks.push(Block::Heading {
+                    level: 2,
+                    text: self.parse_inlines(title),
+                });
+            }
+            blocks.extend(self.parse_blocks(&body[scanner.pos..]));
+            push_page_break(blocks);
+        } else if CODE_ENVIRONMENTS.contains(&env) {
+            let mut scanner = Scanner::new(body);
+            let options = scanner.optional();
+            let language = if env == "minted" {
+                scanner.group().map(str::to_string)
+            } else {
+                options.and_then(|options| {
+                    options.split(',').find_map(|option| {
+                        option
+                            .trim()
+                            .strip_prefix("language=")
+                            .map(|language| language.trim().to_lowercase())
+                    })
+                })
+            };
+            blocks.push(Block::Code {
+                language,
+                text: trim_blank_lines(&body[scanner.pos..]).to_string(),
+            });
+        } else {
+            blocks.push(Block::Unsupported {
+                name: env.to_string(),
+                raw: raw.to_string(),
+            });
+        }
+    }
+
+    fn list_items(&mut self, body: &str) -> Vec<ListItem> {
+        split_items(body)
+            .into_iter()
+            .map(|item| {
+                let mut scanner = Scanner::new(item);
+                let label = scanner.optional().map(|label| self.parse_inlines(label));
+                ListItem {
+                    label,
+                    blocks: self.parse_blocks(&item[scanner.pos..]),
+                }
+            })
+            .collect()
+    }
+
+    fn math_block(&self, kind: MathKind, tex: &str) -> Block {
+        Block::Math {
+            kind,
+            tex: self.clean_math(tex),
+        }
+    }
+
+    /// Expand user macros and drop numbering/labelling commands from math.
+    fn clean_math(&self, tex: &str) -> String {
+        let mut tex = self.expand_macros(tex);
+        for command in ["\\label", "\\tag"] {
+            while let Some(idx) = tex.find(command) {
+                let mut scanner = Scanner::new(&tex[idx + command.len()..]);
+                // `\tag*` numbers without parentheses
+                scanner.eat("*");
+                scanner.group();
+                let end = idx + command.len() + scanner.pos;
+                tex.replace_range(idx..end, "");
+            }
+        }
+        tex.replace("\\nonumber", "")
+            .replace("\\notag", "")
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn expand_macros(&self, tex: &str) -> String {
+        let mut tex = tex.to_string();
+        if self.macros.is_empty() {
+            return tex;
+        }
+
+        // Bounded so that recursive definitions cannot loop forever
+        for _ in 0..8 {
+            let mut out = String::with_capacity(tex.len());
+            let mut scanner = Scanner::new(&tex);
+            let mut changed = false;
+            while let Some(c) = scanner.peek() {
+                if c != '\\' {
+                    out.push(c);
+                    scanner.bump();
+                    continue;
+                }
+                let start = scanner.pos;
+                let name = scanner.command_name();
+                match self.macros.get(name) {
+                    Some(definition) => {
+                        let mut body = definition.body.clone();
+                        for index in 1..=definition.arguments {
+                            let argument = match &definition.default {
+                                Some(default) if index == 1 => {
+                                    scanner.optional().unwrap_or(default)
+                                }
+                                _ => scanner.argument().unwrap_or_default(),
+                            };
+                            body = body.replace(&format!("#{}", index), argument);
+                        }
+                        out.push_str(&body);
+                        // Keep "\R x" from becoming "\mathbb{R}x" merging into a command
+                        if definition.arguments == 0
+                            && scanner.peek().is_some_and(char::is_alphabetic)
+                        {
+                            out.push(' ');
+                        }
+                        changed = true;
+                    }
+                    None => out.push_str(&tex[start..scanner.pos]),
+                }
+            }
+            tex = out;
+            if !changed {
+                break;
+            }
+        }
+        tex
+    }
+
+    fn flush_paragraph(&mut self, paragraph: &mut String, blocks: &mut Vec<Block>) {
+        if !paragraph.trim().is_empty() {
+            let inlines = self.parse_inlines(paragraph);
+            if !inlines.is_empty() {
+                blocks.push(Block::Paragraph(inlines));
+            }
+        }
+        paragraph.clear();
+    }
+
+    fn parse_inlines(&self, src: &str) -> Vec<Inline> {
+        let mut inlines = Vec::new();
+        let mut text = String::new();
+        let mut scanner = Scanner::new(src);
+
+        while let Some(c) = scanner.peek() {
+            match c {
+                '$' => {
+                    scanner.bump();
+                    let tex = scanner.take_until("$");
+                    push_inline(&mut inlines, &mut text, Inline::Math(self.clean_math(tex)));
+                }
+                '%' => scanner.skip_line(),
+                '{' | '}' => scanner.bump(),
+                '~' => {
+                    text.push(' ');
+                    scanner.bump();
+                }
+                '-' if scanner.eat("---") => text.push('\u{2014}'),
+                '-' if scanner.eat("--") => text.push('\u{2013}'),
+                '`' if scanner.eat("``") => text.push('\u{201c}'),
+                '\'' if scanner.eat("''") => text.push('\u{201d}'),
+                '\\' if scanner.eat("\\(") => {
+                    let tex = scanner.take_until("\\)");
+                    push_inline(&mut inlines, &mut text, Inline::Math(self.clean_math(tex)));
+                }
+                '\\' if scanner.eat("\\\\") => {
+                    scanner.optional();
+                    push_inline(&mut inlines, &mut text, Inline::LineBreak);
+                }
+                '\\' => {
+                    let name = scanner.command_name();
+                    match name {
+                        "textbf" => {
+                            let inner = scanner.group().unwrap_or_default();
+                            let inner = self.parse_inlines(inner);
+                            push_inline(&mut inlines, &mut text, Inline::Strong(inner));
+                        }
+                        "emph" | "textit" | "textsl" | "underline" => {
+                            let inner = scanner.group().unwrap_or_default();
+                            let inner = self.parse_inlines(inner);
+                            push_inline(&mut inlines, &mut text, Inline::Emph(inner));
+                        }
+                        "texttt" | "verb" => {
+                            let inner = if name == "verb" {
+                                scanner.verbatim_argument()
+                            } else {
+                                scanner.group().unwrap_or_default()
+                            };
+                            push_inline(&mut inlines, &mut text, Inline::Code(inner.to_string()));
+                        }
+                        _ => match symbol(name) {
+                            Some(symbol) => text.push_str(symbol),
+                            None => {
+                                if let Some((_, arguments)) = IGNORED_COMMANDS
+                                    .iter()
+                                    .find(|(command, _)| *command == name)
+                                {
+                                    for _ in 0..*arguments {
+                                        scanner.group();
+                                    }
+                                }
+                                // Unknown commands contribute their argument text, if any
+                            }
+                        },
+                    }
+                }
+                _ => {
+                    text.push(c);
+                    scanner.bump();
+                }
+            }
+        }
+
+        if !text.is_empty() {
+            inlines.push(Inline::Text(text));
+        }
+        normalize_inlines(inlines)
+    }
+}
+
+/// Text produced by character-like commands outside math.
+fn symbol(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "%" => "%",
+        "&" => "&",
+        "_" => "_",
+        "#" => "#",
+        "$" => "$",
+        "{" => "{",
+        "}" => "}",
+        " " | "," | ";" | "quad" | "qquad" => " ",
+        "ldots" | "dots" | "textellipsis" => "\u{2026}",
+        "S" => "\u{a7}",
+        "P" => "\u{b6}",
+        "LaTeX" => "LaTeX",
+        "TeX" => "TeX",
+        "textbackslash" => "\\",
+        "textendash" => "\u{2013}",
+        "textemdash" => "\u{2014}",
+        "item" => "\u{2022} ",
+        _ => return None,
+    })
+}
+
+fn heading_level(command: &str) -> u8 {
+    match command.trim_end_matches('*') {
+        "chapter" => 1,
+        "section" => 1,
+        "frametitle" => 2,
+        "subsection" => 2,
+        "subsubsection" => 3,
+        _ => 4,
+    }
+}
+
+fn push_page_break(blocks: &mut Vec<Block>) {
+    if !matches!(blocks.last(), None | Some(Block::PageBreak)) {
+        blocks.push(Block::PageBreak);
+    }
+}
+
+fn push_inline(inlines: &mut Vec<Inline>, text: &mut String, inline: Inline) {
+    if !text.is_empty() {
+        inlines.push(Inline::Text(std::mem::take(text)));
+    }
+    inlines.push(inline);
+}
+
+/// Collapse runs of whitespace and trim the ends of a paragraph.
+fn normalize_inlines(inlines: Vec<Inline>) -> Vec<Inline> {
+    let mut out: Vec<Inline> = inlines
+        .into_iter()
+        .filter_map(|inline| match inline {
+            Inline::Text(text) => {
+                let mut collapsed = collapse_whitespace(&text);
+                if text.starts_with(char::is_whitespace) {
+                    collapsed.insert(0, ' ');
+                }
+                if text.ends_with(char::is_whitespace) && !collapsed.ends_with(' ') {
+                    collapsed.push(' ');
+                }
+                (!collapsed.is_empty()).then_some(Inline::Text(collapsed))
+            }
+            Inline::Math(tex) if tex.is_empty() => None,
+            other => Some(other),
+        })
+        .collect();
+
+    if let Some(Inline::Text(first)) = out.first_mut() {
+        *first = first.trim_start().to_string();
+    }
+    for index in 1..out.len() {
+        if matches!(out[index - 1], Inline::LineBreak) {
+            if let Inline::Text(text) = &mut out[index] {
+                *text = text.trim_start().to_string();
+            }
+        }
+    }
+    if let Some(Inline::Text(last)) = out.last_mut() {
+        *last = last.trim_end().to_string();
+    }
+    out.retain(|inline| !matches!(inline, Inline::Text(text) if text.is_empty()));
+    out
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn trim_blank_lines(text: &str) -> &str {
+    text.trim_start_matches(['\n', '\r']).trim_end()
+}
+
+/// Split a list body on top-level `\item`s, ignoring those in nested lists.
+fn split_items(body: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut scanner = Scanner::new(body);
+    let mut depth = 0usize;
+    let mut current: Option<usize> = None;
+
+    while let Some(c) = scanner.peek() {
+        if c == '%' {
+            scanner.skip_line();
+            continue;
+        }
+        if c != '\\' {
+            scanner.bump();
+            continue;
+        }
+        let start = scanner.pos;
+        match scanner.command_name() {
+            "begin" => depth += 1,
+            "end" => depth = depth.saturating_sub(1),
+            "item" if depth == 0 => {
+                if let Some(item_start) = current {
+                    items.push(&body[item_start..start]);
+                }
+                current = Some(scanner.pos);
+            }
+            _ => {}
+        }
+    }
+    if let Some(item_start) = current {
+        items.push(&body[item_start..]);
+    }
+    items
+}
+
+fn find_command_argument(src: &str, command: &str) -> Option<String> {
+    let needle = format!("\\{}", command);
+    let mut offset = 0;
+    while let Some(idx) = src[offset..].find(&needle) {
+        let after = offset + idx + needle.len();
+        let mut scanner = Scanner::new(&src[after..]);
+        if let Some(value) = scanner.group() {
+            return Some(value.to_string());
+        }
+        offset = after;
+    }
+    None
+}
+
+/// Collect `\newcommand`, `\renewcommand` and `\DeclareMathOperator` definitions.
+fn parse_macros(preamble: &str) -> HashMap<String, Macro> {
+    let mut macros = HashMap::new();
+    let mut scanner = Scanner::new(preamble);
+
+    while let Some(c) = scanner.peek() {
+        if c == '%' {
+            scanner.skip_line();
+            continue;
+        }
+        if c != '\\' {
+            scanner.bump();
+            continue;
+        }
+        match scanner.command_name() {
+            "newcommand" | "newcommand*" | "renewcommand" | "renewcommand*" | "providecommand"
+            | "providecommand*" => {
+                let name = scanner
+                    .group()
+                    .map(str::to_string)
+                    .or_else(|| scanner.command().map(str::to_string));
+                let arguments = scanner
+                    .optional()
+                    .and_then(|count| count.trim().parse().ok())
+                    .unwrap_or(0);
+                let default = scanner.optional().map(str::to_string);
+                if let (Some(name), Some(body)) = (name, scanner.group()) {
+                    let name = name.trim().trim_start_matches('\\').to_string();
+                    macros.insert(
+                        name,
+                        Macro {
+                            arguments,
+                            default,
+                            body: body.to_string(),
+                        },
+                    );
+                }
+            }
+            "DeclareMathOperator" | "DeclareMathOperator*" => {
+                let name = scanner
+                    .group()
+                    .map(str::to_string)
+                    .or_else(|| scanner.command().map(str::to_string));
+                if let (Some(name), Some(body)) = (name, scanner.group()) {
+                    let name = name.trim().trim_start_matches('\\').to_string();
+                    macros.insert(
+                        name,
+                        Macro {
+                            arguments: 0,
+                            default: None,
+                            body: format!("\\operatorname{{{}}}", body),
+                        },
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    macros
+}
+
+/// A macro definition statement and where it sits in the source.
+pub(crate) struct Definition {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Find `\newcommand`-style and `\DeclareMathOperator` statements, outside comments.
+pub(crate) fn definitions(source: &str) -> Vec<Definition> {
+    let mut definitions = Vec::new();
+    let mut scanner = Scanner::new(source);
+
+    while let Some(c) = scanner.peek() {
+        if c == '%' {
+            scanner.skip_line();
+            continue;
+        }
+        if c != '\\' {
+            scanner.bump();
+            continue;
+        }
+        let start = scanner.pos;
+        let command = scanner.command_name();
+        if !matches!(
+            command,
+            "newcommand"
+                | "newcommand*"
+                | "renewcommand"
+                | "renewcommand*"
+                | "providecommand"
+                | "providecommand*"
+                | "DeclareMathOperator"
+                | "DeclareMathOperator*"
+        ) {
+            continue;
+        }
+        let name = scanner
+            .group()
+            .map(str::to_string)
+            .or_else(|| scanner.command().map(str::to_string));
+        if !command.starts_with("DeclareMathOperator") {
+            // Argument count and default value of the first argument
+            scanner.optional();
+            scanner.optional();
+        }
+        if let (Some(name), Some(_)) = (name, scanner.group()) {
+            definitions.push(Definition {
+                name: name.trim().trim_start_matches('\\').to_string(),
+                start,
+                end: scanner.pos,
+            });
+        }
+    }
+    definitions
+}
+
+/// Rename every use of the command `\old` to `\new`, leaving longer names alone.
+pub(crate) fn rename_command(source: &str, old: &str, new: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut scanner = Scanner::new(source);
+    let mut copied = 0;
+
+    while let Some(c) = scanner.peek() {
+        if c != '\\' {
+            scanner.bump();
+            continue;
+        }
+        let start = scanner.pos;
+        if scanner.command_name().trim_end_matches('*') == old {
+            out.push_str(&source[copied..start]);
+            out.push('\\');
+            out.push_str(new);
+            copied = start + 1 + old.len();
+        }
+    }
+    out.push_str(&source[copied..]);
+    out
+}
+
+/// Split a preamble into whole statements, outside comments.
+///
+/// A statement runs to the end of its line, or further while a `{...}` group
+/// is open or the next line continues it with another argument, so
+/// multi-line definitions come out in one piece.
+pub(crate) fn statements(source: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut scanner = Scanner::new(source);
+
+    loop {
+        scanner.skip_whitespace();
+        match scanner.peek() {
+            None => break,
+            Some('%') => {
+                scanner.skip_line();
+                continue;
+            }
+            Some(_) => {}
+        }
+        let start = scanner.pos;
+        while let Some(c) = scanner.peek() {
+            match c {
+                '%' => break,
+                '\n' => {
+                    let next = scanner.rest().trim_start();
+                    if !(next.starts_with('{') || next.starts_with('[')) {
+                        break;
+                    }
+                    scanner.skip_whitespace();
+                }
+                '{' => {
+                    scanner.group();
+                }
+                '\\' => {
+                    scanner.command_name();
+                }
+                _ => scanner.bump(),
+            }
+        }
+        statements.push(source[start..scanner.pos].trim_end());
+    }
+    statements
+}
+
+/// Byte offsets of top-level `\chapter` and `\section` commands, outside comments.
+pub(crate) fn section_starts(source: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut scanner = Scanner::new(source);
+
+    while let Some(c) = scanner.peek() {
+        if c == '%' {
+            scanner.skip_line();
+            continue;
+        }
+        if c != '\\' {
+            scanner.bump();
+            continue;
+        }
+        let start = scanner.pos;
+        if matches!(
+            scanner.command_name().trim_end_matches('*'),
+            "chapter" | "section"
+        ) {
+            starts.push(start);
+        }
+    }
+    starts
+}
+
+/// Byte-offset cursor over LaTeX source.
+pub(crate) struct Scanner<'a> {
+    src: &'a str,
+    pub(crate) pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    pub(crate) fn new(src: &'a str) -> Self {
+        Self { src, pos: 0 }
+    }
+
+    pub(crate) fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    pub(crate) fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    pub(crate) fn bump(&mut self) {
+        if let Some(c) = self.peek() {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    pub(crate) fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn skip_line(&mut self) {
+        match self.rest().find('\n') {
+            Some(idx) => self.pos += idx + 1,
+            None => self.pos = self.src.len(),
+        }
+    }
+
+    /// True if only spaces or tabs separate the cursor from the next newline.
+    fn at_blank_line(&self) -> bool {
+        let rest = self.rest();
+        let line_end = rest.find('\n').unwrap_or(rest.len());
+        rest[..line_end].trim().is_empty()
+    }
+
+    /// Consume a command starting at `\` and return its name without the backslash.
+    ///
+    /// Starred forms (`section*`) are returned with their star.
+    pub(crate) fn command_name(&mut self) -> &'a str {
+        let start = self.pos;
+        self.eat("\\");
+        let name_start = self.pos;
+        let letters = self
+            .rest()
+            .char_indices()
+            .find(|(_, c)| !c.is_ascii_alphabetic())
+            .map(|(idx, _)| idx)
+            .unwrap_or(self.rest().len());
+        if letters == 0 {
+            // Control symbol such as \% or \\
+            self.bump();
+            return &self.src[name_start..self.pos];
+        }
+        self.pos += letters;
+        if self.rest().starts_with('*') && self.pos > start + 1 {
+            self.pos += 1;
+        }
+        &self.src[name_start..self.pos]
+    }
+
+    /// Consume a bare `\command` token, as in `\newcommand\R{...}`.
+    fn command(&mut self) -> Option<&'a str> {
+        self.skip_whitespace();
+        if !self.rest().starts_with('\\') {
+            return None;
+        }
+        Some(self.command_name())
+    }
+
+    /// Consume a balanced `{...}` group and return its contents.
+    pub(crate) fn group(&mut self) -> Option<&'a str> {
+        self.delimited('{', '}')
+    }
+
+    /// Consume a macro argument: a `{...}` group, or else the next token.
+    fn argument(&mut self) -> Option<&'a str> {
+        if let Some(group) = self.group() {
+            return Some(group);
+        }
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.peek()? {
+            '\\' => {
+                self.command_name();
+            }
+            '}' => return None,
+            _ => self.bump(),
+        }
+        Some(&self.src[start..self.pos])
+    }
+
+    fn group_if(&mut self, condition: bool) -> Option<&'a str> {
+        if condition {
+            self.group()
+        } else {
+            None
+        }
+    }
+
+    /// Consume a balanced `[...]` optional argument and return its contents.
+    pub(crate) fn optional(&mut self) -> Option<&'a str> {
+        self.delimited('[', ']')
+    }
+
+    fn delimited(&mut self, open: char, close: char) -> Option<&'a str> {
+        let start = self.pos;
+        self.skip_whitespace();
+        if !self.rest().starts_with(open) {
+            self.pos = start;
+            return None;
+        }
+        self.bump();
+        let content_start = self.pos;
+        let mut depth = 1usize;
+        while let Some(c) = self.peek() {
+            if c == '\\' {
+                self.bump();
+                self.bump();
+                continue;
+            }
+            self.bump();
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&self.src[content_start..self.pos - close.len_utf8()]);
+                }
+            }
+        }
+        // Unbalanced: treat the rest of the input as the argument
+        Some(&self.src[content_start..])
+    }
+
+    /// Consume `\verb|...|` style arguments delimited by any character.
+    fn verbatim_argument(&mut self) -> &'a str {
+        let Some(delimiter) = self.peek() else {
+            return "";
+        };
+        self.bump();
+        let start = self.pos;
+        let end = self
+            .rest()
+            .find(delimiter)
+            .map(|idx| start + idx)
+            .unwrap_or(self.src.len());
+        self.pos = (end + delimiter.len_utf8()).min(self.src.len());
+        &self.src[start..end]
+    }
+
+    /// Consume everything up to and including `terminator`.
+    pub(crate) fn take_until(&mut self, terminator: &str) -> &'a str {
+        let start = self.pos;
+        let mut scanner = Scanner::new(self.rest());
+        while scanner.peek().is_some() {
+            if scanner.rest().starts_with(terminator) {
+                let end = start + scanner.pos;
+                self.pos = end + terminator.len();
+                return &self.src[start..end];
+            }
+            if scanner.peek() == Some('\\') {
+                scanner.bump();
+            }
+            scanner.bump();
+        }
+        self.pos = self.src.len();
+        &self.src[start..]
+    }
+
+    /// Having consumed `\begin{env}`, consume through the matching `\end{env}`.
+    ///
+    /// Returns the environment body and the raw source from `begin_start`.
+    fn environment(&mut self, env: &str, begin_start: usize) -> (&'a str, &'a str) {
+        let begin = format!("\\begin{{{}}}", env);
+        let end = format!("\\end{{{}}}", env);
+        let body_start = self.pos;
+        let mut depth = 1usize;
+        let mut cursor = body_start;
+
+        loop {
+            let rest = &self.src[cursor..];
+            let next_begin = rest.find(&begin);
+            let next_end = rest.find(&end);
+            match (next_begin, next_end) {
+                (Some(b), Some(e)) if b < e => {
+                    depth += 1;
+                    cursor += b + begin.len();
+                }
+                (_, Some(e)) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let body_end = cursor + e;
+                        self.pos = body_end + end.len();
+                        return (
+                            &self.src[body_start..body_end],
+                            &self.src[begin_start..self.pos],
+                        );
+                    }
+                    cursor += e + end.len();
+                }
+                (_, None) => {
+                    self.pos = self.src.len();
+                    return (&self.src[body_start..], &self.src[begin_start..]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(preamble: &str, body: &str) -> String {
+        format!(
+            "\\documentclass{{article}}\n{}\n\\begin{{document}}\n{}\n\\end{{document}}\n",
+            preamble, body
+        )
+    }
+
+    fn text(text: &str) -> Inline {
+        Inline::Text(text.to_string())
+    }
+
+    fn paragraph(text: &str) -> Block {
+        Block::Paragraph(vec![Inline::Text(text.to_string())])
+    }
+
+    #[test]
+    fn expands_macros_in_math() {
+        let preamble = "\\newcommand{\\R}{\\mathbb{R}}
+\\newcommand\\norm[1]{\\lVert #1 \\rVert}
+\\renewcommand{\\vec}[1]{\\mathbf{#1}}
+\\DeclareMathOperator{\\Tr}{Tr}
+\\newcommand{\\Rn}{\\R^n}
+\\newcommand{\\loop}{\\loop}
+\\newcommand{\\pd}[2][t]{\\partial_{#1} #2}
+% \\newcommand{\\C}{\\mathbb{C}}";
+        let cases = [
+            ("\\R x", "\\mathbb{R} x"),
+            ("\\R", "\\mathbb{R}"),
+            ("\\Real", "\\Real"),
+            ("\\norm{v}", "\\lVert v \\rVert"),
+            ("\\vec{x} + \\vec y", "\\mathbf{x} + \\mathbf{y}"),
+            ("\\Tr A", "\\operatorname{Tr} A"),
+            ("\\Rn", "\\mathbb{R}^n"),
+            ("\\loop", "\\loop"),
+            ("\\C", "\\C"),
+            ("x \\label{eq} \\nonumber", "x"),
+            ("x \\tag{1}", "x"),
+            ("x \\tag*{A}", "x"),
+            ("\\pd{x}", "\\partial_{t} x"),
+            ("\\pd[s]{x}", "\\partial_{s} x"),
+        ];
+        for (tex, expected) in cases {
+            let content = parse(&document(preamble, &format!("\\[{}\\]", tex)));
+            assert_eq!(
+                content.blocks,
+                [Block::Math {
+                    kind: MathKind::Single,
+                    tex: expected.to_string()
+                }],
+                "{}",
+                tex
+            );
+        }
+    }
+
+    #[test]
+    fn parses_environments() {
+        let cases = [
+            (
+                "\\begin{theorem}[Fermat]\nNo solutions.\n\\end{theorem}",
+                vec![Block::Environment {
+                    name: "theorem".to_string(),
+                    title: Some(vec![text("Fermat")]),
+                    blocks: vec![paragraph("No solutions.")],
+                }],
+            ),
+            (
+                "\\begin{enumerate}\n\\item One\n\\item[b)] Two\n\\end{enumerate}",
+                vec![Block::List {
+                    ordered: true,
+                    items: vec![
+                        ListItem {
+                            label: None,
+                            blocks: vec![paragraph("One")],
+                        },
+                        ListItem {
+                            label: Some(vec![text("b)")]),
+                            blocks: vec![paragraph("Two")],
+                        },
+                    ],
+                }],
+            ),
+            (
+                "\\begin{itemize}\n\\item Outer\n\\begin{itemize}\\item Inner\\end{itemize}\n\\end{itemize}",
+                vec![Block::List {
+                    ordered: false,
+                    items: vec![ListItem {
+                        label: None,
+                        blocks: vec![
+                            paragraph("Outer"),
+                            Block::List {
+                                ordered: false,
+                                items: vec![ListItem {
+                                    label: None,
+                                    blocks: vec![paragraph("Inner")],
+                                }],
+                            },
+                        ],
+                    }],
+                }],
+            ),
+            (
+                "\\begin{align*}\na &= b \\\\\nc &= d\n\\end{align*}",
+                vec![Block::Math {
+                    kind: MathKind::Aligned,
+                    tex: "a &= b \\\\\nc &= d".to_string(),
+                }],
+            ),
+            (
+                "\\begin{alignat}{2}\nx &= 1\n\\end{alignat}",
+                vec![Block::Math {
+                    kind: MathKind::Aligned,
+                    tex: "x &= 1".to_string(),
+                }],
+            ),
+            (
+                "\\begin{center}\nCentred\n\\end{center}",
+                vec![paragraph("Centred")],
+            ),
+            (
+                "\\begin{lstlisting}[language=Python]\nprint(1)\n\\end{lstlisting}",
+                vec![Block::Code {
+                    language: Some("python".to_string()),
+                    text: "print(1)".to_string(),
+                }],
+            ),
+            (
+                "\\begin{tikzpicture}\\draw (0,0);\\end{tikzpicture}",
+                vec![Block::Unsupported {
+                    name: "tikzpicture".to_string(),
+                    raw: "\\begin{tikzpicture}\\draw (0,0);\\end{tikzpicture}".to_string(),
+                }],
+            ),
+        ];
+        for (body, expected) in cases {
+            assert_eq!(parse(&document("", body)).blocks, expected, "{}", body);
+        }
+    }
+
+    #[test]
+    fn skips_comments() {
+        let cases = [
+            (
+                "Kept % dropped \\section{No}\nalso kept",
+                vec![paragraph("Kept also kept")],
+            ),
+            ("50\\% off", vec![paragraph("50% off")]),
+            ("% only a comment", vec![]),
+            (
+                "$x % not a comment in math$",
+                vec![Block::Paragraph(vec![Inline::Math(
+                    "x % not a comment in math".to_string(),
+                )])],
+            ),
+        ];
+        for (body, expected) in cases {
+            assert_eq!(parse(&document("", body)).blocks, expected, "{}", body);
+        }
+
+        let preamble = "% \\newcommand{\\C}{\\mathbb{C}}\n\\newcommand{\\R}{\\mathbb{R}}";
+        let names: Vec<String> = definitions(preamble)
+            .into_iter()
+            .map(|definition| definition.name)
+            .collect();
+        assert_eq!(names, ["R"]);
+        assert_eq!(section_starts("% \\section{A}\n\\section{B}"), [14]);
+    }
+
+    #[test]
+    fn finds_definitions() {
+        let source = "\\newcommand{\\R}{\\mathbb{R}}
+\\renewcommand*\\v[1][x]{\\vec{#1}}
+\\providecommand{\\half}{\\frac{1}{2}}
+\\DeclareMathOperator*{\\argmax}{arg\\,max}
+\\newcommand{\\broken}";
+        let found: Vec<(String, &str)> = definitions(source)
+            .into_iter()
+            .map(|definition| (definition.name, &source[definition.start..definition.end]))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("R".to_string(), "\\newcommand{\\R}{\\mathbb{R}}"),
+                ("v".to_string(), "\\renewcommand*\\v[1][x]{\\vec{#1}}"),
+                ("half".to_string(), "\\providecommand{\\half}{\\frac{1}{2}}"),
+                (
+                    "argmax".to_string(),
+                    "\\DeclareMathOperator*{\\argmax}{arg\\,max}"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn renames_commands() {
+        let cases = [
+            ("\\R + \\R^2", "\\S + \\S^2"),
+            ("\\Real + \\R", "\\Real + \\S"),
+            ("\\R* and \\R{}", "\\S* and \\S{}"),
+            ("\\newcommand{\\R}{x}", "\\newcommand{\\S}{x}"),
+            ("\\\\R", "\\\\R"),
+            ("R and \\r", "R and \\r"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(rename_command(source, "R", "S"), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn splits_statements() {
+        let source = "\\documentclass{article}
+\\usepackage[utf8]{inputenc} % encoding
+% \\usepackage{commented}
+\\newenvironment{boxed}{%
+  \\begin{center}
+}{
+  \\end{center}
+}
+\\newenvironment{aside}
+  {\\itshape}
+  [x]{\\par}
+\\title{A \\{ brace}";
+        assert_eq!(
+            statements(source),
+            [
+                "\\documentclass{article}",
+                "\\usepackage[utf8]{inputenc}",
+                "\\newenvironment{boxed}{%\n  \\begin{center}\n}{\n  \\end{center}\n}",
+                "\\newenvironment{aside}\n  {\\itshape}\n  [x]{\\par}",
+                "\\title{A \\{ brace}",
+            ]
+        );
+    }
+
+    #[test]
+    fn survives_unbalanced_input() {
+        let inputs = [
+            "",
+            "\\",
+            "{{{",
+            "}}}",
+            "$",
+            "$$",
+            "$x",
+            "\\[ x",
+            "\\( x",
+            "\\section{",
+            "\\section",
+            "\\begin{",
+            "\\begin{itemize}\\item",
+            "\\begin{theorem}[",
+            "\\begin{alignat}{",
+            "\\end{itemize}",
+            "\\item[",
+            "\\verb|x",
+            "\\verb",
+            "\\textbf{",
+            "\\label{",
+            "\\newcommand{\\R}{",
+            "\\newcommand{\\R}[",
+            "\\newcommand",
+            "\\DeclareMathOperator{",
+            "\\é",
+            "é\\",
+            "\\begin{document}",
+            "\\begin{document}\\begin{document}",
+        ];
+        for input in inputs {
+            parse(input);
+            parse(&document(input, input));
+            parse(&document(
+                "\\newcommand{\\R}{\\mathbb{R}}",
+                &format!("${}$", input),
+            ));
+            definitions(input);
+            rename_command(input, "R", "S");
+            section_starts(input);
+            statements(input);
+        }
+    }
+}
@@ -1,8 +1,16 @@
 use crate::errors::{ApiError, Result};
-use std::path::PathBuf;
+use std::path::Path;
+use std::time::Duration;
 use tokio::fs;
 use tokio::process::Command;
 
+/// Prefix of the scratch directories compilers work in; the janitor sweeps
+/// any left behind by a crash.
+pub const TEMP_DIR_PREFIX: &str = "noteforge-";
+
+/// Upper bound for a single compiler run, whichever the engine.
+pub const COMPILE_TIMEOUT: Duration = Duration::from_secs(60);
+
 pub struct PdfService;
 
 impl PdfService {
@@ -10,66 +18,134 @@ impl PdfService {
         Self
     }
 
-    pub async fn generate_pdf(
-        &self,
-        latex_content: &str,
-        output_path: &PathBuf,
-    ) -> Result<Vec<u8>> {
+    pub async fn generate_pdf(&self, latex_content: &str) -> Result<Vec<u8>> {
         // Create a temporary directory for processing
-        let temp_dir = std::env::temp_dir().join(format!("noteforge-{}", uuid::Uuid::new_v4()));
-        fs::create_dir_all(&temp_dir)
-            .await
-            .map_err(|e| ApiError::LaTeXError(format!("Failed to create temp dir: {}", e)))?;
+        let temp_dir =
+            std::env::temp_dir().join(format!("{}{}", TEMP_DIR_PREFIX, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).await.map_err(|e| {
+            ApiError::InternalServerError(anyhow::anyhow!("Failed to create temp dir: {}", e))
+        })?;
+
+        let result = Self::compile(&temp_dir, latex_content).await;
+
+        // Clean up temporary directory, whether or not compilation succeeded
+        tokio::spawn(async move {
+            let _ = fs::remove_dir_all(temp_dir).await;
+        });
+
+        result
+    }
 
+    async fn compile(temp_dir: &Path, latex_content: &str) -> Result<Vec<u8>> {
         // Write LaTeX content to a temporary file
         let latex_path = temp_dir.join("output.tex");
-        fs::write(&latex_path, latex_content)
-            .await
-            .map_err(|e| ApiError::LaTeXError(format!("Failed to write LaTeX file: {}", e)))?;
-
-        // Run pdflatex
-        let output = Command::new("pdflatex")
-            .args([
-                "-interaction=nonstopmode",
-                "-output-directory",
-                temp_dir.to_str().unwrap(),
-                latex_path.to_str().unwrap(),
-            ])
-            .output()
+        fs::write(&latex_path, latex_content).await.map_err(|e| {
+            ApiError::InternalServerError(anyhow::anyhow!("Failed to write LaTeX file: {}", e))
+        })?;
+
+        // Documents can be imported, so treat them as untrusted: no shell
+        // escape, file access confined to the temp directory (kpathsea's
+        // `p`aranoid mode refuses absolute paths, `..` and dotfiles) and a
+        // time limit for runaway loops
+        let child = Command::new("pdflatex")
+            .args(["-no-shell-escape", "-interaction=nonstopmode", "output.tex"])
+            .current_dir(temp_dir)
+            .env("openin_any", "p")
+            .env("openout_any", "p")
+            .kill_on_drop(true)
+            .output();
+
+        let output = tokio::time::timeout(COMPILE_TIMEOUT, child)
             .await
-            .map_err(|e| ApiError::LaTeXError(format!("Failed to run pdflatex: {}", e)))?;
+            .map_err(|_| {
+                ApiError::LaTeXError(format!(
+                    "pdflatex timed out after {} seconds",
+                    COMPILE_TIMEOUT.as_secs()
+                ))
+            })?
+            .map_err(|e| {
+                ApiError::InternalServerError(anyhow::anyhow!("Failed to run pdflatex: {}", e))
+            })?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
             let stdout = String::from_utf8_lossy(&output.stdout);
+            tracing::warn!("pdflatex failed: {}\n{}", stderr, stdout);
             return Err(ApiError::LaTeXError(format!(
-                "PDF generation failed: {}\n{}",
-                stderr, stdout
+                "PDF generation failed:\n{}",
+                log_errors(&stdout)
             )));
         }
 
         // Read the generated PDF
         let pdf_path = temp_dir.join("output.pdf");
-        let pdf_data = fs::read(&pdf_path)
-            .await
-            .map_err(|e| ApiError::LaTeXError(format!("Failed to read PDF file: {}", e)))?;
-
-        // Write to output path if provided
-        if let Some(parent) = output_path.parent() {
-            fs::create_dir_all(parent).await.map_err(|e| {
-                ApiError::FileError(format!("Failed to create output directory: {}", e))
-            })?;
+        fs::read(&pdf_path).await.map_err(|e| {
+            ApiError::InternalServerError(anyhow::anyhow!("Failed to read PDF file: {}", e))
+        })
+    }
+}
 
-            fs::write(output_path, &pdf_data)
-                .await
-                .map_err(|e| ApiError::FileError(format!("Failed to write PDF file: {}", e)))?;
+/// The `!` error lines of a pdflatex log, each with the `l.N` line that
+/// locates it. The rest of the log names paths on this machine.
+fn log_errors(log: &str) -> String {
+    let mut errors = Vec::new();
+    let mut error = false;
+    for line in log.lines() {
+        if line.starts_with('!') {
+            errors.push(line);
+            error = true;
+        } else if error && is_location(line) {
+            // Where the error happened; the context lines before it are skipped
+            errors.push(line);
+            error = false;
         }
+    }
+    if errors.is_empty() {
+        return "pdflatex stopped without reporting an error".to_string();
+    }
+    errors.join("\n")
+}
 
-        // Clean up temporary directory
-        tokio::spawn(async move {
-            let _ = fs::remove_dir_all(temp_dir).await;
-        });
+fn is_location(line: &str) -> bool {
+    line.strip_prefix("l.")
+        .and_then(|rest| rest.split(' ').next())
+        .is_some_and(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_only_error_lines_and_their_locations() {
+        let log = "\
+This is pdfTeX, Version 3.141592653-2.6-1.40.25 (TeX Live 2023) (preloaded format=pdflatex)
+(/tmp/noteforge-5f0c/output.tex
+LaTeX2e <2022-11-01> patch level 1
+(/usr/share/texlive/texmf-dist/tex/latex/base/article.cls
+! Undefined control sequence.
+l.5 \\foo
+           {bar}
+! Emergency stop.
+! LaTeX Error: File `missing.sty' not found.
 
-        Ok(pdf_data)
+Type X to quit or <RETURN> to proceed,
+l.7 \\usepackage
+                {missing}^^M
+(/usr/share/texlive/texmf-dist/tex/latex/base/size10.clo)
+Output written on output.pdf (1 page, 1234 bytes).
+";
+        assert_eq!(
+            log_errors(log),
+            "! Undefined control sequence.\n\
+             l.5 \\foo\n\
+             ! Emergency stop.\n\
+             ! LaTeX Error: File `missing.sty' not found.\n\
+             l.7 \\usepackage"
+        );
+        assert_eq!(
+            log_errors("(/usr/share/texlive/texmf-dist/tex/latex/base/article.cls)"),
+            "pdflatex stopped without reporting an error"
+        );
     }
 }
@@ -0,0 +1,105 @@
+//! Signed share tokens and share passwords.
+//!
+//! A token carries its share's ID and expiry, signed with HMAC-SHA256, so
+//! forged or expired links are turned away without touching the database.
+
+use std::sync::Arc;
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::errors::{ApiError, Result};
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone)]
+pub struct ShareSigner {
+    key: Arc<Vec<u8>>,
+}
+
+impl ShareSigner {
+    /// Sign with `SHARE_SECRET`, or a secret generated once and kept in the
+    /// database so links survive restarts.
+    pub fn from_env(db: &Database) -> Result<Self> {
+        let secret = match std::env::var("SHARE_SECRET") {
+            Ok(secret) => secret,
+            Err(_) => db.setting("share_secret", || {
+                format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+            })?,
+        };
+        Ok(Self {
+            key: Arc::new(secret.into_bytes()),
+        })
+    }
+
+    pub fn sign(&self, id: &Uuid, expires_at: DateTime<Utc>) -> String {
+        let mut payload = id.as_bytes().to_vec();
+        payload.extend_from_slice(&expires_at.timestamp().to_be_bytes());
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes())
+        )
+    }
+
+    /// The share ID in a token that is authentic and unexpired.
+    pub fn verify(&self, token: &str) -> Result<Uuid> {
+        let not_found = || ApiError::NotFound("Share link not found or expired".to_string());
+
+        let (payload, signature) = token.split_once('.').ok_or_else(not_found)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| not_found())?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| not_found())?;
+        self.mac(&payload)
+            .verify_slice(&signature)
+            .map_err(|_| not_found())?;
+
+        let (id, expires) = payload.split_at_checked(16).ok_or_else(not_found)?;
+        let expires = i64::from_be_bytes(expires.try_into().map_err(|_| not_found())?);
+        if expires <= Utc::now().timestamp() {
+            return Err(not_found());
+        }
+        Uuid::from_slice(id).map_err(|_| not_found())
+    }
+
+    fn mac(&self, payload: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(payload);
+        mac
+    }
+}
+
+// Argon2 is deliberately slow, so both run off the async workers
+
+pub async fn hash_password(password: &str) -> Result<String> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::encode_b64(Uuid::new_v4().as_bytes())
+            .map_err(|e| ApiError::InternalServerError(anyhow::anyhow!("{}", e)))?;
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| ApiError::InternalServerError(anyhow::anyhow!("{}", e)))
+    })
+    .await
+    .map_err(anyhow::Error::from)?
+}
+
+pub async fn check_password(password: &str, hash: &str) -> bool {
+    let (password, hash) = (password.to_string(), hash.to_string());
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    })
+    .await
+    .unwrap_or(false)
+}
@@ -0,0 +1,151 @@
+use std::path::{Component, Path, PathBuf};
+
+use async_trait::async_trait;
+use tokio::fs;
+use tokio::process::Command;
+
+use super::{BlobStore, Entry};
+use crate::errors::{ApiError, Result};
+
+/// Blobs as files under a root directory, one directory per key prefix.
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        let relative = Path::new(key);
+        if key.is_empty()
+            || !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(ApiError::StorageError(format!(
+                "Invalid storage key: {}",
+                key
+            )));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+#[async_trait]
+impl BlobStore for FsStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await.map_err(|e| {
+                ApiError::StorageError(format!("Failed to create directory: {}", e))
+            })?;
+        }
+
+        // Write then rename, so readers never see a partial file
+        let partial = path.with_extension(format!("partial-{}", uuid::Uuid::new_v4()));
+        fs::write(&partial, data)
+            .await
+            .map_err(|e| ApiError::StorageError(format!("Failed to write {}: {}", key, e)))?;
+        fs::rename(&partial, &path)
+            .await
+            .map_err(|e| ApiError::StorageError(format!("Failed to write {}: {}", key, e)))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        fs::read(self.path(key)?).await.map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ApiError::NotFound(format!("No such file: {}", key)),
+            _ => ApiError::StorageError(format!("Failed to read {}: {}", key, e)),
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(ApiError::StorageError(
+                format!("Failed to delete {}: {}", key, e),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    async fn entries(&self, prefix: &str) -> Result<Vec<Entry>> {
+        // Walk from the deepest directory the prefix names
+        let directory = match prefix.rfind('/') {
+            Some(end) => &prefix[..end],
+            None => "",
+        };
+        let start = if directory.is_empty() {
+            self.root.clone()
+        } else {
+            self.path(directory)?
+        };
+
+        let mut found = Vec::new();
+        let mut pending = vec![(start, directory.to_string())];
+        while let Some((dir, dir_key)) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(ApiError::StorageError(format!(
+                        "Failed to read directory: {}",
+                        e
+                    )))
+                }
+            };
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|e| ApiError::StorageError(format!("Failed to read directory: {}", e)))?
+            {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let key = if dir_key.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}/{}", dir_key, name)
+                };
+                let Ok(metadata) = entry.metadata().await else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    pending.push((entry.path(), key));
+                } else if key.starts_with(prefix) && !name.contains(".partial-") {
+                    found.push(Entry {
+                        key,
+                        size: metadata.len(),
+                        modified: metadata
+                            .modified()
+                            .map(chrono::DateTime::from)
+                            .unwrap_or_else(|_| chrono::Utc::now()),
+                    });
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    async fn available_space(&self) -> Result<Option<u64>> {
+        // POSIX `df` output: a header, then `filesystem blocks used available ...`
+        // in 1024-byte blocks
+        let output = Command::new("df")
+            .arg("-Pk")
+            .arg(&self.root)
+            .output()
+            .await
+            .map_err(|e| ApiError::StorageError(format!("Failed to run df: {}", e)))?;
+        if !output.status.success() {
+            return Err(ApiError::StorageError(format!(
+                "df failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .nth(1)
+            .and_then(|line| line.split_whitespace().nth(3))
+            .and_then(|blocks| blocks.parse::<u64>().ok())
+            .map(|blocks| Some(blocks * 1024))
+            .ok_or_else(|| ApiError::StorageError("Unexpected df output".to_string()))
+    }
+}
@@ -0,0 +1,114 @@
+//! Blob storage for page images, generated LaTeX and PDFs.
+//!
+//! Everything is addressed by a `/`-separated key such as `latex/{id}.tex`,
+//! so blobs can live in an S3 bucket instead of a local directory. The
+//! metadata in [`crate::db`] is still local to one process, though, so this
+//! alone does not make running several replicas possible.
+
+mod fs;
+mod s3;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::config::env::StorageConfig;
+use crate::errors::Result;
+
+pub use fs::FsStore;
+pub use s3::S3Store;
+
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()>;
+
+    /// The blob's contents, or `ApiError::NotFound` if there is none.
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Remove a blob; removing one that does not exist is not an error.
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Blobs whose keys start with `prefix`, in no particular order.
+    async fn entries(&self, prefix: &str) -> Result<Vec<Entry>>;
+
+    /// Keys starting with `prefix`, in no particular order.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let entries = self.entries(prefix).await?;
+        Ok(entries.into_iter().map(|entry| entry.key).collect())
+    }
+
+    /// Bytes left for new blobs, or `None` when the store has no limit it
+    /// can report.
+    async fn available_space(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+}
+
+/// A stored blob, as listed.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub key: String,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+/// Shared handle to the configured store.
+pub type Storage = Arc<dyn BlobStore>;
+
+pub fn from_config(config: &StorageConfig) -> Result<Storage> {
+    Ok(match config {
+        StorageConfig::Filesystem { root } => Arc::new(FsStore::new(root)),
+        StorageConfig::S3 {
+            endpoint,
+            bucket,
+            region,
+            access_key_id,
+            secret_access_key,
+            prefix,
+        } => Arc::new(S3Store::new(
+            endpoint,
+            bucket,
+            region,
+            access_key_id,
+            secret_access_key,
+            prefix,
+        )?),
+    })
+}
+
+pub fn upload_key(filename: &str) -> String {
+    format!("uploads/{}", filename)
+}
+
+pub fn latex_key(file_id: &Uuid) -> String {
+    format!("latex/{}.tex", file_id)
+}
+
+/// Where a PDF compiled from `source` with `engine` is kept. Keys include a
+/// hash of both, so a restyled or outdated build is never served in place of
+/// the current document.
+pub fn pdf_key(file_id: &Uuid, engine: &str, source: &str) -> String {
+    let mut hash = Sha256::new();
+    hash.update(engine.as_bytes());
+    hash.update([0]);
+    hash.update(source.as_bytes());
+    let hash = format!("{:x}", hash.finalize());
+    format!("{}{}.pdf", pdf_prefix(file_id), &hash[..16])
+}
+
+/// The prefix of every PDF kept for a document.
+pub fn pdf_prefix(file_id: &Uuid) -> String {
+    format!("pdf/{}.", file_id)
+}
+
+/// The document a key from [`upload_key`], [`latex_key`] or [`pdf_key`] belongs to.
+pub fn document_id(key: &str) -> Option<Uuid> {
+    let name = key.rsplit('/').next()?;
+    let stem = name.split('.').next()?;
+    // Multi-page uploads are `{id}_{n}.ext`
+    let id = stem.split('_').next()?;
+    id.parse().ok()
+}
@@ -0,0 +1,264 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::errors::{ApiError, Result};
+use crate::services::latex;
+
+pub const DEFAULT_STYLE: &str = "article";
+
+/// How transcribed pages are laid out inside the document body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// One page of notes per output page, separated by `\newpage`.
+    Paged,
+    /// One page of notes per Beamer frame.
+    Slides,
+    /// Continuous flow set in `n` columns.
+    Columns(u8),
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Style {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub document_class: &'static str,
+    #[serde(skip)]
+    pub preamble: &'static str,
+    pub layout: Layout,
+    /// Extra instructions handed to the model for every page.
+    #[serde(skip)]
+    pub rules: &'static str,
+}
+
+const BASE_PACKAGES: &str = "\\usepackage[utf8]{inputenc}
+\\usepackage{amsmath}
+\\usepackage{amssymb}
+\\usepackage{amsthm}
+";
+
+const THEOREMS: &str = "\\newtheorem{theorem}{Theorem}[section]
+\\newtheorem{lemma}[theorem]{Lemma}
+\\newtheorem{proposition}[theorem]{Proposition}
+\\newtheorem{corollary}[theorem]{Corollary}
+\\theoremstyle{definition}
+\\newtheorem{definition}[theorem]{Definition}
+\\newtheorem{example}[theorem]{Example}
+\\theoremstyle{remark}
+\\newtheorem*{remark}{Remark}
+";
+
+/// Loaded after everything else, so documents stored before it was added
+/// still match their style's signature.
+const LISTINGS: &str = "\\usepackage{listings}
+\\lstset{basicstyle=\\ttfamily\\small, columns=fullflexible, breaklines=true}
+";
+
+pub static STYLES: &[Style] = &[
+    Style {
+        name: "article",
+        description: "Plain article, one page of notes per page",
+        document_class: "\\documentclass{article}",
+        preamble: "",
+        layout: Layout::Paged,
+        rules: "Use align* for equations.",
+    },
+    Style {
+        name: "lecture-notes",
+        description: "Sectioned lecture notes with theorem environments",
+        document_class: "\\documentclass[11pt]{article}",
+        preamble: "\\usepackage[margin=1in]{geometry}
+\\usepackage{enumitem}
+",
+        layout: Layout::Paged,
+        rules: "Use \\section and \\subsection for headings in the notes. \
+Wrap definitions, theorems, lemmas, propositions, corollaries, examples and remarks \
+in the environments of the same name, and proofs in the proof environment. \
+Use align* for displayed equations.",
+    },
+    Style {
+        name: "problem-set",
+        description: "Numbered problems with solution environments",
+        document_class: "\\documentclass[11pt]{article}",
+        preamble: "\\usepackage[margin=1in]{geometry}
+\\usepackage{enumitem}
+\\newlist{problems}{enumerate}{1}
+\\setlist[problems]{label=\\textbf{Problem \\arabic*.}, leftmargin=*, resume}
+\\newenvironment{solution}{\\begin{proof}[Solution]}{\\end{proof}}
+",
+        layout: Layout::Paged,
+        rules: "Put the problems in a \\begin{problems} ... \\end{problems} list with one \\item per problem; \
+numbering continues across pages automatically, so never number problems by hand. \
+Use a nested enumerate for sub-parts. Wrap every worked solution in \
+\\begin{solution} ... \\end{solution} directly after its problem statement.",
+    },
+    Style {
+        name: "beamer",
+        description: "Beamer slides, one page of notes per frame",
+        document_class: "\\documentclass{beamer}",
+        preamble: "\\usetheme{default}
+\\setbeamertemplate{navigation symbols}{}
+",
+        layout: Layout::Slides,
+        rules: "The content will be placed inside a single Beamer frame. \
+Start with \\frametitle{...} summarising the page, prefer itemize over prose, \
+and do not use \\section, \\newpage or frame environments.",
+    },
+    Style {
+        name: "cheat-sheet",
+        description: "Dense two-column landscape reference sheet",
+        document_class: "\\documentclass[9pt,landscape]{extarticle}",
+        preamble: "\\usepackage[landscape,margin=0.4in]{geometry}
+\\usepackage{multicol}
+\\usepackage{enumitem}
+\\setlist{nosep}
+\\setlength{\\columnsep}{0.25in}
+\\pagestyle{empty}
+",
+        layout: Layout::Columns(2),
+        rules: "Be terse: use \\subsection* headings, short itemize lists and inline math \
+wherever possible. Do not use \\newpage or \\section.",
+    },
+];
+
+pub fn find(name: &str) -> Result<&'static Style> {
+    STYLES
+        .iter()
+        .find(|style| style.name == name)
+        .ok_or_else(|| {
+            let names: Vec<&str> = STYLES.iter().map(|style| style.name).collect();
+            ApiError::ValidationError(format!(
+                "Unknown style: {}. Available styles: {:?}",
+                name, names
+            ))
+        })
+}
+
+pub fn resolve(name: Option<&str>) -> Result<&'static Style> {
+    find(name.unwrap_or(DEFAULT_STYLE))
+}
+
+/// The style a stored document was assembled with, falling back to the default.
+pub fn detect(content: &str) -> &'static Style {
+    STYLES
+        .iter()
+        .find(|style| content.starts_with(&style.signature()))
+        .unwrap_or(&STYLES[0])
+}
+
+impl Style {
+    /// Full preamble, from `\documentclass` up to (not including) `\begin{document}`.
+    pub fn header(&self) -> String {
+        let mut header = self.signature();
+        header.push_str(LISTINGS);
+        header
+    }
+
+    /// The part of the header that identifies the style in a stored document.
+    fn signature(&self) -> String {
+        let mut signature = format!("{}\n{}", self.document_class, BASE_PACKAGES);
+        signature.push_str(self.preamble);
+        if self.layout != Layout::Slides {
+            signature.push_str(THEOREMS);
+        }
+        signature
+    }
+
+    /// Wrap per-page body fragments into a complete document.
+    pub fn assemble(&self, pages: &[String]) -> String {
+        self.assemble_with(&self.header(), pages)
+    }
+
+    /// Like [`Style::assemble`], but with a preamble of the caller's choosing,
+    /// e.g. one that keeps a document's own packages and macros.
+    pub fn assemble_with(&self, header: &str, pages: &[String]) -> String {
+        let mut body = String::new();
+        match self.layout {
+            Layout::Paged => {
+                for (index, page) in pages.iter().enumerate() {
+                    if index > 0 {
+                        body.push_str("\\newpage\n");
+                    }
+                    push_line(&mut body, page.trim());
+                }
+            }
+            Layout::Slides => {
+                for page in pages {
+                    // Fragile so frames can hold listings
+                    body.push_str("\\begin{frame}[fragile,allowframebreaks]\n");
+                    push_line(&mut body, page.trim());
+                    body.push_str("\\end{frame}\n");
+                }
+            }
+            Layout::Columns(columns) => {
+                body.push_str(&format!("\\begin{{multicols*}}{{{}}}\n", columns));
+                for page in pages {
+                    push_line(&mut body, page.trim());
+                    body.push('\n');
+                }
+                body.push_str("\\end{multicols*}\n");
+            }
+        }
+
+        format!(
+            "{}\n\\begin{{document}}\n{}\\end{{document}}\n",
+            header, body
+        )
+    }
+
+    /// Re-render an existing document, whatever style produced it, in this style.
+    pub fn restyle(&self, content: &str) -> String {
+        self.assemble(&latex::split_pages(content))
+    }
+}
+
+fn push_line(out: &mut String, text: &str) {
+    out.push_str(text);
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pages() -> Vec<String> {
+        vec!["First page.".to_string(), "Second page.".to_string()]
+    }
+
+    #[test]
+    fn finds_styles_by_name() {
+        for style in STYLES {
+            assert_eq!(find(style.name).unwrap().name, style.name);
+        }
+        assert_eq!(resolve(None).unwrap().name, DEFAULT_STYLE);
+        let error = find("memoir").unwrap_err().to_string();
+        assert!(error.contains("Unknown style: memoir"), "{}", error);
+    }
+
+    #[test]
+    fn detects_the_style_a_document_was_assembled_with() {
+        for style in STYLES {
+            assert_eq!(detect(&style.assemble(&pages())).name, style.name);
+            // From before listings were added to every header
+            let old = style.assemble_with(&style.signature(), &pages());
+            assert_eq!(detect(&old).name, style.name);
+        }
+        let imported = "\\documentclass{report}\n\\begin{document}\nHi\n\\end{document}\n";
+        assert_eq!(detect(imported).name, DEFAULT_STYLE);
+    }
+
+    #[test]
+    fn restyles_pages_into_another_layout() {
+        let notes = find("lecture-notes").unwrap().assemble(&pages());
+        for style in STYLES {
+            let restyled = style.restyle(&notes);
+            assert_eq!(detect(&restyled).name, style.name);
+            let expected = match style.layout {
+                // Columns flow the pages into one
+                Layout::Columns(_) => vec!["First page.\n\nSecond page.".to_string()],
+                _ => pages(),
+            };
+            assert_eq!(latex::split_pages(&restyled), expected, "{}", style.name);
+        }
+    }
+}
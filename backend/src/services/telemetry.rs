@@ -0,0 +1,133 @@
+//! Logging, and OpenTelemetry traces exported over OTLP when configured.
+
+use axum::http::HeaderMap;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::config::env::TelemetryConfig;
+use crate::errors::{ApiError, Result};
+
+/// Keeps the exporter alive; call [`Telemetry::shutdown`] to flush it.
+pub struct Telemetry {
+    provider: Option<TracerProvider>,
+}
+
+impl Telemetry {
+    /// Export any spans still buffered.
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!("failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+/// Install the global subscriber: logs filtered by `RUST_LOG` and, with an
+/// exporter configured, this crate's spans at info and above.
+pub fn init(config: Option<TelemetryConfig>) -> Result<Telemetry> {
+    let provider = config.map(|config| provider(&config)).transpose()?;
+
+    let export = provider.as_ref().map(|provider| {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer("noteforge"))
+            .with_filter(Targets::new().with_target("backend", Level::INFO))
+    });
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(EnvFilter::from_default_env()))
+        .with(export)
+        .init();
+
+    Ok(Telemetry { provider })
+}
+
+/// The trace context a caller sent in `traceparent`/`tracestate`, if any.
+pub fn extract(headers: &HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+fn provider(config: &TelemetryConfig) -> Result<TracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .build()
+        .map_err(|e| {
+            ApiError::InternalServerError(anyhow::anyhow!("Invalid OTLP exporter settings: {}", e))
+        })?;
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new_with_defaults([KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )]))
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::body::Bytes;
+    use axum::http::HeaderMap;
+    use axum::routing::post;
+    use axum::Router;
+
+    use super::*;
+
+    // What `docker run -p 4318:4318 otel/opentelemetry-collector` would receive
+    #[tokio::test(flavor = "multi_thread")]
+    async fn exports_spans_to_an_otlp_collector() {
+        let received: Arc<Mutex<Vec<(HeaderMap, Bytes)>>> = Arc::default();
+        let collector = Router::new().route(
+            "/v1/traces",
+            post({
+                let received = received.clone();
+                move |headers: HeaderMap, body: Bytes| async move {
+                    received.lock().unwrap().push((headers, body));
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, collector).await });
+
+        let provider = provider(&TelemetryConfig {
+            service_name: "noteforge-test".to_string(),
+            endpoint: format!("http://{}/v1/traces", addr),
+        })
+        .unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("noteforge")));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("export_check", document.id = "d1").in_scope(|| {});
+        });
+        tokio::task::spawn_blocking(move || provider.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (headers, body) = &received[0];
+        assert_eq!(headers["content-type"], "application/x-protobuf");
+        // Protobuf keeps strings as they are
+        let contains = |text: &str| {
+            body.windows(text.len())
+                .any(|window| window == text.as_bytes())
+        };
+        assert!(contains("export_check"));
+        assert!(contains("noteforge-test"));
+        assert!(contains("document.id"));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::errors::{ApiError, Result};
+use crate::services::pdf::{COMPILE_TIMEOUT, TEMP_DIR_PREFIX};
+use std::path::Path;
+use tokio::fs;
+use tokio::process::Command;
+
+/// Compiles Typst markup to PDF; the counterpart of `PdfService` for the Typst engine.
+pub struct TypstService;
+
+impl TypstService {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub async fn generate_pdf(&self, typst_content: &str) -> Result<Vec<u8>> {
+        // Create a temporary directory for processing
+        let temp_dir =
+            std::env::temp_dir().join(format!("{}{}", TEMP_DIR_PREFIX, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).await.map_err(|e| {
+            ApiError::InternalServerError(anyhow::anyhow!("Failed to create temp dir: {}", e))
+        })?;
+
+        let result = Self::compile(&temp_dir, typst_content).await;
+
+        // Clean up temporary directory, whether or not compilation succeeded
+        tokio::spawn(async move {
+            let _ = fs::remove_dir_all(temp_dir).await;
+        });
+
+        result
+    }
+
+    async fn compile(temp_dir: &Path, typst_content: &str) -> Result<Vec<u8>> {
+        let source_path = temp_dir.join("output.typ");
+        fs::write(&source_path, typst_content).await.map_err(|e| {
+            ApiError::InternalServerError(anyhow::anyhow!("Failed to write Typst file: {}", e))
+        })?;
+
+        // `--root` confines file access (images, includes) to the temp directory
+        let pdf_path = temp_dir.join("output.pdf");
+        let child = Command::new("typst")
+            .arg("compile")
+            .arg("--root")
+            .arg(temp_dir)
+            .args(["--diagnostic-format", "short"])
+            .arg(&source_path)
+            .arg(&pdf_path)
+            .kill_on_drop(true)
+            .output();
+
+        let output = tokio::time::timeout(COMPILE_TIMEOUT, child)
+            .await
+            .map_err(|_| {
+                ApiError::LaTeXError(format!(
+                    "Typst compilation timed out after {} seconds",
+                    COMPILE_TIMEOUT.as_secs()
+                ))
+            })?
+            .map_err(|e| {
+                ApiError::InternalServerError(anyhow::anyhow!("Failed to run typst: {}", e))
+            })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            return Err(ApiError::LaTeXError(format!(
+                "PDF generation failed: {}\n{}",
+                stderr, stdout
+            )));
+        }
+
+        fs::read(&pdf_path).await.map_err(|e| {
+            ApiError::InternalServerError(anyhow::anyhow!("Failed to read PDF file: {}", e))
+        })
+    }
+}
@@ -0,0 +1,522 @@
+//! Webhook events: queued in the database when something happens to a
+//! document, then POSTed by a background dispatcher that retries with
+//! exponential backoff.
+//!
+//! Requests follow Standard Webhooks: `webhook-id` is the event ID,
+//! `webhook-timestamp` the Unix time of the attempt, and `webhook-signature`
+//! is `v1,` and the base64 HMAC-SHA256 of `{id}.{timestamp}.{body}`, keyed
+//! with the owner's secret.
+//!
+//! Unless `WEBHOOK_ALLOW_PRIVATE_URLS` is set, URLs naming a loopback, private
+//! or link-local address are refused, and the dispatcher resolves host names
+//! itself so that names pointing at such addresses are refused too.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::Utc;
+use futures::future::join_all;
+use hmac::{Hmac, Mac};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::CONTENT_TYPE;
+use sha2::Sha256;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::config::env::WebhookConfig;
+use crate::db::{Attempt, Database, Outgoing};
+use crate::errors::{ApiError, Result};
+use crate::models::webhook::{DeliveryStatus, Event, EventData, EventKind};
+use crate::services::metrics;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Marks signing secrets; the rest is the base64 key.
+const SECRET_PREFIX: &str = "whsec_";
+
+/// Deliveries attempted at once.
+const BATCH_SIZE: usize = 20;
+
+/// How often to look for due retries when nothing new has been queued.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Wakes the dispatcher when deliveries are queued.
+static WAKE: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// A new signing secret with 256 random bits.
+pub fn new_secret() -> String {
+    let mut key = Uuid::new_v4().as_bytes().to_vec();
+    key.extend_from_slice(Uuid::new_v4().as_bytes());
+    format!("{}{}", SECRET_PREFIX, STANDARD.encode(key))
+}
+
+/// Queue an event for the owner's webhooks and, if given, a per-request
+/// callback URL. Failures are logged rather than returned: the work the
+/// event reports has already happened.
+pub fn emit(
+    db: &Database,
+    owner: Option<&Uuid>,
+    callback: Option<&str>,
+    kind: EventKind,
+    data: EventData,
+) {
+    // Documents from before authentication have nobody to sign for
+    let Some(owner) = owner else {
+        return;
+    };
+    if let Err(e) = queue(db, owner, callback, kind, data) {
+        tracing::warn!("failed to queue {} webhooks: {}", kind.as_str(), e);
+    }
+}
+
+fn queue(
+    db: &Database,
+    owner: &Uuid,
+    callback: Option<&str>,
+    kind: EventKind,
+    data: EventData,
+) -> Result<()> {
+    let mut targets: Vec<(Option<Uuid>, String)> = db
+        .webhooks(owner)?
+        .into_iter()
+        .filter(|webhook| webhook.events.is_empty() || webhook.events.contains(&kind))
+        .map(|webhook| (Some(webhook.id), webhook.url))
+        .collect();
+    targets.extend(callback.map(|url| (None, url.to_string())));
+    if targets.is_empty() {
+        return Ok(());
+    }
+
+    db.webhook_secret(owner, new_secret)?;
+    let event = Event {
+        id: Uuid::new_v4(),
+        kind,
+        created_at: Utc::now(),
+        data,
+    };
+    let payload = serde_json::to_string(&event).map_err(anyhow::Error::from)?;
+    db.queue_deliveries(owner, &event.id, kind, &payload, &targets)?;
+    wake();
+    Ok(())
+}
+
+/// Have the dispatcher look for due deliveries now.
+pub fn wake() {
+    WAKE.notify_one();
+}
+
+/// Fail unless deliveries may go to `url`, reporting problems against `field`.
+pub fn validate_url(config: &WebhookConfig, field: &str, url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|_| ApiError::invalid_field(field, "Must be an absolute URL"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(ApiError::invalid_field(
+            field,
+            "Must be an http or https URL",
+        ));
+    }
+    if !config.allow_private_urls && is_private_host(parsed.host_str().unwrap_or_default()) {
+        return Err(ApiError::invalid_field(field, PRIVATE_ADDRESS));
+    }
+    Ok(())
+}
+
+const PRIVATE_ADDRESS: &str = "Loopback and private network addresses are not allowed";
+
+// Only literal addresses can be checked from the URL; `PublicResolver`
+// checks what names resolve to when delivering
+fn is_private_host(host: &str) -> bool {
+    let host = host.trim_end_matches('.');
+    if host.is_empty() || host == "localhost" || host.ends_with(".localhost") {
+        return true;
+    }
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .is_ok_and(is_private)
+}
+
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                // "This network", 0.0.0.0/8, which reaches the local host
+                || first == 0
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (first == 100 && second & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            // The IPv4 address a NAT64 prefix translates to
+            let embedded = Ipv4Addr::from(u128::from(ip) as u32);
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || segments[0] & 0xfe00 == 0xfc00
+                || segments[0] & 0xffc0 == 0xfe80
+                || ip
+                    .to_ipv4_mapped()
+                    .is_some_and(|ip| is_private(IpAddr::V4(ip)))
+                // NAT64, 64:ff9b::/96, reaches that address
+                || (segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] && is_private(IpAddr::V4(embedded)))
+                // Local-use NAT64, 64:ff9b:1::/48, translates into private networks
+                || segments[..3] == [0x64, 0xff9b, 1]
+        }
+    }
+}
+
+/// Resolves host names for deliveries, keeping only public addresses, so
+/// that a name can't be pointed at the internal network.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+                .await?
+                .filter(|addr| !is_private(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// The `webhook-signature` value for one attempt.
+pub fn sign(secret: &str, event_id: &Uuid, timestamp: i64, body: &str) -> String {
+    let key = secret
+        .strip_prefix(SECRET_PREFIX)
+        .and_then(|key| STANDARD.decode(key).ok())
+        .unwrap_or_else(|| secret.as_bytes().to_vec());
+    let mut mac = HmacSha256::new_from_slice(&key).expect("HMAC takes keys of any size");
+    mac.update(format!("{}.{}.", event_id, timestamp).as_bytes());
+    mac.update(body.as_bytes());
+    format!("v1,{}", STANDARD.encode(mac.finalize().into_bytes()))
+}
+
+pub struct Dispatcher {
+    db: Database,
+    client: reqwest::Client,
+    config: WebhookConfig,
+}
+
+impl Dispatcher {
+    pub fn new(db: Database, config: WebhookConfig) -> Result<Self> {
+        let mut client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            // A redirect could point anywhere, including places `validate_url` refuses
+            .redirect(reqwest::redirect::Policy::none())
+            // A proxy would resolve names itself, out of `PublicResolver`'s reach
+            .no_proxy()
+            .user_agent(concat!("noteforge-webhooks/", env!("CARGO_PKG_VERSION")));
+        if !config.allow_private_urls {
+            client = client.dns_resolver(Arc::new(PublicResolver));
+        }
+        let client = client.build().map_err(anyhow::Error::from)?;
+        Ok(Self { db, client, config })
+    }
+
+    /// Send deliveries as they are queued or come due, until the process exits.
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                match self.run().await {
+                    // A full batch means more may be waiting
+                    Ok(attempted) if attempted == BATCH_SIZE => continue,
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("webhook dispatch failed: {}", e),
+                }
+                tokio::select! {
+                    _ = WAKE.notified() => {}
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                }
+            }
+        })
+    }
+
+    /// Attempt every due delivery once, returning how many there were.
+    pub async fn run(&self) -> Result<usize> {
+        let due = self.db.due_deliveries(BATCH_SIZE)?;
+        let attempts = join_all(due.iter().map(|delivery| self.attempt(delivery))).await;
+        for (delivery, attempt) in due.iter().zip(&attempts) {
+            let outcome = match attempt.status {
+                DeliveryStatus::Succeeded => "success",
+                DeliveryStatus::Pending => "retry",
+                DeliveryStatus::Failed => {
+                    tracing::warn!(
+                        "giving up on webhook delivery {} to {} after {} attempts: {}",
+                        delivery.id,
+                        delivery.url,
+                        delivery.attempts + 1,
+                        attempt.error.as_deref().unwrap_or_default()
+                    );
+                    "failure"
+                }
+            };
+            metrics::WEBHOOK_ATTEMPTS
+                .with_label_values(&[delivery.event.as_str(), outcome])
+                .inc();
+            self.db.record_attempt(&delivery.id, attempt)?;
+        }
+        Ok(due.len())
+    }
+
+    async fn attempt(&self, delivery: &Outgoing) -> Attempt {
+        // Literal addresses never reach the resolver, and the URL may have
+        // been accepted while private addresses were allowed
+        let host = reqwest::Url::parse(&delivery.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        if !self.config.allow_private_urls && is_private_host(&host) {
+            return Attempt {
+                status: DeliveryStatus::Failed,
+                response_status: None,
+                error: Some(PRIVATE_ADDRESS.to_string()),
+                next_attempt_at: None,
+            };
+        }
+
+        let timestamp = Utc::now().timestamp();
+        let signature = sign(
+            &delivery.secret,
+            &delivery.event_id,
+            timestamp,
+            &delivery.payload,
+        );
+        let result = self
+            .client
+            .post(&delivery.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header("webhook-id", delivery.event_id.to_string())
+            .header("webhook-timestamp", timestamp.to_string())
+            .header("webhook-signature", signature)
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        let (response_status, error) = match result {
+            Ok(response) if response.status().is_success() => {
+                return Attempt {
+                    status: DeliveryStatus::Succeeded,
+                    response_status: Some(response.status().as_u16()),
+                    error: None,
+                    next_attempt_at: None,
+                };
+            }
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                format!("Receiver answered {}", response.status()),
+            ),
+            // reqwest's message alone doesn't say what went wrong
+            Err(e) => (None, format!("{:#}", anyhow::Error::from(e))),
+        };
+
+        let attempts = delivery.attempts + 1;
+        if attempts >= self.config.max_attempts {
+            return Attempt {
+                status: DeliveryStatus::Failed,
+                response_status,
+                error: Some(error),
+                next_attempt_at: None,
+            };
+        }
+        let backoff = self.config.retry_base * 2u32.saturating_pow(attempts - 1);
+        Attempt {
+            status: DeliveryStatus::Pending,
+            response_status,
+            error: Some(error),
+            next_attempt_at: chrono::Duration::from_std(backoff)
+                .ok()
+                .map(|backoff| Utc::now() + backoff),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::sync::Mutex;
+
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+
+    use super::*;
+
+    /// What the receiver was sent, and the status it answers with.
+    #[derive(Default)]
+    struct Receiver {
+        requests: Mutex<Vec<(HeaderMap, String)>>,
+        status: AtomicU16,
+    }
+
+    async fn receive(
+        State(receiver): State<Arc<Receiver>>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        receiver.requests.lock().unwrap().push((headers, body));
+        StatusCode::from_u16(receiver.status.load(Ordering::SeqCst)).unwrap()
+    }
+
+    /// Serve a receiver on a free local port, returning its URL.
+    async fn serve(receiver: Arc<Receiver>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let app = Router::new()
+            .route("/hook", post(receive))
+            .with_state(receiver);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_events_with_retries() {
+        let config = WebhookConfig {
+            max_attempts: 3,
+            retry_base: Duration::from_secs(60),
+            timeout: Duration::from_secs(5),
+            allow_private_urls: true,
+        };
+        let receiver = Arc::new(Receiver::default());
+        receiver.status.store(500, Ordering::SeqCst);
+        let url = serve(receiver.clone()).await;
+        validate_url(&config, "url", &url).unwrap();
+
+        let db = Database::open(":memory:").unwrap();
+        let owner = db.create_owner("receiver", false).unwrap();
+        db.create_webhook(&owner.id, &url, &[EventKind::PdfReady])
+            .unwrap();
+        let dispatcher = Dispatcher::new(db.clone(), config).unwrap();
+        let document_id = Uuid::new_v4();
+        let data = || EventData {
+            document_id,
+            job_id: None,
+            error_code: None,
+            error: None,
+        };
+
+        // Not subscribed to, so nothing is queued
+        emit(
+            &db,
+            Some(&owner.id),
+            None,
+            EventKind::DocumentDeleted,
+            data(),
+        );
+        assert!(db.deliveries(&owner.id, None, 10).unwrap().is_empty());
+
+        emit(&db, Some(&owner.id), None, EventKind::PdfReady, data());
+        assert_eq!(dispatcher.run().await.unwrap(), 1);
+
+        // Signed with the owner's secret, as Standard Webhooks verifiers expect
+        let (headers, body) = receiver.requests.lock().unwrap()[0].clone();
+        let header = |name: &str| headers[name].to_str().unwrap().to_string();
+        let secret = db.webhook_secret(&owner.id, new_secret).unwrap();
+        let event_id: Uuid = header("webhook-id").parse().unwrap();
+        let timestamp: i64 = header("webhook-timestamp").parse().unwrap();
+        assert_eq!(
+            header("webhook-signature"),
+            sign(&secret, &event_id, timestamp, &body)
+        );
+        let event: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(event["type"], "pdf.ready");
+        assert_eq!(event["data"]["document_id"], document_id.to_string());
+
+        // The 500 leaves it pending, with the next attempt backed off
+        let failed = db.deliveries(&owner.id, None, 10).unwrap().remove(0);
+        assert_eq!(failed.status, DeliveryStatus::Pending);
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(failed.response_status, Some(500));
+        let retry_at = failed.next_attempt_at.unwrap();
+        assert!(retry_at >= failed.created_at + chrono::Duration::seconds(59));
+        assert_eq!(dispatcher.run().await.unwrap(), 0);
+
+        // Redelivery is a new delivery of the same event, sent right away
+        receiver.status.store(204, Ordering::SeqCst);
+        let copy = db.redeliver(&owner.id, &failed.id).unwrap().unwrap();
+        assert_ne!(copy.id, failed.id);
+        assert_eq!(copy.event_id, event_id);
+        assert_eq!(copy.status, DeliveryStatus::Pending);
+        assert_eq!(copy.attempts, 0);
+        assert_eq!(dispatcher.run().await.unwrap(), 1);
+
+        let copy = db.get_delivery(&owner.id, &copy.id).unwrap().unwrap();
+        assert_eq!(copy.status, DeliveryStatus::Succeeded);
+        assert_eq!(copy.response_status, Some(204));
+        assert!(copy.delivered_at.is_some());
+        let (headers, _) = receiver.requests.lock().unwrap()[1].clone();
+        assert_eq!(headers["webhook-id"], event_id.to_string());
+        let original = db.get_delivery(&owner.id, &failed.id).unwrap().unwrap();
+        assert_eq!(original.next_attempt_at, Some(retry_at));
+    }
+
+    #[test]
+    fn refuses_private_hosts() {
+        for host in [
+            "localhost",
+            "localhost.",
+            "api.localhost",
+            "127.0.0.1",
+            "10.1.2.3",
+            "169.254.169.254",
+            "100.100.0.1",
+            "0.1.2.3",
+            "224.0.0.1",
+            "[::1]",
+            "[fd00::1]",
+            "[ff02::1]",
+            "[::ffff:192.168.0.1]",
+            "[64:ff9b::7f00:1]",
+            "[64:ff9b:1::1]",
+        ] {
+            assert!(is_private_host(host), "{} should be private", host);
+        }
+        for host in [
+            "example.com",
+            "93.184.216.34",
+            "[2606:4700::1111]",
+            "[64:ff9b::5db8:d822]",
+        ] {
+            assert!(!is_private_host(host), "{} should be public", host);
+        }
+    }
+
+    #[test]
+    fn refuses_private_urls_unless_allowed() {
+        let mut config = WebhookConfig {
+            max_attempts: 1,
+            retry_base: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            allow_private_urls: false,
+        };
+        assert!(validate_url(&config, "url", "http://10.0.0.1/hook").is_err());
+        assert!(validate_url(&config, "url", "ftp://example.com/hook").is_err());
+        validate_url(&config, "url", "https://example.com/hook").unwrap();
+
+        config.allow_private_urls = true;
+        validate_url(&config, "url", "http://10.0.0.1/hook").unwrap();
+    }
+
+    #[tokio::test]
+    async fn resolver_refuses_names_of_private_addresses() {
+        let name = Name::from_str("localhost").unwrap();
+        let error = PublicResolver.resolve(name).await.err().unwrap();
+        assert!(error.to_string().contains("no public address"));
+    }
+}
@@ -1,5 +1,46 @@
 pub use axum::http::HeaderMap;
 
+/// Headers for serving generated content as a file download.
+pub fn attachment(content_type: &str, filename: &str) -> HeaderMap {
+    disposition("attachment", content_type, filename)
+}
+
+/// Headers for content the browser should display, keeping a filename for saving.
+pub fn inline(content_type: &str, filename: &str) -> HeaderMap {
+    disposition("inline", content_type, filename)
+}
+
+fn disposition(kind: &str, content_type: &str, filename: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        axum::http::header::CONTENT_TYPE,
+        content_type.parse().unwrap(),
+    );
+    headers.insert(
+        axum::http::header::CONTENT_DISPOSITION,
+        format!("{}; filename=\"{}\"", kind, filename)
+            .parse()
+            .unwrap(),
+    );
+    headers
+}
+
+/// Report non-fatal problems in a header such as `x-export-warnings`.
+pub fn insert_warnings(headers: &mut HeaderMap, name: &'static str, warnings: &[String]) {
+    if warnings.is_empty() {
+        return;
+    }
+    // Header values must be visible ASCII
+    let value: String = warnings
+        .join("; ")
+        .chars()
+        .filter(|c| c.is_ascii_graphic() || *c == ' ')
+        .collect();
+    if let Ok(value) = value.parse() {
+        headers.insert(name, value);
+    }
+}
+
 #[macro_export]
 macro_rules! headers_map {
     ($($key:expr => $value:expr),* $(,)?) => {{
@@ -1 +1,2 @@
 pub mod headers;
+pub mod request_id;
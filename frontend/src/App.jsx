@@ -1,8 +1,10 @@
-import React, { useState } from 'react';
+import React, { useState, useEffect } from 'react';
 import FileUpload from './components/FileUpload';
 import LatexView from './components/LatexView';
 import PdfView from './components/PdfView';
 import ErrorMessage from './components/ErrorMessage';
+import ApiKeyForm from './components/ApiKeyForm';
+import { loginEnabled, login, logout, completeLogin, getToken, setApiKey } from './services/auth';
 
 function App() {
   const [currentStep, setCurrentStep] = useState('upload');
@@ -10,6 +12,34 @@ function App() {
   const [latex, setLatex] = useState('');
   const [isMultiPage, setIsMultiPage] = useState(false);
   const [error, setError] = useState('');
+  const [signedIn, setSignedIn] = useState(Boolean(getToken()));
+
+  useEffect(() => {
+    completeLogin()
+      .then(() => setSignedIn(Boolean(getToken())))
+      .catch(err => setError(err.message));
+  }, []);
+
+  // The API rejected the key or token, so ask for another
+  useEffect(() => {
+    const signedOut = () => {
+      setSignedIn(false);
+      setCurrentStep('upload');
+    };
+    window.addEventListener('noteforge:logout', signedOut);
+    return () => window.removeEventListener('noteforge:logout', signedOut);
+  }, []);
+
+  const handleLogout = () => {
+    logout();
+    handleBackToUpload();
+  };
+
+  const handleApiKey = (key) => {
+    setApiKey(key);
+    setSignedIn(true);
+    setError('');
+  };
 
   const handleUploadSuccess = (id, isMulti) => {
     setFileId(id);
@@ -43,11 +73,29 @@ function App() {
       <header>
         <h1>noteforge</h1>
         <p>converts hand-written math notes to latex and then to PDF</p>
+        {signedIn && (
+          <button className="action-button secondary" onClick={handleLogout}>
+            sign out
+          </button>
+        )}
       </header>
 
       {error && <ErrorMessage message={error} />}
 
-      {currentStep === 'upload' && (
+      {loginEnabled && !signedIn && (
+        <div className="action-buttons">
+          <button
+            className="action-button"
+            onClick={() => login().catch(err => setError(err.message))}
+          >
+            sign in
+          </button>
+        </div>
+      )}
+
+      {!loginEnabled && !signedIn && <ApiKeyForm onSubmit={handleApiKey} />}
+
+      {signedIn && currentStep === 'upload' && (
         <FileUpload 
           onUploadSuccess={handleUploadSuccess} 
           onError={handleError}
@@ -0,0 +1,32 @@
+import React, { useState } from 'react';
+
+// Sign in with an API key when no identity provider is configured
+function ApiKeyForm({ onSubmit }) {
+  const [key, setKey] = useState('');
+
+  const handleSubmit = (e) => {
+    e.preventDefault();
+    if (key.trim()) {
+      onSubmit(key);
+    }
+  };
+
+  return (
+    <form className="api-key-form" onSubmit={handleSubmit}>
+      <label htmlFor="api-key">API key</label>
+      <input
+        id="api-key"
+        type="password"
+        autoComplete="off"
+        placeholder="nf_..."
+        value={key}
+        onChange={(e) => setKey(e.target.value)}
+      />
+      <button type="submit" className="action-button">
+        continue
+      </button>
+    </form>
+  );
+}
+
+export default ApiKeyForm;
@@ -1,6 +1,6 @@
 
 import React, { useState, useEffect } from 'react';
-import { getPdfUrl } from '../services/api';
+import { fetchPdf } from '../services/api';
 
 function PdfView({ fileId, onBackClick, onBackToUpload, onError }) {
   const [pdfUrl, setPdfUrl] = useState('');
@@ -13,27 +13,21 @@ function PdfView({ fileId, onBackClick, onBackToUpload, onError }) {
     setError(null);
     setErrorDetails(null);
     
-    // Generate the PDF URL directly from the fileId
-    const url = getPdfUrl(fileId);
-    setPdfUrl(url);
-    
-    // Test the URL - this will only complete after the PDF is generated
-    fetch(url)
-      .then(response => {
-        if (!response.ok) {
-          return response.json().then(err => {
-            throw err;
-          });
-        }
+    // This will only complete after the PDF is generated
+    let objectUrl;
+    fetchPdf(fileId)
+      .then(url => {
+        objectUrl = url;
+        setPdfUrl(url);
         setLoading(false);
       })
       .catch(err => {
         setLoading(false);
         setError('Failed to generate PDF');
         // Check if the error has detailed information
-        if (err.error && err.error.message) {
+        if (err.message) {
           // Look for LaTeX errors in the message
-          const message = err.error.message;
+          const message = err.message;
           
           // Check for common LaTeX errors
           if (message.includes('LaTeX Error') || 
@@ -48,6 +42,10 @@ function PdfView({ fileId, onBackClick, onBackToUpload, onError }) {
         }
         onError('PDF generation failed');
       });
+
+    return () => {
+      if (objectUrl) URL.revokeObjectURL(objectUrl);
+    };
   }, [fileId, onError]);
 
   const handleRegeneratePdf = () => {
@@ -1,4 +1,26 @@
-const API_BASE_URL = 'https://backend-billowing-waterfall-6116.fly.dev';
+import { getToken, logout } from './auth';
+
+const API_BASE_URL = 'https://backend-billowing-waterfall-6116.fly.dev/api/v1';
+
+// Errors are RFC 7807 problem documents: `detail` explains, `code` is stable
+async function problem(response, fallback) {
+  // A rejected key or expired login; App asks for a new one
+  if (response.status === 401) {
+    logout();
+  }
+  const body = await response.json().catch(() => ({}));
+  const error = new Error(body.detail || body.title || fallback);
+  error.code = body.code;
+  error.requestId = body.request_id;
+  error.errors = body.errors || [];
+  return error;
+}
+
+// The signed-in user's token or the API key they entered
+function authHeaders() {
+  const token = getToken();
+  return token ? { Authorization: `Bearer ${token}` } : {};
+}
 
 export async function uploadFiles(files) {
   const formData = new FormData();
@@ -16,35 +38,42 @@ export async function uploadFiles(files) {
     formData.append('is_multi_page', 'false');
   }
 
-  const response = await fetch(`${API_BASE_URL}/upload`, {
+  const response = await fetch(`${API_BASE_URL}/uploads`, {
     method: 'POST',
+    headers: authHeaders(),
     body: formData,
   });
 
   if (!response.ok) {
-    const errorData = await response.json();
-    throw new Error(errorData.error?.message || 'Upload failed');
+    throw await problem(response, 'Upload failed');
   }
 
   return response.json();
 }
 
 export async function convertToLatex(fileId, isMultiPage) {
-  const response = await fetch(
-    `${API_BASE_URL}/convert/${fileId}?is_multi_page=${isMultiPage}`, 
-    {
-      method: 'GET',
-    }
-  );
+  const response = await fetch(`${API_BASE_URL}/documents/${fileId}/conversions`, {
+    method: 'POST',
+    headers: { ...authHeaders(), 'Content-Type': 'application/json' },
+    body: JSON.stringify({ is_multi_page: isMultiPage }),
+  });
 
   if (!response.ok) {
-    const errorData = await response.json();
-    throw new Error(errorData.error?.message || 'Conversion failed');
+    throw await problem(response, 'Conversion failed');
   }
 
   return response.json();
 }
 
-export function getPdfUrl(fileId) {
-  return `${API_BASE_URL}/pdf/${fileId}`;
+// Resolves to an object URL for the generated PDF
+export async function fetchPdf(fileId) {
+  const response = await fetch(`${API_BASE_URL}/documents/${fileId}/exports/pdf`, {
+    headers: authHeaders(),
+  });
+
+  if (!response.ok) {
+    throw await problem(response, 'PDF generation failed');
+  }
+
+  return URL.createObjectURL(await response.blob());
 }
\ No newline at end of file
@@ -0,0 +1,126 @@
+// OpenID Connect login (authorization code flow with PKCE), enabled when
+// VITE_OIDC_AUTHORITY and VITE_OIDC_CLIENT_ID are set.
+const AUTHORITY = import.meta.env.VITE_OIDC_AUTHORITY;
+const CLIENT_ID = import.meta.env.VITE_OIDC_CLIENT_ID;
+const SCOPE = import.meta.env.VITE_OIDC_SCOPE || 'openid profile email';
+
+const TOKEN_KEY = 'noteforge.token';
+const PENDING_KEY = 'noteforge.login';
+// Without a login, users paste an API key issued to them by an admin
+const API_KEY_KEY = 'noteforge.apiKey';
+
+export const loginEnabled = Boolean(AUTHORITY && CLIENT_ID);
+
+function base64Url(bytes) {
+  return btoa(String.fromCharCode(...new Uint8Array(bytes)))
+    .replace(/\+/g, '-')
+    .replace(/\//g, '_')
+    .replace(/=+$/, '');
+}
+
+// The claims of a JWT; only read, the backend checks the signature
+function claims(token) {
+  const payload = token.split('.')[1].replace(/-/g, '+').replace(/_/g, '/');
+  return JSON.parse(atob(payload.padEnd(payload.length + ((4 - (payload.length % 4)) % 4), '=')));
+}
+
+function randomString() {
+  return base64Url(crypto.getRandomValues(new Uint8Array(32)));
+}
+
+async function discover() {
+  const response = await fetch(`${AUTHORITY.replace(/\/$/, '')}/.well-known/openid-configuration`);
+  if (!response.ok) {
+    throw new Error('Could not reach the identity provider');
+  }
+  return response.json();
+}
+
+function redirectUri() {
+  return window.location.origin + window.location.pathname;
+}
+
+export async function login() {
+  const { authorization_endpoint } = await discover();
+  const verifier = randomString();
+  const state = randomString();
+  const challenge = base64Url(
+    await crypto.subtle.digest('SHA-256', new TextEncoder().encode(verifier))
+  );
+  sessionStorage.setItem(PENDING_KEY, JSON.stringify({ verifier, state }));
+
+  const params = new URLSearchParams({
+    response_type: 'code',
+    client_id: CLIENT_ID,
+    redirect_uri: redirectUri(),
+    scope: SCOPE,
+    state,
+    code_challenge: challenge,
+    code_challenge_method: 'S256',
+  });
+  window.location.assign(`${authorization_endpoint}?${params}`);
+}
+
+// Finish a login if the identity provider just redirected back here
+export async function completeLogin() {
+  const params = new URLSearchParams(window.location.search);
+  const code = params.get('code');
+  const pending = JSON.parse(sessionStorage.getItem(PENDING_KEY) || 'null');
+  if (!code || !pending) {
+    return;
+  }
+  sessionStorage.removeItem(PENDING_KEY);
+  window.history.replaceState(null, '', redirectUri());
+  if (params.get('state') !== pending.state) {
+    throw new Error('Login failed: state mismatch');
+  }
+
+  const { token_endpoint } = await discover();
+  const response = await fetch(token_endpoint, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/x-www-form-urlencoded' },
+    body: new URLSearchParams({
+      grant_type: 'authorization_code',
+      code,
+      redirect_uri: redirectUri(),
+      client_id: CLIENT_ID,
+      code_verifier: pending.verifier,
+    }),
+  });
+  if (!response.ok) {
+    throw new Error('Login failed');
+  }
+
+  // The backend wants `aud` to be this client's ID. That holds for the ID
+  // token, while access tokens are often issued for another audience.
+  const tokens = await response.json();
+  if (!tokens.id_token) {
+    throw new Error('Login failed: no ID token (is the openid scope requested?)');
+  }
+  sessionStorage.setItem(
+    TOKEN_KEY,
+    JSON.stringify({
+      token: tokens.id_token,
+      expiresAt: claims(tokens.id_token).exp * 1000,
+    })
+  );
+}
+
+export function setApiKey(key) {
+  sessionStorage.setItem(API_KEY_KEY, key.trim());
+}
+
+// The login's ID token, or else the API key the user entered
+export function getToken() {
+  const stored = JSON.parse(sessionStorage.getItem(TOKEN_KEY) || 'null');
+  if (stored && stored.expiresAt > Date.now()) {
+    return stored.token;
+  }
+  return sessionStorage.getItem(API_KEY_KEY);
+}
+
+export function logout() {
+  sessionStorage.removeItem(TOKEN_KEY);
+  sessionStorage.removeItem(API_KEY_KEY);
+  window.dispatchEvent(new Event('noteforge:logout'));
+}